@@ -1,9 +1,127 @@
+use core::cell::UnsafeCell;
 use core::fmt;
+use spin::{Mutex, MutexGuard};
 use crate::util::sbi;
 
+/// 控制台输出锁，保证一条消息的字符不会与其他核心的输出交错
+///
+/// 同时保护当前的输出目标，更换目标也要持有该锁
+pub static CONSOLE_LOCK: Mutex<()> = Mutex::new(());
+
+/// `print!`和`println!`的输出目标
+///
+/// 默认是SBI控制台，测试可以换成缓冲区来检查输出，或者换成`NullSink`关闭输出。
+/// 调用时已经持有`CONSOLE_LOCK`，实现中不能再输出到控制台。
+pub trait ConsoleSink: Sync {
+    /// 写出一段字节
+    fn write_bytes(&self, bytes: &[u8]);
+}
+
+/// 通过SBI控制台输出，默认的输出目标
+pub struct SbiSink;
+
+impl ConsoleSink for SbiSink {
+    fn write_bytes(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            sbi::console_putchar(byte as char);
+        }
+    }
+}
+
+/// 丢弃所有输出
+pub struct NullSink;
+
+impl ConsoleSink for NullSink {
+    fn write_bytes(&self, _bytes: &[u8]) {}
+}
+
+/// 把输出保存到固定大小的缓冲区，写满后丢弃后续的输出
+pub struct BufferSink<const N: usize> {
+    buffer: Mutex<([u8; N], usize)>,
+}
+
+impl<const N: usize> BufferSink<N> {
+    /// 创建空缓冲区
+    pub const fn new() -> Self {
+        Self {
+            buffer: Mutex::new(([0; N], 0)),
+        }
+    }
+
+    /// 以字符串形式访问已保存的输出，截断处不是完整字符时只包含之前的部分
+    ///
+    /// 回调期间持有缓冲区的锁并关闭中断，回调中不能输出到控制台
+    pub fn with_contents<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        let was_enabled = crate::trap::infrastructure::disable_interrupts();
+        let buffer = self.buffer.lock();
+        let bytes = &buffer.0[..buffer.1];
+        let text = match core::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""),
+        };
+        let result = f(text);
+        drop(buffer);
+        crate::trap::infrastructure::restore_interrupts(was_enabled);
+        result
+    }
+
+    /// 清空已保存的输出
+    pub fn clear(&self) {
+        let was_enabled = crate::trap::infrastructure::disable_interrupts();
+        self.buffer.lock().1 = 0;
+        crate::trap::infrastructure::restore_interrupts(was_enabled);
+    }
+}
+
+impl<const N: usize> ConsoleSink for BufferSink<N> {
+    fn write_bytes(&self, bytes: &[u8]) {
+        let mut buffer = self.buffer.lock();
+        let (data, len) = &mut *buffer;
+        let count = bytes.len().min(N - *len);
+        data[*len..*len + count].copy_from_slice(&bytes[..count]);
+        *len += count;
+    }
+}
+
+/// 当前的输出目标，只在持有`CONSOLE_LOCK`时访问
+struct SinkCell(UnsafeCell<&'static dyn ConsoleSink>);
+
+// 所有访问都在CONSOLE_LOCK之内
+unsafe impl Sync for SinkCell {}
+
+static SINK: SinkCell = SinkCell(UnsafeCell::new(&SbiSink));
+
+/// 更换`print!`和`println!`的输出目标，返回之前的目标
+///
+/// 等待正在输出的消息写完后再更换，之后的消息写到新的目标。
+/// `print_str`等直接输出的函数不受影响，仍然输出到SBI控制台，panic信息不会被吞掉。
+pub fn set_console_sink(sink: &'static dyn ConsoleSink) -> &'static dyn ConsoleSink {
+    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    let guard = CONSOLE_LOCK.lock();
+    let previous = unsafe { core::mem::replace(&mut *SINK.0.get(), sink) };
+    drop(guard);
+    crate::trap::infrastructure::restore_interrupts(was_enabled);
+    previous
+}
+
+/// 阻塞式输出，等待控制台锁空闲
+///
+/// 持锁期间关闭中断，避免同一核心上的中断处理程序再次获取控制台锁。
+/// 在持有其他锁的处理路径中应使用`klog::try_log`。
 pub fn print(args: fmt::Arguments) {
+    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    let guard = CONSOLE_LOCK.lock();
+    write_locked(&guard, args);
+    drop(guard);
+    crate::trap::infrastructure::restore_interrupts(was_enabled);
+}
+
+/// 在已持有的控制台锁下输出，`_lock`证明调用者持有`CONSOLE_LOCK`
+pub fn write_locked(_lock: &MutexGuard<'_, ()>, args: fmt::Arguments) {
     use core::fmt::Write;
-    Stdout.write_fmt(args).unwrap();
+    // 持有CONSOLE_LOCK，set_console_sink不会同时修改SINK
+    let sink = unsafe { *SINK.0.get() };
+    Stdout(sink).write_fmt(args).unwrap();
 }
 
 pub fn print_str(s: &str) {
@@ -34,11 +152,100 @@ pub fn print_num(num: usize) {
     }
 }
 
-struct Stdout;
+/// 十六进制数字
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// 十六进制转储每行的字节数
+pub const HEXDUMP_BYTES_PER_LINE: usize = 16;
+
+/// 十六进制转储一行的最大长度：地址、两组十六进制字节、ASCII栏和换行
+pub const HEXDUMP_LINE_LEN: usize = 16 + 1 + HEXDUMP_BYTES_PER_LINE * 3 + 1 + 3 + HEXDUMP_BYTES_PER_LINE + 2;
+
+/// 以固定位数的十六进制输出数字
+pub fn print_hex(num: usize, digits: usize) {
+    for i in (0..digits.min(16)).rev() {
+        sbi::console_putchar(HEX_DIGITS[(num >> (i * 4)) & 0xf] as char);
+    }
+}
+
+/// 格式化十六进制转储的一行
+///
+/// 输出形如`0000000080200000  48 65 6c 6c 6f 00 ...  |Hello...|`，
+/// 不足16字节时用空格补齐十六进制部分，使ASCII栏保持对齐。
+/// 超过16字节的部分被忽略。
+pub fn hexdump_line<'a>(addr: usize, bytes: &[u8], out: &'a mut [u8; HEXDUMP_LINE_LEN]) -> &'a str {
+    let bytes = &bytes[..bytes.len().min(HEXDUMP_BYTES_PER_LINE)];
+    let mut len = 0;
+    let mut push = |out: &mut [u8; HEXDUMP_LINE_LEN], byte: u8| {
+        out[len] = byte;
+        len += 1;
+    };
+
+    for i in (0..16).rev() {
+        push(out, HEX_DIGITS[(addr >> (i * 4)) & 0xf]);
+    }
+    push(out, b' ');
+
+    for i in 0..HEXDUMP_BYTES_PER_LINE {
+        // 前后两组各8字节，中间多一个空格
+        push(out, b' ');
+        if i == HEXDUMP_BYTES_PER_LINE / 2 {
+            push(out, b' ');
+        }
+        match bytes.get(i) {
+            Some(&byte) => {
+                push(out, HEX_DIGITS[(byte >> 4) as usize]);
+                push(out, HEX_DIGITS[(byte & 0xf) as usize]);
+            }
+            None => {
+                push(out, b' ');
+                push(out, b' ');
+            }
+        }
+    }
+
+    push(out, b' ');
+    push(out, b' ');
+    push(out, b'|');
+    for &byte in bytes {
+        push(out, if byte.is_ascii_graphic() || byte == b' ' { byte } else { b'.' });
+    }
+    push(out, b'|');
+    push(out, b'\n');
+
+    // 只写入了ASCII字符
+    core::str::from_utf8(&out[..len]).unwrap_or("")
+}
+
+/// 以十六进制和ASCII形式输出一段内存
+///
+/// 每行16字节，左侧为地址，右侧为ASCII栏（不可打印字符显示为`.`）。
+/// 不获取控制台锁也不使用堆，可以在异常处理中使用。
+///
+/// # Safety
+///
+/// 调用者需要保证`[addr, addr + len)`可读，读取过程中再次发生页错误无法恢复。
+pub unsafe fn hexdump(addr: *const u8, len: usize) {
+    let mut line = [0u8; HEXDUMP_LINE_LEN];
+    let mut chunk = [0u8; HEXDUMP_BYTES_PER_LINE];
+    let mut offset = 0;
+
+    while offset < len {
+        let count = (len - offset).min(HEXDUMP_BYTES_PER_LINE);
+        for (i, byte) in chunk[..count].iter_mut().enumerate() {
+            *byte = core::ptr::read_volatile(addr.add(offset + i));
+        }
+        print_str(hexdump_line(addr as usize + offset, &chunk[..count], &mut line));
+        offset += count;
+    }
+}
+
+/// 把格式化输出写到输出目标
+struct Stdout(&'static dyn ConsoleSink);
 
 impl core::fmt::Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        print_str(s);
+        self.0.write_bytes(s.as_bytes());
         Ok(())
     }
 }
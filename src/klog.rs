@@ -0,0 +1,61 @@
+//! 非阻塞内核日志
+//!
+//! 在持有`HANDLER_STORAGE`、`REGISTRY`、`TRAP_SYSTEMS`等锁的代码中调用阻塞的`println!`，
+//! 一旦控制台锁被其他核心占用，就会在持锁状态下自旋，形成锁顺序问题甚至死锁。
+//! 这里的输出只尝试获取控制台锁，失败时丢弃消息并计数，绝不阻塞。
+//! 面向用户的顶层输出仍使用阻塞的`println!`。
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::console::{self, CONSOLE_LOCK};
+
+/// 因控制台锁被占用而丢弃的消息数量
+static DROPPED_MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// 尝试输出一条消息，控制台锁被占用时丢弃
+///
+/// 返回消息是否已输出
+pub fn try_log(args: fmt::Arguments) -> bool {
+    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    let written = match CONSOLE_LOCK.try_lock() {
+        Some(guard) => {
+            console::write_locked(&guard, args);
+            true
+        }
+        None => {
+            DROPPED_MESSAGES.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    };
+    crate::trap::infrastructure::restore_interrupts(was_enabled);
+    written
+}
+
+/// 获取被丢弃的消息数量
+pub fn dropped_messages() -> usize {
+    DROPPED_MESSAGES.load(Ordering::Relaxed)
+}
+
+/// 非阻塞版本的`println!`，用于持有其他锁的代码
+#[macro_export]
+macro_rules! try_println {
+    () => {
+        $crate::klog::try_log(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::klog::try_log(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// 每次trap都会执行的诊断输出，只在开启`trap_trace`特性时生效
+///
+/// 未开启特性时条件在编译期为假，整条语句被优化掉，默认不拖慢时钟中断等热路径。
+/// 致命错误的报告不应使用它。
+#[macro_export]
+macro_rules! trap_log {
+    ($($arg:tt)*) => {
+        if cfg!(feature = "trap_trace") {
+            $crate::try_println!($($arg)*);
+        }
+    };
+}
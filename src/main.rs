@@ -6,10 +6,14 @@
 
 use core::panic::PanicInfo;
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 mod console;
+mod klog;
 mod util;
 mod trap;
+mod mm;
+mod sched;
 mod test;
 
 // 启动栈大小
@@ -19,6 +23,15 @@ const STACK_SIZE: usize = 4096 * 4;
 #[link_section = ".bss.stack"]
 static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
+// SBI传入的设备树地址，由kernel_entry在清除BSS之后保存
+static mut DTB_ADDR: usize = 0;
+
+// 是否已经在处理panic，错误处理路径本身panic时不再进入
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+// panic时打印的最大调用栈深度
+const PANIC_BACKTRACE_FRAMES: usize = 16;
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     if let Some(location) = info.location() {
@@ -37,38 +50,80 @@ fn panic(info: &PanicInfo) -> ! {
     } else {
         console::print_str("Panicked: Unknown location");
     }
+    console::print_str("\n");
+
+    // 交给错误管理器的致命错误路径（记录日志并关机），
+    // trap系统尚未初始化或处理过程中再次panic时退回到打印后停机
+    let first_panic = !PANICKING.swap(true, Ordering::SeqCst);
+    if first_panic && trap::infrastructure::di::get_trap_system_initialized() {
+        // panic可能发生在输出过程中，之后不会再回到持有控制台锁的代码
+        if console::CONSOLE_LOCK.is_locked() {
+            unsafe { console::CONSOLE_LOCK.force_unlock() };
+        }
+
+        // PanicInfo只给出源码位置，panic点的代码地址从当前的帧指针回溯，
+        // 前几帧是core的panic路径，之后是panic点及其调用者
+        let fp: usize;
+        unsafe { asm!("mv {0}, s0", out(reg) fp) };
+        trap::infrastructure::backtrace::walk(fp, PANIC_BACKTRACE_FRAMES);
+
+        let location = info.location().map_or(0, |location| location as *const _ as usize);
+        trap::infrastructure::error_handler::handle_panic(location);
+    }
     loop {}
 }
 
+/// 内核入口，SBI跳转到这里时a0为hartid，a1为设备树地址
+///
+/// 此时还没有可用的栈，只能写成裸函数：设置tp、清除BSS段并设置sp后，
+/// 把a0、a1原样交给`kernel_entry`。启动栈也在BSS段中，必须在使用它之前清除。
+#[naked]
 #[no_mangle]
 #[link_section = ".text.entry"]
-fn _start() -> ! {
-    unsafe {
+unsafe extern "C" fn _start() -> ! {
+    core::arch::naked_asm!(
+        // 保存hartid到tp，供current_hart_id()使用
+        "mv tp, a0",
+        // 逐字节清除BSS段，只使用临时寄存器，a0、a1保持不变
+        "la t0, {sbss}",
+        "la t1, {ebss}",
+        "1:",
+        "bgeu t0, t1, 2f",
+        "sb zero, 0(t0)",
+        "addi t0, t0, 1",
+        "j 1b",
+        "2:",
         // 设置栈指针
-        let stack_top = STACK.as_ptr().add(STACK_SIZE);
-        asm!(
-            "mv sp, {0}",
-            in(reg) stack_top,
-        );
-        
-        // 清除BSS段
-        extern "C" {
-            fn sbss();
-            fn ebss();
-        }
-        let sbss_addr = sbss as usize;
-        let ebss_addr = ebss as usize;
-        
-        // 逐字节清零
-        for addr in sbss_addr..ebss_addr {
-            core::ptr::write_volatile(addr as *mut u8, 0);
-        }
-        
-        // 跳转到Rust主函数
-        rust_main();
+        "la sp, {stack}",
+        "li t0, {stack_size}",
+        "add sp, sp, t0",
+        "call {entry}",
+        sbss = sym sbss,
+        ebss = sym ebss,
+        stack = sym STACK,
+        stack_size = const STACK_SIZE,
+        entry = sym kernel_entry,
+    )
+}
+
+extern "C" {
+    fn sbss();
+    fn ebss();
+}
+
+/// `_start`设置好tp和栈之后进入的Rust入口
+extern "C" fn kernel_entry(hartid: usize, dtb: usize) -> ! {
+    // BSS段已清除，保存的地址不会再被覆盖
+    unsafe { DTB_ADDR = dtb };
+
+    // 按核心划分的数据只有MAX_HARTS个槽位，超出范围的核心在访问它们之前停下
+    if hartid >= util::sbi::hart::MAX_HARTS {
+        console::print_str("Hart id exceeds MAX_HARTS, stopping this hart\n");
+        util::sbi::hart::hart_stop();
     }
-    
-    loop {}
+
+    // 跳转到Rust主函数
+    rust_main(hartid)
 }
 
 fn run_kernel_tests() {
@@ -87,9 +142,19 @@ fn run_kernel_tests() {
     }
 }
 
-#[no_mangle]
-fn rust_main() -> ! {
-    println!("Hello, RISC-V RustOS!");
+fn rust_main(hartid: usize) -> ! {
+    println!("Hello, RISC-V RustOS! Booting on hart {}", hartid);
+
+    // 从设备树读取时基频率、ISA扩展、Sstc扩展和PLIC地址，读取失败时使用QEMU virt的默认值
+    // 和编译目标启用的扩展，并通过SBI设置定时器
+    if let Some(fdt) = unsafe { util::fdt::Fdt::from_addr(DTB_ADDR) } {
+        util::sbi::timer::init_timebase_from_fdt(&fdt);
+        util::cpu::init_from_fdt(&fdt);
+        util::sbi::timer::init_sstc_from_fdt(&fdt);
+        trap::infrastructure::plic::init_from_fdt(&fdt);
+    }
+    println!("Timebase frequency: {} Hz, Sstc: {}",
+             util::sbi::timer::timebase_frequency(), util::sbi::timer::has_sstc());
 
     // 初始化中断系统
     trap::init();  // 这应该内部调用DI系统的初始化
@@ -100,7 +165,13 @@ fn rust_main() -> ! {
     // 使用新封装的系统信息功能
     let sys_info = util::sbi::system::get_system_info();
     sys_info.print();
+    util::cpu::isa_extensions().print();
     
+    // 控制台输入改由外部中断送入缓冲区
+    if !util::sbi::console::init_interrupt_input() {
+        println!("Interrupt-driven console input unavailable, falling back to polling");
+    }
+
     // 测试控制台输入功能
     println!("Please input some text (max 20 characters):");
     let mut buffer = [0u8; 21];
@@ -110,7 +181,7 @@ fn rust_main() -> ! {
     // 测试时钟功能
     println!("Current time count: {}", util::sbi::timer::get_time());
     println!("Waiting for a while...");
-    util::sbi::timer::sleep_cycles(10000000); // 等待一段时间
+    util::delay::busy_wait_ms(1000); // 等待1秒
     println!("Current time count: {}", util::sbi::timer::get_time());
     
     // 演示TLB刷新
@@ -120,10 +191,11 @@ fn rust_main() -> ! {
     // 设置一个相对定时器
     println!("Setting relative timer, interrupt will be triggered after 1 second...");
     // 注意：实际使用需要设置中断处理程序
-    util::sbi::timer::set_timer_rel(10000000); // 假设10M周期约为1秒
+    util::sbi::timer::set_timer_rel(util::delay::ms_to_ticks(1000));
     
     // 循环等待
     println!("System startup completed, entering main loop");
+    trap::infrastructure::enable_interrupts();
     loop {
         // 尝试获取控制台输入
         if let Some(c) = util::sbi::console::try_getchar() {
@@ -141,9 +213,20 @@ fn rust_main() -> ! {
                 }
             }
         }
-        
-        // 使用自旋循环提示处理器可以省电
-        core::hint::spin_loop();
+
+        if !util::sbi::console::is_interrupt_input() {
+            // 控制台输入只能轮询，不能休眠
+            core::hint::spin_loop();
+            continue;
+        }
+
+        // 关中断后再确认没有输入，然后休眠到定时器或控制台中断到来；
+        // 开中断后等待中的中断立即进入trap，下一轮循环读取它送来的输入
+        let was_enabled = trap::infrastructure::disable_interrupts();
+        if util::sbi::console::input_available() == 0 {
+            util::sbi::hart::wait_for_interrupt();
+        }
+        trap::infrastructure::restore_interrupts(was_enabled);
     }
 }
 
@@ -0,0 +1,62 @@
+//! 页错误访问类型解析
+//!
+//! scause只能区分取指、加载和存储/AMO三类页错误，无法判断存储页错误
+//! 是普通存储还是原子操作。写时复制等处理需要知道这一点（AMO要求读-改-写
+//! 都在新页上完成），因此这里通过解码出错的指令进一步区分。
+
+use crate::trap::ds::TrapType;
+use super::probe;
+
+/// AMO/LR/SC指令的主操作码
+const OPCODE_AMO: u32 = 0b010_1111;
+
+/// 页错误的访问类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// 取指
+    Execute,
+    /// 加载
+    Load,
+    /// 存储，`is_atomic`表示是否由AMO/SC指令产生
+    Store {
+        is_atomic: bool,
+    },
+}
+
+/// 读取出错位置的指令
+///
+/// 16位压缩指令在低16位返回；地址为0、未按2字节对齐或不可读时返回None。
+/// 通过探测读取访问，`sepc`指向无效地址时也不会再次引发异常。
+pub fn fetch_faulting_instruction(sepc: usize) -> Option<u32> {
+    if sepc == 0 || sepc & 0x1 != 0 {
+        return None;
+    }
+
+    // 指令可能只按2字节对齐，分两次读取
+    let low = probe::try_read_u16(sepc)? as u32;
+    if low & 0x3 != 0x3 {
+        return Some(low);
+    }
+    let high = probe::try_read_u16(sepc + 2)? as u32;
+    Some(low | (high << 16))
+}
+
+/// 判断指令是否为原子内存操作（AMO、LR或SC）
+pub fn is_atomic_instruction(instruction: u32) -> bool {
+    // 压缩指令中没有原子操作
+    instruction & 0x3 == 0x3 && instruction & 0x7F == OPCODE_AMO
+}
+
+/// 根据页错误类型和出错的指令确定访问类型
+///
+/// 非页错误返回None；无法取得指令时存储页错误按普通存储处理。
+pub fn access_kind(trap_type: TrapType, instruction: Option<u32>) -> Option<AccessKind> {
+    match trap_type {
+        TrapType::InstructionPageFault => Some(AccessKind::Execute),
+        TrapType::LoadPageFault => Some(AccessKind::Load),
+        TrapType::StorePageFault => Some(AccessKind::Store {
+            is_atomic: instruction.is_some_and(is_atomic_instruction),
+        }),
+        _ => None,
+    }
+}
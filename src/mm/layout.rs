@@ -0,0 +1,39 @@
+//! 内核镜像布局
+//!
+//! 通过链接脚本导出的符号获取内核各段的地址范围。
+
+use core::ops::Range;
+
+extern "C" {
+    fn stext();
+    fn etext();
+    fn ekernel();
+}
+
+/// 内核代码段的地址范围
+pub fn kernel_text() -> Range<usize> {
+    stext as usize..etext as usize
+}
+
+/// 地址是否位于内核代码段内
+pub fn is_kernel_text(addr: usize) -> bool {
+    kernel_text().contains(&addr)
+}
+
+/// 整个内核镜像（代码段到bss段末尾）的地址范围，启动栈位于bss段内
+pub fn kernel_image() -> Range<usize> {
+    stext as usize..ekernel as usize
+}
+
+/// 内核镜像的起止地址`(start, end)`，`end`不含在内
+///
+/// 即元组形式的[`kernel_image`]
+pub fn kernel_range() -> (usize, usize) {
+    let image = kernel_image();
+    (image.start, image.end)
+}
+
+/// 地址是否位于内核镜像内
+pub fn is_kernel_addr(addr: usize) -> bool {
+    kernel_image().contains(&addr)
+}
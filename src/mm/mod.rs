@@ -0,0 +1,10 @@
+//! 内存管理模块
+//!
+//! 目前只包含地址空间的激活、页错误访问类型解析、内核镜像布局和可失败的探测读取，页表管理将在此基础上扩展。
+
+pub mod paging;
+pub mod fault;
+pub mod layout;
+pub mod probe;
+
+pub use probe::{try_read_u8, try_read_u16, try_read_u32, try_read_usize};
@@ -0,0 +1,49 @@
+//! 分页模式与地址空间激活
+//!
+//! 负责构造satp的值并切换当前核心的地址空间。
+
+use crate::util::csr;
+
+/// satp中的分页模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PagingMode {
+    /// 不进行地址转换
+    Bare = 0,
+    /// 39位虚拟地址
+    Sv39 = 8,
+    /// 48位虚拟地址
+    Sv48 = 9,
+}
+
+/// satp寄存器的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Satp(usize);
+
+impl Satp {
+    /// 关闭地址转换
+    pub const fn bare() -> Self {
+        Self(0)
+    }
+
+    /// 由分页模式、地址空间ID和根页表物理页号构造
+    pub const fn new(mode: PagingMode, asid: u16, root_ppn: usize) -> Self {
+        Self(((mode as usize) << 60) | ((asid as usize) << 44) | (root_ppn & ((1 << 44) - 1)))
+    }
+
+    /// 原始值
+    pub const fn bits(&self) -> usize {
+        self.0
+    }
+}
+
+/// 激活地址空间
+///
+/// 写入satp后会执行`sfence.vma`，保证新的映射立即生效。
+pub fn activate(satp: Satp) {
+    csr::write_satp(satp.bits());
+}
+
+/// 获取当前的satp值
+pub fn current() -> Satp {
+    Satp(csr::read_satp())
+}
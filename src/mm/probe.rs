@@ -0,0 +1,131 @@
+//! 可失败的内存探测读取
+//!
+//! 诊断代码（指令解码、栈回溯等）经常要读取来源不可靠的地址，直接读取在地址无效时
+//! 会在异常处理器中再次触发异常，被判定为双重故障而停机。这里的读取函数在读取前
+//! 设置当前核心的探测状态，trap入口在双重故障检测之前检查它：出错的正是探测的地址时
+//! 跳过出错的加载指令并记录失败，读取函数据此返回None，而不是交给异常处理器。
+//!
+//! 读取期间关闭中断，中断处理程序中的探测不会覆盖被打断的探测状态。
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::trap::ds::{TrapContext, TrapType};
+use crate::trap::infrastructure::{disable_interrupts, restore_interrupts, insn};
+use crate::util::percpu::PerCpu;
+use crate::util::sbi::hart::MAX_HARTS;
+
+/// 单个核心的探测状态
+struct ProbeState {
+    /// 正在进行探测读取，此时探测地址上的加载错误是预期的
+    active: AtomicBool,
+    /// 探测的起始地址
+    addr: AtomicUsize,
+    /// 探测的字节数
+    len: AtomicUsize,
+    /// 探测读取触发了异常
+    faulted: AtomicBool,
+}
+
+impl ProbeState {
+    const fn new() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            addr: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            faulted: AtomicBool::new(false),
+        }
+    }
+}
+
+/// 每个核心的探测状态
+static PROBE_STATE: PerCpu<ProbeState, MAX_HARTS> =
+    PerCpu::new([const { ProbeState::new() }; MAX_HARTS]);
+
+/// 在探测状态下执行`load`，出错时返回None
+///
+/// `load`中只能有一条访问`[addr, addr + len)`的加载指令
+fn probe(addr: usize, len: usize, load: impl FnOnce() -> usize) -> Option<usize> {
+    let was_enabled = disable_interrupts();
+    let state = PROBE_STATE.get();
+    state.addr.store(addr, Ordering::SeqCst);
+    state.len.store(len, Ordering::SeqCst);
+    state.faulted.store(false, Ordering::SeqCst);
+    state.active.store(true, Ordering::SeqCst);
+
+    let value = load();
+
+    state.active.store(false, Ordering::SeqCst);
+    let faulted = state.faulted.swap(false, Ordering::SeqCst);
+    restore_interrupts(was_enabled);
+
+    (!faulted).then_some(value)
+}
+
+/// 尝试读取一个字节，地址不可读时返回None
+pub fn try_read_u8(addr: usize) -> Option<u8> {
+    probe(addr, 1, || {
+        let value: usize;
+        unsafe { asm!("lbu {0}, 0({1})", out(reg) value, in(reg) addr, options(nostack, readonly)) };
+        value
+    })
+    .map(|value| value as u8)
+}
+
+/// 尝试读取一个16位值，地址不可读时返回None
+pub fn try_read_u16(addr: usize) -> Option<u16> {
+    probe(addr, 2, || {
+        let value: usize;
+        unsafe { asm!("lhu {0}, 0({1})", out(reg) value, in(reg) addr, options(nostack, readonly)) };
+        value
+    })
+    .map(|value| value as u16)
+}
+
+/// 尝试读取一个32位值，地址不可读时返回None
+pub fn try_read_u32(addr: usize) -> Option<u32> {
+    probe(addr, 4, || {
+        let value: usize;
+        unsafe { asm!("lwu {0}, 0({1})", out(reg) value, in(reg) addr, options(nostack, readonly)) };
+        value
+    })
+    .map(|value| value as u32)
+}
+
+/// 尝试读取一个usize，地址不可读时返回None
+pub fn try_read_usize(addr: usize) -> Option<usize> {
+    probe(addr, core::mem::size_of::<usize>(), || {
+        let value: usize;
+        unsafe { asm!("ld {0}, 0({1})", out(reg) value, in(reg) addr, options(nostack, readonly)) };
+        value
+    })
+}
+
+/// 处理探测读取触发的异常，由trap入口在分发之前调用
+///
+/// 当前核心正在探测、异常是加载类异常且出错地址落在探测范围内时，
+/// 记录失败并让sepc跳过出错的指令，返回true；其他情况返回false，按普通异常处理。
+pub fn fixup(ctx: &mut TrapContext) -> bool {
+    let state = PROBE_STATE.get();
+    if !state.active.load(Ordering::SeqCst) {
+        return false;
+    }
+
+    let cause = ctx.get_cause();
+    let is_load_fault = matches!(
+        cause.to_trap_type(),
+        TrapType::LoadAccessFault | TrapType::LoadPageFault | TrapType::LoadMisaligned
+    );
+    let start = state.addr.load(Ordering::SeqCst);
+    let len = state.len.load(Ordering::SeqCst);
+    if cause.is_interrupt() || !is_load_fault || ctx.stval.wrapping_sub(start) >= len {
+        return false;
+    }
+
+    // 出错的加载指令在内核代码段内，可以直接读取以确定长度
+    let Some(instruction) = insn::read_instruction(ctx.sepc) else {
+        return false;
+    };
+    state.faulted.store(true, Ordering::SeqCst);
+    ctx.set_return_addr(ctx.sepc + insn::instruction_length(instruction));
+    true
+}
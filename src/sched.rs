@@ -0,0 +1,354 @@
+//! 协作式调度
+//!
+//! 固定数量的任务槽位，每个任务有自己的内核栈、`TaskContext`和进程控制块。
+//! `yield_now`按轮转顺序切换到下一个可运行的任务：进程状态为`ContextState::Active`
+//! 的任务可以运行，`Waiting`等其他状态的任务被跳过，状态改回`Active`后重新参与轮转。
+//! 没有抢占，任务只在调用`yield_now`或入口函数返回时让出CPU。
+//!
+//! `start_timeslice`开启时间片抢占后，时钟回调设置重新调度请求，
+//! trap返回路径在返回被打断的任务之前切换到下一个任务，忙循环的任务也会轮流运行。
+//! 需要不被切换走的代码段使用`PreemptGuard`关闭抢占。
+//!
+//! `sleep_ms`用任务的上下文定时器登记唤醒时间，把进程状态设为`Sleeping`后让出CPU，
+//! 定时器到期时回调把状态改回`Active`，任务重新参与轮转。
+//!
+//! 0号槽位是启动代码本身（引导任务），它没有进程控制块，总是可运行。
+//! `run`让引导任务不断让出CPU，直到其他任务都结束。目前只在启动核心上调度。
+//!
+//! 切换直接使用`task_switch`而不是`di::switch_task_context`，
+//! 后者在切换期间持有trap系统的写锁，切换到的任务再分发trap时会死锁。
+//! 切换总是在关中断的情况下进行，每个任务切换回来后恢复自己原来的中断状态。
+//! 浮点寄存器不随`TaskContext`保存，切换时交给`lazy_fp`按归属惰性切换。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::trap::ds::{TaskContext, ContextState, ContextManagerAccessError};
+use crate::trap::infrastructure::{
+    self as infra, lazy_fp, task_switch, disable_interrupts, restore_interrupts,
+};
+use crate::util::sbi::timer;
+use crate::trap::infrastructure::di::context::ContextId;
+use crate::trap::infrastructure::di::context_pool::{self, ProcessHandle, PoolError};
+
+/// 任务槽位数量，包括引导任务
+pub const MAX_TASKS: usize = 4;
+
+/// 每个任务的内核栈大小
+pub const TASK_STACK_SIZE: usize = 16 * 1024;
+
+/// 引导任务的槽位
+const BOOT_TASK: usize = 0;
+
+/// 创建任务失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// 没有空闲的任务槽位
+    NoFreeSlot,
+    /// 创建进程控制块失败
+    Process(PoolError),
+}
+
+/// 一个已创建的任务
+struct Task {
+    /// 任务入口
+    entry: fn(),
+    /// 任务的进程控制块
+    process: ProcessHandle,
+    /// 任务开始运行时是否开中断，与创建它的代码一致
+    interrupts_enabled: bool,
+    /// 入口函数已返回，等待其他任务回收槽位
+    finished: bool,
+}
+
+/// 任务的内核栈
+#[repr(C, align(16))]
+struct TaskStack([u8; TASK_STACK_SIZE]);
+
+/// 除引导任务外每个槽位的内核栈，第`slot`个槽位使用第`slot - 1`个
+static mut TASK_STACKS: [TaskStack; MAX_TASKS - 1] =
+    [const { TaskStack([0; TASK_STACK_SIZE]) }; MAX_TASKS - 1];
+
+/// 调度器状态
+struct Scheduler {
+    /// 每个槽位切换出去时保存的寄存器
+    contexts: [TaskContext; MAX_TASKS],
+    /// 槽位上的任务，引导任务的槽位总是None
+    tasks: [Option<Task>; MAX_TASKS],
+    /// 正在运行的槽位
+    current: usize,
+}
+
+impl Scheduler {
+    const fn new() -> Self {
+        Self {
+            contexts: [const { TaskContext::new() }; MAX_TASKS],
+            tasks: [const { None }; MAX_TASKS],
+            current: BOOT_TASK,
+        }
+    }
+
+    /// 槽位上的任务是否可以运行
+    fn is_runnable(&self, slot: usize) -> bool {
+        if slot == BOOT_TASK {
+            return true;
+        }
+        self.tasks[slot].as_ref().is_some_and(|task| {
+            !task.finished
+                && task.process
+                    .get_state()
+                    .is_ok_and(|state| ContextState::from_u8(state) == Some(ContextState::Active))
+        })
+    }
+
+    /// 取出已结束的任务，当前任务即使已结束也还在它的栈上运行，不能回收
+    fn take_finished(&mut self) -> [Option<Task>; MAX_TASKS] {
+        let mut finished = [const { None }; MAX_TASKS];
+        for slot in 0..MAX_TASKS {
+            if slot != self.current && self.tasks[slot].as_ref().is_some_and(|task| task.finished) {
+                finished[slot] = self.tasks[slot].take();
+            }
+        }
+        finished
+    }
+
+    /// 选出当前任务之后的下一个可运行任务并把它设为当前任务
+    ///
+    /// 返回(当前任务的上下文, 下一个任务的上下文)，没有其他可运行的任务时返回None。
+    /// 浮点寄存器的归属记录正被占用时也返回None，本次不切换。
+    fn switch_to_next(&mut self) -> Option<(*mut TaskContext, *const TaskContext)> {
+        let current = self.current;
+        let next = (1..MAX_TASKS)
+            .map(|offset| (current + offset) % MAX_TASKS)
+            .find(|&slot| self.is_runnable(slot))?;
+
+        // task_switch不保存浮点寄存器，由lazy_fp按归属设置下一个任务的FS
+        let next_pid = self.tasks[next].as_ref().map(|task| task.process.pid);
+        if lazy_fp::switch_current(next_pid) == Err(ContextManagerAccessError::Busy) {
+            return None;
+        }
+
+        self.current = next;
+        // 上下文保存在静态变量中，释放锁后指针仍然有效
+        Some((&mut self.contexts[current] as *mut _, &self.contexts[next] as *const _))
+    }
+}
+
+static SCHEDULER: Mutex<Scheduler> = Mutex::new(Scheduler::new());
+
+/// 时间片抢占使用的时钟回调，未开启时为usize::MAX
+static TIMESLICE_CALLBACK: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 回收已结束的任务
+///
+/// 进程句柄在锁外释放，最后一个句柄会销毁进程并注销它的处理器
+fn reap() {
+    let finished = SCHEDULER.lock().take_finished();
+    drop(finished);
+}
+
+/// 创建一个任务，返回它的进程句柄
+///
+/// 任务从`entry`开始执行，`entry`返回后任务结束，进程状态被设为`Terminated`。
+/// 任务在引导任务下一次调用`yield_now`或`run`时才开始运行。
+pub fn spawn(name: &'static str, entry: fn()) -> Result<ProcessHandle, SpawnError> {
+    reap();
+
+    let mut scheduler = SCHEDULER.lock();
+    let slot = (0..MAX_TASKS)
+        .find(|&slot| slot != BOOT_TASK && scheduler.tasks[slot].is_none())
+        .ok_or(SpawnError::NoFreeSlot)?;
+
+    let process = context_pool::create_process(None).map_err(SpawnError::Process)?;
+    process.set_name(name).map_err(SpawnError::Process)?;
+
+    let stack_top = unsafe { core::ptr::addr_of!(TASK_STACKS[slot - 1]) as usize + TASK_STACK_SIZE };
+    scheduler.contexts[slot] = TaskContext::new_for_task(task_entry as usize, stack_top);
+    scheduler.tasks[slot] = Some(Task {
+        entry,
+        process: process.try_clone().map_err(SpawnError::Process)?,
+        interrupts_enabled: riscv::register::sstatus::read().sie(),
+        finished: false,
+    });
+
+    Ok(process)
+}
+
+/// 让出CPU，切换到下一个可运行的任务
+///
+/// 没有其他可运行的任务时立即返回
+pub fn yield_now() {
+    let was_enabled = disable_interrupts();
+    // 锁在切换前释放，下一个任务还要使用调度器
+    let switch = SCHEDULER.lock().switch_to_next();
+    if let Some((current, next)) = switch {
+        unsafe { task_switch(current, next) };
+    }
+    restore_interrupts(was_enabled);
+}
+
+/// 唤醒回调拿不到锁时，隔多少个时钟周期后重试
+const WAKE_RETRY_CYCLES: u64 = 1000;
+
+/// 让当前任务睡眠至少`ms`毫秒，期间运行其他任务
+///
+/// 唤醒依赖时钟中断，调用前需要开启时钟中断。引导任务没有进程，
+/// 上下文定时器槽位已满时也无法登记唤醒，这两种情况退回忙等待。
+pub fn sleep_ms(ms: u64) {
+    let Some(pid) = current_pid() else {
+        timer::sleep_ms(ms);
+        return;
+    };
+    let cycles = ms.saturating_mul(timer::timebase_frequency()) / 1000;
+
+    // 关中断后再登记，定时器不会在状态改为Sleeping之前到期
+    let was_enabled = disable_interrupts();
+    let armed = timer::set_context_timer_callback(pid, wake_sleeping) &&
+        timer::set_context_timer(pid, cycles);
+    let sleeping = armed && with_current_process(|process| {
+        process.set_state(ContextState::Sleeping as u8).is_ok()
+    }).unwrap_or(false);
+    if !sleeping {
+        if armed {
+            timer::cancel_context_timer(pid);
+        }
+        restore_interrupts(was_enabled);
+        timer::sleep_ms(ms);
+        return;
+    }
+
+    // 睡眠中的任务不可运行，唤醒后才会被切换回来
+    while with_current_process(|process| process.get_state()) == Some(Ok(ContextState::Sleeping as u8)) {
+        yield_now();
+    }
+    restore_interrupts(was_enabled);
+}
+
+/// 在调度器锁内访问当前任务的进程，引导任务返回None
+fn with_current_process<R>(f: impl FnOnce(&ProcessHandle) -> R) -> Option<R> {
+    let scheduler = SCHEDULER.lock();
+    scheduler.tasks[scheduler.current].as_ref().map(|task| f(&task.process))
+}
+
+/// 睡眠任务的上下文定时器到期时调用，把任务恢复为可运行
+///
+/// 在时钟中断中运行，调度器或进程池被占用时稍后重试
+fn wake_sleeping(pid: ContextId) {
+    let woken = SCHEDULER.try_lock().is_some_and(|scheduler| {
+        scheduler.tasks.iter().flatten()
+            .find(|task| task.process.pid == pid)
+            .is_none_or(|task| task.process.set_state(ContextState::Active as u8).is_ok())
+    });
+    if !woken {
+        timer::set_context_timer(pid, WAKE_RETRY_CYCLES);
+    }
+}
+
+/// trap返回路径处理重新调度请求时调用，切换到下一个任务
+fn preempt() {
+    let was_enabled = disable_interrupts();
+    // 被打断的任务可能正持有调度器锁，这时推迟到下一次trap返回
+    let switch = match SCHEDULER.try_lock() {
+        Some(mut scheduler) => scheduler.switch_to_next(),
+        None => {
+            infra::set_need_resched();
+            None
+        }
+    };
+    if let Some((current, next)) = switch {
+        unsafe { task_switch(current, next) };
+    }
+    restore_interrupts(was_enabled);
+}
+
+/// 时间片用完时由时钟中断调用
+fn on_timeslice_tick() {
+    infra::set_need_resched();
+}
+
+/// 开启时间片抢占，每`timeslice_cycles`个时钟周期切换一次任务
+///
+/// 使用周期时钟，时钟中断需要已经开启。已经开启或时钟回调槽位已满时返回false。
+pub fn start_timeslice(timeslice_cycles: u64) -> bool {
+    if timeslice_cycles == 0 || TIMESLICE_CALLBACK.load(Ordering::SeqCst) != usize::MAX {
+        return false;
+    }
+    let Some(id) = timer::register_tick_callback(on_timeslice_tick) else {
+        return false;
+    };
+
+    TIMESLICE_CALLBACK.store(id, Ordering::SeqCst);
+    infra::set_resched_handler(Some(preempt));
+    timer::set_periodic(timeslice_cycles);
+    true
+}
+
+/// 关闭时间片抢占，之后只在`yield_now`时切换任务
+pub fn stop_timeslice() {
+    let id = TIMESLICE_CALLBACK.swap(usize::MAX, Ordering::SeqCst);
+    if id == usize::MAX {
+        return;
+    }
+    timer::set_periodic(0);
+    timer::unregister_tick_callback(id);
+    infra::set_resched_handler(None);
+}
+
+/// 在引导任务中运行所有已创建的任务，直到它们都结束
+///
+/// 还有任务处于`Waiting`等状态时会一直等待它们恢复并结束
+pub fn run() {
+    loop {
+        reap();
+        if task_count() == 0 {
+            break;
+        }
+        yield_now();
+    }
+}
+
+/// 尚未结束的任务数量
+pub fn task_count() -> usize {
+    SCHEDULER.lock().tasks.iter().flatten().filter(|task| !task.finished).count()
+}
+
+/// 当前任务的进程ID，引导任务返回None
+pub fn current_pid() -> Option<ContextId> {
+    let scheduler = SCHEDULER.lock();
+    scheduler.tasks[scheduler.current].as_ref().map(|task| task.process.pid)
+}
+
+/// 新任务第一次被切换到时从这里开始执行
+extern "C" fn task_entry() -> ! {
+    let start = {
+        let scheduler = SCHEDULER.lock();
+        scheduler.tasks[scheduler.current]
+            .as_ref()
+            .map(|task| (task.entry, task.interrupts_enabled))
+    };
+    if let Some((entry, interrupts_enabled)) = start {
+        // 切换时中断是关闭的，按创建时的状态开始运行
+        restore_interrupts(interrupts_enabled);
+        entry();
+    }
+    exit_current()
+}
+
+/// 结束当前任务并切换到下一个任务
+///
+/// 任务只被标记为已结束，槽位和栈由之后运行的任务回收
+fn exit_current() -> ! {
+    {
+        let mut scheduler = SCHEDULER.lock();
+        let slot = scheduler.current;
+        if let Some(task) = scheduler.tasks[slot].as_mut() {
+            task.finished = true;
+            let _ = task.process.set_state(ContextState::Terminated as u8);
+            // 浮点保存区不等进程被回收，现在就还给其他任务
+            let _ = lazy_fp::release(task.process.pid);
+        }
+    }
+
+    // 已结束的任务不再可运行，引导任务总是可运行，这里不会返回
+    yield_now();
+    unreachable!("finished task was scheduled again");
+}
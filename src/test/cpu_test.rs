@@ -0,0 +1,92 @@
+//! CPU特性检测测试模块
+//!
+//! 测试 util::cpu 的ISA扩展解析
+
+use crate::util::cpu::{self, IsaExtensions};
+use crate::println;
+
+// 测试解析riscv,isa字符串
+fn test_parse_isa_string() -> bool {
+    println!("Testing riscv,isa string parsing...");
+
+    let Some(isa) = IsaExtensions::parse("rv64imafdc") else {
+        println!("Failed to parse rv64imafdc");
+        return false;
+    };
+    if !(isa.has_f() && isa.has_d() && isa.has_c() && isa.has_a()) || isa.xlen() != 64 {
+        println!("Unexpected extensions for rv64imafdc: {}", isa);
+        return false;
+    }
+
+    // g展开为imafd，多字母扩展被忽略
+    let Some(general) = IsaExtensions::parse("rv64gc_zicsr_zifencei") else {
+        println!("Failed to parse rv64gc_zicsr_zifencei");
+        return false;
+    };
+    if general != isa {
+        println!("rv64gc should equal rv64imafdc, got {}", general);
+        return false;
+    }
+
+    let Some(integer) = IsaExtensions::parse("rv32imc") else {
+        println!("Failed to parse rv32imc");
+        return false;
+    };
+    if integer.has_f() || integer.has_d() || integer.has_a() || !integer.has_c() || integer.xlen() != 32 {
+        println!("Unexpected extensions for rv32imc: {}", integer);
+        return false;
+    }
+
+    if IsaExtensions::parse("x86_64").is_some() {
+        println!("Non-RISC-V ISA string should be rejected");
+        return false;
+    }
+
+    println!("riscv,isa string parsing tests passed");
+    true
+}
+
+// 测试misa编码与运行时检测结果
+fn test_misa_and_detection() -> bool {
+    println!("Testing misa decoding and detection...");
+
+    // MXL=2（64位），扩展ACDFIM
+    let misa = (2usize << 62) | 0b1_0001_0010_1101;
+    let from_misa = IsaExtensions::from_misa(misa);
+    if Some(from_misa) != IsaExtensions::parse("rv64imafdc") {
+        println!("misa {:#x} decoded as {}", misa, from_misa);
+        return false;
+    }
+
+    // 内核以riscv64gc编译，运行时至少支持这些扩展
+    let detected = cpu::isa_extensions();
+    if !(detected.has_a() && detected.has_c()) {
+        println!("Detected extensions missing compiled-in A/C: {}", detected);
+        return false;
+    }
+
+    println!("misa decoding and detection tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running CPU feature tests ===");
+
+    println!("Starting riscv,isa parsing tests...");
+    let parse_test = test_parse_isa_string();
+    println!("riscv,isa parsing tests completed with result: {}", parse_test);
+
+    println!("Starting misa decoding tests...");
+    let misa_test = test_misa_and_detection();
+    println!("misa decoding tests completed with result: {}", misa_test);
+
+    let all_passed = parse_test && misa_test;
+
+    println!("=== CPU feature test results ===");
+    println!("riscv,isa parsing: {}", if parse_test { "PASSED" } else { "FAILED" });
+    println!("misa decoding and detection: {}", if misa_test { "PASSED" } else { "FAILED" });
+    println!("Overall CPU feature tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
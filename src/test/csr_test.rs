@@ -0,0 +1,74 @@
+//! CSR与地址空间测试模块
+//!
+//! 测试 util::csr 的屏障以及 mm::paging 的地址空间激活
+
+use crate::mm::paging::{self, Satp, PagingMode};
+use crate::util::csr;
+use crate::println;
+
+// 测试satp值的构造
+fn test_satp_encoding() -> bool {
+    println!("Testing satp encoding...");
+
+    let satp = Satp::new(PagingMode::Sv39, 0x12, 0x80200);
+    let expected = (8usize << 60) | (0x12usize << 44) | 0x80200;
+    if satp.bits() != expected {
+        println!("Unexpected satp value: {:#x}, expected {:#x}", satp.bits(), expected);
+        return false;
+    }
+
+    if Satp::bare().bits() != 0 {
+        println!("Bare satp should be zero");
+        return false;
+    }
+
+    println!("Satp encoding tests passed");
+    true
+}
+
+// 测试激活地址空间时发出sfence.vma
+fn test_activate_issues_sfence() -> bool {
+    println!("Testing paging::activate barrier...");
+
+    // 内核当前未开启分页，重新激活当前的satp不会改变地址空间
+    let current = paging::current();
+    let before = csr::barrier_counts();
+    paging::activate(current);
+    let after = csr::barrier_counts();
+
+    if after.sfence_vma != before.sfence_vma + 1 {
+        println!("paging::activate issued {} sfence.vma, expected 1",
+                 after.sfence_vma - before.sfence_vma);
+        return false;
+    }
+
+    if after.fence_i != before.fence_i {
+        println!("paging::activate unexpectedly issued fence.i");
+        return false;
+    }
+
+    println!("paging::activate barrier tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running CSR tests ===");
+
+    println!("Starting satp encoding tests...");
+    let encoding_test = test_satp_encoding();
+    println!("Satp encoding tests completed with result: {}", encoding_test);
+
+    println!("Starting activate barrier tests...");
+    let barrier_test = test_activate_issues_sfence();
+    println!("Activate barrier tests completed with result: {}", barrier_test);
+
+    let all_passed = encoding_test && barrier_test;
+
+    println!("=== CSR test results ===");
+    println!("Satp encoding: {}", if encoding_test { "PASSED" } else { "FAILED" });
+    println!("Activate barrier: {}", if barrier_test { "PASSED" } else { "FAILED" });
+    println!("Overall CSR tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
@@ -0,0 +1,154 @@
+//! 延迟功能测试模块
+//!
+//! 测试 util::delay 模块的功能
+
+use crate::util::delay;
+use crate::util::sbi::timer;
+use crate::println;
+use core::sync::atomic::AtomicU64;
+
+// 测试毫秒到计数值的换算
+fn test_ms_to_ticks() -> bool {
+    println!("Testing ms to ticks conversion...");
+
+    let freq = timer::timebase_frequency();
+    if delay::ms_to_ticks(1000) != freq {
+        println!("1000ms should equal timebase frequency {}, got {}",
+                 freq, delay::ms_to_ticks(1000));
+        return false;
+    }
+
+    if delay::ms_to_ticks(0) != 0 {
+        println!("0ms should convert to 0 ticks");
+        return false;
+    }
+
+    println!("Ms to ticks conversion tests passed");
+    true
+}
+
+// 测试busy_wait_ms的等待时间
+fn test_busy_wait_duration() -> bool {
+    println!("Testing busy_wait_ms duration...");
+
+    const WAIT_MS: u64 = 10;
+    let expected = delay::ms_to_ticks(WAIT_MS);
+
+    let start = timer::now();
+    delay::busy_wait_ms(WAIT_MS);
+    let elapsed = timer::now() - start;
+
+    if elapsed < expected {
+        println!("busy_wait_ms returned too early: {} ticks, expected at least {}",
+                 elapsed, expected);
+        return false;
+    }
+
+    // 允许模拟器调度带来的误差，但不应远超请求的时间
+    if elapsed > expected * 4 {
+        println!("busy_wait_ms waited too long: {} ticks, expected about {}",
+                 elapsed, expected);
+        return false;
+    }
+
+    println!("busy_wait_ms waited {} ticks for {}ms", elapsed, WAIT_MS);
+    true
+}
+
+// 测试now()在原始读数回退时保持单调
+fn test_monotonic_now() -> bool {
+    println!("Testing monotonic time reading...");
+
+    // 使用局部计数器，注入的超前读数不会推进全局时间
+    let last = AtomicU64::new(0);
+    let base = timer::now();
+
+    // 先观察一个略超前的读数，再注入回退的读数
+    let ahead = timer::observe(&last, base + 100);
+    let regressed = timer::observe(&last, base);
+    if regressed < ahead {
+        println!("now() went backward: {} after {}", regressed, ahead);
+        return false;
+    }
+
+    // 读数恢复前进后跟随原始读数
+    let later = timer::observe(&last, base + 200);
+    if later != base + 200 {
+        println!("Time did not advance after regression: {} instead of {}", later, base + 200);
+        return false;
+    }
+
+    if timer::now() < base {
+        println!("Global now() went backward");
+        return false;
+    }
+
+    println!("Monotonic time reading tests passed");
+    true
+}
+
+// 测试纳秒时钟的换算
+fn test_nanosecond_clock() -> bool {
+    println!("Testing nanosecond clock...");
+
+    let original = timer::timebase_frequency();
+    timer::set_timebase_frequency(timer::DEFAULT_TIMEBASE_FREQUENCY);
+    let ok = timer::ticks_to_ns(1) == 100 && timer::ticks_to_ns(10_000_000) == 1_000_000_000;
+    if !ok {
+        println!("10 MHz: 1 tick = {}ns, 1s = {}ns", timer::ticks_to_ns(1), timer::ticks_to_ns(10_000_000));
+    }
+
+    // 很大的计数值不能在乘法中溢出
+    timer::set_timebase_frequency(1 << 40);
+    let large = timer::ticks_to_ns(u64::MAX);
+    let ok = ok && large == ((u64::MAX as u128 * 1_000_000_000) >> 40) as u64;
+    timer::set_timebase_frequency(original);
+    if !ok {
+        println!("Large tick count converted to {}ns", large);
+        return false;
+    }
+
+    const SLEEP_MS: u64 = 5;
+    let start = timer::now_ns();
+    timer::sleep_ms(SLEEP_MS);
+    let elapsed = timer::now_ns() - start;
+    if elapsed < SLEEP_MS * 1_000_000 {
+        println!("sleep_ms returned after only {}ns", elapsed);
+        return false;
+    }
+
+    println!("sleep_ms({}) took {}ns", SLEEP_MS, elapsed);
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running delay tests ===");
+
+    println!("Starting ms to ticks conversion tests...");
+    let conversion_test = test_ms_to_ticks();
+    println!("Ms to ticks conversion tests completed with result: {}", conversion_test);
+
+    println!("Starting busy wait duration tests...");
+    let wait_test = test_busy_wait_duration();
+    println!("Busy wait duration tests completed with result: {}", wait_test);
+
+    println!("Starting monotonic time tests...");
+    let monotonic_test = test_monotonic_now();
+    println!("Monotonic time tests completed with result: {}", monotonic_test);
+
+    println!("Starting nanosecond clock tests...");
+    let ns_test = test_nanosecond_clock();
+    println!("Nanosecond clock tests completed with result: {}", ns_test);
+
+    let all_passed = conversion_test && wait_test && monotonic_test && ns_test;
+
+    println!("=== Delay test results ===");
+    println!("Ms to ticks conversion: {}", if conversion_test { "PASSED" } else { "FAILED" });
+    println!("Busy wait duration: {}", if wait_test { "PASSED" } else { "FAILED" });
+    println!("Monotonic time reading: {}", if monotonic_test { "PASSED" } else { "FAILED" });
+    println!("Nanosecond clock: {}", if ns_test { "PASSED" } else { "FAILED" });
+    println!("Overall delay tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
@@ -0,0 +1,484 @@
+//! 错误处理数据结构测试模块
+//!
+//! 测试 trap::ds::error 中不依赖堆分配的输出路径
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::trap::ds::{
+    ErrorManager, ErrorHandlerEntry, ErrorSource, ErrorLevel, ErrorResult, SystemError, TrapContext,
+    ErrorCode, ErrorLog, ErrorLogEntry, Exception, TrapHandlerResult,
+};
+use crate::trap::ds::error::FilterDisplay;
+use crate::trap::infrastructure::enhanced_handlers;
+use crate::trap::api;
+use crate::util::SliceWriter;
+use crate::println;
+
+fn dummy_error_handler(_error: &SystemError) -> ErrorResult {
+    ErrorResult::Handled
+}
+
+// 测试过滤条件的格式化
+fn test_filter_display() -> bool {
+    println!("Testing handler filter formatting...");
+
+    let mut buffer = [0u8; 64];
+    let mut writer = SliceWriter::new(&mut buffer);
+    let written = write!(writer, "{} {} {}",
+                         FilterDisplay(Some(ErrorSource::Memory)),
+                         FilterDisplay::<ErrorLevel>(None),
+                         FilterDisplay(Some(ErrorLevel::Warning)));
+
+    if written.is_err() || writer.as_str() != "Memory Any Warning" {
+        println!("Unexpected filter formatting: '{}'", writer.as_str());
+        return false;
+    }
+
+    println!("Handler filter formatting tests passed");
+    true
+}
+
+// 测试打印处理器列表，整个路径不需要alloc
+fn test_print_handlers() -> bool {
+    println!("Testing error handler printing...");
+
+    let mut manager = ErrorManager::new();
+    let registered = manager.register_handler(ErrorHandlerEntry::new(
+        dummy_error_handler, 10, "Filtered Handler", Some(ErrorSource::Memory), Some(ErrorLevel::Critical)
+    )) && manager.register_handler(ErrorHandlerEntry::new(
+        dummy_error_handler, 20, "Catch-all Handler", None, None
+    ));
+
+    if !registered {
+        println!("Failed to register error handlers on a fresh manager");
+        return false;
+    }
+
+    manager.print_handlers();
+
+    println!("Error handler printing tests passed");
+    true
+}
+
+// 测试公共API使用的就是导出的ErrorManager
+fn test_error_manager_through_api() -> bool {
+    println!("Testing error manager through public API...");
+
+    const DESC: &str = "Unified Manager Test Handler";
+    let before = api::capacity_report().error_handlers;
+
+    // 容量来自导出的ErrorManager
+    if before.total != ErrorManager::MAX_HANDLERS {
+        println!("API error handler capacity {} does not match ErrorManager", before.total);
+        return false;
+    }
+
+    if let Err(e) = api::register_error_handler(dummy_error_handler, 5, DESC,
+                                                Some(ErrorSource::Device), Some(ErrorLevel::Warning)) {
+        println!("Failed to register error handler: {:?}", e);
+        return false;
+    }
+
+    let during = api::capacity_report().error_handlers;
+    let error = api::create_system_error(ErrorSource::Device, ErrorLevel::Warning, 42, None, 0);
+    let result = api::handle_system_error(error);
+    let unregistered = api::unregister_error_handler(DESC).is_ok();
+
+    if during.used != before.used + 1 {
+        println!("Registered handler not visible in ErrorManager: {} -> {}", before.used, during.used);
+        return false;
+    }
+
+    if result != ErrorResult::Handled {
+        println!("Error not handled by registered handler: {:?}", result);
+        return false;
+    }
+
+    if !unregistered || api::capacity_report().error_handlers.used != before.used {
+        println!("Handler not removed from ErrorManager");
+        return false;
+    }
+
+    println!("Error manager API tests passed");
+    true
+}
+
+// 接收上下文的处理器被调用的次数
+static RECOVERY_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+// 跳过出错指令来恢复
+fn recovery_error_handler(_error: &SystemError, ctx: &mut TrapContext) -> ErrorResult {
+    RECOVERY_CALLS.fetch_add(1, Ordering::SeqCst);
+    ctx.sepc += 4;
+    ErrorResult::Handled
+}
+
+// 测试接收上下文的错误处理器可以修改trap上下文
+fn test_error_handler_with_context() -> bool {
+    println!("Testing error handler with trap context...");
+
+    const DESC: &str = "Recovery Context Handler";
+    if let Err(e) = api::register_error_handler_with_context(recovery_error_handler, 1, DESC,
+                                                             Some(ErrorSource::Memory), Some(ErrorLevel::Warning)) {
+        println!("Failed to register context error handler: {:?}", e);
+        return false;
+    }
+    RECOVERY_CALLS.store(0, Ordering::SeqCst);
+
+    // 没有上下文时处理器不会被调用
+    let error = api::create_system_error(ErrorSource::Memory, ErrorLevel::Warning, 7, Some(0x1000), 0x8020_0000);
+    api::handle_system_error(error);
+    let calls_without_context = RECOVERY_CALLS.load(Ordering::SeqCst);
+
+    let mut ctx = TrapContext::new();
+    ctx.sepc = 0x8020_0000;
+    let result = api::handle_system_error_with_context(error, &mut ctx);
+
+    let _ = api::unregister_error_handler(DESC);
+
+    if calls_without_context != 0 {
+        println!("Context handler ran without a trap context");
+        return false;
+    }
+
+    if result != ErrorResult::Handled || ctx.sepc != 0x8020_0004 {
+        println!("Context not updated by handler: result {:?}, sepc {:#x}", result, ctx.sepc);
+        return false;
+    }
+
+    println!("Error handler with trap context tests passed");
+    true
+}
+
+// 写入total条记录后检查最近n条的顺序，记录的错误编号等于其序号
+fn check_recent_order(total: usize, n: usize) -> bool {
+    let mut log = ErrorLog::new();
+    for seq in 1..=total {
+        let code = ErrorCode::new(ErrorSource::Unknown, ErrorLevel::Info, seq as u16);
+        log.log(SystemError::new(code, None, 0, 0), true, ErrorResult::Handled);
+    }
+
+    let expected = n.min(total).min(ErrorLog::MAX_ENTRIES);
+    let mut visited = 0;
+    let mut in_order = true;
+    log.for_each_recent(n, |seq, entry| {
+        let expected_seq = total - expected + visited + 1;
+        if seq != expected_seq || entry.error.code().code() as usize != expected_seq {
+            println!("Record {} of {} (n={}): got seq {} with code {}, expected {}",
+                     visited, total, n, seq, entry.error.code().code(), expected_seq);
+            in_order = false;
+        }
+        visited += 1;
+    });
+
+    if visited != expected {
+        println!("Visited {} records of {} (n={}), expected {}", visited, total, n, expected);
+        return false;
+    }
+
+    in_order
+}
+
+// 测试错误日志循环缓冲区的索引计算
+fn test_error_log_order() -> bool {
+    println!("Testing error log recent record order...");
+
+    let max = ErrorLog::MAX_ENTRIES;
+    let cases = [
+        (0, 5),            // 空日志
+        (5, 10),           // 记录少于请求数
+        (max, max),        // 恰好填满
+        (max, max + 3),    // 填满且请求数超过容量
+        (max + 7, 10),     // 回绕之后
+        (max + 7, max + 5),
+        (3 * max + 1, max),
+    ];
+
+    let mut passed = true;
+    for (total, n) in cases {
+        if !check_recent_order(total, n) {
+            passed = false;
+        }
+    }
+
+    if passed {
+        println!("Error log recent record order tests passed");
+    }
+    passed
+}
+
+// 页错误恢复处理器看到的错误地址和错误编号
+static RECOVERED_FAULT_ADDR: AtomicUsize = AtomicUsize::new(0);
+static RECOVERED_FAULT_CODE: AtomicUsize = AtomicUsize::new(0);
+
+// 出错位置的指令：amoadd.w a0, a1, (a2)和sw a1, 0(a2)
+static AMO_FAULT_INSN: u32 = 0x00b6_252f;
+static STORE_FAULT_INSN: u32 = 0x00b6_2023;
+
+// 模拟映射缺失的页面，返回Handled让出错的指令重新执行
+fn page_fault_recovery_handler(error: &SystemError, _ctx: &mut TrapContext) -> ErrorResult {
+    RECOVERED_FAULT_ADDR.store(error.address().unwrap_or(0), Ordering::SeqCst);
+    RECOVERED_FAULT_CODE.store(error.code().code() as usize, Ordering::SeqCst);
+    ErrorResult::Handled
+}
+
+// 以`insn`为出错指令触发存储页错误，返回恢复处理器看到的错误编号
+fn recovered_store_fault_code(insn: &'static u32) -> usize {
+    RECOVERED_FAULT_CODE.store(0, Ordering::SeqCst);
+    let mut ctx = TrapContext::new();
+    ctx.scause = Exception::StorePageFault as usize;
+    ctx.sepc = insn as *const u32 as usize;
+    ctx.stval = 0x4000_0000;
+    let _ = enhanced_handlers::enhanced_store_page_fault_handler(&mut ctx);
+    RECOVERED_FAULT_CODE.load(Ordering::SeqCst)
+}
+
+// 测试页错误交给错误处理器恢复后重新执行指令
+fn test_page_fault_recovery() -> bool {
+    println!("Testing page fault recovery through error handlers...");
+
+    const DESC: &str = "Page Fault Recovery Handler";
+    if let Err(e) = api::register_error_handler_with_context(page_fault_recovery_handler, 0, DESC,
+                                                             Some(ErrorSource::Memory), Some(ErrorLevel::Error)) {
+        println!("Failed to register page fault recovery handler: {:?}", e);
+        return false;
+    }
+    RECOVERED_FAULT_ADDR.store(0, Ordering::SeqCst);
+
+    let mut ctx = TrapContext::new();
+    ctx.scause = Exception::LoadPageFault as usize;
+    ctx.sepc = 0x8020_1000;
+    ctx.stval = 0x4000_0000;
+    let result = enhanced_handlers::enhanced_load_page_fault_handler(&mut ctx);
+
+    // 恢复处理器从错误编号得知存储页错误是否由AMO产生
+    let amo_code = recovered_store_fault_code(&AMO_FAULT_INSN);
+    let store_code = recovered_store_fault_code(&STORE_FAULT_INSN);
+
+    let _ = api::unregister_error_handler(DESC);
+
+    if !matches!(result, TrapHandlerResult::Handled) || ctx.sepc != 0x8020_1000 {
+        println!("Recovered page fault should retry the instruction, sepc {:#x}", ctx.sepc);
+        return false;
+    }
+    if RECOVERED_FAULT_ADDR.load(Ordering::SeqCst) != 0x4000_0000 {
+        println!("Recovery handler saw address {:#x}", RECOVERED_FAULT_ADDR.load(Ordering::SeqCst));
+        return false;
+    }
+
+    let store_fault = (enhanced_handlers::PAGE_FAULT_ERROR_CODE + Exception::StorePageFault as u16) as usize;
+    let amo_fault = store_fault | enhanced_handlers::AMO_PAGE_FAULT_FLAG as usize;
+    if amo_code != amo_fault || store_code != store_fault {
+        println!("Recovery handler saw codes {:#x} (AMO) and {:#x} (store), expected {:#x} and {:#x}",
+                 amo_code, store_code, amo_fault, store_fault);
+        return false;
+    }
+
+    println!("Page fault recovery tests passed");
+    true
+}
+
+// 测试重复错误合并到同一条记录
+fn test_error_log_dedup() -> bool {
+    println!("Testing error log deduplication...");
+
+    let mut log = ErrorLog::new();
+    let code = ErrorCode::new(ErrorSource::Memory, ErrorLevel::Error, 13);
+    let fault = SystemError::new(code, Some(0x4000_0000), 0x8020_0000, 0);
+    for _ in 0..5 {
+        log.log(fault, false, ErrorResult::Unhandled);
+    }
+    log.log(fault, true, ErrorResult::Handled);
+
+    let merged = log.get(0);
+    if log.len() != 1 || !merged.is_some_and(|e| e.repeat_count == 6 && e.handled) {
+        println!("Repeated errors should merge into one entry, log has {}", log.len());
+        return false;
+    }
+
+    // 地址不同的错误是新记录，之后再出现的原错误也不再与之合并
+    log.log(SystemError::new(code, Some(0x4000_1000), 0x8020_0000, 0), false, ErrorResult::Unhandled);
+    log.log(fault, false, ErrorResult::Unhandled);
+    let counts = [log.get(1).map(|e| e.repeat_count), log.get(2).map(|e| e.repeat_count)];
+    if log.len() != 3 || counts != [Some(1), Some(1)] {
+        println!("Unexpected log after distinct errors: len {}, counts {:?}", log.len(), counts);
+        return false;
+    }
+
+    log.print_recent(3);
+    println!("Error log deduplication tests passed");
+    true
+}
+
+// 测试把错误日志导出到调用者的缓冲区
+fn test_error_log_snapshot() -> bool {
+    println!("Testing error log snapshot...");
+
+    // 写入超过容量的记录，使缓冲区回绕；错误编号等于序号
+    let total = ErrorLog::MAX_ENTRIES + 8;
+    let mut log = ErrorLog::new();
+    for seq in 1..=total {
+        let code = ErrorCode::new(ErrorSource::Unknown, ErrorLevel::Info, seq as u16);
+        log.log(SystemError::new(code, None, 0, 0), true, ErrorResult::Handled);
+    }
+
+    let empty = ErrorLogEntry {
+        error: SystemError::new(ErrorCode::new(ErrorSource::Unknown, ErrorLevel::Info, 0), None, 0, 0),
+        handled: false,
+        result: ErrorResult::Unhandled,
+        repeat_count: 0,
+    };
+
+    // 缓冲区较小时只导出最新的记录
+    let mut small = [empty; 5];
+    let copied = log.snapshot(&mut small);
+    let in_order = small.iter().enumerate()
+        .all(|(i, entry)| entry.error.code().code() as usize == total - 5 + 1 + i);
+    if copied != 5 || !in_order {
+        println!("Small snapshot copied {} entries, in order: {}", copied, in_order);
+        return false;
+    }
+
+    // 缓冲区较大时导出所有保留的记录
+    let mut large = [empty; ErrorLog::MAX_ENTRIES + 4];
+    let copied = log.snapshot(&mut large);
+    let in_order = large[..copied].iter().enumerate()
+        .all(|(i, entry)| entry.error.code().code() as usize == total - ErrorLog::MAX_ENTRIES + 1 + i);
+    if copied != ErrorLog::MAX_ENTRIES || !in_order {
+        println!("Large snapshot copied {} entries, in order: {}", copied, in_order);
+        return false;
+    }
+
+    // 通过公共API导出全局错误日志
+    let error = api::create_system_error(ErrorSource::Device, ErrorLevel::Info, 0x277, None, 0);
+    api::handle_system_error(error);
+    let mut latest = [empty; 1];
+    if api::export_error_log(&mut latest) != 1 || latest[0].error.code() != error.code() {
+        println!("export_error_log did not return the latest error");
+        return false;
+    }
+
+    println!("Error log snapshot tests passed");
+    true
+}
+
+// 错误处理器最后看到的错误级别
+static LAST_ERROR_LEVEL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+fn level_recording_handler(error: &SystemError) -> ErrorResult {
+    LAST_ERROR_LEVEL.store(error.code().level() as usize, Ordering::SeqCst);
+    ErrorResult::Handled
+}
+
+// 按错误源分发一个错误，返回处理器看到的级别
+fn dispatch_level(manager: &mut ErrorManager, source: ErrorSource, address: usize) -> usize {
+    LAST_ERROR_LEVEL.store(usize::MAX, Ordering::SeqCst);
+    let code = ErrorCode::new(source, ErrorLevel::Error, 0x310);
+    manager.handle_error(SystemError::new(code, Some(address), 0, 0));
+    LAST_ERROR_LEVEL.load(Ordering::SeqCst)
+}
+
+// 测试每个错误源的计数和频繁出错时的升级
+fn test_error_source_escalation() -> bool {
+    println!("Testing per-source error counts and escalation...");
+
+    let mut manager = ErrorManager::new();
+    manager.register_handler(ErrorHandlerEntry::new(level_recording_handler, 0, "Level Recorder", None, None));
+    manager.set_escalation_threshold(Some(3));
+
+    // 前三个设备错误按原级别处理，第四个超过阈值，升级为严重错误
+    let levels = [0x1000, 0x2000, 0x3000, 0x4000].map(|addr| dispatch_level(&mut manager, ErrorSource::Device, addr));
+    let (error, critical) = (ErrorLevel::Error as usize, ErrorLevel::Critical as usize);
+    if levels != [error, error, error, critical] {
+        println!("Unexpected levels for a flooding source: {:?}", levels);
+        return false;
+    }
+
+    // 其他错误源不受影响
+    if dispatch_level(&mut manager, ErrorSource::Memory, 0x1000) != error {
+        println!("Escalation leaked into another error source");
+        return false;
+    }
+
+    // 关闭升级后按原级别处理
+    manager.set_escalation_threshold(None);
+    if dispatch_level(&mut manager, ErrorSource::Device, 0x5000) != error {
+        println!("Errors were escalated with escalation disabled");
+        return false;
+    }
+
+    let counts = [ErrorSource::Device, ErrorSource::Memory, ErrorSource::Network].map(|source| manager.error_count(source));
+    if counts != [5, 1, 0] {
+        println!("Unexpected per-source error counts: {:?}", counts);
+        return false;
+    }
+
+    // 清空日志不影响累计计数
+    manager.get_log_mut().clear();
+    if manager.error_count(ErrorSource::Device) != 5 {
+        println!("Clearing the log reset the error count");
+        return false;
+    }
+
+    manager.print_error_summary();
+    println!("Per-source error count tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running error handling tests ===");
+
+    println!("Starting filter formatting tests...");
+    let filter_test = test_filter_display();
+    println!("Filter formatting tests completed with result: {}", filter_test);
+
+    println!("Starting handler printing tests...");
+    let print_test = test_print_handlers();
+    println!("Handler printing tests completed with result: {}", print_test);
+
+    println!("Starting error manager API tests...");
+    let api_test = test_error_manager_through_api();
+    println!("Error manager API tests completed with result: {}", api_test);
+
+    println!("Starting context error handler tests...");
+    let context_test = test_error_handler_with_context();
+    println!("Context error handler tests completed with result: {}", context_test);
+
+    println!("Starting error log order tests...");
+    let log_test = test_error_log_order();
+    println!("Error log order tests completed with result: {}", log_test);
+
+    println!("Starting page fault recovery tests...");
+    let recovery_test = test_page_fault_recovery();
+    println!("Page fault recovery tests completed with result: {}", recovery_test);
+
+    println!("Starting error log dedup tests...");
+    let dedup_test = test_error_log_dedup();
+    println!("Error log dedup tests completed with result: {}", dedup_test);
+
+    println!("Starting error log snapshot tests...");
+    let snapshot_test = test_error_log_snapshot();
+    println!("Error log snapshot tests completed with result: {}", snapshot_test);
+
+    println!("Starting error source escalation tests...");
+    let escalation_test = test_error_source_escalation();
+    println!("Error source escalation tests completed with result: {}", escalation_test);
+
+    let all_passed = filter_test && print_test && api_test && context_test && log_test &&
+                     recovery_test && dedup_test && snapshot_test && escalation_test;
+
+    println!("=== Error handling test results ===");
+    println!("Filter formatting: {}", if filter_test { "PASSED" } else { "FAILED" });
+    println!("Handler printing: {}", if print_test { "PASSED" } else { "FAILED" });
+    println!("Error manager through API: {}", if api_test { "PASSED" } else { "FAILED" });
+    println!("Error handler with context: {}", if context_test { "PASSED" } else { "FAILED" });
+    println!("Error log order: {}", if log_test { "PASSED" } else { "FAILED" });
+    println!("Page fault recovery: {}", if recovery_test { "PASSED" } else { "FAILED" });
+    println!("Error log deduplication: {}", if dedup_test { "PASSED" } else { "FAILED" });
+    println!("Error log snapshot: {}", if snapshot_test { "PASSED" } else { "FAILED" });
+    println!("Error source escalation: {}", if escalation_test { "PASSED" } else { "FAILED" });
+    println!("Overall error handling tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
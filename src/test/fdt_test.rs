@@ -0,0 +1,281 @@
+//! 设备树解析测试模块
+//!
+//! 测试 util::fdt 模块的属性查找
+
+use crate::util::fdt::{self, Fdt};
+use crate::util::sbi::timer;
+use crate::println;
+
+/// 测试用设备树的最大长度
+const BLOB_SIZE: usize = 512;
+
+/// 按FDT格式拼装测试用的设备树
+struct FdtBuilder {
+    structs: [u8; BLOB_SIZE],
+    structs_len: usize,
+    strings: [u8; 128],
+    strings_len: usize,
+}
+
+impl FdtBuilder {
+    fn new() -> Self {
+        Self {
+            structs: [0; BLOB_SIZE],
+            structs_len: 0,
+            strings: [0; 128],
+            strings_len: 0,
+        }
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.structs[self.structs_len..self.structs_len + 4].copy_from_slice(&value.to_be_bytes());
+        self.structs_len += 4;
+    }
+
+    fn push_padded(&mut self, bytes: &[u8]) {
+        self.structs[self.structs_len..self.structs_len + bytes.len()].copy_from_slice(bytes);
+        self.structs_len = (self.structs_len + bytes.len() + 3) & !3;
+    }
+
+    fn begin_node(&mut self, name: &str) {
+        self.push_u32(1);
+        self.push_padded(name.as_bytes());
+        // 名字以0结尾
+        if name.len() % 4 == 0 {
+            self.structs_len += 4;
+        }
+    }
+
+    fn end_node(&mut self) {
+        self.push_u32(2);
+    }
+
+    fn property(&mut self, name: &str, value: &[u8]) {
+        let name_offset = self.strings_len;
+        self.strings[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+        self.strings_len += name.len() + 1;
+
+        self.push_u32(3);
+        self.push_u32(value.len() as u32);
+        self.push_u32(name_offset as u32);
+        self.push_padded(value);
+    }
+
+    /// 生成完整的设备树，返回数据和长度
+    fn finish(mut self) -> ([u8; BLOB_SIZE], usize) {
+        self.push_u32(9);
+
+        let struct_offset = 40;
+        let strings_offset = struct_offset + self.structs_len;
+        let total = strings_offset + self.strings_len;
+
+        let mut blob = [0u8; BLOB_SIZE];
+        let header = [
+            0xd00d_feed,
+            total as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            0,
+            17,
+            16,
+            0,
+            self.strings_len as u32,
+            self.structs_len as u32,
+        ];
+        for (i, field) in header.iter().enumerate() {
+            blob[i * 4..i * 4 + 4].copy_from_slice(&field.to_be_bytes());
+        }
+        blob[struct_offset..strings_offset].copy_from_slice(&self.structs[..self.structs_len]);
+        blob[strings_offset..total].copy_from_slice(&self.strings[..self.strings_len]);
+        (blob, total)
+    }
+}
+
+// 构造一个与QEMU virt类似的小设备树
+fn sample_fdt() -> ([u8; BLOB_SIZE], usize) {
+    let mut builder = FdtBuilder::new();
+    builder.begin_node("");
+    builder.property("model", b"riscv-virtio\0");
+    builder.begin_node("memory@80000000");
+    builder.property("timebase-frequency", &1u32.to_be_bytes());
+    builder.end_node();
+    builder.begin_node("cpus");
+    builder.property("timebase-frequency", &10_000_000u32.to_be_bytes());
+    builder.begin_node("cpu@0");
+    builder.property("riscv,isa", b"rv64imafdc\0");
+    builder.end_node();
+    builder.end_node();
+    builder.end_node();
+    builder.finish()
+}
+
+// 测试按路径查找属性
+fn test_property_lookup() -> bool {
+    println!("Testing device tree property lookup...");
+
+    let (blob, len) = sample_fdt();
+    let Some(fdt) = Fdt::new(&blob[..len]) else {
+        println!("Failed to parse the sample device tree");
+        return false;
+    };
+
+    if fdt.timebase_frequency() != Some(10_000_000) {
+        println!("Unexpected timebase frequency {:?}", fdt.timebase_frequency());
+        return false;
+    }
+    if fdt.property("/", "model") != Some(&b"riscv-virtio\0"[..]) {
+        println!("Root property lookup failed");
+        return false;
+    }
+    if fdt.property("/cpus/cpu", "riscv,isa") != Some(&b"rv64imafdc\0"[..]) {
+        println!("Nested property lookup ignoring the unit address failed");
+        return false;
+    }
+    if fdt.property("/cpus/cpu@1", "riscv,isa").is_some() || fdt.property("/cpus", "model").is_some() {
+        println!("Lookup of a missing node or property should fail");
+        return false;
+    }
+
+    println!("Device tree property lookup tests passed");
+    true
+}
+
+// 测试头部校验和cell解析
+fn test_header_and_cells() -> bool {
+    println!("Testing device tree header validation...");
+
+    let (mut blob, len) = sample_fdt();
+    blob[0] = 0;
+    if Fdt::new(&blob[..len]).is_some() || Fdt::new(&blob[..8]).is_some() {
+        println!("Invalid device trees should be rejected");
+        return false;
+    }
+    if unsafe { Fdt::from_addr(0) }.is_some() {
+        println!("A null device tree address should be rejected");
+        return false;
+    }
+
+    let wide = [0, 0, 0, 1, 0, 0, 0, 2];
+    if fdt::cells_to_u64(&wide) != Some((1 << 32) | 2) || fdt::cells_to_u64(&[1, 2, 3]).is_some() {
+        println!("Cell decoding failed");
+        return false;
+    }
+
+    println!("Device tree header tests passed");
+    true
+}
+
+// 测试从设备树检测Sstc扩展
+fn test_sstc_detection() -> bool {
+    println!("Testing Sstc detection from the device tree...");
+
+    let (blob, len) = sample_fdt();
+    let without = Fdt::new(&blob[..len]).is_some_and(|fdt| timer::fdt_reports_sstc(&fdt));
+
+    // 只有riscv,isa字符串的设备树
+    let mut builder = FdtBuilder::new();
+    builder.begin_node("");
+    builder.begin_node("cpus");
+    builder.begin_node("cpu@0");
+    builder.property("riscv,isa", b"rv64imafdc_zicsr_Sstc\0");
+    builder.end_node();
+    builder.end_node();
+    builder.end_node();
+    let (blob, len) = builder.finish();
+    let with_isa = Fdt::new(&blob[..len]).is_some_and(|fdt| timer::fdt_reports_sstc(&fdt));
+    if without || !with_isa {
+        println!("Sstc should be read from riscv,isa (without: {}, with: {})", without, with_isa);
+        return false;
+    }
+
+    // riscv,isa-extensions优先于riscv,isa字符串
+    let mut builder = FdtBuilder::new();
+    builder.begin_node("");
+    builder.begin_node("cpus");
+    builder.begin_node("cpu@0");
+    builder.property("riscv,isa", b"rv64imafdc\0");
+    builder.property("riscv,isa-extensions", b"i\0m\0a\0sstc\0");
+    builder.end_node();
+    builder.end_node();
+    builder.end_node();
+    let (blob, len) = builder.finish();
+    if !Fdt::new(&blob[..len]).is_some_and(|fdt| timer::fdt_reports_sstc(&fdt)) {
+        println!("Sstc should be read from riscv,isa-extensions");
+        return false;
+    }
+
+    println!("Sstc detection tests passed");
+    true
+}
+
+// 测试按父节点的#address-cells读取reg中的地址
+fn test_reg_address() -> bool {
+    println!("Testing reg address lookup...");
+
+    let mut builder = FdtBuilder::new();
+    builder.begin_node("");
+    builder.begin_node("soc");
+    builder.property("#address-cells", &2u32.to_be_bytes());
+    builder.begin_node("plic@c000000");
+    builder.property("reg", &[0, 0, 0, 0, 0x0c, 0, 0, 0, 0, 0, 0, 0, 0, 0x60, 0, 0]);
+    builder.end_node();
+    builder.end_node();
+    builder.begin_node("bus");
+    builder.property("#address-cells", &1u32.to_be_bytes());
+    builder.begin_node("uart@10000000");
+    builder.property("reg", &[0x10, 0, 0, 0, 0, 0, 0x01, 0]);
+    builder.end_node();
+    builder.end_node();
+    builder.end_node();
+    let (blob, len) = builder.finish();
+    let Some(fdt) = Fdt::new(&blob[..len]) else {
+        println!("Failed to parse the reg device tree");
+        return false;
+    };
+
+    let plic = fdt.reg_address("/soc/plic");
+    let uart = fdt.reg_address("/bus/uart");
+    if plic != Some(0x0c00_0000) || uart != Some(0x1000_0000) {
+        println!("Unexpected reg addresses: plic {:?}, uart {:?}", plic, uart);
+        return false;
+    }
+    if fdt.reg_address("/soc/clint").is_some() {
+        println!("Lookup of a missing node should fail");
+        return false;
+    }
+
+    println!("Reg address lookup tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running device tree tests ===");
+
+    println!("Starting property lookup tests...");
+    let lookup_test = test_property_lookup();
+    println!("Property lookup tests completed with result: {}", lookup_test);
+
+    println!("Starting header validation tests...");
+    let header_test = test_header_and_cells();
+    println!("Header validation tests completed with result: {}", header_test);
+
+    println!("Starting Sstc detection tests...");
+    let sstc_test = test_sstc_detection();
+    println!("Sstc detection tests completed with result: {}", sstc_test);
+
+    println!("Starting reg address tests...");
+    let reg_test = test_reg_address();
+    println!("Reg address tests completed with result: {}", reg_test);
+
+    let all_passed = lookup_test && header_test && sstc_test && reg_test;
+
+    println!("=== Device tree test results ===");
+    println!("Property lookup: {}", if lookup_test { "PASSED" } else { "FAILED" });
+    println!("Header validation: {}", if header_test { "PASSED" } else { "FAILED" });
+    println!("Sstc detection: {}", if sstc_test { "PASSED" } else { "FAILED" });
+    println!("Reg address lookup: {}", if reg_test { "PASSED" } else { "FAILED" });
+    println!("Overall device tree tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
@@ -0,0 +1,152 @@
+//! 非阻塞日志测试模块
+//!
+//! 测试 klog 模块的功能
+
+use crate::console::{self, CONSOLE_LOCK};
+use crate::klog;
+use crate::println;
+
+// 测试控制台锁空闲时消息正常输出
+fn test_try_log_writes() -> bool {
+    println!("Testing try_log with a free console...");
+
+    let dropped_before = klog::dropped_messages();
+    if !crate::try_println!("try_log output check") {
+        println!("try_log dropped a message while the console was free");
+        return false;
+    }
+
+    if klog::dropped_messages() != dropped_before {
+        println!("Drop counter changed while the console was free");
+        return false;
+    }
+
+    println!("try_log free console tests passed");
+    true
+}
+
+// 测试控制台锁被占用时消息被丢弃而不是阻塞
+fn test_try_log_drops_when_locked() -> bool {
+    println!("Testing try_log with a held console lock...");
+
+    let dropped_before = klog::dropped_messages();
+
+    // 持锁期间不能调用println!，否则测试本身会死锁
+    let guard = CONSOLE_LOCK.lock();
+    let written = crate::try_println!("this message should be dropped");
+    drop(guard);
+
+    if written {
+        println!("try_log reported success while the console lock was held");
+        return false;
+    }
+
+    if klog::dropped_messages() != dropped_before + 1 {
+        println!("Drop counter should increase by 1: before {}, after {}",
+                 dropped_before, klog::dropped_messages());
+        return false;
+    }
+
+    println!("try_log held lock tests passed");
+    true
+}
+
+// 测试十六进制转储的行格式
+fn test_hexdump_line() -> bool {
+    println!("Testing hexdump line formatting...");
+
+    let mut line = [0u8; console::HEXDUMP_LINE_LEN];
+    let full = console::hexdump_line(0x8020_0000, b"Hello, world!\n\x00\x7f", &mut line);
+    let expected = "0000000080200000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 7f  |Hello, world!...|\n";
+    if full != expected {
+        println!("Full line mismatch: {:?}", full);
+        return false;
+    }
+    if full.len() != console::HEXDUMP_LINE_LEN {
+        println!("Full line should fill the buffer, got {} bytes", full.len());
+        return false;
+    }
+
+    // 不足16字节时补齐十六进制部分
+    let mut line = [0u8; console::HEXDUMP_LINE_LEN];
+    let partial = console::hexdump_line(0x10, b"AB", &mut line);
+    let expected = "0000000000000010  41 42                                             |AB|\n";
+    if partial != expected {
+        println!("Partial line mismatch: {:?}", partial);
+        return false;
+    }
+
+    let data = *b"RustOS hexdump test!";
+    unsafe { console::hexdump(data.as_ptr(), data.len()) };
+
+    println!("Hexdump line tests passed");
+    true
+}
+
+/// 捕获测试输出的缓冲区
+static CAPTURE: console::BufferSink<256> = console::BufferSink::new();
+
+// 测试把println!的输出重定向到缓冲区和空目标
+fn test_console_sink_redirect() -> bool {
+    println!("Testing console sink redirection...");
+
+    CAPTURE.clear();
+    let previous = console::set_console_sink(&CAPTURE);
+    println!("captured line {}", 311);
+    crate::try_println!("captured try_println");
+    console::set_console_sink(previous);
+
+    // 恢复之后的输出不再进入缓冲区
+    println!("this line goes to the original sink");
+
+    let captured = CAPTURE.with_contents(|text| text == "captured line 311\ncaptured try_println\n");
+    if !captured {
+        println!("Redirected output was not captured");
+        return false;
+    }
+
+    // 空目标丢弃输出，但消息仍算作已输出
+    static NULL: console::NullSink = console::NullSink;
+    let previous = console::set_console_sink(&NULL);
+    let written = crate::try_println!("this message is silenced");
+    console::set_console_sink(previous);
+    if !written {
+        println!("Output to the null sink was reported as dropped");
+        return false;
+    }
+
+    println!("Console sink redirection tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running klog tests ===");
+
+    println!("Starting try_log output tests...");
+    let write_test = test_try_log_writes();
+    println!("try_log output tests completed with result: {}", write_test);
+
+    println!("Starting try_log drop tests...");
+    let drop_test = test_try_log_drops_when_locked();
+    println!("try_log drop tests completed with result: {}", drop_test);
+
+    println!("Starting hexdump line tests...");
+    let hexdump_test = test_hexdump_line();
+    println!("Hexdump line tests completed with result: {}", hexdump_test);
+
+    println!("Starting console sink tests...");
+    let sink_test = test_console_sink_redirect();
+    println!("Console sink tests completed with result: {}", sink_test);
+
+    let all_passed = write_test && drop_test && hexdump_test && sink_test;
+
+    println!("=== klog test results ===");
+    println!("try_log output: {}", if write_test { "PASSED" } else { "FAILED" });
+    println!("try_log drop on contention: {}", if drop_test { "PASSED" } else { "FAILED" });
+    println!("Hexdump line formatting: {}", if hexdump_test { "PASSED" } else { "FAILED" });
+    println!("Console sink: {}", if sink_test { "PASSED" } else { "FAILED" });
+    println!("Overall klog tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
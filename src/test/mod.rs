@@ -6,6 +6,21 @@ use crate::println;
 
 // 导出子模块
 pub mod trap_api_test;
+pub mod trap_infra_test;
+pub mod delay_test;
+pub mod trap_types_test;
+pub mod csr_test;
+pub mod sbi_test;
+pub mod klog_test;
+pub mod percpu_test;
+pub mod mm_test;
+pub mod error_test;
+pub mod cpu_test;
+pub mod fdt_test;
+pub mod watchdog_test;
+pub mod sched_test;
+pub mod spsc_test;
+pub mod test_console;
 
 // 测试系统初始化函数
 pub fn init_test_system() {
@@ -20,12 +35,44 @@ pub fn run_all_tests() -> bool {
     
     // 运行各测试模块的测试
     let trap_api_success = trap_api_test::run_tests();
+    let trap_infra_success = trap_infra_test::run_tests();
+    let delay_success = delay_test::run_tests();
+    let trap_types_success = trap_types_test::run_tests();
+    let csr_success = csr_test::run_tests();
+    let sbi_success = sbi_test::run_tests();
+    let klog_success = klog_test::run_tests();
+    let percpu_success = percpu_test::run_tests();
+    let mm_success = mm_test::run_tests();
+    let error_success = error_test::run_tests();
+    let cpu_success = cpu_test::run_tests();
+    let fdt_success = fdt_test::run_tests();
+    let watchdog_success = watchdog_test::run_tests();
+    let sched_success = sched_test::run_tests();
+    let spsc_success = spsc_test::run_tests();
     
     // 汇总结果
-    let all_success = trap_api_success;
+    let all_success = trap_api_success && trap_infra_success && delay_success &&
+                      trap_types_success && csr_success && sbi_success &&
+                      klog_success && percpu_success && mm_success &&
+                      error_success && cpu_success && fdt_success &&
+                      watchdog_success && sched_success && spsc_success;
     
     println!("=== Test summary ===");
     println!("Trap API tests: {}", if trap_api_success { "PASSED" } else { "FAILED" });
+    println!("Trap infrastructure tests: {}", if trap_infra_success { "PASSED" } else { "FAILED" });
+    println!("Delay tests: {}", if delay_success { "PASSED" } else { "FAILED" });
+    println!("Trap type tests: {}", if trap_types_success { "PASSED" } else { "FAILED" });
+    println!("CSR tests: {}", if csr_success { "PASSED" } else { "FAILED" });
+    println!("SBI tests: {}", if sbi_success { "PASSED" } else { "FAILED" });
+    println!("klog tests: {}", if klog_success { "PASSED" } else { "FAILED" });
+    println!("Per-CPU data tests: {}", if percpu_success { "PASSED" } else { "FAILED" });
+    println!("Memory management tests: {}", if mm_success { "PASSED" } else { "FAILED" });
+    println!("Error handling tests: {}", if error_success { "PASSED" } else { "FAILED" });
+    println!("CPU feature tests: {}", if cpu_success { "PASSED" } else { "FAILED" });
+    println!("Device tree tests: {}", if fdt_success { "PASSED" } else { "FAILED" });
+    println!("Watchdog tests: {}", if watchdog_success { "PASSED" } else { "FAILED" });
+    println!("Scheduler tests: {}", if sched_success { "PASSED" } else { "FAILED" });
+    println!("SPSC queue tests: {}", if spsc_success { "PASSED" } else { "FAILED" });
     println!("Overall result: {}", if all_success { "PASSED" } else { "FAILED" });
     
     all_success
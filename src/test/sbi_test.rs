@@ -0,0 +1,667 @@
+//! SBI封装测试模块
+//!
+//! 测试 util::sbi 模块中不依赖具体SBI实现的部分
+
+use crate::util::sbi::system::{
+    self, SbiCapabilities, SbiExtension, SystemInfo,
+    ResetType, ResetReason, ShutdownReason, RebootType,
+};
+use crate::util::sbi::hart::{self, HartState};
+use crate::util::sbi::{self as sbi, timer, tlb, pmu, SbiError};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::fmt::Write;
+use crate::util::SliceWriter;
+use crate::trap::infrastructure::{disable_interrupts, restore_interrupts};
+use crate::println;
+
+// 测试从探测结果构造能力集合
+fn test_capabilities_from_probe() -> bool {
+    println!("Testing SBI capability construction...");
+
+    let none = SbiCapabilities::from_probe(|_| false);
+    if none != SbiCapabilities::empty() {
+        println!("No available extensions should produce an empty set");
+        return false;
+    }
+
+    let all = SbiCapabilities::from_probe(|_| true);
+    for ext in SbiExtension::ALL {
+        if !all.has(ext) {
+            println!("Extension {:?} missing from full set", ext);
+            return false;
+        }
+    }
+
+    // 典型的旧版OpenSBI：有TIME/IPI/RFENCE/SRST，没有HSM/DBCN/PMU
+    let partial = SbiCapabilities::from_probe(|ext| matches!(ext,
+        SbiExtension::Time | SbiExtension::Ipi | SbiExtension::Rfence | SbiExtension::Srst));
+    if !(partial.has_time() && partial.has_ipi() && partial.has_rfence() && partial.has_srst()) {
+        println!("Probed extensions missing from capability set: {:?}", partial);
+        return false;
+    }
+    if partial.has_hsm() || partial.has_dbcn() || partial.has_pmu() {
+        println!("Unprobed extensions present in capability set: {:?}", partial);
+        return false;
+    }
+
+    println!("SBI capability construction tests passed");
+    true
+}
+
+// 测试将系统信息格式化到缓冲区
+fn test_system_info_format() -> bool {
+    println!("Testing system info formatting...");
+
+    let info = SystemInfo {
+        sbi_spec_version_major: 2,
+        sbi_spec_version_minor: 0,
+        sbi_impl_id: 1,
+        sbi_impl_version: 0x10003,
+        mvendorid: 0,
+        marchid: 0x2a,
+        mimpid: 0xff,
+        extensions: SbiCapabilities::from_probe(|ext| matches!(ext, SbiExtension::Time | SbiExtension::Srst)),
+    };
+
+    let mut buffer = [0u8; 512];
+    let len = info.format_into(&mut buffer);
+    let text = match core::str::from_utf8(&buffer[..len]) {
+        Ok(text) => text,
+        Err(_) => {
+            println!("Formatted system info is not valid UTF-8");
+            return false;
+        }
+    };
+
+    let expected_lines = [
+        "==== System Information ====",
+        "SBI Spec Version: 2.0",
+        "SBI Implementation ID: 1",
+        "SBI Implementation Version: 65539",
+        "Machine Vendor ID: 0x0",
+        "Machine Architecture ID: 0x2a",
+        "Machine Implementation ID: 0xff",
+        "SBI Extensions: TIME SRST",
+    ];
+    for line in expected_lines {
+        if !text.contains(line) {
+            println!("Formatted system info missing line '{}'", line);
+            return false;
+        }
+    }
+
+    // 缓冲区不足时截断而不是越界
+    let mut small = [0u8; 16];
+    let truncated = info.format_into(&mut small);
+    if truncated != small.len() || small[..] != text.as_bytes()[..small.len()] {
+        println!("Truncated output incorrect: {} bytes", truncated);
+        return false;
+    }
+
+    println!("System info formatting tests passed");
+    true
+}
+
+// 测试HSM状态与SBI错误码的解码
+fn test_hsm_decoding() -> bool {
+    println!("Testing HSM state and SBI error decoding...");
+
+    let states = [
+        (0, HartState::Started),
+        (1, HartState::Stopped),
+        (2, HartState::StartPending),
+        (3, HartState::StopPending),
+        (4, HartState::Suspended),
+        (42, HartState::Unknown),
+    ];
+    for (code, expected) in states {
+        if HartState::from_code(code) != expected {
+            println!("HSM state {} decoded as {:?}, expected {:?}", code, HartState::from_code(code), expected);
+            return false;
+        }
+    }
+
+    let errors = [
+        (-1isize, SbiError::Failed),
+        (-3, SbiError::InvalidParam),
+        (-6, SbiError::AlreadyAvailable),
+        (-7, SbiError::AlreadyStarted),
+        (-100, SbiError::Unknown(-100)),
+    ];
+    for (code, expected) in errors {
+        if SbiError::from_code(code as usize) != Some(expected) {
+            println!("SBI error {} decoded as {:?}, expected {:?}", code, SbiError::from_code(code as usize), expected);
+            return false;
+        }
+    }
+    if SbiError::from_code(0).is_some() {
+        println!("Success code should not decode as an error");
+        return false;
+    }
+
+    // 当前核心必然已启动，再次启动应返回明确的错误
+    if system::capabilities().has_hsm() {
+        let current = hart::current_hart_id();
+        if hart::hart_get_status(current) != HartState::Started {
+            println!("Current hart {} not reported as started", current);
+            return false;
+        }
+        let result = hart::hart_start(current, 0, 0);
+        if result != Err(SbiError::AlreadyAvailable) {
+            println!("Starting the running hart returned {:?}", result);
+            return false;
+        }
+    } else {
+        println!("HSM extension unavailable, skipping live hart status checks");
+    }
+
+    println!("HSM state and SBI error decoding tests passed");
+    true
+}
+
+// 测试关机原因和重启类型到SRST参数的映射
+fn test_reset_mapping() -> bool {
+    println!("Testing SRST reset parameter mapping...");
+
+    let reasons = [
+        (ShutdownReason::Normal, ResetReason::NoReason),
+        (ShutdownReason::UserRequest, ResetReason::NoReason),
+        (ShutdownReason::SystemFailure, ResetReason::SystemFailure),
+    ];
+    for (reason, expected) in reasons {
+        if reason.reset_reason() != expected {
+            println!("{:?} mapped to {:?}, expected {:?}", reason, reason.reset_reason(), expected);
+            return false;
+        }
+    }
+
+    if RebootType::Cold.reset_type() != ResetType::ColdReboot ||
+        RebootType::Warm.reset_type() != ResetType::WarmReboot {
+        println!("Reboot types mapped incorrectly");
+        return false;
+    }
+
+    // 编码必须与SBI规范一致
+    if ResetType::Shutdown as u32 != 0 || ResetType::ColdReboot as u32 != 1 ||
+        ResetType::WarmReboot as u32 != 2 || ResetReason::SystemFailure as u32 != 1 {
+        println!("SRST parameter encoding does not match the SBI specification");
+        return false;
+    }
+
+    println!("SRST reset parameter mapping tests passed");
+    true
+}
+
+static TICK_CALLBACK_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn tick_test_callback() {
+    TICK_CALLBACK_CALLS.fetch_add(1, Ordering::SeqCst);
+}
+
+// 测试时钟回调与周期时钟
+fn test_tick_callbacks() -> bool {
+    println!("Testing timer tick callbacks...");
+
+    let Some(id) = timer::register_tick_callback(tick_test_callback) else {
+        println!("Failed to register tick callback");
+        return false;
+    };
+    TICK_CALLBACK_CALLS.store(0, Ordering::SeqCst);
+
+    // 关闭中断，到期的周期时钟只由下面手动调用的handle_tick处理
+    let was_enabled = disable_interrupts();
+    let interval = timer::timebase_frequency() / 1000;
+    timer::set_periodic(interval);
+    let ticks_before = timer::tick_count();
+
+    // 周期时钟未到期时不调用时钟回调
+    let early = timer::handle_tick();
+    timer::sleep_cycles(interval);
+    let called = timer::handle_tick();
+    let periodic = timer::periodic_interval();
+
+    let unregistered = timer::unregister_tick_callback(id);
+    timer::set_periodic(0);
+    restore_interrupts(was_enabled);
+
+    if early != 0 {
+        println!("Tick callbacks ran {} times before the periodic tick was due", early);
+        return false;
+    }
+
+    if called == 0 || TICK_CALLBACK_CALLS.load(Ordering::SeqCst) != 1 {
+        println!("Tick callback not invoked exactly once: {} calls",
+                 TICK_CALLBACK_CALLS.load(Ordering::SeqCst));
+        return false;
+    }
+
+    if periodic != interval || timer::tick_count() != ticks_before + 2 {
+        println!("Unexpected periodic state: interval {}, ticks {}", periodic, timer::tick_count() - ticks_before);
+        return false;
+    }
+
+    if !unregistered || timer::periodic_interval() != 0 {
+        println!("Tick callback or periodic timer not cleared");
+        return false;
+    }
+
+    println!("Timer tick callback tests passed");
+    true
+}
+
+// 测试核心掩码只覆盖实际存在的核心
+fn test_hart_mask() -> bool {
+    println!("Testing hart mask construction...");
+
+    let (mask, base) = hart::harts_mask(3).into_inner();
+    if mask != 0b111 || base != 0 {
+        println!("harts_mask(3) produced mask {:#x} base {}", mask, base);
+        return false;
+    }
+    if hart::harts_mask(usize::BITS as usize).into_inner().0 != usize::MAX {
+        println!("Full-width hart mask should set every bit");
+        return false;
+    }
+
+    let count = hart::hart_count();
+    let current = hart::current_hart_id();
+    if count == 0 || count > hart::MAX_HARTS || current >= count {
+        println!("Invalid hart count {} for current hart {}", count, current);
+        return false;
+    }
+
+    let all = hart::all_harts();
+    if !all.has_bit(current) || all.has_bit(count) {
+        println!("all_harts() mask {:#x} does not match hart count {}", all.into_inner().0, count);
+        return false;
+    }
+
+    let others = hart::other_harts();
+    if others.has_bit(current) || others.into_inner().0.count_ones() as usize != count - 1 {
+        println!("other_harts() mask {:#x} should exclude hart {}", others.into_inner().0, current);
+        return false;
+    }
+
+    println!("Detected {} harts", count);
+    println!("Hart mask tests passed");
+    true
+}
+
+// 测试控制台输入缓冲区
+#[cfg(feature = "test_hooks")]
+fn test_console_input_buffer() -> bool {
+    use crate::util::sbi::console;
+
+    println!("Testing console input buffer...");
+
+    console::clear_input();
+    if console::input_available() != 0 {
+        println!("Input buffer should be empty after clearing");
+        return false;
+    }
+
+    if console::simulate_input(b"hi") != 2 || console::input_available() != 2 {
+        println!("Expected 2 buffered characters, found {}", console::input_available());
+        return false;
+    }
+    if console::try_getchar() != Some('h') || console::try_getchar() != Some('i') {
+        println!("Buffered characters should be read in arrival order");
+        return false;
+    }
+    if console::input_available() != 0 {
+        println!("Input buffer should be empty after reading everything");
+        return false;
+    }
+
+    // 缓冲区满后多出的字符被丢弃
+    let dropped = console::dropped_input();
+    let overflow = [b'x'; console::INPUT_BUFFER_SIZE + 3];
+    let pushed = console::simulate_input(&overflow);
+    if pushed != console::INPUT_BUFFER_SIZE || console::dropped_input() != dropped + 3 {
+        println!("Overflow pushed {} and dropped {}", pushed, console::dropped_input() - dropped);
+        console::clear_input();
+        return false;
+    }
+    console::clear_input();
+
+    // getline从缓冲区读取，并处理退格
+    console::simulate_input(b"ab\x7fc\r");
+    let mut line = [0u8; 8];
+    let len = console::getline(&mut line, false);
+    if &line[..len] != b"ac" {
+        println!("getline returned {:?}", &line[..len]);
+        console::clear_input();
+        return false;
+    }
+
+    println!("Console input buffer tests passed");
+    true
+}
+
+// 模拟控制台输入需要test_hooks特性
+#[cfg(not(feature = "test_hooks"))]
+fn test_console_input_buffer() -> bool {
+    println!("Console input buffer test needs the test_hooks feature, skipping");
+    true
+}
+
+// 测试按ASID刷新TLB
+fn test_tlb_flush_asid() -> bool {
+    println!("Testing TLB flush by ASID...");
+
+    let bits = tlb::asid_bits();
+    println!("Implemented ASID bits: {}", bits);
+    if bits > 16 {
+        println!("ASIDLEN should not exceed 16 bits");
+        return false;
+    }
+
+    // ASID 0总是有效的
+    if !tlb::flush_local_asid(0) || !tlb::flush_asid_all_harts(0) {
+        println!("Flushing ASID 0 should succeed");
+        return false;
+    }
+
+    let max_asid = (1 << bits) - 1;
+    if !tlb::flush_local_asid(max_asid) {
+        println!("Flushing the largest ASID {:#x} should succeed", max_asid);
+        return false;
+    }
+    if tlb::flush_local_asid(max_asid + 1) || tlb::flush_asid_all_harts(max_asid + 1) {
+        println!("ASID {:#x} exceeds ASIDLEN and should be rejected", max_asid + 1);
+        return false;
+    }
+
+    println!("TLB flush by ASID tests passed");
+    true
+}
+
+// 测试按扩展ID探测SBI扩展
+fn test_probe_extension_id() -> bool {
+    println!("Testing SBI extension probing by ID...");
+
+    // 基础扩展（EID 0x10）总是存在，0x0A00_0000没有被分配给任何扩展
+    const BASE_EID: usize = 0x10;
+    const UNASSIGNED_EID: usize = 0x0A00_0000;
+    if !sbi::probe_extension(BASE_EID) || sbi::probe_extension(UNASSIGNED_EID) {
+        println!("Base extension should be available and an unassigned EID should not");
+        return false;
+    }
+
+    // 按ID探测的结果与缓存的能力集合一致（TIME扩展的EID为"TIME"的ASCII编码）
+    const TIME_EID: usize = 0x5449_4D45;
+    let supported = system::supported_extensions();
+    if supported.has_time() != sbi::probe_extension(TIME_EID) {
+        println!("TIME availability differs between EID probe and capabilities: {}", supported);
+        return false;
+    }
+    println!("Supported SBI extensions: {}", supported);
+
+    let mut buffer = [0u8; 16];
+    let mut writer = SliceWriter::new(&mut buffer);
+    if write!(writer, "{}", SbiCapabilities::empty()).is_err() || writer.as_str() != "none" {
+        println!("Empty capability set should display as 'none', got '{}'", writer.as_str());
+        return false;
+    }
+
+    println!("SBI extension probing tests passed");
+    true
+}
+
+// 测试通过DBCN批量写入调试控制台
+fn test_debug_console_write() -> bool {
+    println!("Testing DBCN debug console write...");
+
+    if sbi::debug_console_write(&[]) != 0 {
+        println!("Writing an empty buffer should write nothing");
+        return false;
+    }
+
+    const MESSAGE: &[u8] = b"DBCN batched write\n";
+    let written = sbi::debug_console_write(MESSAGE);
+    let expected = if system::capabilities().has_dbcn() { MESSAGE.len() } else { 0 };
+    if written != expected {
+        println!("Expected {} bytes written through DBCN, got {}", expected, written);
+        return false;
+    }
+
+    println!("DBCN debug console write tests passed");
+    true
+}
+
+// 测试远程屏障调用返回的SBI错误
+fn test_remote_fence_result() -> bool {
+    println!("Testing remote fence results...");
+
+    let current = hart::current_hart_id();
+    let fence_i = hart::fence_i_on_hart(current);
+    let sfence = hart::sfence_vma_on_hart(current, 0, usize::MAX);
+
+    // 没有RFENCE扩展时调用应报告不支持，而不是被悄悄忽略
+    let expected = if system::capabilities().has_rfence() {
+        Ok(())
+    } else {
+        Err(SbiError::NotSupported)
+    };
+    if fence_i != expected || sfence != expected {
+        println!("Remote fences on hart {} returned {:?} and {:?}, expected {:?}",
+                 current, fence_i, sfence, expected);
+        return false;
+    }
+
+    println!("Remote fence result tests passed");
+    true
+}
+
+/// 测试中使用的上下文，不对应任何进程
+const TIMER_TEST_CONTEXT: usize = 0xc0ff_ee00;
+
+static CONTEXT_TIMER_FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn context_timer_test_callback(ctx: usize) {
+    if ctx == TIMER_TEST_CONTEXT {
+        CONTEXT_TIMER_FIRED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// 测试按上下文的定时器
+fn test_context_timers() -> bool {
+    println!("Testing per-context timers...");
+
+    let ctx = TIMER_TEST_CONTEXT;
+    let second = timer::timebase_frequency();
+    CONTEXT_TIMER_FIRED.store(0, Ordering::SeqCst);
+    if !timer::set_context_timer_callback(ctx, context_timer_test_callback) {
+        println!("Failed to register context timer callback");
+        return false;
+    }
+
+    // 保留最早的到期时间
+    timer::set_context_timer(ctx, 10 * second);
+    let late = timer::context_timer_deadline(ctx);
+    timer::set_context_timer(ctx, 20 * second);
+    let unchanged = timer::context_timer_deadline(ctx);
+    timer::set_context_timer(ctx, 5 * second);
+    let earlier = timer::context_timer_deadline(ctx);
+    if late.is_none() || unchanged != late || earlier >= late {
+        println!("Unexpected deadlines: {:?}, {:?}, {:?}", late, unchanged, earlier);
+        timer::cancel_context_timer(ctx);
+        return false;
+    }
+
+    // 未到期时时钟中断不调用回调，到期后调用一次并清除定时器
+    timer::handle_tick();
+    let fired_early = CONTEXT_TIMER_FIRED.load(Ordering::SeqCst);
+    timer::set_context_timer(ctx, 0);
+    timer::handle_tick();
+    let fired = CONTEXT_TIMER_FIRED.load(Ordering::SeqCst);
+    if fired_early != 0 || fired != 1 || timer::context_timer_deadline(ctx).is_some() {
+        println!("Context timer fired {} times early and {} times in total", fired_early, fired);
+        timer::cancel_context_timer(ctx);
+        return false;
+    }
+
+    timer::set_context_timer(ctx, second);
+    if !timer::cancel_context_timer(ctx) || timer::context_timer_deadline(ctx).is_some() {
+        println!("Context timer was not cancelled");
+        return false;
+    }
+
+    // 进程销毁时取消它的定时器
+    let process = match crate::trap::infrastructure::di::context_pool::create_process(None) {
+        Ok(process) => process,
+        Err(e) => {
+            println!("Failed to create process: {}", e);
+            return false;
+        }
+    };
+    let pid = process.pid;
+    timer::set_context_timer(pid, second);
+    let armed = timer::context_timer_deadline(pid).is_some();
+    drop(process);
+    if !armed || timer::context_timer_deadline(pid).is_some() {
+        println!("Process timer not cancelled on destruction: armed {}", armed);
+        return false;
+    }
+
+    println!("Per-context timer tests passed");
+    true
+}
+
+// 测试性能计数器
+fn test_pmu_counters() -> bool {
+    println!("Testing performance counters...");
+
+    // cycle和instret直接读取，执行一段指令后都应增加
+    let cycles = pmu::read_cycles();
+    let instret = pmu::read_instret();
+    let mut sum = 0usize;
+    for i in 0..1000 {
+        sum = core::hint::black_box(sum.wrapping_add(i));
+    }
+    let cycles_elapsed = pmu::read_cycles().wrapping_sub(cycles);
+    let instret_elapsed = pmu::read_instret().wrapping_sub(instret);
+    if cycles_elapsed == 0 || instret_elapsed < 1000 {
+        println!("Counters did not advance: {} cycles, {} instructions", cycles_elapsed, instret_elapsed);
+        return false;
+    }
+
+    if !pmu::is_available() {
+        println!("PMU extension not available, skipping SBI counter tests");
+        return true;
+    }
+
+    let counters = pmu::num_counters();
+    let counter = match pmu::counter_config_matching(pmu::Event::Instructions) {
+        Ok(counter) => counter,
+        Err(SbiError::NotSupported) => {
+            println!("No counter can count instructions ({} counters), skipping", counters);
+            return true;
+        }
+        Err(e) => {
+            println!("Failed to configure an instruction counter: {:?}", e);
+            return false;
+        }
+    };
+
+    let started = pmu::counter_start(counter, Some(0));
+    let before = pmu::counter_read(counter);
+    for i in 0..1000 {
+        sum = core::hint::black_box(sum.wrapping_add(i));
+    }
+    let after = pmu::counter_read(counter);
+    let stopped = pmu::counter_stop(counter, true);
+    core::hint::black_box(sum);
+
+    match (started, before, after, stopped) {
+        (Ok(()), Ok(before), Ok(after), Ok(())) if after.wrapping_sub(before) >= 1000 => {
+            println!("Counter {} of {} counted {} instructions", counter, counters, after - before);
+        }
+        result => {
+            println!("Unexpected SBI counter results: {:?}", result);
+            return false;
+        }
+    }
+
+    println!("Performance counter tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running SBI tests ===");
+
+    println!("Starting capability construction tests...");
+    let capability_test = test_capabilities_from_probe();
+    println!("Capability construction tests completed with result: {}", capability_test);
+
+    println!("Starting system info formatting tests...");
+    let format_test = test_system_info_format();
+    println!("System info formatting tests completed with result: {}", format_test);
+
+    println!("Starting HSM decoding tests...");
+    let hsm_test = test_hsm_decoding();
+    println!("HSM decoding tests completed with result: {}", hsm_test);
+
+    println!("Starting reset mapping tests...");
+    let reset_test = test_reset_mapping();
+    println!("Reset mapping tests completed with result: {}", reset_test);
+
+    println!("Starting tick callback tests...");
+    let tick_test = test_tick_callbacks();
+    println!("Tick callback tests completed with result: {}", tick_test);
+
+    println!("Starting hart mask tests...");
+    let mask_test = test_hart_mask();
+    println!("Hart mask tests completed with result: {}", mask_test);
+
+    println!("Starting console input buffer tests...");
+    let input_test = test_console_input_buffer();
+    println!("Console input buffer tests completed with result: {}", input_test);
+
+    println!("Starting TLB ASID flush tests...");
+    let tlb_asid_test = test_tlb_flush_asid();
+    println!("TLB ASID flush tests completed with result: {}", tlb_asid_test);
+
+    println!("Starting SBI extension probe tests...");
+    let probe_eid_test = test_probe_extension_id();
+    println!("SBI extension probe tests completed with result: {}", probe_eid_test);
+
+    println!("Starting DBCN console write tests...");
+    let dbcn_test = test_debug_console_write();
+    println!("DBCN console write tests completed with result: {}", dbcn_test);
+
+    println!("Starting remote fence result tests...");
+    let fence_result_test = test_remote_fence_result();
+    println!("Remote fence result tests completed with result: {}", fence_result_test);
+
+    println!("Starting context timer tests...");
+    let context_timer_test = test_context_timers();
+    println!("Context timer tests completed with result: {}", context_timer_test);
+
+    println!("Starting performance counter tests...");
+    let pmu_test = test_pmu_counters();
+    println!("Performance counter tests completed with result: {}", pmu_test);
+
+    let all_passed = capability_test && format_test && hsm_test && reset_test && tick_test &&
+                     mask_test && input_test && tlb_asid_test && probe_eid_test && dbcn_test &&
+                     fence_result_test && context_timer_test && pmu_test;
+
+    println!("=== SBI test results ===");
+    println!("Capability construction: {}", if capability_test { "PASSED" } else { "FAILED" });
+    println!("System info formatting: {}", if format_test { "PASSED" } else { "FAILED" });
+    println!("HSM state decoding: {}", if hsm_test { "PASSED" } else { "FAILED" });
+    println!("SRST reset mapping: {}", if reset_test { "PASSED" } else { "FAILED" });
+    println!("Timer tick callbacks: {}", if tick_test { "PASSED" } else { "FAILED" });
+    println!("Hart mask construction: {}", if mask_test { "PASSED" } else { "FAILED" });
+    println!("Console input buffer: {}", if input_test { "PASSED" } else { "FAILED" });
+    println!("TLB ASID flush: {}", if tlb_asid_test { "PASSED" } else { "FAILED" });
+    println!("SBI extension probe: {}", if probe_eid_test { "PASSED" } else { "FAILED" });
+    println!("DBCN console write: {}", if dbcn_test { "PASSED" } else { "FAILED" });
+    println!("Remote fence result: {}", if fence_result_test { "PASSED" } else { "FAILED" });
+    println!("Per-context timers: {}", if context_timer_test { "PASSED" } else { "FAILED" });
+    println!("Performance counters: {}", if pmu_test { "PASSED" } else { "FAILED" });
+    println!("Overall SBI tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
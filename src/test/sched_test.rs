@@ -0,0 +1,289 @@
+//! 协作式调度测试模块
+//!
+//! 测试 sched 的轮转切换、对等待中任务的跳过以及时间片抢占
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::sched;
+use crate::trap::ds::{ContextState, Interrupt};
+use crate::trap::infrastructure::{
+    enable_interrupts, disable_interrupts, restore_interrupts,
+    enable_interrupt, disable_interrupt, is_interrupt_enabled, preemption_enabled, PreemptGuard,
+};
+use crate::util::sbi::timer;
+use crate::println;
+
+/// 记录任务的执行顺序
+static TRACE: Mutex<([u8; 16], usize)> = Mutex::new(([0; 16], 0));
+
+fn record(mark: u8) {
+    let mut trace = TRACE.lock();
+    let (marks, len) = &mut *trace;
+    if *len < marks.len() {
+        marks[*len] = mark;
+        *len += 1;
+    }
+}
+
+fn take_trace() -> ([u8; 16], usize) {
+    core::mem::replace(&mut *TRACE.lock(), ([0; 16], 0))
+}
+
+fn task_a() {
+    for step in 0..3 {
+        record(b'a');
+        println!("Task A: step {} (pid {:?})", step, sched::current_pid());
+        sched::yield_now();
+    }
+}
+
+fn task_b() {
+    for step in 0..3 {
+        record(b'b');
+        println!("Task B: step {} (pid {:?})", step, sched::current_pid());
+        sched::yield_now();
+    }
+}
+
+// 测试两个任务轮流执行
+fn test_round_robin() -> bool {
+    println!("Testing cooperative round-robin scheduling...");
+
+    take_trace();
+    let (a, b) = match (sched::spawn("task_a", task_a), sched::spawn("task_b", task_b)) {
+        (Ok(a), Ok(b)) => (a, b),
+        _ => {
+            println!("Failed to spawn tasks");
+            return false;
+        }
+    };
+
+    sched::run();
+
+    let (marks, len) = take_trace();
+    let order = &marks[..len];
+    if order != b"ababab" {
+        println!("Unexpected execution order: {:?}", core::str::from_utf8(order));
+        return false;
+    }
+
+    // 结束的任务状态为Terminated
+    let terminated = Ok(ContextState::Terminated as u8);
+    if a.get_state() != terminated || b.get_state() != terminated || sched::task_count() != 0 {
+        println!("Finished tasks were not cleaned up");
+        return false;
+    }
+
+    println!("Round-robin scheduling tests passed");
+    true
+}
+
+static WAITER_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn waiter_task() {
+    WAITER_RUNS.fetch_add(1, Ordering::SeqCst);
+}
+
+// 测试等待中的任务不会被调度
+fn test_skip_waiting() -> bool {
+    println!("Testing that waiting tasks are skipped...");
+
+    WAITER_RUNS.store(0, Ordering::SeqCst);
+    let waiter = match sched::spawn("waiter", waiter_task) {
+        Ok(waiter) => waiter,
+        Err(e) => {
+            println!("Failed to spawn task: {:?}", e);
+            return false;
+        }
+    };
+
+    let _ = waiter.set_state(ContextState::Waiting as u8);
+    sched::yield_now();
+    if WAITER_RUNS.load(Ordering::SeqCst) != 0 {
+        println!("Waiting task was scheduled");
+        return false;
+    }
+
+    let _ = waiter.set_state(ContextState::Active as u8);
+    sched::run();
+    if WAITER_RUNS.load(Ordering::SeqCst) != 1 {
+        println!("Task did not run after becoming active");
+        return false;
+    }
+
+    println!("Waiting task tests passed");
+    true
+}
+
+/// 最近一次运行的忙循环任务
+static LAST_RUNNER: AtomicUsize = AtomicUsize::new(0);
+
+/// 忙循环任务之间的切换次数
+static RUNNER_SWITCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// 忙循环结束的时间
+static SPIN_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+/// 不让出CPU地循环到截止时间，记录执行权在任务之间的转移
+fn busy_loop(id: usize) {
+    while timer::get_time() < SPIN_DEADLINE.load(Ordering::SeqCst) {
+        if LAST_RUNNER.swap(id, Ordering::SeqCst) != id {
+            RUNNER_SWITCHES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+fn busy_task_a() {
+    busy_loop(1);
+}
+
+fn busy_task_b() {
+    busy_loop(2);
+}
+
+// 测试时间片抢占让两个忙循环任务轮流运行
+fn test_timeslice_preemption() -> bool {
+    println!("Testing timeslice preemption...");
+
+    // 守卫期间关闭抢占，离开作用域后恢复
+    {
+        let _guard = PreemptGuard::new();
+        if preemption_enabled() {
+            println!("Preemption still enabled inside a guard");
+            return false;
+        }
+    }
+    if !preemption_enabled() {
+        println!("Preemption not re-enabled after the guard");
+        return false;
+    }
+
+    // 10ms的时间片，两个任务一共忙循环20个时间片
+    let timeslice = timer::timebase_frequency() / 100;
+    LAST_RUNNER.store(0, Ordering::SeqCst);
+    RUNNER_SWITCHES.store(0, Ordering::SeqCst);
+    SPIN_DEADLINE.store(timer::get_time() + timeslice * 20, Ordering::SeqCst);
+
+    if !sched::start_timeslice(timeslice) {
+        println!("Failed to start timeslice preemption");
+        return false;
+    }
+
+    let timer_was_enabled = is_interrupt_enabled(Interrupt::SupervisorTimer);
+    let was_enabled = disable_interrupts();
+    enable_interrupt(Interrupt::SupervisorTimer);
+    enable_interrupts();
+
+    let spawned = sched::spawn("busy_a", busy_task_a).is_ok() && sched::spawn("busy_b", busy_task_b).is_ok();
+    sched::run();
+
+    disable_interrupts();
+    sched::stop_timeslice();
+    if !timer_was_enabled {
+        disable_interrupt(Interrupt::SupervisorTimer);
+    }
+    restore_interrupts(was_enabled);
+
+    // 没有抢占时先运行的任务一直循环到截止时间，执行权只会转移两次
+    let switches = RUNNER_SWITCHES.load(Ordering::SeqCst);
+    if !spawned || switches < 3 {
+        println!("Busy tasks were not preempted: spawned {}, {} switches", spawned, switches);
+        return false;
+    }
+
+    println!("Busy tasks switched {} times", switches);
+    println!("Timeslice preemption tests passed");
+    true
+}
+
+/// 睡眠任务醒来的时间
+static WOKE_AT: AtomicU64 = AtomicU64::new(0);
+
+/// 睡眠的毫秒数
+const SLEEP_MS: u64 = 10;
+
+fn sleeper_task() {
+    record(b's');
+    sched::sleep_ms(SLEEP_MS);
+    WOKE_AT.store(timer::get_time(), Ordering::SeqCst);
+    record(b'S');
+}
+
+fn worker_task() {
+    for _ in 0..3 {
+        record(b'w');
+        sched::yield_now();
+    }
+}
+
+// 测试睡眠的任务让出CPU，并由定时器唤醒
+fn test_sleep_ms() -> bool {
+    println!("Testing task sleep...");
+
+    take_trace();
+    WOKE_AT.store(0, Ordering::SeqCst);
+    let timer_was_enabled = is_interrupt_enabled(Interrupt::SupervisorTimer);
+    let was_enabled = disable_interrupts();
+    enable_interrupt(Interrupt::SupervisorTimer);
+    enable_interrupts();
+
+    let start = timer::get_time();
+    let spawned = sched::spawn("sleeper", sleeper_task).is_ok() && sched::spawn("worker", worker_task).is_ok();
+    sched::run();
+
+    disable_interrupts();
+    if !timer_was_enabled {
+        disable_interrupt(Interrupt::SupervisorTimer);
+    }
+    restore_interrupts(was_enabled);
+
+    // 睡眠期间工作任务运行完毕，睡眠任务最后才醒来
+    let (marks, len) = take_trace();
+    let order = &marks[..len];
+    if !spawned || order != b"swwwS" {
+        println!("Unexpected execution order: {:?}", core::str::from_utf8(order));
+        return false;
+    }
+
+    let slept = WOKE_AT.load(Ordering::SeqCst).saturating_sub(start);
+    let expected = SLEEP_MS * timer::timebase_frequency() / 1000;
+    if slept < expected {
+        println!("Task woke after {} cycles, expected at least {}", slept, expected);
+        return false;
+    }
+
+    println!("Task sleep tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running scheduler tests ===");
+
+    println!("Starting round-robin tests...");
+    let round_robin_test = test_round_robin();
+    println!("Round-robin tests completed with result: {}", round_robin_test);
+
+    println!("Starting waiting task tests...");
+    let waiting_test = test_skip_waiting();
+    println!("Waiting task tests completed with result: {}", waiting_test);
+
+    println!("Starting timeslice preemption tests...");
+    let preemption_test = test_timeslice_preemption();
+    println!("Timeslice preemption tests completed with result: {}", preemption_test);
+
+    println!("Starting task sleep tests...");
+    let sleep_test = test_sleep_ms();
+    println!("Task sleep tests completed with result: {}", sleep_test);
+
+    let all_passed = round_robin_test && waiting_test && preemption_test && sleep_test;
+
+    println!("=== Scheduler test results ===");
+    println!("Round-robin scheduling: {}", if round_robin_test { "PASSED" } else { "FAILED" });
+    println!("Skip waiting tasks: {}", if waiting_test { "PASSED" } else { "FAILED" });
+    println!("Timeslice preemption: {}", if preemption_test { "PASSED" } else { "FAILED" });
+    println!("Task sleep: {}", if sleep_test { "PASSED" } else { "FAILED" });
+    println!("Overall scheduler tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
@@ -0,0 +1,139 @@
+//! 单生产者单消费者队列测试模块
+//!
+//! 测试 util::spsc 的溢出处理和交替读写
+
+use crate::util::spsc::{OverflowPolicy, SpscRing};
+use crate::println;
+
+/// 测试用的队列容量
+const RING_SIZE: usize = 8;
+
+// 测试两种溢出处理方式
+fn test_overflow_policies() -> bool {
+    println!("Testing SPSC overflow policies...");
+
+    let ring: SpscRing<u8, RING_SIZE> = SpscRing::new(OverflowPolicy::DropNewest);
+    for byte in 0..RING_SIZE as u8 + 2 {
+        ring.push(byte);
+    }
+    if ring.len() != RING_SIZE || ring.dropped() != 2 || ring.pop() != Some(0) {
+        println!("DropNewest kept {} items and dropped {}", ring.len(), ring.dropped());
+        return false;
+    }
+
+    // 队列满后再放入两个，最早的1和2被丢弃
+    ring.set_policy(OverflowPolicy::DropOldest);
+    for byte in 100..103 {
+        if !ring.push(byte) {
+            println!("DropOldest rejected a new item");
+            return false;
+        }
+    }
+    let mut expected = [0u8; RING_SIZE];
+    for (i, byte) in (3..RING_SIZE as u8).chain(100..103).enumerate() {
+        expected[i] = byte;
+    }
+    for &byte in &expected {
+        if ring.pop() != Some(byte) {
+            println!("DropOldest returned items out of order, expected {}", byte);
+            return false;
+        }
+    }
+    if ring.pop().is_some() || ring.dropped() != 4 {
+        println!("Unexpected ring state: {} items, {} dropped", ring.len(), ring.dropped());
+        return false;
+    }
+
+    println!("SPSC overflow policy tests passed");
+    true
+}
+
+/// 线性同余生成器，决定每一轮生产者和消费者各执行多少次
+struct Lcg(u32);
+
+impl Lcg {
+    fn next(&mut self, bound: u32) -> u32 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (self.0 >> 16) % bound
+    }
+}
+
+/// 模拟生产者（中断处理程序）和消费者（主循环）交替运行，检查顺序和数量
+fn stress(policy: OverflowPolicy) -> bool {
+    const ITEMS: u32 = 20_000;
+
+    let ring: SpscRing<u32, RING_SIZE> = SpscRing::new(policy);
+    let mut rng = Lcg(0x2545_f491);
+    let mut produced = 0;
+    let mut consumed = 0u32;
+    let mut last = 0;
+
+    while produced < ITEMS || !ring.is_empty() {
+        // 生产者一次放入的数量可能超过容量，覆盖两种溢出情况
+        for _ in 0..rng.next(2 * RING_SIZE as u32) {
+            if produced == ITEMS {
+                break;
+            }
+            produced += 1;
+            ring.push(produced);
+        }
+
+        for _ in 0..rng.next(2 * RING_SIZE as u32) {
+            let Some(value) = ring.pop() else {
+                break;
+            };
+            // 丢弃元素时序号可以跳过，但不能重复或倒退
+            if value <= last {
+                println!("{:?}: popped {} after {}", policy, value, last);
+                return false;
+            }
+            last = value;
+            consumed += 1;
+        }
+    }
+
+    if consumed as usize + ring.dropped() != ITEMS as usize {
+        println!("{:?}: consumed {} and dropped {} of {}", policy, consumed, ring.dropped(), ITEMS);
+        return false;
+    }
+    // 丢弃最早的元素时，最后放入的元素一定会被读到
+    if policy == OverflowPolicy::DropOldest && last != ITEMS {
+        println!("DropOldest lost the newest item, last was {}", last);
+        return false;
+    }
+    true
+}
+
+// 测试交替读写下的顺序和计数
+fn test_interleaved_stress() -> bool {
+    println!("Testing interleaved SPSC push/pop...");
+
+    if !stress(OverflowPolicy::DropNewest) || !stress(OverflowPolicy::DropOldest) {
+        return false;
+    }
+
+    println!("Interleaved SPSC tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running SPSC queue tests ===");
+
+    println!("Starting overflow policy tests...");
+    let policy_test = test_overflow_policies();
+    println!("Overflow policy tests completed with result: {}", policy_test);
+
+    println!("Starting interleaved stress tests...");
+    let stress_test = test_interleaved_stress();
+    println!("Interleaved stress tests completed with result: {}", stress_test);
+
+    let all_passed = policy_test && stress_test;
+
+    println!("=== SPSC queue test results ===");
+    println!("Overflow policies: {}", if policy_test { "PASSED" } else { "FAILED" });
+    println!("Interleaved push/pop: {}", if stress_test { "PASSED" } else { "FAILED" });
+    println!("Overall SPSC queue tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
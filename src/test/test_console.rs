@@ -0,0 +1,93 @@
+//! 捕获控制台输出的测试辅助模块
+//!
+//! 测试期间把`println!`的输出重定向到固定大小的缓冲区，结束捕获后检查输出内容，
+//! 例如模拟中断后确认处理器打印了预期的消息，而不是靠人工查看日志。
+
+use crate::console::{self, BufferSink, ConsoleSink};
+use crate::println;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// 捕获缓冲区的大小，超出的输出被丢弃
+pub const CAPTURE_BUFFER_SIZE: usize = 4096;
+
+/// 最近一次捕获的输出
+static CAPTURED: BufferSink<CAPTURE_BUFFER_SIZE> = BufferSink::new();
+
+/// 是否有正在进行的捕获
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+/// 结束捕获时每次复制出缓冲区再输出的字节数
+const REPLAY_CHUNK_SIZE: usize = 256;
+
+/// 捕获控制台输出的守卫
+///
+/// 创建时清空缓冲区并开始捕获，离开作用域时恢复原来的输出目标，
+/// 并把捕获的内容原样输出，测试日志不会因此缺失。同一时间只能有一个捕获。
+pub struct TestConsole {
+    /// 开始捕获前的输出目标
+    previous: &'static dyn ConsoleSink,
+}
+
+impl TestConsole {
+    /// 清空缓冲区并开始捕获
+    ///
+    /// # Panics
+    ///
+    /// 已经有捕获在进行时panic，嵌套捕获会覆盖外层捕获的内容
+    pub fn start() -> Self {
+        assert!(!CAPTURING.swap(true, Ordering::AcqRel), "console capture already active");
+        CAPTURED.clear();
+        Self {
+            previous: console::set_console_sink(&CAPTURED),
+        }
+    }
+
+    /// 结束捕获
+    pub fn finish(self) {}
+}
+
+impl Drop for TestConsole {
+    fn drop(&mut self) {
+        console::set_console_sink(self.previous);
+        replay_captured();
+        CAPTURING.store(false, Ordering::Release);
+    }
+}
+
+/// 把捕获的内容原样输出
+///
+/// `with_contents`的回调中持有缓冲区的锁，不能在其中输出，
+/// 因此分段复制到栈上，释放锁后再输出
+fn replay_captured() {
+    let mut offset = 0;
+    loop {
+        let mut chunk = [0u8; REPLAY_CHUNK_SIZE];
+        let len = CAPTURED.with_contents(|text| {
+            let rest = &text.as_bytes()[offset.min(text.len())..];
+            let len = rest.len().min(REPLAY_CHUNK_SIZE);
+            chunk[..len].copy_from_slice(&rest[..len]);
+            len
+        });
+        // 分段处可能截断多字节字符，只输出完整的部分，剩余字节留到下一段
+        let text = match core::str::from_utf8(&chunk[..len]) {
+            Ok(text) => text,
+            Err(e) => core::str::from_utf8(&chunk[..e.valid_up_to()]).unwrap_or(""),
+        };
+        if text.is_empty() {
+            break;
+        }
+        crate::print!("{}", text);
+        offset += text.len();
+    }
+}
+
+/// 检查最近一次捕获的输出是否包含`expected`
+///
+/// 应在捕获结束后调用，不包含时输出说明并返回false，可以直接作为测试结果
+pub fn assert_output_contains(expected: &str) -> bool {
+    if CAPTURED.with_contents(|text| text.contains(expected)) {
+        return true;
+    }
+    println!("Expected output not found: {:?}", expected);
+    false
+}
@@ -3,12 +3,17 @@
 //! 测试 trap::api 模块的功能
 
 use riscv::register::sstatus; // 需要引入 sstatus
+use spin::Mutex;
 use crate::trap::api;
+use crate::trap::syscall;
 use crate::trap::ds::{
-    TrapType, TrapContext, TrapHandlerResult, Interrupt, 
+    TrapType, TrapContext, TrapHandlerResult, Interrupt, TrapMode,
     SystemError, ErrorResult, ErrorSource, ErrorLevel, TrapError
 };
-use crate::trap::ds::handler::RegistrarId;
+use crate::trap::ds::handler::{ProtectionLevel, RegistrarId};
+use crate::trap::infrastructure::di;
+use crate::util::csr;
+use super::test_console::{TestConsole, assert_output_contains};
 use crate::println;
 
 // 全局测试模块注册者ID
@@ -314,12 +319,18 @@ fn test_error_handling() -> bool {
         0x1000
     );
     
+    let capture = TestConsole::start();
     let result = api::handle_system_error(error);
+    capture.finish();
     
     if result != ErrorResult::Handled {
         println!("Error was not handled correctly: {:?}", result);
         return false;
     }
+
+    if !assert_output_contains("Test error handler called") {
+        return false;
+    }
     
     println!("Error was correctly handled");
     
@@ -349,6 +360,393 @@ fn test_error_handling() -> bool {
     true
 }
 
+// 测试运行时切换中断模式
+fn test_trap_mode_switch() -> bool {
+    println!("Testing trap mode switching...");
+
+    let original = api::trap_mode();
+
+    for mode in [TrapMode::Direct, TrapMode::Vectored, TrapMode::Direct] {
+        match api::set_trap_mode(mode) {
+            Ok(()) => {}
+            Err(api::TrapApiError::UnsupportedTrapMode(_)) => {
+                // 硬件不支持该模式时，原来的模式必须保持不变
+                println!("Trap mode {:?} not supported by hardware", mode);
+                if TrapMode::from_stvec(csr::read_stvec()) != Some(api::trap_mode()) {
+                    println!("stvec changed after rejected mode switch");
+                    return false;
+                }
+                continue;
+            }
+            Err(e) => {
+                println!("Failed to switch trap mode to {:?}: {:?}", mode, e);
+                return false;
+            }
+        }
+
+        let stvec = csr::read_stvec();
+        if TrapMode::from_stvec(stvec) != Some(mode) || api::trap_mode() != mode {
+            println!("stvec mode bits mismatch after switching to {:?}: stvec={:#x}", mode, stvec);
+            return false;
+        }
+    }
+
+    if let Err(e) = api::set_trap_mode(original) {
+        println!("Failed to restore original trap mode {:?}: {:?}", original, e);
+        return false;
+    }
+
+    println!("Trap mode switching tests passed");
+    true
+}
+
+// 测试容量报告随注册和注销变化
+fn test_capacity_report() -> bool {
+    println!("Testing handler capacity report...");
+
+    const REGISTRY_DESCS: [&str; 2] = ["Capacity Test Handler 1", "Capacity Test Handler 2"];
+    const ERROR_DESC: &str = "Capacity Test Error Handler";
+
+    let before = api::capacity_report();
+    before.print();
+
+    for desc in REGISTRY_DESCS {
+        if let Err(e) = api::register_trap_handler(TrapType::SoftwareInterrupt, test_trap_handler,
+                                                   200, desc, None) {
+            println!("Failed to register '{}': {:?}", desc, e);
+            for desc in REGISTRY_DESCS {
+                let _ = api::unregister_trap_handler(TrapType::SoftwareInterrupt, desc);
+            }
+            return false;
+        }
+    }
+    let error_registered = api::register_error_handler(test_error_handler, 200, ERROR_DESC, None, None).is_ok();
+    let storage_registered = di::register_handler(TrapType::Unknown, test_trap_handler, 200,
+                                                  "Capacity Test DI Handler", None);
+
+    let during = api::capacity_report();
+
+    for desc in REGISTRY_DESCS {
+        let _ = api::unregister_trap_handler(TrapType::SoftwareInterrupt, desc);
+    }
+    let _ = api::unregister_error_handler(ERROR_DESC);
+    di::unregister_handler(TrapType::Unknown, "Capacity Test DI Handler");
+
+    let after = api::capacity_report();
+
+    if !error_registered || !storage_registered {
+        println!("Failed to register capacity test handlers");
+        return false;
+    }
+
+    let moved = |name: &str, before: api::CapacityUsage, during: api::CapacityUsage, count: usize| {
+        if during.used != before.used + count || during.free + count != before.free ||
+            during.total != before.total {
+            println!("{} usage did not move by {}: {:?} -> {:?}", name, count, before, during);
+            return false;
+        }
+        true
+    };
+
+    if !moved("Registry", before.registry, during.registry, REGISTRY_DESCS.len()) ||
+        !moved("Error handler", before.error_handlers, during.error_handlers, 1) ||
+        !moved("Handler storage", before.handler_storage, during.handler_storage, 1) {
+        return false;
+    }
+
+    if after != before {
+        println!("Capacity not restored after unregistering: {:?} -> {:?}", before, after);
+        return false;
+    }
+
+    println!("Handler capacity report tests passed");
+    true
+}
+
+// 测试统计快照的差值
+fn test_stats_snapshot_diff() -> bool {
+    println!("Testing trap statistics snapshot diff...");
+
+    const STATS_DESC: &str = "Stats Test Handler";
+    const TRAPS: usize = 5;
+
+    if !di::register_handler(TrapType::Unknown, test_trap_handler, 0, STATS_DESC, None) {
+        println!("Failed to register statistics test handler");
+        return false;
+    }
+
+    let before = api::StatsSnapshot::capture();
+
+    // scause=14是保留的异常编号，解码为TrapType::Unknown
+    let mut ctx = TrapContext::new();
+    ctx.scause = 14;
+    for _ in 0..TRAPS {
+        di::internal_handle_trap(&mut ctx);
+    }
+
+    let after = api::StatsSnapshot::capture();
+    di::unregister_handler(TrapType::Unknown, STATS_DESC);
+
+    let delta = before.diff(&after);
+    before.print_delta(&after);
+
+    if delta.trap_count(TrapType::Unknown) != TRAPS {
+        println!("Expected {} Unknown traps, got {}", TRAPS, delta.trap_count(TrapType::Unknown));
+        return false;
+    }
+
+    if delta.handler_count(STATS_DESC) != Some(TRAPS) {
+        println!("Expected {} invocations of '{}', got {:?}", TRAPS, STATS_DESC,
+                 delta.handler_count(STATS_DESC));
+        return false;
+    }
+
+    // 快照差值不会清除绝对计数
+    if after.trap_counts[TrapType::Unknown as usize] < TRAPS {
+        println!("Absolute Unknown trap count smaller than the interval delta");
+        return false;
+    }
+
+    println!("Trap statistics snapshot tests passed");
+    true
+}
+
+fn sum_syscall(args: &[usize; syscall::SYSCALL_ARGS]) -> isize {
+    args.iter().sum::<usize>() as isize
+}
+
+// 测试系统调用按a7分发
+fn test_syscall_dispatch() -> bool {
+    println!("Testing syscall dispatch table...");
+
+    const SUM_SYSCALL: usize = 42;
+    if !syscall::register_syscall(SUM_SYSCALL, sum_syscall) {
+        println!("Failed to register test syscall");
+        return false;
+    }
+    if syscall::register_syscall(SUM_SYSCALL, sum_syscall) ||
+        syscall::register_syscall(syscall::MAX_SYSCALLS, sum_syscall) {
+        println!("Duplicate or out-of-range syscall registration should fail");
+        syscall::unregister_syscall(SUM_SYSCALL);
+        return false;
+    }
+
+    // 经默认系统调用处理器分发
+    let mut ctx = TrapContext::new();
+    ctx.sepc = 0x8020_1000;
+    ctx.x[17] = SUM_SYSCALL;
+    for (i, reg) in ctx.x[10..16].iter_mut().enumerate() {
+        *reg = i + 1;
+    }
+    let result = di::dispatch_trap(TrapType::SystemCall, &mut ctx);
+    syscall::unregister_syscall(SUM_SYSCALL);
+
+    if !matches!(result, TrapHandlerResult::Handled) || ctx.x[10] != 21 || ctx.sepc != 0x8020_1004 {
+        println!("Syscall returned a0={}, sepc={:#x}, result {:?}", ctx.x[10], ctx.sepc, result);
+        return false;
+    }
+
+    // 未注册的调用号返回-ENOSYS
+    let mut ctx = TrapContext::new();
+    ctx.x[17] = SUM_SYSCALL;
+    if syscall::dispatch(&mut ctx) != -syscall::ENOSYS || ctx.x[10] as isize != -syscall::ENOSYS {
+        println!("Unknown syscall should return -ENOSYS, a0={}", ctx.x[10] as isize);
+        return false;
+    }
+
+    println!("Syscall dispatch tests passed");
+    true
+}
+
+// 持有IrqGuard时提前返回，返回守卫作用域内的中断状态
+fn guarded_early_return(bail: bool) -> Option<bool> {
+    let _irq = api::IrqGuard::new();
+    if bail {
+        return None;
+    }
+    Some(sstatus::read().sie())
+}
+
+// 测试IrqGuard在各种返回路径上恢复中断状态
+fn test_irq_guard() -> bool {
+    println!("Testing IrqGuard interrupt restore...");
+
+    let original = sstatus::read().sie();
+
+    unsafe { sstatus::set_sie(); }
+    let inside = guarded_early_return(false);
+    let after_normal = sstatus::read().sie();
+    let bailed = guarded_early_return(true);
+    let after_early = sstatus::read().sie();
+
+    // 原本关闭的中断在守卫释放后保持关闭
+    unsafe { sstatus::clear_sie(); }
+    guarded_early_return(true);
+    let after_disabled = sstatus::read().sie();
+
+    if original {
+        unsafe { sstatus::set_sie(); }
+    }
+
+    if inside != Some(false) {
+        println!("Interrupts not disabled while guard held: {:?}", inside);
+        return false;
+    }
+
+    if bailed.is_some() || !after_normal || !after_early {
+        println!("Interrupts not restored: normal={}, early return={}", after_normal, after_early);
+        return false;
+    }
+
+    if after_disabled {
+        println!("Guard enabled interrupts that were originally disabled");
+        return false;
+    }
+
+    println!("IrqGuard tests passed");
+    true
+}
+
+// 测试按描述查询处理器调用次数
+fn test_handler_invocation_count() -> bool {
+    println!("Testing handler invocation counts...");
+
+    const COUNT_DESC: &str = "Invocation Count Test Handler";
+    const TRAPS: u64 = 3;
+
+    if di::handler_invocation_count(COUNT_DESC) != 0 {
+        println!("Unregistered handler should report zero invocations");
+        return false;
+    }
+    if !di::register_handler(TrapType::Unknown, test_trap_handler, 0, COUNT_DESC, None) {
+        println!("Failed to register invocation count test handler");
+        return false;
+    }
+
+    // scause=14是保留的异常编号，解码为TrapType::Unknown
+    let mut ctx = TrapContext::new();
+    ctx.scause = 14;
+    let capture = TestConsole::start();
+    for _ in 0..TRAPS {
+        di::internal_handle_trap(&mut ctx);
+    }
+    capture.finish();
+
+    let count = di::handler_invocation_count(COUNT_DESC);
+    api::print_handler_stats();
+    di::unregister_handler(TrapType::Unknown, COUNT_DESC);
+
+    if count != TRAPS {
+        println!("Expected {} invocations of '{}', got {}", TRAPS, COUNT_DESC, count);
+        return false;
+    }
+
+    // 处理器确实运行并输出了消息
+    if !assert_output_contains("Test trap handler called") {
+        return false;
+    }
+
+    println!("Handler invocation count tests passed");
+    true
+}
+
+/// 处理器中观察到的trap上下文状态和嵌套层级
+static OBSERVED_NESTING: Mutex<Option<(bool, usize)>> = Mutex::new(None);
+
+// 记录处理器运行时的trap上下文状态
+fn nesting_probe_handler(_ctx: &mut TrapContext) -> TrapHandlerResult {
+    let in_trap = api::is_in_trap_context();
+    debug_assert!(in_trap, "trap handler running outside trap context");
+    *OBSERVED_NESTING.lock() = Some((in_trap, api::current_trap_nest_level()));
+    TrapHandlerResult::Handled
+}
+
+// 测试处理器运行期间嵌套层级被正确计入
+fn test_handler_nest_level() -> bool {
+    println!("Testing trap nesting level inside handlers...");
+
+    const NESTING_DESC: &str = "Nesting Level Test Handler";
+    if !di::register_handler(TrapType::Unknown, nesting_probe_handler, 0, NESTING_DESC, None) {
+        println!("Failed to register nesting test handler");
+        return false;
+    }
+
+    let before = api::current_trap_nest_level();
+    *OBSERVED_NESTING.lock() = None;
+    let mut ctx = TrapContext::new();
+    ctx.scause = 14;
+    di::internal_handle_trap(&mut ctx);
+    di::unregister_handler(TrapType::Unknown, NESTING_DESC);
+
+    let observed = OBSERVED_NESTING.lock().take();
+    if observed != Some((true, before + 1)) {
+        println!("Handler observed {:?}, expected (true, {})", observed, before + 1);
+        return false;
+    }
+
+    // 处理结束后层级恢复
+    if api::current_trap_nest_level() != before || api::is_in_trap_context() {
+        println!("Nesting level not restored after the handler: {}", api::current_trap_nest_level());
+        return false;
+    }
+
+    println!("Handler nesting level tests passed");
+    true
+}
+
+// 测试通过HandlerBuilder注册处理器
+fn test_handler_builder() -> bool {
+    println!("Testing handler registration builder...");
+
+    const BUILDER_DESC: &str = "Builder Test Handler";
+    const CONTEXT_ID: usize = 4242;
+    // 使用已定义的中断类型，结果不依赖`Unknown`是否参与处理器枚举
+    const TRAP_TYPE: TrapType = TrapType::SoftwareInterrupt;
+    let registrar = get_test_registrar_id();
+
+    // 默认值：优先级128、用户级、无上下文
+    if let Err(e) = api::HandlerBuilder::new(TRAP_TYPE, test_trap_handler).register(registrar) {
+        println!("Failed to register handler with defaults: {:?}", e);
+        return false;
+    }
+    let defaults = di::get_handler_info(TRAP_TYPE, api::HandlerBuilder::DEFAULT_DESCRIPTION);
+    let _ = api::unregister_trap_handler_secure(TRAP_TYPE, api::HandlerBuilder::DEFAULT_DESCRIPTION, registrar);
+
+    let default_ok = defaults.is_some_and(|info| {
+        info.priority == api::HandlerBuilder::DEFAULT_PRIORITY && info.context_id.is_none()
+            && info.protection_level == ProtectionLevel::User && info.registrar_id == registrar
+    });
+    if !default_ok {
+        println!("Unexpected defaults: {:?}", defaults);
+        return false;
+    }
+
+    let registered = api::HandlerBuilder::new(TRAP_TYPE, test_trap_handler)
+        .priority(5)
+        .description(BUILDER_DESC)
+        .context(CONTEXT_ID)
+        .protection(ProtectionLevel::User)
+        .register(registrar);
+    let info = di::get_handler_info(TRAP_TYPE, BUILDER_DESC);
+    let unregistered = api::unregister_trap_handler_secure(TRAP_TYPE, BUILDER_DESC, registrar);
+
+    if registered.is_err() || unregistered.is_err() {
+        println!("Builder registration {:?}, unregistration {:?}", registered, unregistered);
+        return false;
+    }
+    let explicit_ok = info.is_some_and(|info| {
+        info.priority == 5 && info.context_id == Some(CONTEXT_ID)
+            && info.protection_level == ProtectionLevel::User
+    });
+    if !explicit_ok {
+        println!("Builder settings not applied: {:?}", info);
+        return false;
+    }
+
+    println!("Handler builder tests passed");
+    true
+}
+
 // 运行所有测试
 pub fn run_tests() -> bool {
     println!("=== Running Trap API tests ===");
@@ -374,8 +772,42 @@ pub fn run_tests() -> bool {
     let error_test = test_error_handling();
     println!("Error handling tests completed with result: {}", error_test);
     
+    println!("Starting trap mode tests...");
+    let mode_test = test_trap_mode_switch();
+    println!("Trap mode tests completed with result: {}", mode_test);
+    
+    println!("Starting capacity report tests...");
+    let capacity_test = test_capacity_report();
+    println!("Capacity report tests completed with result: {}", capacity_test);
+    
+    println!("Starting statistics snapshot tests...");
+    let stats_test = test_stats_snapshot_diff();
+    println!("Statistics snapshot tests completed with result: {}", stats_test);
+    
+    println!("Starting syscall dispatch tests...");
+    let syscall_test = test_syscall_dispatch();
+    println!("Syscall dispatch tests completed with result: {}", syscall_test);
+
+    println!("Starting IrqGuard tests...");
+    let irq_guard_test = test_irq_guard();
+    println!("IrqGuard tests completed with result: {}", irq_guard_test);
+
+    println!("Starting handler invocation count tests...");
+    let invocation_count_test = test_handler_invocation_count();
+    println!("Handler invocation count tests completed with result: {}", invocation_count_test);
+
+    println!("Starting handler nesting level tests...");
+    let nesting_test = test_handler_nest_level();
+    println!("Handler nesting level tests completed with result: {}", nesting_test);
+
+    println!("Starting handler builder tests...");
+    let builder_test = test_handler_builder();
+    println!("Handler builder tests completed with result: {}", builder_test);
+
     let all_passed = handler_test && interrupt_test && status_test && 
-                     context_test && error_test;
+                     context_test && error_test && mode_test && capacity_test &&
+                     stats_test && syscall_test && irq_guard_test && invocation_count_test &&
+                     nesting_test && builder_test;
     
     println!("=== Trap API test results ===");
     println!("Handler management: {}", if handler_test { "PASSED" } else { "FAILED" });
@@ -383,6 +815,14 @@ pub fn run_tests() -> bool {
     println!("Status queries: {}", if status_test { "PASSED" } else { "FAILED" });
     println!("Context ID management: {}", if context_test { "PASSED" } else { "FAILED" });
     println!("Error handling: {}", if error_test { "PASSED" } else { "FAILED" });
+    println!("Trap mode switching: {}", if mode_test { "PASSED" } else { "FAILED" });
+    println!("Capacity report: {}", if capacity_test { "PASSED" } else { "FAILED" });
+    println!("Statistics snapshot diff: {}", if stats_test { "PASSED" } else { "FAILED" });
+    println!("Syscall dispatch table: {}", if syscall_test { "PASSED" } else { "FAILED" });
+    println!("IrqGuard restore: {}", if irq_guard_test { "PASSED" } else { "FAILED" });
+    println!("Handler invocation count: {}", if invocation_count_test { "PASSED" } else { "FAILED" });
+    println!("Handler nesting level: {}", if nesting_test { "PASSED" } else { "FAILED" });
+    println!("Handler builder: {}", if builder_test { "PASSED" } else { "FAILED" });
     println!("Overall Trap API tests: {}", if all_passed { "PASSED" } else { "FAILED" });
     
     all_passed
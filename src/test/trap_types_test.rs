@@ -0,0 +1,233 @@
+//! Trap 类型测试模块
+//!
+//! 测试 trap::ds::types 中scause编码与类型之间的转换，以及类型的遍历和显示
+
+use crate::trap::ds::{Interrupt, Exception, TrapCause, TrapType};
+use crate::util::SliceWriter;
+use core::fmt::Write;
+use crate::println;
+
+// 中断标志位
+const INTERRUPT_BIT: usize = 1 << (usize::BITS - 1);
+
+// 所有已定义的中断
+const ALL_INTERRUPTS: [Interrupt; 3] = [
+    Interrupt::SupervisorSoft,
+    Interrupt::SupervisorTimer,
+    Interrupt::SupervisorExternal,
+];
+
+// 所有已定义的异常
+const ALL_EXCEPTIONS: [Exception; 13] = [
+    Exception::InstructionMisaligned,
+    Exception::InstructionFault,
+    Exception::IllegalInstruction,
+    Exception::Breakpoint,
+    Exception::LoadMisaligned,
+    Exception::LoadFault,
+    Exception::StoreMisaligned,
+    Exception::StoreFault,
+    Exception::UserEnvCall,
+    Exception::SupervisorEnvCall,
+    Exception::InstructionPageFault,
+    Exception::LoadPageFault,
+    Exception::StorePageFault,
+];
+
+// 测试中断编码的双向转换
+fn test_interrupt_codes() -> bool {
+    println!("Testing interrupt code conversion...");
+
+    for interrupt in ALL_INTERRUPTS {
+        if Interrupt::from_code(interrupt.code()) != Some(interrupt) {
+            println!("Interrupt {:?} did not round-trip through code {}",
+                     interrupt, interrupt.code());
+            return false;
+        }
+
+        let cause = TrapCause::from_bits(INTERRUPT_BIT | interrupt.code());
+        if cause.interrupt() != Some(interrupt) || cause.exception().is_some() {
+            println!("TrapCause decoding failed for interrupt {:?}", interrupt);
+            return false;
+        }
+    }
+
+    for code in [0, 2, 3, 4, 6, 7, 8, 10, 11, 12, 16, 1000] {
+        if let Some(interrupt) = Interrupt::from_code(code) {
+            println!("Invalid interrupt code {} decoded as {:?}", code, interrupt);
+            return false;
+        }
+    }
+
+    println!("Interrupt code conversion tests passed");
+    true
+}
+
+// 测试异常编码的双向转换
+fn test_exception_codes() -> bool {
+    println!("Testing exception code conversion...");
+
+    for exception in ALL_EXCEPTIONS {
+        if Exception::from_code(exception.code()) != Some(exception) {
+            println!("Exception {:?} did not round-trip through code {}",
+                     exception, exception.code());
+            return false;
+        }
+
+        let cause = TrapCause::from_bits(exception.code());
+        if cause.exception() != Some(exception) || cause.interrupt().is_some() {
+            println!("TrapCause decoding failed for exception {:?}", exception);
+            return false;
+        }
+    }
+
+    for code in [10, 11, 14, 16, 24, 1000] {
+        if let Some(exception) = Exception::from_code(code) {
+            println!("Invalid exception code {} decoded as {:?}", code, exception);
+            return false;
+        }
+    }
+
+    println!("Exception code conversion tests passed");
+    true
+}
+
+// 测试scause到TrapType的映射
+fn test_trap_type_mapping() -> bool {
+    println!("Testing trap type mapping...");
+
+    let cases = [
+        (INTERRUPT_BIT | 1, TrapType::SoftwareInterrupt),
+        (INTERRUPT_BIT | 5, TrapType::TimerInterrupt),
+        (INTERRUPT_BIT | 9, TrapType::ExternalInterrupt),
+        (INTERRUPT_BIT | 3, TrapType::Unknown),
+        (0, TrapType::InstructionMisaligned),
+        (1, TrapType::InstructionAccessFault),
+        (2, TrapType::IllegalInstruction),
+        (3, TrapType::Breakpoint),
+        (4, TrapType::LoadMisaligned),
+        (5, TrapType::LoadAccessFault),
+        (6, TrapType::StoreMisaligned),
+        (7, TrapType::StoreAccessFault),
+        (8, TrapType::SystemCall),
+        (9, TrapType::SupervisorCall),
+        (12, TrapType::InstructionPageFault),
+        (13, TrapType::LoadPageFault),
+        (15, TrapType::StorePageFault),
+        (14, TrapType::Unknown),
+    ];
+
+    for (bits, expected) in cases {
+        let actual = TrapCause::from_bits(bits).to_trap_type();
+        if actual != expected {
+            println!("scause {:#x} mapped to {:?}, expected {:?}", bits, actual, expected);
+            return false;
+        }
+    }
+
+    // from_index覆盖所有已定义的类型，越界编号映射为Unknown
+    for index in 0..TrapType::COUNT {
+        if TrapType::from_index(index) as usize != index {
+            println!("from_index({}) returned {:?}", index, TrapType::from_index(index));
+            return false;
+        }
+    }
+    if TrapType::from_index(TrapType::COUNT) != TrapType::Unknown {
+        println!("Index past COUNT should map to Unknown");
+        return false;
+    }
+    if TrapType::Unknown as usize + 1 != TrapType::SLOTS {
+        println!("Per-type slots should end with Unknown");
+        return false;
+    }
+
+    println!("Trap type mapping tests passed");
+    true
+}
+
+// 测试类型遍历、中断分类和显示名称
+fn test_trap_type_iteration() -> bool {
+    println!("Testing trap type iteration and display...");
+
+    // 按编号顺序遍历所有已定义的类型，不含Unknown
+    if TrapType::iter().count() != TrapType::COUNT ||
+        TrapType::iter().enumerate().any(|(index, trap_type)| trap_type as usize != index) ||
+        TrapType::iter().any(|trap_type| trap_type == TrapType::Unknown) {
+        println!("TrapType::iter() does not list the defined types in index order");
+        return false;
+    }
+
+    // 中断类型与scause的分类一致
+    for interrupt in ALL_INTERRUPTS {
+        let trap_type = TrapCause::from_bits(INTERRUPT_BIT | interrupt.code()).to_trap_type();
+        if !trap_type.is_interrupt() {
+            println!("{:?} not classified as an interrupt", trap_type);
+            return false;
+        }
+    }
+    for exception in ALL_EXCEPTIONS {
+        let trap_type = TrapCause::from_bits(exception as usize).to_trap_type();
+        if trap_type.is_interrupt() {
+            println!("{:?} classified as an interrupt", trap_type);
+            return false;
+        }
+    }
+    if TrapType::iter().filter(TrapType::is_interrupt).count() != ALL_INTERRUPTS.len() ||
+        TrapType::Unknown.is_interrupt() {
+        println!("Unexpected number of interrupt trap types");
+        return false;
+    }
+
+    let mut buffer = [0u8; 64];
+    let mut writer = SliceWriter::new(&mut buffer);
+    let _ = write!(writer, "{}", TrapType::LoadPageFault);
+    if writer.as_str() != "Load Page Fault" {
+        println!("LoadPageFault displayed as '{}'", writer.as_str());
+        return false;
+    }
+
+    // 每个类型的名称都不为空且互不相同
+    for (index, trap_type) in TrapType::iter().chain([TrapType::Unknown]).enumerate() {
+        let duplicate = TrapType::iter().chain([TrapType::Unknown]).skip(index + 1)
+            .any(|other| other.name() == trap_type.name());
+        if trap_type.name().is_empty() || duplicate {
+            println!("Display name of {:?} is empty or duplicated", trap_type);
+            return false;
+        }
+    }
+
+    println!("Trap type iteration tests passed");
+    true
+}
+
+// 运行所有测试
+pub fn run_tests() -> bool {
+    println!("=== Running Trap type tests ===");
+
+    println!("Starting interrupt code tests...");
+    let interrupt_test = test_interrupt_codes();
+    println!("Interrupt code tests completed with result: {}", interrupt_test);
+
+    println!("Starting exception code tests...");
+    let exception_test = test_exception_codes();
+    println!("Exception code tests completed with result: {}", exception_test);
+
+    println!("Starting trap type mapping tests...");
+    let mapping_test = test_trap_type_mapping();
+    println!("Trap type mapping tests completed with result: {}", mapping_test);
+
+    println!("Starting trap type iteration tests...");
+    let iteration_test = test_trap_type_iteration();
+    println!("Trap type iteration tests completed with result: {}", iteration_test);
+
+    let all_passed = interrupt_test && exception_test && mapping_test && iteration_test;
+
+    println!("=== Trap type test results ===");
+    println!("Interrupt codes: {}", if interrupt_test { "PASSED" } else { "FAILED" });
+    println!("Exception codes: {}", if exception_test { "PASSED" } else { "FAILED" });
+    println!("Trap type mapping: {}", if mapping_test { "PASSED" } else { "FAILED" });
+    println!("Trap type iteration: {}", if iteration_test { "PASSED" } else { "FAILED" });
+    println!("Overall Trap type tests: {}", if all_passed { "PASSED" } else { "FAILED" });
+
+    all_passed
+}
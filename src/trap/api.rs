@@ -4,20 +4,26 @@
 //! interacting with the trap system.
 
 use crate::trap::ds::{
-    TrapType, TrapContext, TrapHandler, TrapHandlerResult, Interrupt, 
-    SystemError, ErrorResult, ErrorSource, ErrorLevel, ErrorCode,
+    TrapType, TrapContext, TrapHandler, TrapHandlerResult, Interrupt, TrapMode,
+    SystemError, ErrorResult, ErrorSource, ErrorLevel, ErrorCode, ErrorLogEntry,
+    InitPhase, InitPhaseError,
 };
+use crate::trap::ds::init_phase;
 use crate::trap::ds::handler::{ProtectionLevel, RegistrarId, SYSTEM_REGISTRAR_ID, generate_registrar_id};
 use crate::trap::infrastructure::di::context::ContextId;
+use crate::trap::infrastructure::di::RegisterError;
 use crate::trap::infrastructure::{
     SecurityError,             // 直接引用re-export的SecurityError
     register_handler_with_owner,  // 直接引用re-export的函数
     unregister_handler,           // 直接引用re-export的函数
     unregister_handler_secure,    // 直接引用re-export的函数  
+    set_handler_enabled,          // 直接引用re-export的函数
     unregister_handlers_for_context_secure  // 直接引用re-export的函数
 };
 use crate::println;
 
+pub use crate::trap::infrastructure::hooks::{PreTrapHook, PostTrapHook, HookId};
+
 /// Errors that can occur when interacting with the trap API
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TrapApiError {
@@ -43,6 +49,10 @@ pub enum TrapApiError {
     InvalidRegistrarId,
     /// System level operation not permitted
     SystemLevelRequired,
+    /// A required initialization phase has not completed yet
+    NotReady(InitPhaseError),
+    /// The requested trap mode is not supported by the hardware
+    UnsupportedTrapMode(TrapMode),
 }
 
 impl core::fmt::Display for TrapApiError {
@@ -59,10 +69,43 @@ impl core::fmt::Display for TrapApiError {
             Self::ProtectedHandler => write!(f, "Cannot modify protected handler"),
             Self::InvalidRegistrarId => write!(f, "Invalid registrar ID, not original owner"),
             Self::SystemLevelRequired => write!(f, "System level permission required"),
+            Self::NotReady(err) => write!(f, "{}", err),
+            Self::UnsupportedTrapMode(mode) => write!(f, "Trap mode {:?} not supported", mode),
+        }
+    }
+}
+
+impl From<InitPhaseError> for TrapApiError {
+    fn from(err: InitPhaseError) -> Self {
+        if err.current == InitPhase::Uninit {
+            Self::SystemNotInitialized
+        } else {
+            Self::NotReady(err)
+        }
+    }
+}
+
+impl From<RegisterError> for TrapApiError {
+    fn from(err: RegisterError) -> Self {
+        match err {
+            RegisterError::NotInitialized => Self::SystemNotInitialized,
+            RegisterError::StorageLocked => Self::StorageLocked,
+            RegisterError::StorageFull => Self::TooManyHandlers,
+            RegisterError::Rejected => Self::RegistrationFailed,
         }
     }
 }
 
+/// Get the initialization phase the trap system has completed
+pub fn init_phase() -> InitPhase {
+    init_phase::current_phase()
+}
+
+/// Check that the trap system has reached the given initialization phase
+fn require_phase(required: InitPhase) -> Result<(), TrapApiError> {
+    init_phase::require_phase(required).map_err(TrapApiError::from)
+}
+
 /// 获取当前模块的注册者ID
 /// 
 /// 每个模块在使用Trap API前应该获取一个唯一的注册者ID
@@ -75,8 +118,100 @@ pub fn get_registrar_id() -> RegistrarId {
     generate_registrar_id()
 }
 
+/// Builder for registering a trap handler
+///
+/// Collects the optional registration parameters by name instead of position.
+/// Defaults: priority 128, user protection, no associated context.
+#[derive(Clone, Copy)]
+#[must_use = "the handler is only registered by calling `register`"]
+pub struct HandlerBuilder {
+    trap_type: TrapType,
+    handler: TrapHandler,
+    priority: u8,
+    description: &'static str,
+    context_id: Option<ContextId>,
+    protection: ProtectionLevel,
+}
+
+impl HandlerBuilder {
+    /// Default priority of a handler registered through the builder
+    pub const DEFAULT_PRIORITY: u8 = 128;
+
+    /// Description used when none is given
+    pub const DEFAULT_DESCRIPTION: &'static str = "Unnamed Handler";
+
+    /// Start building a registration of `handler` for `trap_type`
+    pub const fn new(trap_type: TrapType, handler: TrapHandler) -> Self {
+        Self {
+            trap_type,
+            handler,
+            priority: Self::DEFAULT_PRIORITY,
+            description: Self::DEFAULT_DESCRIPTION,
+            context_id: None,
+            protection: ProtectionLevel::User,
+        }
+    }
+
+    /// Priority level (lower values mean higher priority)
+    pub const fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Static description of the handler, also used to unregister it
+    pub const fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    /// Associate the handler with a context, so it is removed with the context
+    pub const fn context(mut self, context_id: ContextId) -> Self {
+        self.context_id = Some(context_id);
+        self
+    }
+
+    /// Protection level deciding who may unregister the handler
+    pub const fn protection(mut self, protection: ProtectionLevel) -> Self {
+        self.protection = protection;
+        self
+    }
+
+    /// Register the handler on behalf of `registrar_id`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if registration was successful
+    /// * `Err(TrapApiError::StorageLocked)` if the registry stayed locked; retry later
+    /// * `Err(TrapApiError::TooManyHandlers)` if no slot is left for this trap type
+    /// * `Err(TrapApiError)` if the trap system is not ready
+    pub fn register(self, registrar_id: RegistrarId) -> Result<(), TrapApiError> {
+        // 检查系统是否初始化
+        require_phase(InitPhase::VectorReady)?;
+
+        register_handler_with_owner(
+            self.trap_type,
+            self.handler,
+            self.priority,
+            self.description,
+            self.protection,
+            registrar_id,
+            self.context_id
+        ).map_err(TrapApiError::from)
+    }
+}
+
+/// Apply an optional context ID to a builder
+fn with_optional_context(builder: HandlerBuilder, context_id: Option<ContextId>) -> HandlerBuilder {
+    match context_id {
+        Some(id) => builder.context(id),
+        None => builder,
+    }
+}
+
 /// Register a trap handler for a specific trap type with ownership tracking
 ///
+/// Equivalent to `HandlerBuilder` with user protection.
+///
 /// # Parameters
 ///
 /// * `trap_type` - The type of trap to handle
@@ -89,7 +224,7 @@ pub fn get_registrar_id() -> RegistrarId {
 /// # Returns
 ///
 /// * `Ok(())` if registration was successful
-/// * `Err(TrapApiError)` if registration failed
+/// * `Err(TrapApiError)` if registration failed, see [`HandlerBuilder::register`]
 pub fn register_trap_handler_secure(
     trap_type: TrapType,
     handler: TrapHandler,
@@ -98,25 +233,11 @@ pub fn register_trap_handler_secure(
     context_id: Option<ContextId>,
     registrar_id: RegistrarId
 ) -> Result<(), TrapApiError> {
-    // 检查系统是否初始化
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
-
-    // 使用正确导入的函数
-    if register_handler_with_owner(
-        trap_type,
-        handler,
-        priority,
-        description,
-        ProtectionLevel::User, // 用户级
-        registrar_id,          // 记录注册者
-        context_id
-    ) {
-        Ok(())
-    } else {
-        Err(TrapApiError::RegistrationFailed)
-    }
+    let builder = HandlerBuilder::new(trap_type, handler)
+        .priority(priority)
+        .description(description)
+        .protection(ProtectionLevel::User); // 用户级
+    with_optional_context(builder, context_id).register(registrar_id)
 }
 
 /// 保持原有的注册函数，但在内部设为系统级
@@ -127,25 +248,11 @@ pub fn register_trap_handler(
     description: &'static str,
     context_id: Option<ContextId>
 ) -> Result<(), TrapApiError> {
-    // 检查系统是否初始化
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
-
-    // 使用正确导入的函数
-    if register_handler_with_owner(
-        trap_type,
-        handler,
-        priority,
-        description,
-        ProtectionLevel::System, // 系统级
-        SYSTEM_REGISTRAR_ID,     // 系统注册者
-        context_id
-    ) {
-        Ok(())
-    } else {
-        Err(TrapApiError::RegistrationFailed)
-    }
+    let builder = HandlerBuilder::new(trap_type, handler)
+        .priority(priority)
+        .description(description)
+        .protection(ProtectionLevel::System); // 系统级
+    with_optional_context(builder, context_id).register(SYSTEM_REGISTRAR_ID)
 }
 
 /// Unregister a trap handler with ownership verification
@@ -166,9 +273,7 @@ pub fn unregister_trap_handler_secure(
     registrar_id: RegistrarId
 ) -> Result<(), TrapApiError> {
     // 检查系统是否初始化
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
+    require_phase(InitPhase::VectorReady)?;
 
     // 调用安全版解注册函数，使用正确的路径
     match unregister_handler_secure(
@@ -192,9 +297,7 @@ pub fn unregister_trap_handler(
     description: &'static str
 ) -> Result<(), TrapApiError> {
     // 检查系统是否初始化
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
+    require_phase(InitPhase::VectorReady)?;
 
     // 调用原有函数，使用正确的导入路径
     if unregister_handler(trap_type, description) {
@@ -204,6 +307,36 @@ pub fn unregister_trap_handler(
     }
 }
 
+/// Enable or disable a registered trap handler without unregistering it
+///
+/// A disabled handler keeps its slot and priority position but is skipped
+/// during dispatch until it is enabled again.
+///
+/// # Parameters
+///
+/// * `trap_type` - The type of trap the handler was registered for
+/// * `description` - The description used when registering the handler
+/// * `enabled` - Whether the handler should be dispatched to
+///
+/// # Returns
+///
+/// * `Ok(())` if the handler's state was updated
+/// * `Err(TrapApiError::HandlerNotFound)` if no such handler is registered
+pub fn set_trap_handler_enabled(
+    trap_type: TrapType,
+    description: &str,
+    enabled: bool
+) -> Result<(), TrapApiError> {
+    // 检查系统是否初始化
+    require_phase(InitPhase::VectorReady)?;
+
+    if set_handler_enabled(trap_type, description, enabled) {
+        Ok(())
+    } else {
+        Err(TrapApiError::HandlerNotFound)
+    }
+}
+
 /// Unregister all trap handlers associated with a specific context ID
 ///
 /// # Parameters
@@ -245,6 +378,83 @@ pub fn unregister_trap_handlers_for_context(context_id: ContextId) -> usize {
 }
 
 
+//
+// Trap Hooks
+//
+
+/// Register a hook that runs before every trap is dispatched
+///
+/// Hooks receive a read-only view of the trap context and cannot influence
+/// which handler runs.
+///
+/// # Returns
+///
+/// * `Ok(HookId)` identifying the hook for later unregistration
+/// * `Err(TrapApiError::TooManyHandlers)` if all hook slots are in use
+pub fn register_pre_hook(hook: PreTrapHook) -> Result<HookId, TrapApiError> {
+    crate::trap::infrastructure::hooks::register_pre_hook(hook)
+        .ok_or(TrapApiError::TooManyHandlers)
+}
+
+/// Register a hook that runs after every trap has been dispatched
+///
+/// The hook receives the dispatch result but cannot change it.
+///
+/// # Returns
+///
+/// * `Ok(HookId)` identifying the hook for later unregistration
+/// * `Err(TrapApiError::TooManyHandlers)` if all hook slots are in use
+pub fn register_post_hook(hook: PostTrapHook) -> Result<HookId, TrapApiError> {
+    crate::trap::infrastructure::hooks::register_post_hook(hook)
+        .ok_or(TrapApiError::TooManyHandlers)
+}
+
+/// Unregister a pre-dispatch hook
+pub fn unregister_pre_hook(id: HookId) -> Result<(), TrapApiError> {
+    if crate::trap::infrastructure::hooks::unregister_pre_hook(id) {
+        Ok(())
+    } else {
+        Err(TrapApiError::HandlerNotFound)
+    }
+}
+
+/// Unregister a post-dispatch hook
+pub fn unregister_post_hook(id: HookId) -> Result<(), TrapApiError> {
+    if crate::trap::infrastructure::hooks::unregister_post_hook(id) {
+        Ok(())
+    } else {
+        Err(TrapApiError::HandlerNotFound)
+    }
+}
+
+//
+// Trap Mode Functions
+//
+
+/// Switch the trap vector mode at runtime
+///
+/// Rewrites `stvec` with interrupts disabled, pointing it at the single trap
+/// entry in direct mode or at the vector table in vectored mode.
+///
+/// # Returns
+///
+/// * `Ok(())` if the new mode is active
+/// * `Err(TrapApiError::UnsupportedTrapMode)` if the hardware rejected the mode;
+///   the previous mode stays in effect
+pub fn set_trap_mode(mode: TrapMode) -> Result<(), TrapApiError> {
+    require_phase(InitPhase::VectorReady)?;
+
+    crate::trap::infrastructure::set_trap_mode(mode)
+        .map_err(TrapApiError::UnsupportedTrapMode)?;
+    println!("Trap mode switched to {:?}", mode);
+    Ok(())
+}
+
+/// Get the trap vector mode currently in use
+pub fn trap_mode() -> TrapMode {
+    crate::trap::infrastructure::trap_mode()
+}
+
 //
 // Interrupt Control Functions
 //
@@ -313,6 +523,37 @@ pub fn restore_interrupts(was_enabled: bool) {
     crate::trap::infrastructure::di::restore_interrupts(was_enabled)
 }
 
+/// Disables interrupts for as long as it is alive
+///
+/// The constructor disables interrupts and remembers whether they were enabled;
+/// dropping the guard restores that state, including on early return.
+/// Unlike `disable_interrupts`, the guard also works before the trap system is
+/// initialized, so infrastructure code can use it during boot.
+#[must_use = "interrupts are restored as soon as the guard is dropped"]
+pub struct IrqGuard {
+    was_enabled: bool,
+}
+
+impl IrqGuard {
+    /// Disable interrupts and capture the previous state
+    pub fn new() -> Self {
+        Self {
+            was_enabled: crate::trap::infrastructure::disable_interrupts(),
+        }
+    }
+
+    /// Whether interrupts were enabled when the guard was created
+    pub fn was_enabled(&self) -> bool {
+        self.was_enabled
+    }
+}
+
+impl Drop for IrqGuard {
+    fn drop(&mut self) {
+        crate::trap::infrastructure::restore_interrupts(self.was_enabled);
+    }
+}
+
 /// Enable a specific type of interrupt
 ///
 /// # Parameters
@@ -459,7 +700,12 @@ pub fn is_interrupt_pending(interrupt: Interrupt) -> bool {
 ///
 /// # Returns
 ///
-/// A new unique context ID
+/// A context ID not currently in use. IDs released by destroyed processes
+/// are handed out again before new ones.
+///
+/// # Panics
+///
+/// Panics if context IDs are exhausted.
 ///
 /// # Thread Safety
 ///
@@ -499,10 +745,8 @@ pub fn register_error_handler(
     source: Option<ErrorSource>,
     level: Option<ErrorLevel>
 ) -> Result<(), TrapApiError> {
-    // Check if trap system is initialized
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
+    // Check if the error handling system is initialized
+    require_phase(InitPhase::ErrorReady)?;
 
     // Call the internal function to register the error handler
     let result = crate::trap::infrastructure::di::register_error_handler(
@@ -516,6 +760,36 @@ pub fn register_error_handler(
     }
 }
 
+/// Register an error handler that also receives the faulting trap context
+///
+/// The handler only runs for errors raised through
+/// [`handle_system_error_with_context`], and may modify the context to
+/// recover, e.g. advance `sepc` past the faulting instruction.
+///
+/// # Returns
+///
+/// * `Ok(())` if registration was successful
+/// * `Err(TrapApiError)` if registration failed
+pub fn register_error_handler_with_context(
+    handler: crate::trap::ds::ErrorHandlerWithContext,
+    priority: u8,
+    description: &'static str,
+    source: Option<ErrorSource>,
+    level: Option<ErrorLevel>
+) -> Result<(), TrapApiError> {
+    require_phase(InitPhase::ErrorReady)?;
+
+    let result = crate::trap::infrastructure::di::register_error_handler_with_context(
+        handler, priority, description, source, level
+    );
+
+    if result {
+        Ok(())
+    } else {
+        Err(TrapApiError::RegistrationFailed)
+    }
+}
+
 /// Unregister an error handler
 ///
 /// # Parameters
@@ -531,10 +805,8 @@ pub fn register_error_handler(
 ///
 /// This function is safe to call from multiple threads.
 pub fn unregister_error_handler(description: &str) -> Result<(), TrapApiError> {
-    // Check if trap system is initialized
-    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
-        return Err(TrapApiError::SystemNotInitialized);
-    }
+    // Check if the error handling system is initialized
+    require_phase(InitPhase::ErrorReady)?;
 
     // Call the internal function to unregister the error handler
     let result = crate::trap::infrastructure::di::unregister_error_handler(description);
@@ -572,6 +844,19 @@ pub fn handle_system_error(error: SystemError) -> ErrorResult {
     crate::trap::infrastructure::di::handle_system_error(error)
 }
 
+/// Handle a system error raised while handling a trap
+///
+/// Like [`handle_system_error`], but handlers registered with
+/// [`register_error_handler_with_context`] also run and may modify `context`.
+/// Changes take effect when the trap returns.
+pub fn handle_system_error_with_context(error: SystemError, context: &mut TrapContext) -> ErrorResult {
+    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
+        return ErrorResult::Unhandled;
+    }
+
+    crate::trap::infrastructure::di::handle_system_error_with_context(error, context)
+}
+
 /// Create a new system error
 ///
 /// # Parameters
@@ -601,7 +886,7 @@ pub fn create_system_error(
         // Create error directly if system not initialized
         let error_code = ErrorCode::new(source, level, code);
         // Use current time or zero if not available
-        let time = crate::util::sbi::timer::get_time();
+        let time = crate::util::sbi::timer::now();
         return SystemError::new(error_code, address, ip, time);
     }
 
@@ -630,6 +915,24 @@ pub fn print_error_log(count: usize) {
     crate::trap::infrastructure::di::print_error_log(count)
 }
 
+/// Copy the most recent error records into a caller-provided buffer
+///
+/// Up to `out.len()` of the newest records are copied in chronological
+/// order (oldest first). Returns the number of records copied, which is 0
+/// if the trap system is not initialized.
+///
+/// # Thread Safety
+///
+/// This function is safe to call from any context.
+pub fn export_error_log(out: &mut [ErrorLogEntry]) -> usize {
+    // Check if trap system is initialized
+    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
+        return 0;
+    }
+
+    crate::trap::infrastructure::di::export_error_log(out)
+}
+
 /// Clear the error log
 ///
 /// # Thread Safety
@@ -645,6 +948,48 @@ pub fn clear_error_log() {
     crate::trap::infrastructure::di::clear_error_log()
 }
 
+/// Get the number of errors reported by `source` since boot
+///
+/// The count is kept separately from the error log and survives `clear_error_log`.
+/// Returns 0 if the trap system is not initialized.
+pub fn error_count(source: ErrorSource) -> u64 {
+    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
+        return 0;
+    }
+
+    crate::trap::infrastructure::di::error_count(source)
+}
+
+/// Print the number of errors reported by each source
+///
+/// # Thread Safety
+///
+/// This function is safe to call from any context but may produce interleaved
+/// output if called concurrently.
+pub fn print_error_summary() {
+    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
+        println!("Error summary not available: trap system not initialized");
+        return;
+    }
+
+    crate::trap::infrastructure::di::print_error_summary()
+}
+
+/// Escalate errors from a source that floods the error log
+///
+/// Once more than `threshold` errors from the same source are retained in the
+/// error log, further non-fatal errors from that source are handled as
+/// `ErrorLevel::Critical` and a warning is printed. `None` disables escalation,
+/// which is the default.
+pub fn set_error_escalation_threshold(threshold: Option<usize>) -> Result<(), TrapApiError> {
+    if !crate::trap::infrastructure::di::get_trap_system_initialized() {
+        return Err(TrapApiError::SystemNotInitialized);
+    }
+
+    crate::trap::infrastructure::di::set_error_escalation_threshold(threshold);
+    Ok(())
+}
+
 /// Print the registered error handlers
 ///
 /// # Thread Safety
@@ -697,4 +1042,217 @@ pub fn reset_panic_mode() {
 
     // Call the internal function to reset panic mode
     crate::trap::infrastructure::di::reset_panic_mode()
-}
\ No newline at end of file
+}
+
+//
+// Capacity Reporting
+//
+
+/// Slot usage of a single handler table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityUsage {
+    /// Total number of slots
+    pub total: usize,
+    /// Slots currently holding a handler
+    pub used: usize,
+    /// Slots still available
+    pub free: usize,
+}
+
+impl CapacityUsage {
+    fn new(total: usize, used: usize) -> Self {
+        Self { total, used, free: total.saturating_sub(used) }
+    }
+}
+
+/// Handler capacity of every table in the trap system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityReport {
+    /// DI handler storage, including the slots reserved for default handlers
+    pub handler_storage: CapacityUsage,
+    /// Per-type handler registry used by `register_trap_handler`
+    pub registry: CapacityUsage,
+    /// Error manager handlers
+    pub error_handlers: CapacityUsage,
+}
+
+impl CapacityReport {
+    /// Print the report in a table-like format
+    pub fn print(&self) {
+        println!("=== Trap Handler Capacity ===");
+        for (name, usage) in [
+            ("Handler storage", self.handler_storage),
+            ("Handler registry", self.registry),
+            ("Error handlers", self.error_handlers),
+        ] {
+            println!("{:<18} used {:>3} / {:>3}, free {:>3}", name, usage.used, usage.total, usage.free);
+        }
+        println!("=============================");
+    }
+}
+
+/// Report total, used and free handler slots for each trap subsystem
+///
+/// Error handlers can only be registered once the trap system is initialized,
+/// so their capacity is reported as zero before that.
+///
+/// # Thread Safety
+///
+/// Each table is sampled under its own lock, so the report is not an atomic
+/// snapshot if handlers are registered concurrently.
+pub fn capacity_report() -> CapacityReport {
+    let di = crate::trap::infrastructure::di::handler_storage_capacity();
+    let di_used = crate::trap::infrastructure::di::custom_handler_count();
+    let (registry_total, registry_used) = crate::trap::infrastructure::registry::capacity();
+    let (error_total, error_used) = if crate::trap::infrastructure::di::get_trap_system_initialized() {
+        crate::trap::infrastructure::di::error_handler_capacity()
+    } else {
+        (0, 0)
+    };
+
+    CapacityReport {
+        handler_storage: CapacityUsage::new(di, di_used),
+        registry: CapacityUsage::new(registry_total, registry_used),
+        error_handlers: CapacityUsage::new(error_total, error_used),
+    }
+}
+
+/// Print the handler capacity of each trap subsystem
+pub fn print_capacity_report() {
+    capacity_report().print();
+}
+
+/// Print all DI handlers sorted by how often they have been invoked
+pub fn print_handler_stats() {
+    crate::trap::infrastructure::di::print_handler_stats();
+}
+
+/// Number of per-type trap counters in a `StatsSnapshot`
+pub const TRAP_STATS_TYPES: usize = TrapType::SLOTS;
+
+/// Number of handler slots tracked in a `StatsSnapshot`
+pub const TRAP_STATS_HANDLERS: usize = crate::trap::infrastructure::di::handler_storage_capacity();
+
+/// Invocation count of a single DI handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerStat {
+    /// Trap type the handler is registered for
+    pub trap_type: TrapType,
+    /// Handler description, unique within the handler storage
+    pub description: &'static str,
+    /// Number of times the handler has been invoked
+    pub count: usize,
+}
+
+/// Trap occurrence and handler invocation counters at a point in time
+///
+/// The absolute counters are only cleared by `registry::reset_trap_stats` for
+/// test isolation; take a snapshot before and after a workload and `diff` them
+/// to profile that interval.
+#[derive(Debug, Clone, Copy)]
+pub struct StatsSnapshot {
+    /// Trap occurrences indexed by `TrapType as usize`
+    pub trap_counts: [usize; TRAP_STATS_TYPES],
+    /// Handler invocations indexed by handler storage slot
+    pub handlers: [Option<HandlerStat>; TRAP_STATS_HANDLERS],
+}
+
+impl StatsSnapshot {
+    /// Sample the current counters
+    pub fn capture() -> Self {
+        let mut handlers = [None; TRAP_STATS_HANDLERS];
+        for (stat, invocation) in handlers.iter_mut()
+            .zip(crate::trap::infrastructure::di::handler_invocations().iter())
+        {
+            *stat = invocation.map(|invocation| HandlerStat {
+                trap_type: invocation.trap_type,
+                description: invocation.description,
+                count: invocation.count,
+            });
+        }
+
+        Self {
+            trap_counts: crate::trap::infrastructure::di::trap_counts(),
+            handlers,
+        }
+    }
+
+    /// Compute the counter changes between this snapshot and a later one
+    ///
+    /// Handlers are matched by description. A handler registered after this
+    /// snapshot counts from zero; one unregistered before `later` is omitted.
+    pub fn diff(&self, later: &StatsSnapshot) -> StatsDelta {
+        let mut trap_counts = [0; TRAP_STATS_TYPES];
+        for (i, delta) in trap_counts.iter_mut().enumerate() {
+            *delta = later.trap_counts[i].saturating_sub(self.trap_counts[i]);
+        }
+
+        let mut handlers = [None; TRAP_STATS_HANDLERS];
+        for (delta, stat) in handlers.iter_mut().zip(later.handlers.iter()) {
+            *delta = stat.map(|stat| {
+                let before = self.handler_count(stat.description).unwrap_or(0);
+                HandlerStat { count: stat.count.saturating_sub(before), ..stat }
+            });
+        }
+
+        StatsDelta { trap_counts, handlers }
+    }
+
+    /// Invocation count of the handler with the given description
+    pub fn handler_count(&self, description: &str) -> Option<usize> {
+        self.handlers.iter()
+            .flatten()
+            .find(|stat| stat.description == description)
+            .map(|stat| stat.count)
+    }
+
+    /// Print the changes between this snapshot and a later one
+    pub fn print_delta(&self, later: &StatsSnapshot) {
+        self.diff(later).print_delta();
+    }
+}
+
+/// Counter changes between two `StatsSnapshot`s
+#[derive(Debug, Clone, Copy)]
+pub struct StatsDelta {
+    /// Trap occurrences during the interval, indexed by `TrapType as usize`
+    pub trap_counts: [usize; TRAP_STATS_TYPES],
+    /// Handler invocations during the interval, indexed by handler storage slot
+    pub handlers: [Option<HandlerStat>; TRAP_STATS_HANDLERS],
+}
+
+impl StatsDelta {
+    /// Number of traps of the given type during the interval
+    pub fn trap_count(&self, trap_type: TrapType) -> usize {
+        self.trap_counts[trap_type as usize]
+    }
+
+    /// Invocations of the handler with the given description during the interval
+    pub fn handler_count(&self, description: &str) -> Option<usize> {
+        self.handlers.iter()
+            .flatten()
+            .find(|stat| stat.description == description)
+            .map(|stat| stat.count)
+    }
+
+    /// Total number of traps during the interval
+    pub fn total_traps(&self) -> usize {
+        self.trap_counts.iter().sum()
+    }
+
+    /// Print the non-zero counters
+    pub fn print_delta(&self) {
+        println!("=== Trap Statistics Delta ===");
+        println!("Total traps: {}", self.total_traps());
+        for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
+            let count = self.trap_count(trap_type);
+            if count != 0 {
+                println!("  {}: {}", trap_type, count);
+            }
+        }
+        for stat in self.handlers.iter().flatten().filter(|stat| stat.count != 0) {
+            println!("  Handler '{}' ({:?}): {}", stat.description, stat.trap_type, stat.count);
+        }
+        println!("=============================");
+    }
+}
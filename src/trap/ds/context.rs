@@ -4,6 +4,62 @@
 
 use core::fmt;
 use super::types::TrapCause;
+use super::context_manager::ContextError;
+use crate::mm::layout;
+use crate::util::cpu;
+
+/// sstatus中的SPIE位，sret时会被复制到SIE
+const SSTATUS_SPIE: usize = 1 << 5;
+
+/// sstatus中的SPP位，为1表示trap来自S模式，sret返回S模式
+const SSTATUS_SPP: usize = 1 << 8;
+
+/// sstatus中的FS字段（第13-14位），记录浮点单元的状态
+const SSTATUS_FS_SHIFT: usize = 13;
+const SSTATUS_FS_MASK: usize = 0b11 << SSTATUS_FS_SHIFT;
+
+/// 上下文在trap栈帧中占用的字节数，与trap_entry.asm中的CONTEXT_SIZE一致
+///
+/// 按16字节对齐，保证进入Rust处理函数时栈指针满足ABI要求
+pub const TRAP_FRAME_SIZE: usize = 560;
+
+/// 浮点寄存器在上下文中的偏移，与trap_entry.asm中的FP_OFFSET一致
+pub const TRAP_FP_OFFSET: usize = 288;
+
+/// fcsr在上下文中的偏移，与trap_entry.asm中的FCSR_OFFSET一致
+pub const TRAP_FCSR_OFFSET: usize = 544;
+
+// 汇编代码按固定偏移访问上下文，布局变化时在编译期报错
+const _: () = assert!(core::mem::offset_of!(TrapContext, sstatus) == 256);
+const _: () = assert!(core::mem::offset_of!(TrapContext, f) == TRAP_FP_OFFSET);
+const _: () = assert!(core::mem::offset_of!(TrapContext, fcsr) == TRAP_FCSR_OFFSET);
+const _: () = assert!(core::mem::size_of::<TrapContext>() <= TRAP_FRAME_SIZE);
+const _: () = assert!(TRAP_FRAME_SIZE % 16 == 0);
+
+/// 浮点单元状态，对应sstatus.FS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpState {
+    /// 浮点单元关闭，浮点指令会触发非法指令异常
+    Off,
+    /// 初始状态
+    Initial,
+    /// 寄存器与上次保存的值一致
+    Clean,
+    /// 寄存器已被修改，需要保存
+    Dirty,
+}
+
+impl FpState {
+    /// 从sstatus解析浮点单元状态
+    pub const fn from_sstatus(sstatus: usize) -> Self {
+        match (sstatus & SSTATUS_FS_MASK) >> SSTATUS_FS_SHIFT {
+            0 => FpState::Off,
+            1 => FpState::Initial,
+            2 => FpState::Clean,
+            _ => FpState::Dirty,
+        }
+    }
+}
 
 /// 中断上下文结构体，与汇编代码中的布局对应
 #[repr(C)]
@@ -15,6 +71,9 @@ pub struct TrapContext {
     pub sepc: usize,
     pub scause: usize,
     pub stval: usize,
+    // 浮点寄存器，只在sstatus.FS为Dirty时保存
+    pub f: [u64; 32],
+    pub fcsr: usize,
 }
 
 impl TrapContext {
@@ -26,7 +85,55 @@ impl TrapContext {
             sepc: 0,
             scause: 0,
             stval: 0,
+            f: [0; 32],
+            fcsr: 0,
+        }
+    }
+
+    /// 被中断代码的浮点单元状态
+    pub fn fp_state(&self) -> FpState {
+        FpState::from_sstatus(self.sstatus)
+    }
+
+    /// 浮点寄存器是否需要保存和恢复
+    ///
+    /// 只有被中断的代码修改过浮点寄存器（FS为Dirty）时才需要，
+    /// 纯整数代码的trap不付出保存浮点寄存器的开销。没有F扩展时FS恒为Off。
+    pub fn needs_fp_save(&self) -> bool {
+        self.fp_state() == FpState::Dirty && cpu::isa_extensions().has_f()
+    }
+
+    /// 将当前的浮点寄存器保存到上下文
+    ///
+    /// `sstatus`必须已经保存，FS不为Dirty时什么也不做
+    pub fn save_fp_context(&mut self) {
+        if !self.needs_fp_save() {
+            return;
+        }
+
+        self.fcsr = unsafe { store_fp_registers(&mut self.f) };
+    }
+
+    /// 从上下文恢复浮点寄存器
+    ///
+    /// 与`save_fp_context`对应，FS不为Dirty时上下文中没有保存浮点寄存器，什么也不做
+    pub fn restore_fp_context(&self) {
+        if !self.needs_fp_save() {
+            return;
         }
+
+        unsafe { load_fp_registers(&self.f, self.fcsr) };
+    }
+
+    /// 设置trap返回后的浮点单元状态
+    pub fn set_fp_state(&mut self, state: FpState) {
+        let bits = match state {
+            FpState::Off => 0,
+            FpState::Initial => 1,
+            FpState::Clean => 2,
+            FpState::Dirty => 3,
+        };
+        self.sstatus = (self.sstatus & !SSTATUS_FS_MASK) | (bits << SSTATUS_FS_SHIFT);
     }
     
     /// 从上下文中获取异常原因
@@ -38,9 +145,244 @@ impl TrapContext {
     pub fn set_return_addr(&mut self, addr: usize) {
         self.sepc = addr;
     }
+
+    /// 获取第`n`个参数寄存器（a0–a7）的值
+    ///
+    /// `n`大于7时panic
+    pub fn arg(&self, n: usize) -> usize {
+        assert!(n < 8, "argument register a{} does not exist", n);
+        self.x[10 + n]
+    }
+
+    /// 设置返回值（写入a0）
+    pub fn set_return_value(&mut self, val: usize) {
+        self.x[10] = val;
+    }
+
+    /// 返回地址寄存器ra
+    pub fn ra(&self) -> usize {
+        self.x[1]
+    }
+
+    /// trap发生时的栈指针
+    pub fn sp(&self) -> usize {
+        self.x[2]
+    }
+
+    /// trap发生时的指令地址（sepc）
+    pub fn pc(&self) -> usize {
+        self.sepc
+    }
+
+    /// 让trap返回后保持中断关闭
+    ///
+    /// 清除保存的SPIE位，sret恢复时SIE就会保持为0
+    pub fn mask_interrupts_on_return(&mut self) {
+        self.sstatus &= !SSTATUS_SPIE;
+    }
+
+    /// trap返回后中断是否会被开启
+    pub fn interrupts_enabled_on_return(&self) -> bool {
+        self.sstatus & SSTATUS_SPIE != 0
+    }
+
+    /// sret是否会返回S模式
+    pub fn returns_to_supervisor(&self) -> bool {
+        self.sstatus & SSTATUS_SPP != 0
+    }
+
+    /// 检查上下文能否安全地用于trap返回
+    ///
+    /// * `sepc`不能为0，且必须按指令长度对齐（支持C扩展时为2字节，否则为4字节）
+    /// * SPP必须与`sepc`所在的位置一致：返回S模式时`sepc`应在内核代码段内，
+    ///   返回U模式时则不应指向内核代码段
+    ///
+    /// SPIE不做检查，`mask_interrupts_on_return`会合法地清除它。
+    pub fn validate_for_return(&self) -> Result<(), ContextError> {
+        if self.sepc == 0 {
+            return Err(ContextError::NullReturnAddress);
+        }
+
+        let alignment = if cpu::isa_extensions().has_c() { 2 } else { 4 };
+        if self.sepc % alignment != 0 {
+            return Err(ContextError::MisalignedReturnAddress(self.sepc));
+        }
+
+        let supervisor = self.returns_to_supervisor();
+        if supervisor != layout::is_kernel_text(self.sepc) {
+            return Err(ContextError::PrivilegeMismatch { supervisor, sepc: self.sepc });
+        }
+
+        Ok(())
+    }
+
+    /// 打印trap相关的CSR和全部通用寄存器
+    ///
+    /// 每行四个寄存器，以ABI名称标注，直接输出到控制台，不使用堆
+    pub fn dump(&self) {
+        crate::print!("{:?}", self);
+    }
+}
+
+/// 通用寄存器的ABI名称，以寄存器编号为下标
+pub const GPR_ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// 每行输出的通用寄存器数量
+const DUMP_REGS_PER_LINE: usize = 4;
+
+impl fmt::Debug for TrapContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  sstatus: {:#018x}  sepc:  {:#018x}", self.sstatus, self.sepc)?;
+        writeln!(f, "  scause:  {:#018x}  stval: {:#018x}", self.scause, self.stval)?;
+        for (line, regs) in self.x.chunks(DUMP_REGS_PER_LINE).enumerate() {
+            write!(f, " ")?;
+            for (column, value) in regs.iter().enumerate() {
+                let index = line * DUMP_REGS_PER_LINE + column;
+                write!(f, " {:>4}(x{:02}): {:#018x}", GPR_ABI_NAMES[index], index, value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// 把当前核心的浮点寄存器写入`f`，返回fcsr
+///
+/// # 安全性
+///
+/// 调用时浮点单元必须处于开启状态（sstatus.FS不为Off）
+unsafe fn store_fp_registers(f: &mut [u64; 32]) -> usize {
+    let fcsr;
+    core::arch::asm!(
+        "fsd f0, 0({0})",
+        "fsd f1, 8({0})",
+        "fsd f2, 16({0})",
+        "fsd f3, 24({0})",
+        "fsd f4, 32({0})",
+        "fsd f5, 40({0})",
+        "fsd f6, 48({0})",
+        "fsd f7, 56({0})",
+        "fsd f8, 64({0})",
+        "fsd f9, 72({0})",
+        "fsd f10, 80({0})",
+        "fsd f11, 88({0})",
+        "fsd f12, 96({0})",
+        "fsd f13, 104({0})",
+        "fsd f14, 112({0})",
+        "fsd f15, 120({0})",
+        "fsd f16, 128({0})",
+        "fsd f17, 136({0})",
+        "fsd f18, 144({0})",
+        "fsd f19, 152({0})",
+        "fsd f20, 160({0})",
+        "fsd f21, 168({0})",
+        "fsd f22, 176({0})",
+        "fsd f23, 184({0})",
+        "fsd f24, 192({0})",
+        "fsd f25, 200({0})",
+        "fsd f26, 208({0})",
+        "fsd f27, 216({0})",
+        "fsd f28, 224({0})",
+        "fsd f29, 232({0})",
+        "fsd f30, 240({0})",
+        "fsd f31, 248({0})",
+        "frcsr {1}",
+        in(reg) f.as_mut_ptr(),
+        out(reg) fcsr,
+        options(nostack)
+    );
+    fcsr
+}
+
+/// 从`f`载入当前核心的浮点寄存器并写入fcsr
+///
+/// # 安全性
+///
+/// 调用时浮点单元必须处于开启状态（sstatus.FS不为Off）
+unsafe fn load_fp_registers(f: &[u64; 32], fcsr: usize) {
+    core::arch::asm!(
+        "fld f0, 0({0})",
+        "fld f1, 8({0})",
+        "fld f2, 16({0})",
+        "fld f3, 24({0})",
+        "fld f4, 32({0})",
+        "fld f5, 40({0})",
+        "fld f6, 48({0})",
+        "fld f7, 56({0})",
+        "fld f8, 64({0})",
+        "fld f9, 72({0})",
+        "fld f10, 80({0})",
+        "fld f11, 88({0})",
+        "fld f12, 96({0})",
+        "fld f13, 104({0})",
+        "fld f14, 112({0})",
+        "fld f15, 120({0})",
+        "fld f16, 128({0})",
+        "fld f17, 136({0})",
+        "fld f18, 144({0})",
+        "fld f19, 152({0})",
+        "fld f20, 160({0})",
+        "fld f21, 168({0})",
+        "fld f22, 176({0})",
+        "fld f23, 184({0})",
+        "fld f24, 192({0})",
+        "fld f25, 200({0})",
+        "fld f26, 208({0})",
+        "fld f27, 216({0})",
+        "fld f28, 224({0})",
+        "fld f29, 232({0})",
+        "fld f30, 240({0})",
+        "fld f31, 248({0})",
+        "fscsr {1}",
+        in(reg) f.as_ptr(),
+        in(reg) fcsr,
+        options(nostack)
+    );
+}
+
+/// 保存在上下文之外的浮点寄存器组
+///
+/// 惰性切换浮点上下文时，浮点寄存器属于某个任务而不是某次trap，保存在这里
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FpRegisters {
+    pub f: [u64; 32],
+    pub fcsr: usize,
+}
+
+impl FpRegisters {
+    /// 全部为0的寄存器组，对应从未使用过浮点的任务
+    pub const fn new() -> Self {
+        Self { f: [0; 32], fcsr: 0 }
+    }
+
+    /// 把当前核心的浮点寄存器保存到这里
+    ///
+    /// # 安全性
+    ///
+    /// 调用时浮点单元必须处于开启状态（sstatus.FS不为Off）
+    pub unsafe fn save(&mut self) {
+        self.fcsr = store_fp_registers(&mut self.f);
+    }
+
+    /// 把这里的值载入当前核心的浮点寄存器
+    ///
+    /// # 安全性
+    ///
+    /// 调用时浮点单元必须处于开启状态（sstatus.FS不为Off）
+    pub unsafe fn restore(&self) {
+        load_fp_registers(&self.f, self.fcsr);
+    }
 }
 
 /// 任务上下文结构体
+///
+/// 只保存整数的callee-saved寄存器，浮点寄存器由`lazy_fp`按任务惰性切换
 #[repr(C)]
 #[derive(Clone)]
 pub struct TaskContext {
@@ -54,7 +396,7 @@ pub struct TaskContext {
 
 impl TaskContext {
     /// 创建一个新的空任务上下文
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
         Self {
             ra: 0,
             sp: 0,
@@ -4,9 +4,15 @@
 //! 并提供嵌套中断处理和上下文生命周期管理。
 
 use core::marker::PhantomData;
+use core::fmt;
 use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
 use crate::println;
 use super::context::{TrapContext, TaskContext};
+use super::init_phase::{InitPhase, InitPhaseError, advance_phase, require_phase};
+use crate::util::percpu::{PerCpu, this_hart};
+use crate::util::sbi::hart::MAX_HARTS;
+use crate::trap::infrastructure::di::context::ContextId;
 
 /// 上下文数据所有权标记，用于提供类型安全
 pub struct ContextOwnership<T>(PhantomData<T>);
@@ -24,6 +30,17 @@ pub enum ContextError {
     OutOfMemory,
     /// 操作不允许
     OperationNotAllowed,
+    /// 返回地址为0
+    NullReturnAddress,
+    /// 返回地址未按指令长度对齐
+    MisalignedReturnAddress(usize),
+    /// sstatus.SPP与返回地址所在的特权级不符
+    PrivilegeMismatch {
+        /// SPP是否为S模式
+        supervisor: bool,
+        /// 返回地址
+        sepc: usize,
+    },
 }
 
 /// 上下文类型枚举
@@ -36,20 +53,98 @@ pub enum ContextType {
 }
 
 /// 上下文状态枚举
-#[derive(Debug, Clone, Copy)]
+///
+/// 取值与进程控制块中保存的`state`相同，新建进程的状态0即`Active`
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ContextState {
     /// 活动状态
-    Active,
+    Active = 0,
     /// 挂起状态
-    Suspended,
+    Suspended = 1,
     /// 等待状态
-    Waiting,
+    Waiting = 2,
     /// 已完成状态
-    Terminated,
+    Terminated = 3,
+    /// 睡眠状态，定时器到期后恢复为活动状态
+    Sleeping = 4,
+}
+
+impl ContextState {
+    /// 由进程控制块中的状态值转换，无法识别的值返回None
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ContextState::Active),
+            1 => Some(ContextState::Suspended),
+            2 => Some(ContextState::Waiting),
+            3 => Some(ContextState::Terminated),
+            4 => Some(ContextState::Sleeping),
+            _ => None,
+        }
+    }
+}
+
+/// 按核心划分的中断嵌套计数
+///
+/// 每个核心只修改自己的计数，一个核心进入中断不会改变其他核心看到的嵌套层级。
+/// 不带`_on`后缀的方法作用于当前核心。
+pub struct NestCounter {
+    levels: PerCpu<AtomicUsize, MAX_HARTS>,
+}
+
+impl NestCounter {
+    /// 创建所有核心嵌套层级都为0的计数器
+    pub const fn new() -> Self {
+        Self {
+            levels: PerCpu::new([const { AtomicUsize::new(0) }; MAX_HARTS]),
+        }
+    }
+
+    /// 当前核心的嵌套层级
+    pub fn level(&self) -> usize {
+        self.level_on(this_hart())
+    }
+
+    /// 指定核心的嵌套层级
+    pub fn level_on(&self, hart: usize) -> usize {
+        self.levels.get_for(hart).load(Ordering::Relaxed)
+    }
+
+    /// 当前核心进入一层中断，超过`max_level`时返回`ContextError::StackOverflow`
+    pub fn enter(&self, max_level: usize) -> Result<usize, ContextError> {
+        self.enter_on(this_hart(), max_level)
+    }
+
+    /// 指定核心进入一层中断
+    pub fn enter_on(&self, hart: usize, max_level: usize) -> Result<usize, ContextError> {
+        let level = self.levels.get_for(hart);
+        let current = level.fetch_add(1, Ordering::SeqCst);
+        if current >= max_level {
+            // 回滚计数器
+            level.fetch_sub(1, Ordering::SeqCst);
+            return Err(ContextError::StackOverflow);
+        }
+        Ok(current + 1)
+    }
+
+    /// 当前核心退出一层中断，没有嵌套时返回`ContextError::StackUnderflow`
+    pub fn exit(&self) -> Result<usize, ContextError> {
+        self.exit_on(this_hart())
+    }
+
+    /// 指定核心退出一层中断
+    pub fn exit_on(&self, hart: usize) -> Result<usize, ContextError> {
+        let level = self.levels.get_for(hart);
+        if level.load(Ordering::Relaxed) == 0 {
+            return Err(ContextError::StackUnderflow);
+        }
+
+        Ok(level.fetch_sub(1, Ordering::SeqCst) - 1)
+    }
 }
 
 /// 中断嵌套计数器
-static INTERRUPT_NEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPT_NEST_COUNT: NestCounter = NestCounter::new();
 
 /// 上下文管理器
 /// 
@@ -61,6 +156,10 @@ pub struct ContextManager {
     interrupt_stack_top: usize,
     /// 最大允许的嵌套中断层级
     max_nest_level: usize,
+    /// 各核心正在运行的上下文
+    current_context: [Option<ContextId>; MAX_HARTS],
+    /// 各核心浮点寄存器中的值属于哪个上下文
+    fp_owner: [Option<ContextId>; MAX_HARTS],
 }
 
 impl ContextManager {
@@ -76,43 +175,69 @@ impl ContextManager {
             interrupt_stack: [0; Self::INTERRUPT_STACK_SIZE],
             interrupt_stack_top: 0,
             max_nest_level: Self::DEFAULT_MAX_NEST_LEVEL,
+            current_context: [None; MAX_HARTS],
+            fp_owner: [None; MAX_HARTS],
         }
     }
     
     /// 获取当前中断嵌套层级
     pub fn get_nest_level() -> usize {
-        INTERRUPT_NEST_COUNT.load(Ordering::Relaxed)
+        INTERRUPT_NEST_COUNT.level()
     }
     
     /// 增加中断嵌套层级
     fn enter_interrupt(&mut self) -> Result<usize, ContextError> {
-        let current = INTERRUPT_NEST_COUNT.fetch_add(1, Ordering::SeqCst);
-        if current >= self.max_nest_level {
-            // 回滚计数器
-            INTERRUPT_NEST_COUNT.fetch_sub(1, Ordering::SeqCst);
-            return Err(ContextError::StackOverflow);
-        }
-        Ok(current + 1)
+        INTERRUPT_NEST_COUNT.enter(self.max_nest_level)
     }
     
     /// 减少中断嵌套层级
     fn exit_interrupt(&mut self) -> Result<usize, ContextError> {
-        let current = INTERRUPT_NEST_COUNT.load(Ordering::Relaxed);
-        if current == 0 {
-            return Err(ContextError::StackUnderflow);
-        }
-        
-        Ok(INTERRUPT_NEST_COUNT.fetch_sub(1, Ordering::SeqCst) - 1)
+        INTERRUPT_NEST_COUNT.exit()
     }
     
     /// 设置最大嵌套层级
     pub fn set_max_nest_level(&mut self, level: usize) {
         self.max_nest_level = level;
     }
+
+    /// 获取最大嵌套层级
+    pub fn max_nest_level(&self) -> usize {
+        self.max_nest_level
+    }
     
+    /// 当前核心正在运行的上下文
+    pub fn current_context(&self) -> Option<ContextId> {
+        self.current_context[this_hart()]
+    }
+
+    /// 记录当前核心切换到了哪个上下文
+    pub fn set_current_context(&mut self, id: Option<ContextId>) {
+        self.current_context[this_hart()] = id;
+    }
+
+    /// 当前核心浮点寄存器的所有者
+    pub fn fp_owner(&self) -> Option<ContextId> {
+        self.fp_owner[this_hart()]
+    }
+
+    /// 设置当前核心浮点寄存器的所有者
+    pub fn set_fp_owner(&mut self, owner: Option<ContextId>) {
+        self.fp_owner[this_hart()] = owner;
+    }
+
+    /// 上下文销毁后，从所有核心的记录中移除它
+    pub fn forget_context(&mut self, id: ContextId) {
+        for slot in self.current_context.iter_mut().chain(self.fp_owner.iter_mut()) {
+            if *slot == Some(id) {
+                *slot = None;
+            }
+        }
+    }
+
     /// 为中断保存当前上下文
     /// 
-    /// 返回上下文指针和嵌套层级
+    /// 返回上下文指针和嵌套层级。嵌套过深时返回`ContextError::StackOverflow`，
+    /// 调用者应交给`trap::infrastructure::nest_overflow::handle_stack_overflow`处理。
     pub fn save_context_for_interrupt(&mut self) -> Result<(*mut TrapContext, usize), ContextError> {
         // 增加嵌套层级
         let level = self.enter_interrupt()?;
@@ -246,25 +371,54 @@ impl<'a> Drop for InterruptContextGuard<'a> {
     }
 }
 
+/// 访问全局上下文管理器失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextManagerAccessError {
+    /// 上下文管理器尚未初始化
+    NotReady(InitPhaseError),
+    /// 上下文管理器正在被使用（例如在闭包内再次访问）
+    Busy,
+}
+
+impl fmt::Display for ContextManagerAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextManagerAccessError::NotReady(err) => write!(f, "{}", err),
+            ContextManagerAccessError::Busy => write!(f, "Context manager is busy"),
+        }
+    }
+}
+
 /// 单例模式实现全局上下文管理器
-static mut GLOBAL_CONTEXT_MANAGER: Option<ContextManager> = None;
+///
+/// 管理器内含16KB的中断栈，直接在静态区常量初始化，
+/// 避免运行时在启动栈上构造临时对象；是否可用由初始化阶段决定。
+static GLOBAL_CONTEXT_MANAGER: Mutex<ContextManager> = Mutex::new(ContextManager::new());
 
 /// 全局接口函数
 
 /// 初始化全局上下文管理器
 pub fn init_global_context_manager() {
-    unsafe {
-        GLOBAL_CONTEXT_MANAGER = Some(ContextManager::new());
-    }
+    GLOBAL_CONTEXT_MANAGER.lock().set_max_nest_level(ContextManager::DEFAULT_MAX_NEST_LEVEL);
+    advance_phase(InitPhase::ContextReady);
     
     println!("Global context manager initialized");
 }
 
-/// 获取全局上下文管理器引用
-pub fn get_context_manager() -> &'static mut ContextManager {
-    unsafe {
-        GLOBAL_CONTEXT_MANAGER.as_mut().expect("Context manager not initialized")
-    }
+/// 在持有全局上下文管理器的情况下执行闭包
+///
+/// 上下文管理器初始化之前调用返回`NotReady`；
+/// 管理器已被占用时（例如在闭包内再次调用）返回`Busy`而不是死锁。
+pub fn with_context_manager<F, R>(f: F) -> Result<R, ContextManagerAccessError>
+where
+    F: FnOnce(&mut ContextManager) -> R,
+{
+    require_phase(InitPhase::ContextReady).map_err(ContextManagerAccessError::NotReady)?;
+
+    let mut guard = GLOBAL_CONTEXT_MANAGER
+        .try_lock()
+        .ok_or(ContextManagerAccessError::Busy)?;
+    Ok(f(&mut guard))
 }
 
 /// 是否在中断上下文中
@@ -4,7 +4,8 @@
 //! 设计为不依赖堆内存分配器。
 
 use core::fmt;
-use core::sync::atomic::{AtomicUsize, Ordering, AtomicBool}; // 添加AtomicBool的导入
+use core::sync::atomic::{AtomicUsize, AtomicU64, Ordering, AtomicBool}; // 添加AtomicBool的导入
+use super::context::TrapContext;
 
 
 /// 错误级别枚举
@@ -51,6 +52,26 @@ pub enum ErrorSource {
     Scheduler = 10,
 }
 
+impl ErrorSource {
+    /// 错误源的数量
+    pub const COUNT: usize = 11;
+
+    /// 所有错误源，按编号排列
+    pub const ALL: [ErrorSource; Self::COUNT] = [
+        ErrorSource::Unknown,
+        ErrorSource::Interrupt,
+        ErrorSource::Memory,
+        ErrorSource::Process,
+        ErrorSource::FileSystem,
+        ErrorSource::Device,
+        ErrorSource::Network,
+        ErrorSource::Syscall,
+        ErrorSource::Power,
+        ErrorSource::Synchronization,
+        ErrorSource::Scheduler,
+    ];
+}
+
 /// 记录错误码
 /// 
 /// 采用32位整数:
@@ -179,6 +200,12 @@ impl SystemError {
     pub fn timestamp(&self) -> u64 {
         self.timestamp
     }
+
+    /// 返回错误源和错误编号不变、级别改为`level`的错误
+    pub fn with_level(self, level: ErrorLevel) -> Self {
+        let code = ErrorCode::new(self.code.source(), level, self.code.code());
+        Self { code, ..self }
+    }
 }
 
 impl fmt::Display for SystemError {
@@ -207,11 +234,25 @@ pub enum ErrorResult {
 /// 错误处理器函数类型
 pub type ErrorHandler = fn(&SystemError) -> ErrorResult;
 
+/// 接收trap上下文的错误处理器函数类型
+///
+/// 用于需要修改出错上下文来恢复的处理器，例如映射页面后调整返回地址重试
+pub type ErrorHandlerWithContext = fn(&SystemError, &mut TrapContext) -> ErrorResult;
+
+/// 错误处理器函数
+#[derive(Copy, Clone)]
+pub enum ErrorHandlerFn {
+    /// 只接收错误信息的处理器
+    Plain(ErrorHandler),
+    /// 同时接收trap上下文的处理器，只在错误来自trap时调用
+    WithContext(ErrorHandlerWithContext),
+}
+
 /// 错误处理器注册信息
 #[derive(Copy, Clone)]
 pub struct ErrorHandlerEntry {
     /// 处理器函数
-    pub handler: ErrorHandler,
+    pub handler: ErrorHandlerFn,
     /// 处理器优先级，数字越小优先级越高
     pub priority: u8,
     /// 处理器描述
@@ -232,7 +273,24 @@ impl ErrorHandlerEntry {
         level: Option<ErrorLevel>,
     ) -> Self {
         Self {
-            handler,
+            handler: ErrorHandlerFn::Plain(handler),
+            priority,
+            description,
+            source,
+            level,
+        }
+    }
+
+    /// 创建接收trap上下文的错误处理器入口
+    pub const fn with_context(
+        handler: ErrorHandlerWithContext,
+        priority: u8,
+        description: &'static str,
+        source: Option<ErrorSource>,
+        level: Option<ErrorLevel>,
+    ) -> Self {
+        Self {
+            handler: ErrorHandlerFn::WithContext(handler),
             priority,
             description,
             source,
@@ -271,6 +329,21 @@ impl fmt::Debug for ErrorHandlerEntry {
     }
 }
 
+/// 处理器过滤条件的显示包装
+///
+/// `Some`按`Debug`格式输出，`None`输出"Any"，不需要构造`String`
+#[derive(Copy, Clone)]
+pub struct FilterDisplay<T>(pub Option<T>);
+
+impl<T: fmt::Debug> fmt::Display for FilterDisplay<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(value) => write!(f, "{:?}", value),
+            None => f.write_str("Any"),
+        }
+    }
+}
+
 /// 错误记录项
 #[derive(Copy, Clone)]
 pub struct ErrorLogEntry {
@@ -280,9 +353,22 @@ pub struct ErrorLogEntry {
     pub handled: bool,
     /// 处理结果
     pub result: ErrorResult,
+    /// 连续发生的次数，重复的错误合并到同一条记录中
+    pub repeat_count: usize,
+}
+
+impl ErrorLogEntry {
+    /// 是否与记录中的错误相同（错误码和地址都相同）
+    fn is_repeat_of(&self, error: &SystemError) -> bool {
+        self.error.code() == error.code() && self.error.address() == error.address()
+    }
 }
 
 /// 固定大小的错误日志
+///
+/// 与最近一条记录的错误码和地址都相同的错误不会占用新的记录，
+/// 只增加那条记录的`repeat_count`，避免同一个错误反复触发时（例如页错误风暴）
+/// 冲掉之前的记录。
 pub struct ErrorLog {
     /// 错误记录数组
     entries: [Option<ErrorLogEntry>; Self::MAX_ENTRIES],
@@ -307,12 +393,25 @@ impl ErrorLog {
     }
     
     /// 记录一个新错误
+    ///
+    /// 与最近一条记录重复时只增加其重复次数，并更新为最新的处理结果
     pub fn log(&mut self, error: SystemError, handled: bool, result: ErrorResult) {
+        let latest = (self.current + Self::MAX_ENTRIES - 1) % Self::MAX_ENTRIES;
+        if let Some(entry) = self.entries[latest].as_mut() {
+            if entry.is_repeat_of(&error) {
+                entry.repeat_count += 1;
+                entry.handled = handled;
+                entry.result = result;
+                return;
+            }
+        }
+
         // 创建记录
         let entry = ErrorLogEntry {
             error,
             handled,
             result,
+            repeat_count: 1,
         };
         
         // 更新索引，采用循环缓冲方式
@@ -326,33 +425,59 @@ impl ErrorLog {
         self.count.fetch_add(1, Ordering::Relaxed);
     }
     
-    /// 获取记录总数
+    /// 获取记录总数，合并的重复错误只算一条
     pub fn count(&self) -> usize {
         self.count.load(Ordering::Relaxed)
     }
     
-    /// 获取指定索引的记录
+    /// 获取仍保留在日志中的记录数，最多为`MAX_ENTRIES`
+    pub fn len(&self) -> usize {
+        self.count().min(Self::MAX_ENTRIES)
+    }
+
+    /// 日志中是否没有记录
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 获取指定索引的记录，索引0是保留的记录中最旧的一条
     pub fn get(&self, index: usize) -> Option<ErrorLogEntry> {
-        if index >= Self::MAX_ENTRIES {
+        let len = self.len();
+        if index >= len {
             return None;
         }
-        
-        // 计算实际索引，考虑循环缓冲
-        let count = self.count();
-        if count <= Self::MAX_ENTRIES {
-            // 未填满，直接使用索引
-            if index < count {
-                return self.entries[index];
+
+        // current指向下一条记录的写入位置，最旧的记录在它之前len个槽位处。
+        // len不超过MAX_ENTRIES，先加MAX_ENTRIES再减不会下溢
+        let oldest = (self.current + Self::MAX_ENTRIES - len) % Self::MAX_ENTRIES;
+        self.entries[(oldest + index) % Self::MAX_ENTRIES]
+    }
+    
+    /// 把最近的记录按从旧到新的顺序复制到`out`，返回复制的条数
+    ///
+    /// 记录多于`out.len()`时只复制最新的`out.len()`条
+    pub fn snapshot(&self, out: &mut [ErrorLogEntry]) -> usize {
+        let len = self.len();
+        let copied = out.len().min(len);
+        let first = len - copied;
+        for (i, slot) in out[..copied].iter_mut().enumerate() {
+            if let Some(entry) = self.get(first + i) {
+                *slot = entry;
             }
-        } else {
-            // 已填满，需要考虑当前位置
-            let actual_index = (self.current + index) % Self::MAX_ENTRIES;
-            return self.entries[actual_index];
         }
-        
-        None
+        copied
     }
-    
+
+    /// 统计仍保留在日志中的、来自指定错误源的错误数，合并的重复错误按次数计算
+    pub fn count_from(&self, source: ErrorSource) -> usize {
+        self.entries
+            .iter()
+            .flatten()
+            .filter(|entry| entry.error.code().source() == source)
+            .map(|entry| entry.repeat_count)
+            .sum()
+    }
+
     /// 清空日志
     pub fn clear(&mut self) {
         for i in 0..Self::MAX_ENTRIES {
@@ -362,10 +487,31 @@ impl ErrorLog {
         self.count.store(0, Ordering::Relaxed);
     }
     
+    /// 按从旧到新的顺序遍历最近的n条记录
+    ///
+    /// 回调参数依次为记录的序号（从1开始，包括已被覆盖的记录）和记录本身
+    pub fn for_each_recent<F>(&self, n: usize, mut f: F)
+    where
+        F: FnMut(usize, &ErrorLogEntry),
+    {
+        let total = self.count();
+        let len = self.len();
+        let to_print = n.min(len);
+
+        // to_print不超过len，len不超过total，两处减法都不会下溢
+        let first = len - to_print;
+        let first_seq = total - to_print + 1;
+        for i in 0..to_print {
+            if let Some(entry) = self.get(first + i) {
+                f(first_seq + i, &entry);
+            }
+        }
+    }
+
     /// 打印最近的n条记录
     pub fn print_recent(&self, n: usize) {
         let total = self.count();
-        let to_print = if total < n { total } else { n };
+        let to_print = n.min(self.len());
         
         if to_print == 0 {
             crate::println!("No error records found.");
@@ -374,38 +520,25 @@ impl ErrorLog {
         
         crate::println!("Recent {} error(s) of total {}:", to_print, total);
         
-        // 打印最近的n条记录
-        let start_idx = if total <= Self::MAX_ENTRIES {
-            // 未填满，从0开始
-            if to_print > total {
-                0
-            } else {
-                total - to_print
-            }
-        } else {
-            // 已填满，需要考虑循环
-            let current = self.current;
-            if to_print >= Self::MAX_ENTRIES {
-                // 打印所有可见记录
-                0
+        self.for_each_recent(n, |seq, entry| {
+            let status = if entry.handled { "Handled" } else { "Unhandled" };
+            if entry.repeat_count > 1 {
+                crate::println!("[{}] {}: {} - {:?} (x{})",
+                    seq,
+                    entry.error,
+                    status,
+                    entry.result,
+                    entry.repeat_count
+                );
             } else {
-                // 计算起始索引，确保打印最近的n条
-                (current + Self::MAX_ENTRIES - to_print) % Self::MAX_ENTRIES
-            }
-        };
-        
-        for i in 0..to_print {
-            let idx = (start_idx + i) % Self::MAX_ENTRIES;
-            if let Some(entry) = self.entries[idx] {
-                let status = if entry.handled { "Handled" } else { "Unhandled" };
                 crate::println!("[{}] {}: {} - {:?}", 
-                    total - to_print + i + 1,
+                    seq,
                     entry.error,
                     status,
                     entry.result
                 );
             }
-        }
+        });
     }
 }
 
@@ -422,9 +555,19 @@ pub struct ErrorManager {
     log: ErrorLog,
     /// 恐慌模式标志
     panic_mode: AtomicBool,
+    /// 每个错误源累计发生的错误数，按`ErrorSource`编号索引
+    source_counts: [AtomicU64; ErrorSource::COUNT],
+    /// 升级阈值，0表示不升级
+    escalation_threshold: AtomicUsize,
 }
 
 impl ErrorManager {
+    /// 最大处理器数量
+    pub const MAX_HANDLERS: usize = MAX_ERROR_HANDLERS;
+
+    /// 发生致命错误时输出的错误日志条数
+    pub const FATAL_LOG_ENTRIES: usize = 5;
+
     /// 创建新的错误处理管理器
     pub const fn new() -> Self {
         const NONE_HANDLER: Option<ErrorHandlerEntry> = None;
@@ -433,6 +576,8 @@ impl ErrorManager {
             handler_count: 0,
             log: ErrorLog::new(),
             panic_mode: AtomicBool::new(false),
+            source_counts: [const { AtomicU64::new(0) }; ErrorSource::COUNT],
+            escalation_threshold: AtomicUsize::new(0),
         }
     }
     
@@ -502,7 +647,24 @@ impl ErrorManager {
     }
     
     /// 处理错误
+    ///
+    /// 没有trap上下文可用，接收上下文的处理器会被跳过
     pub fn handle_error(&mut self, error: SystemError) -> ErrorResult {
+        self.dispatch_error(error, None)
+    }
+
+    /// 处理来自trap的错误
+    ///
+    /// 接收上下文的处理器可以修改`context`，修改在trap返回时生效
+    pub fn handle_error_with_context(&mut self, error: SystemError, context: &mut TrapContext) -> ErrorResult {
+        self.dispatch_error(error, Some(context))
+    }
+
+    /// 把错误分发给匹配的处理器
+    fn dispatch_error(&mut self, error: SystemError, mut context: Option<&mut TrapContext>) -> ErrorResult {
+        self.source_counts[error.code().source() as usize].fetch_add(1, Ordering::Relaxed);
+        let error = self.escalate(error);
+
         // 如果在恐慌模式，直接返回
         if self.panic_mode.load(Ordering::Relaxed) {
             // 仍然记录，但不尝试处理
@@ -523,7 +685,13 @@ impl ErrorManager {
         for i in 0..self.handler_count {
             if let Some(h) = &self.handlers[i] {
                 if h.matches(&error) {
-                    match (h.handler)(&error) {
+                    let result = match (h.handler, context.as_deref_mut()) {
+                        (ErrorHandlerFn::Plain(handler), _) => handler(&error),
+                        (ErrorHandlerFn::WithContext(handler), Some(ctx)) => handler(&error, ctx),
+                        // 没有上下文时无法调用
+                        (ErrorHandlerFn::WithContext(_), None) => continue,
+                    };
+                    match result {
                         ErrorResult::Handled => {
                             // 已处理，可以停止
                             handled = true;
@@ -549,6 +717,11 @@ impl ErrorManager {
         
         // 记录错误
         self.log.log(error, handled, final_result);
+
+        // 致命错误输出最近的错误日志，处理器在持有错误管理器时无法读取它
+        if error.code().is_fatal() {
+            self.log.print_recent(Self::FATAL_LOG_ENTRIES);
+        }
         
         // 如果是致命错误且未处理，必须终止系统
         if error.code().is_fatal() && !handled {
@@ -569,6 +742,58 @@ impl ErrorManager {
         final_result
     }
     
+    /// 错误源频繁出错时把错误升级为严重错误
+    ///
+    /// 日志中保留的来自同一错误源的错误（包括这一个）超过阈值时，
+    /// 比严重错误轻的错误按`ErrorLevel::Critical`分发和记录，致命错误保持不变。
+    fn escalate(&self, error: SystemError) -> SystemError {
+        let threshold = self.escalation_threshold.load(Ordering::Relaxed);
+        let source = error.code().source();
+        if threshold == 0 || error.code().level() <= ErrorLevel::Critical ||
+            self.log.count_from(source) < threshold {
+            return error;
+        }
+
+        crate::try_println!("Warning: more than {} {:?} errors in the error log, escalating {} to Critical",
+                            threshold, source, error.code());
+        error.with_level(ErrorLevel::Critical)
+    }
+
+    /// 设置升级阈值，`None`表示不升级
+    ///
+    /// 日志中保留的同一错误源的错误超过`threshold`个后，该错误源之后的错误按严重错误处理
+    pub fn set_escalation_threshold(&self, threshold: Option<usize>) {
+        self.escalation_threshold.store(threshold.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// 获取升级阈值
+    pub fn escalation_threshold(&self) -> Option<usize> {
+        match self.escalation_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            threshold => Some(threshold),
+        }
+    }
+
+    /// 获取指定错误源累计发生的错误数，清空日志不影响该计数
+    pub fn error_count(&self, source: ErrorSource) -> u64 {
+        self.source_counts[source as usize].load(Ordering::Relaxed)
+    }
+
+    /// 打印每个错误源累计发生的错误数，跳过没有错误的错误源
+    pub fn print_error_summary(&self) {
+        crate::println!("=== Error Summary ===");
+        let mut total = 0;
+        for source in ErrorSource::ALL {
+            let count = self.error_count(source);
+            if count > 0 {
+                crate::println!("{:?}: {} (in log: {})", source, count, self.log.count_from(source));
+                total += count;
+            }
+        }
+        crate::println!("Total: {}", total);
+        crate::println!("=====================");
+    }
+
     /// 检查是否处于恐慌模式
     pub fn is_panic_mode(&self) -> bool {
         self.panic_mode.load(Ordering::Relaxed)
@@ -589,16 +814,25 @@ impl ErrorManager {
         &mut self.log
     }
     
+    /// 获取已注册的处理器数量
+    pub fn handler_count(&self) -> usize {
+        self.handler_count
+    }
+
+    /// 获取可注册的处理器总数
+    pub const fn handler_capacity(&self) -> usize {
+        MAX_ERROR_HANDLERS
+    }
+
     /// 打印所有注册的处理器
     pub fn print_handlers(&self) {
         crate::println!("=== Registered Error Handlers ({}) ===", self.handler_count);
         for i in 0..self.handler_count {
             if let Some(h) = &self.handlers[i] {
                 // 不使用format!宏，直接打印
-                crate::println!("{}. {} (Priority: {}, Source: {:?}, Level: {:?})",
-                    i + 1, h.description, h.priority, 
-                    h.source.unwrap_or(ErrorSource::Unknown), 
-                    h.level.unwrap_or(ErrorLevel::Error));
+                crate::println!("{}. {} (Priority: {}, Source: {}, Level: {})",
+                    i + 1, h.description, h.priority,
+                    FilterDisplay(h.source), FilterDisplay(h.level));
             }
         }
         crate::println!("===================================");
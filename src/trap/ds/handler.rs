@@ -32,6 +32,8 @@ pub fn generate_registrar_id() -> RegistrarId {
 pub enum TrapHandlerResult {
     /// 已处理
     Handled,
+    /// 已处理，并要求trap返回后保持中断关闭
+    HandledAndMaskInterrupts,
     /// 需要传递给下一个处理器
     Pass,
     /// 中断处理失败
@@ -52,6 +54,11 @@ pub enum TrapError {
 /// 中断处理器函数类型
 pub type TrapHandler = fn(&mut TrapContext) -> TrapHandlerResult;
 
+/// 携带用户数据的中断处理器函数类型
+///
+/// 第二个参数是注册时传入的数据指针，原样传回给处理器
+pub type TrapHandlerWithData = fn(&mut TrapContext, *mut ()) -> TrapHandlerResult;
+
 /// 中断处理器注册信息
 #[derive(Copy, Clone)]
 pub struct HandlerEntry {
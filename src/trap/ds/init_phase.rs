@@ -0,0 +1,85 @@
+//! 初始化阶段管理
+//!
+//! `trap::init`按固定顺序初始化各个子系统。本模块记录当前完成到的阶段，
+//! 公共接口在访问依赖某一阶段的全局状态前先检查阶段，
+//! 未就绪时返回明确的错误而不是panic。
+
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// 初始化阶段，按完成顺序排列
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InitPhase {
+    /// 尚未初始化
+    Uninit = 0,
+    /// 中断向量和DI trap系统已就绪
+    VectorReady = 1,
+    /// 全局上下文管理器已就绪
+    ContextReady = 2,
+    /// 错误处理系统已就绪
+    ErrorReady = 3,
+    /// 全部初始化完成
+    Complete = 4,
+}
+
+impl InitPhase {
+    /// 从原始值转换
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => InitPhase::Uninit,
+            1 => InitPhase::VectorReady,
+            2 => InitPhase::ContextReady,
+            3 => InitPhase::ErrorReady,
+            _ => InitPhase::Complete,
+        }
+    }
+}
+
+/// 初始化阶段未满足的错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitPhaseError {
+    /// 需要的阶段
+    pub required: InitPhase,
+    /// 当前所处的阶段
+    pub current: InitPhase,
+}
+
+impl fmt::Display for InitPhaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Trap system initialization phase {:?} required, current phase is {:?}",
+               self.required, self.current)
+    }
+}
+
+/// 当前完成的初始化阶段
+static INIT_PHASE: AtomicU8 = AtomicU8::new(InitPhase::Uninit as u8);
+
+/// 获取当前完成的初始化阶段
+pub fn current_phase() -> InitPhase {
+    InitPhase::from_u8(INIT_PHASE.load(Ordering::SeqCst))
+}
+
+/// 标记某个阶段已完成
+///
+/// 阶段只会前进，不会因为重复初始化而回退
+pub fn advance_phase(phase: InitPhase) {
+    INIT_PHASE.fetch_max(phase as u8, Ordering::SeqCst);
+}
+
+/// 检查是否已经完成所需的阶段
+pub fn require_phase(required: InitPhase) -> Result<(), InitPhaseError> {
+    let current = current_phase();
+    if current >= required {
+        Ok(())
+    } else {
+        Err(InitPhaseError { required, current })
+    }
+}
+
+/// 强制设置当前阶段，返回之前的阶段
+///
+/// 仅供测试模拟初始化过程中的状态，调用后必须恢复原阶段
+pub(crate) fn override_phase(phase: InitPhase) -> InitPhase {
+    InitPhase::from_u8(INIT_PHASE.swap(phase as u8, Ordering::SeqCst))
+}
@@ -8,17 +8,19 @@ pub mod types;
 pub mod handler;
 pub mod context_manager;  // 新增上下文管理器模块
 pub mod error;  // 添加错误处理数据结构模块
+pub mod init_phase;  // 初始化阶段管理
 
 // 从子模块重新导出所有公共类型，方便使用
-pub use context::{TrapContext, TaskContext};
+pub use context::{TrapContext, TaskContext, FpState, FpRegisters, TRAP_FRAME_SIZE, GPR_ABI_NAMES};
 pub use types::{TrapMode, Interrupt, Exception, TrapType, TrapCause};
 pub use handler::{TrapHandler, TrapHandlerResult, TrapError, HandlerEntry};
 pub use context_manager::{
-    ContextManager, ContextError, ContextType, ContextState,
+    ContextManager, ContextError, ContextType, ContextState, NestCounter,
     InterruptContextGuard, is_in_interrupt_context, get_interrupt_nest_level,
-    init_global_context_manager, get_context_manager,
+    init_global_context_manager, with_context_manager, ContextManagerAccessError,
 };
 pub use error::{  // 导出错误处理类型
-    SystemError, ErrorResult, ErrorHandler, ErrorHandlerEntry,
-    ErrorSource, ErrorLevel, ErrorCode, ErrorLog, ErrorManager
-};
\ No newline at end of file
+    SystemError, ErrorResult, ErrorHandler, ErrorHandlerWithContext, ErrorHandlerEntry,
+    ErrorSource, ErrorLevel, ErrorCode, ErrorLog, ErrorLogEntry, ErrorManager
+};
+pub use init_phase::{InitPhase, InitPhaseError};
\ No newline at end of file
@@ -5,7 +5,7 @@
 use core::fmt;
 
 /// Trap mode enum
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TrapMode {
     /// Direct mode - all traps use the same handler function
     Direct = 0,
@@ -13,16 +13,53 @@ pub enum TrapMode {
     Vectored = 1,
 }
 
+impl TrapMode {
+    /// Decode the mode from the low 2 bits of stvec
+    ///
+    /// Returns None for the reserved encodings
+    pub const fn from_stvec(stvec: usize) -> Option<Self> {
+        match stvec & 0x3 {
+            0 => Some(TrapMode::Direct),
+            1 => Some(TrapMode::Vectored),
+            _ => None,
+        }
+    }
+}
+
 /// Interrupt type enum - only includes interrupts available in S mode
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Interrupt {
     SupervisorSoft = 1,
     SupervisorTimer = 5,
     SupervisorExternal = 9,
 }
 
+impl Interrupt {
+    /// All supervisor interrupts
+    pub const ALL: [Interrupt; 3] = [
+        Interrupt::SupervisorSoft,
+        Interrupt::SupervisorTimer,
+        Interrupt::SupervisorExternal,
+    ];
+
+    /// Get the scause code of this interrupt
+    pub const fn code(self) -> usize {
+        self as usize
+    }
+
+    /// Recover the interrupt from its scause code
+    pub const fn from_code(code: usize) -> Option<Self> {
+        match code {
+            1 => Some(Interrupt::SupervisorSoft),
+            5 => Some(Interrupt::SupervisorTimer),
+            9 => Some(Interrupt::SupervisorExternal),
+            _ => None,
+        }
+    }
+}
+
 /// Exception type enum
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Exception {
     InstructionMisaligned = 0,
     InstructionFault = 1,
@@ -39,6 +76,33 @@ pub enum Exception {
     StorePageFault = 15,
 }
 
+impl Exception {
+    /// Get the scause code of this exception
+    pub const fn code(self) -> usize {
+        self as usize
+    }
+
+    /// Recover the exception from its scause code
+    pub const fn from_code(code: usize) -> Option<Self> {
+        match code {
+            0 => Some(Exception::InstructionMisaligned),
+            1 => Some(Exception::InstructionFault),
+            2 => Some(Exception::IllegalInstruction),
+            3 => Some(Exception::Breakpoint),
+            4 => Some(Exception::LoadMisaligned),
+            5 => Some(Exception::LoadFault),
+            6 => Some(Exception::StoreMisaligned),
+            7 => Some(Exception::StoreFault),
+            8 => Some(Exception::UserEnvCall),
+            9 => Some(Exception::SupervisorEnvCall),
+            12 => Some(Exception::InstructionPageFault),
+            13 => Some(Exception::LoadPageFault),
+            15 => Some(Exception::StorePageFault),
+            _ => None,
+        }
+    }
+}
+
 /// Comprehensive trap type enum
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TrapType {
@@ -57,6 +121,7 @@ pub enum TrapType {
     StoreMisaligned,
     LoadAccessFault,     // 新增：加载访问错误
     StoreAccessFault,    // 新增：存储访问错误
+    SupervisorCall,      // S模式ecall，例如转发给SBI的调用
     Unknown,
 }
 
@@ -87,30 +152,50 @@ impl TrapCause {
         self.bits & !(1 << (core::mem::size_of::<usize>() * 8 - 1))
     }
     
+    /// Get the interrupt, if this cause is a known interrupt
+    pub fn interrupt(&self) -> Option<Interrupt> {
+        if self.is_interrupt() {
+            Interrupt::from_code(self.code())
+        } else {
+            None
+        }
+    }
+
+    /// Get the exception, if this cause is a known exception
+    pub fn exception(&self) -> Option<Exception> {
+        if self.is_interrupt() {
+            None
+        } else {
+            Exception::from_code(self.code())
+        }
+    }
+
     /// Convert to TrapType
     pub fn to_trap_type(&self) -> TrapType {
         if self.is_interrupt() {
-            match self.code() {
-                5 => TrapType::TimerInterrupt,
-                9 => TrapType::ExternalInterrupt,
-                1 => TrapType::SoftwareInterrupt,
-                _ => TrapType::Unknown,
+            match self.interrupt() {
+                Some(Interrupt::SupervisorTimer) => TrapType::TimerInterrupt,
+                Some(Interrupt::SupervisorExternal) => TrapType::ExternalInterrupt,
+                Some(Interrupt::SupervisorSoft) => TrapType::SoftwareInterrupt,
+                None => TrapType::Unknown,
             }
         } else {
-            match self.code() {
-                8 => TrapType::SystemCall,
-                12 => TrapType::InstructionPageFault,
-                13 => TrapType::LoadPageFault,
-                15 => TrapType::StorePageFault,
-                0 => TrapType::InstructionMisaligned,  // 新增：指令地址未对齐映射
-                1 => TrapType::InstructionAccessFault,
-                2 => TrapType::IllegalInstruction,
-                3 => TrapType::Breakpoint,    // 新增断点异常映射
-                4 => TrapType::LoadMisaligned,         // 新增：加载地址未对齐映射
-                5 => TrapType::LoadAccessFault,    // 正确映射：加载访问错误
-                6 => TrapType::StoreMisaligned,        // 新增：存储地址未对齐映射
-                7 => TrapType::StoreAccessFault,   // 正确映射：存储访问错误
-                _ => TrapType::Unknown,
+            match self.exception() {
+                Some(Exception::UserEnvCall) => TrapType::SystemCall,
+                Some(Exception::InstructionPageFault) => TrapType::InstructionPageFault,
+                Some(Exception::LoadPageFault) => TrapType::LoadPageFault,
+                Some(Exception::StorePageFault) => TrapType::StorePageFault,
+                Some(Exception::InstructionMisaligned) => TrapType::InstructionMisaligned,
+                Some(Exception::InstructionFault) => TrapType::InstructionAccessFault,
+                Some(Exception::IllegalInstruction) => TrapType::IllegalInstruction,
+                Some(Exception::Breakpoint) => TrapType::Breakpoint,
+                Some(Exception::LoadMisaligned) => TrapType::LoadMisaligned,
+                Some(Exception::LoadFault) => TrapType::LoadAccessFault,
+                Some(Exception::StoreMisaligned) => TrapType::StoreMisaligned,
+                Some(Exception::StoreFault) => TrapType::StoreAccessFault,
+                // S模式自身的ecall与用户系统调用分开处理
+                Some(Exception::SupervisorEnvCall) => TrapType::SupervisorCall,
+                None => TrapType::Unknown,
             }
         }
     }
@@ -125,27 +210,79 @@ impl fmt::Debug for TrapCause {
 
 impl TrapType {
     /// Number of trap types
-    pub const COUNT: usize = 15; // Includes all defined types
-    
+    pub const COUNT: usize = 16; // Includes all defined types
+
+    /// Number of per-type slots, covering the defined types and `Unknown` after them
+    pub const SLOTS: usize = Self::COUNT + 1;
+
+    /// All defined trap types in index order, excluding `Unknown`
+    pub const ALL: [TrapType; Self::COUNT] = [
+        TrapType::TimerInterrupt,
+        TrapType::ExternalInterrupt,
+        TrapType::SoftwareInterrupt,
+        TrapType::SystemCall,
+        TrapType::InstructionPageFault,
+        TrapType::LoadPageFault,
+        TrapType::StorePageFault,
+        TrapType::InstructionAccessFault,
+        TrapType::IllegalInstruction,
+        TrapType::Breakpoint,
+        TrapType::InstructionMisaligned,
+        TrapType::LoadMisaligned,
+        TrapType::StoreMisaligned,
+        TrapType::LoadAccessFault,
+        TrapType::StoreAccessFault,
+        TrapType::SupervisorCall,
+    ];
+
+    /// Iterate over all defined trap types in index order, excluding `Unknown`
+    pub fn iter() -> impl Iterator<Item = TrapType> {
+        Self::ALL.into_iter()
+    }
+
     /// Convert from index to trap type
+    ///
+    /// Indices outside the defined types map to `Unknown`
     pub fn from_index(index: usize) -> Self {
-        match index {
-            0 => TrapType::TimerInterrupt,
-            1 => TrapType::ExternalInterrupt,
-            2 => TrapType::SoftwareInterrupt,
-            3 => TrapType::SystemCall,
-            4 => TrapType::InstructionPageFault,
-            5 => TrapType::LoadPageFault,
-            6 => TrapType::StorePageFault,
-            7 => TrapType::InstructionAccessFault,
-            8 => TrapType::IllegalInstruction,
-            9 => TrapType::Breakpoint,    // 新增断点映射
-            10 => TrapType::InstructionMisaligned, // 新增
-            11 => TrapType::LoadMisaligned,        // 新增
-            12 => TrapType::StoreMisaligned,       // 新增
-            13 => TrapType::LoadAccessFault,
-            14 => TrapType::StoreAccessFault,
-            _ => TrapType::Unknown,
+        Self::ALL.get(index).copied().unwrap_or(TrapType::Unknown)
+    }
+
+    /// Check if this trap type is an interrupt (timer, external or software)
+    ///
+    /// All other types, including `Unknown`, are exceptions
+    pub const fn is_interrupt(&self) -> bool {
+        matches!(
+            self,
+            TrapType::TimerInterrupt | TrapType::ExternalInterrupt | TrapType::SoftwareInterrupt
+        )
+    }
+
+    /// Human-friendly name of the trap type
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TrapType::TimerInterrupt => "Timer Interrupt",
+            TrapType::ExternalInterrupt => "External Interrupt",
+            TrapType::SoftwareInterrupt => "Software Interrupt",
+            TrapType::SystemCall => "System Call",
+            TrapType::InstructionPageFault => "Instruction Page Fault",
+            TrapType::LoadPageFault => "Load Page Fault",
+            TrapType::StorePageFault => "Store Page Fault",
+            TrapType::InstructionAccessFault => "Instruction Access Fault",
+            TrapType::IllegalInstruction => "Illegal Instruction",
+            TrapType::Breakpoint => "Breakpoint",
+            TrapType::InstructionMisaligned => "Instruction Address Misaligned",
+            TrapType::LoadMisaligned => "Load Address Misaligned",
+            TrapType::StoreMisaligned => "Store Address Misaligned",
+            TrapType::LoadAccessFault => "Load Access Fault",
+            TrapType::StoreAccessFault => "Store Access Fault",
+            TrapType::SupervisorCall => "Supervisor Call",
+            TrapType::Unknown => "Unknown Trap",
         }
     }
+}
+
+impl fmt::Display for TrapType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,68 @@
+//! 基于帧指针的栈回溯
+//!
+//! 内核以`-Cforce-frame-pointers=yes`编译，每个栈帧中s0/fp指向调用者的栈顶，
+//! 返回地址保存在`fp - 8`，上一帧的fp保存在`fp - 16`。
+//! 回溯发生在异常处理中，读取的地址都先限制在给定范围内，并通过探测读取访问，
+//! 避免在回溯时再次触发异常。
+
+use core::ops::Range;
+use crate::println;
+use crate::mm::{layout, probe};
+
+/// 帧指针需要满足的对齐
+const FRAME_ALIGN: usize = core::mem::size_of::<usize>();
+
+/// 沿fp链收集返回地址，返回收集到的帧数
+///
+/// 遇到以下情况停止：fp为0或未对齐、帧不在`bounds`内、帧不可读、返回地址为0、
+/// 上一帧的fp没有向高地址移动（防止成环），或者`frames`已满。
+pub fn collect(fp: usize, bounds: Range<usize>, frames: &mut [usize]) -> usize {
+    let mut fp = fp;
+    let mut depth = 0;
+
+    while depth < frames.len() {
+        if fp == 0 || fp % FRAME_ALIGN != 0 || fp < bounds.start + 16 || fp > bounds.end {
+            break;
+        }
+
+        // 已确认[fp - 16, fp)位于bounds内，范围内的地址仍可能没有映射，用探测读取
+        let (Some(ra), Some(prev_fp)) = (probe::try_read_usize(fp - 8), probe::try_read_usize(fp - 16)) else {
+            break;
+        };
+        if ra == 0 {
+            break;
+        }
+
+        frames[depth] = ra;
+        depth += 1;
+
+        // 栈向低地址增长，调用者的帧一定在更高的地址
+        if prev_fp <= fp {
+            break;
+        }
+        fp = prev_fp;
+    }
+
+    depth
+}
+
+/// 从给定的fp开始打印调用栈，最多`max_frames`帧，返回打印的帧数
+///
+/// 只回溯内核镜像范围内的栈帧
+pub fn walk(fp: usize, max_frames: usize) -> usize {
+    const MAX_WALK_FRAMES: usize = 32;
+    let mut frames = [0; MAX_WALK_FRAMES];
+    let limit = max_frames.min(MAX_WALK_FRAMES);
+
+    let depth = collect(fp, layout::kernel_image(), &mut frames[..limit]);
+
+    println!("\nBacktrace (fp={:#018x}):", fp);
+    for (index, ra) in frames[..depth].iter().enumerate() {
+        println!("  #{:<2} {:#018x}", index, ra);
+    }
+    if depth == 0 {
+        println!("  <no frames>");
+    }
+
+    depth
+}
@@ -5,8 +5,12 @@
 
 use core::fmt;
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
 use crate::println;
-use crate::trap::ds::{TaskContext, TrapContext};
+use crate::trap::ds::{TaskContext, TrapContext, FpState};
+use crate::util::percpu::PerCpu;
+use crate::util::sbi::hart::MAX_HARTS;
 use riscv::register::{sstatus, scause, stval, sepc};
 
 /// 保存当前上下文到目标位置并切换到新上下文
@@ -16,6 +20,9 @@ use riscv::register::{sstatus, scause, stval, sepc};
 /// * `current_ctx` - 当前上下文的指针
 /// * `next_ctx` - 下一个上下文的指针
 ///
+/// 之后切换回`current_ctx`时，从这里返回到调用者。
+/// 寄存器的保存和恢复在trap_entry.asm的`__task_switch`中完成。
+///
 /// # 安全性
 ///
 /// 这个函数是不安全的，因为它：
@@ -23,49 +30,144 @@ use riscv::register::{sstatus, scause, stval, sepc};
 /// 2. 假设两个上下文指针都有效
 /// 3. 可能导致不一致状态，如果调用不当
 pub unsafe extern "C" fn task_switch(
-    _current_ctx: *mut TaskContext,
-    _next_ctx: *const TaskContext,
+    current_ctx: *mut TaskContext,
+    next_ctx: *const TaskContext,
 ) {
-    // 这个函数无法用Rust代码实现，因为它需要直接控制
-    // 寄存器保存和恢复。因此使用内联汇编实现。
-    asm!(
-        // 保存当前上下文的callee-saved寄存器
-        "sd ra, 0(a0)",
-        "sd sp, 8(a0)",
-        "sd s0, 16(a0)",
-        "sd s1, 24(a0)",
-        "sd s2, 32(a0)",
-        "sd s3, 40(a0)",
-        "sd s4, 48(a0)",
-        "sd s5, 56(a0)",
-        "sd s6, 64(a0)",
-        "sd s7, 72(a0)",
-        "sd s8, 80(a0)",
-        "sd s9, 88(a0)",
-        "sd s10, 96(a0)",
-        "sd s11, 104(a0)",
-        
-        // 恢复下一个上下文的callee-saved寄存器
-        "ld ra, 0(a1)",
-        "ld sp, 8(a1)",
-        "ld s0, 16(a1)",
-        "ld s1, 24(a1)",
-        "ld s2, 32(a1)",
-        "ld s3, 40(a1)",
-        "ld s4, 48(a1)",
-        "ld s5, 56(a1)",
-        "ld s6, 64(a1)",
-        "ld s7, 72(a1)",
-        "ld s8, 80(a1)",
-        "ld s9, 88(a1)",
-        "ld s10, 96(a1)",
-        "ld s11, 104(a1)",
-        
-        // 返回，实际上是跳转到新上下文的ra寄存器指向的地址
-        "ret",
-        
-        options(noreturn)
-    );
+    extern "C" {
+        fn __task_switch(current_ctx: *mut TaskContext, next_ctx: *const TaskContext);
+    }
+    __task_switch(current_ctx, next_ctx);
+}
+
+/// 每个核心的抢占状态
+struct PreemptState {
+    /// 时钟中断要求在trap返回前切换任务
+    need_resched: AtomicBool,
+    /// 关闭抢占的嵌套层数，不为0时不切换任务
+    disable_depth: AtomicUsize,
+}
+
+impl PreemptState {
+    const fn new() -> Self {
+        Self {
+            need_resched: AtomicBool::new(false),
+            disable_depth: AtomicUsize::new(0),
+        }
+    }
+}
+
+static PREEMPT_STATE: PerCpu<PreemptState, MAX_HARTS> =
+    PerCpu::new([const { PreemptState::new() }; MAX_HARTS]);
+
+/// 切换到下一个任务的函数，由调度器安装
+static RESCHED_HANDLER: Mutex<Option<fn()>> = Mutex::new(None);
+
+/// 安装或移除重新调度时调用的函数
+pub fn set_resched_handler(handler: Option<fn()>) {
+    *RESCHED_HANDLER.lock() = handler;
+}
+
+/// 要求当前核心在下一次可以抢占时切换任务
+///
+/// 通常由时钟回调在时间片用完时调用
+pub fn set_need_resched() {
+    PREEMPT_STATE.get().need_resched.store(true, Ordering::SeqCst);
+}
+
+/// 当前核心是否有待处理的重新调度请求
+pub fn need_resched() -> bool {
+    PREEMPT_STATE.get().need_resched.load(Ordering::SeqCst)
+}
+
+/// 关闭当前核心的抢占，可以嵌套
+///
+/// 关闭期间中断照常处理，但trap返回时不会切换任务
+pub fn disable_preemption() {
+    PREEMPT_STATE.get().disable_depth.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 重新开启抢占
+///
+/// 最外层的调用结束关闭状态，此时如果关闭期间有重新调度请求就立即切换任务
+pub fn enable_preemption() {
+    let state = PREEMPT_STATE.get();
+    let previous = state
+        .disable_depth
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| depth.checked_sub(1))
+        .unwrap_or(0);
+
+    // trap处理过程中由trap返回路径负责切换
+    if previous == 1 && super::double_fault::depth() == 0 {
+        resched_if_needed();
+    }
+}
+
+/// 当前核心是否允许抢占
+pub fn preemption_enabled() -> bool {
+    PREEMPT_STATE.get().disable_depth.load(Ordering::SeqCst) == 0
+}
+
+/// 关闭抢占的守卫，离开作用域时重新开启抢占
+#[must_use = "守卫被丢弃时会立即重新开启抢占"]
+pub struct PreemptGuard {
+    _private: (),
+}
+
+impl PreemptGuard {
+    /// 关闭抢占直到守卫被丢弃
+    pub fn new() -> Self {
+        disable_preemption();
+        Self { _private: () }
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        enable_preemption();
+    }
+}
+
+/// 清除重新调度请求并调用调度器切换任务
+///
+/// 调用了调度器的切换函数时返回true
+fn resched_if_needed() -> bool {
+    if !PREEMPT_STATE.get().need_resched.swap(false, Ordering::SeqCst) {
+        return false;
+    }
+    // 被打断的代码可能正在安装处理函数，这时把请求留到下一次
+    let handler = match RESCHED_HANDLER.try_lock() {
+        Some(handler) => *handler,
+        None => {
+            set_need_resched();
+            return false;
+        }
+    };
+    match handler {
+        Some(handler) => {
+            handler();
+            true
+        }
+        None => false,
+    }
+}
+
+/// trap返回前处理重新调度请求
+///
+/// 只在最外层trap返回、抢占未被关闭且被打断的代码开着中断时切换任务；
+/// 被打断的代码关着中断说明它处在临界区中，请求保留到之后的trap返回时处理。
+/// 切换时中断仍处于关闭状态，切换回来后由sret按被打断代码保存的sstatus恢复，
+/// 其中的FS先由`lazy_fp`按切换期间浮点寄存器归属的变化修正。
+pub(super) fn resched_on_trap_return(ctx: &mut TrapContext) {
+    if !need_resched()
+        || super::double_fault::depth() != 0
+        || !preemption_enabled()
+        || !ctx.interrupts_enabled_on_return()
+    {
+        return;
+    }
+    if resched_if_needed() {
+        let _ = super::lazy_fp::resume_preempted(ctx);
+    }
 }
 
 /// 在指定地址上创建一个新的任务上下文以准备启动
@@ -103,6 +205,9 @@ pub fn prepare_task_context(
     status.set_spp(sstatus::SPP::User); // 用户模式
     status.set_spie(true); // 开启中断
     ctx.sstatus = status.bits();
+
+    // 新任务不持有浮点寄存器，第一次执行浮点指令时由lazy_fp分配
+    ctx.set_fp_state(FpState::Off);
     
     // 设置程序计数器为入口点
     ctx.sepc = entry;
@@ -126,53 +231,9 @@ pub fn prepare_task_context(
 ///
 /// 这个函数是不安全的，因为它会导致特权级切换
 pub unsafe extern "C" fn trap_return() -> ! {
-    // 恢复陷阱上下文并返回
+    // 与trap入口共用同一段恢复代码，栈帧布局（包括浮点寄存器）只在trap_entry.asm中维护
     asm!(
-        // 从栈上加载特权级寄存器
-        "ld t0, 256(sp)",
-        "ld t1, 264(sp)",
-        "csrw sstatus, t0",
-        "csrw sepc, t1",
-        
-        // 恢复通用寄存器
-        "ld x1, 8(sp)",
-        "ld x3, 24(sp)",
-        "ld x4, 32(sp)",
-        "ld x5, 40(sp)",
-        "ld x6, 48(sp)",
-        "ld x7, 56(sp)",
-        "ld x8, 64(sp)",
-        "ld x9, 72(sp)",
-        "ld x10, 80(sp)",
-        "ld x11, 88(sp)",
-        "ld x12, 96(sp)",
-        "ld x13, 104(sp)",
-        "ld x14, 112(sp)",
-        "ld x15, 120(sp)",
-        "ld x16, 128(sp)",
-        "ld x17, 136(sp)",
-        "ld x18, 144(sp)",
-        "ld x19, 152(sp)",
-        "ld x20, 160(sp)",
-        "ld x21, 168(sp)",
-        "ld x22, 176(sp)",
-        "ld x23, 184(sp)",
-        "ld x24, 192(sp)",
-        "ld x25, 200(sp)",
-        "ld x26, 208(sp)",
-        "ld x27, 216(sp)",
-        "ld x28, 224(sp)",
-        "ld x29, 232(sp)",
-        "ld x30, 240(sp)",
-        "ld x31, 248(sp)",
-        
-        // 最后恢复sp
-        "ld x2, 16(sp)",
-        "addi sp, sp, 288",  // 释放栈空间
-        
-        // 返回到用户空间
-        "sret",
-        
+        "j __trap_return",
         options(noreturn)
     );
 }
@@ -230,6 +291,9 @@ pub fn save_full_context() -> TrapContext {
         ctx.scause = scause::read().bits();
         ctx.stval = stval::read();
     }
+
+    // 浮点寄存器只在FS为Dirty时保存
+    ctx.save_fp_context();
     
     ctx
 }
@@ -246,6 +310,12 @@ pub fn save_full_context() -> TrapContext {
 /// 
 /// 这个函数是不安全的，因为它直接改变处理器状态
 pub unsafe fn restore_full_context(ctx: &TrapContext) {
+    // 调试构建中拒绝恢复损坏的上下文，避免以错误的特权级或地址返回
+    #[cfg(debug_assertions)]
+    if let Err(err) = ctx.validate_for_return() {
+        panic!("refusing to restore invalid trap context: {:?}", err);
+    }
+
     // 恢复特权级寄存器
     // 直接写入sepc寄存器
     sepc::write(ctx.sepc);
@@ -256,6 +326,9 @@ pub unsafe fn restore_full_context(ctx: &TrapContext) {
         in(reg) ctx.sstatus,
         options(nostack)
     );
+
+    // sstatus恢复后浮点单元状态与上下文一致，再恢复浮点寄存器
+    ctx.restore_fp_context();
     
     // 恢复通用寄存器
     asm!(
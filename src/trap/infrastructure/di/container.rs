@@ -4,6 +4,7 @@
 //! It manages component registration and lifecycle.
 
 use crate::println;
+use crate::trap_log;
 use crate::trap::ds::{
     TrapContext, TaskContext, TrapType, TrapHandlerResult, TrapError,
     ContextType, TrapCause
@@ -14,6 +15,57 @@ use super::traits::{
 };
 use super::impls::StandardTrapHandler;
 use super::context::ContextId;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 进入默认处理逻辑的trap次数
+static UNHANDLED_TRAP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 获取进入默认处理逻辑的trap次数
+pub fn unhandled_trap_count() -> usize {
+    UNHANDLED_TRAP_COUNT.load(Ordering::SeqCst)
+}
+
+/// 每种trap类型发生的次数
+///
+/// DI分发和注册表分发共用这一组计数
+static TRAP_COUNTS: [AtomicUsize; TrapType::SLOTS] = [const { AtomicUsize::new(0) }; TrapType::SLOTS];
+
+/// 获取每种trap类型发生的次数，以`TrapType as usize`为下标
+pub fn trap_counts() -> [usize; TrapType::SLOTS] {
+    let mut counts = [0; TrapType::SLOTS];
+    for (count, counter) in counts.iter_mut().zip(TRAP_COUNTS.iter()) {
+        *count = counter.load(Ordering::SeqCst);
+    }
+    counts
+}
+
+/// 记录一次trap，两种分发路径在进入分发时各自调用
+pub fn record_trap(trap_type: TrapType) {
+    TRAP_COUNTS[trap_type as usize].fetch_add(1, Ordering::SeqCst);
+}
+
+/// 获取某一trap类型发生的次数，不获取任何锁
+pub fn trap_count(trap_type: TrapType) -> usize {
+    TRAP_COUNTS[trap_type as usize].load(Ordering::SeqCst)
+}
+
+/// 清零每种trap类型发生的次数，用于测试隔离
+pub fn reset_trap_counts() {
+    for counter in TRAP_COUNTS.iter() {
+        counter.store(0, Ordering::SeqCst);
+    }
+}
+
+/// 一次分发的详细结果
+#[derive(Debug, Clone, Copy)]
+pub struct DispatchOutcome {
+    /// 分发结果，与`dispatch_trap`的返回值相同
+    pub result: TrapHandlerResult,
+    /// 实际运行过的处理器数量
+    pub handlers_tried: usize,
+    /// 处理了该trap的处理器的描述，没有处理器处理时为None
+    pub handler_that_handled: Option<&'static str>,
+}
 
 /// Static reference pointer implementation without heap allocation
 ///
@@ -88,16 +140,25 @@ pub struct HandlerInfo {
     pub trap_type: TrapType,
     /// 关联的上下文ID
     pub context_id: Option<ContextId>,
+    /// 注册时的描述，用于与 HANDLER_STORAGE 核对
+    pub description: &'static str,
 }
 
 impl HandlerInfo {
     /// 创建新的处理器信息
-    pub const fn new(index: usize, priority: u8, trap_type: TrapType, context_id: Option<ContextId>) -> Self {
+    pub const fn new(
+        index: usize,
+        priority: u8,
+        trap_type: TrapType,
+        context_id: Option<ContextId>,
+        description: &'static str
+    ) -> Self {
         Self {
             index,
             priority,
             trap_type,
             context_id,
+            description,
         }
     }
 }
@@ -190,14 +251,25 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         }
 
         // 创建 HandlerInfo 实例，包含上下文ID
-        let handler_info = HandlerInfo::new(index, priority, trap_type, context_id);
+        let handler_info = HandlerInfo::new(index, priority, trap_type, context_id, description);
+        self.insert_sorted(handler_info);
+
+        println!("Registered trap handler: {} for {:?} with priority {} (index: {}, context_id: {:?})",
+                 description, trap_type, priority, index, context_id);
 
+        true
+    }
+
+    /// 按类型和优先级插入处理器信息，排在同类型同优先级的处理器之后
+    ///
+    /// 调用者需要保证处理器表还有空位
+    fn insert_sorted(&mut self, handler_info: HandlerInfo) {
         // 查找插入位置，基于trap_type和priority
         let mut insert_idx = self.handler_count;
 
         for i in 0..self.handler_count {
             if let Some(existing) = self.handlers[i] {
-                if existing.trap_type == trap_type && existing.priority > priority {
+                if existing.trap_type == handler_info.trap_type && existing.priority > handler_info.priority {
                     // 找到优先级较低的处理器
                     insert_idx = i;
                     break;
@@ -215,32 +287,15 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         // 插入新的处理器信息
         self.handlers[insert_idx] = Some(handler_info);
         self.handler_count += 1;
-
-        println!("Registered trap handler: {} for {:?} with priority {} (index: {}, context_id: {:?})",
-                 description, trap_type, priority, index, context_id);
-
-        true
     }
 
-    /// Unregister a trap handler by index
-    pub fn unregister_handler(&mut self, index: usize) -> bool {
-        let mut found = false;
-        let mut found_idx = 0;
-
+    /// 从处理器表中取出指向存储索引`index`的处理器信息
+    fn remove_by_index(&mut self, index: usize) -> Option<HandlerInfo> {
         // 查找匹配索引的处理器
-        for i in 0..self.handler_count {
-            if let Some(handler_info) = self.handlers[i] {
-                if handler_info.index == index {
-                    found = true;
-                    found_idx = i;
-                    break;
-                }
-            }
-        }
-
-        if !found {
-            return false;
-        }
+        let found_idx = self.handlers[..self.handler_count]
+            .iter()
+            .position(|handler_info| handler_info.is_some_and(|info| info.index == index))?;
+        let handler_info = self.handlers[found_idx];
 
         // 移动元素填补空位
         for i in found_idx..self.handler_count-1 {
@@ -251,10 +306,49 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         self.handlers[self.handler_count - 1] = None;
         self.handler_count -= 1;
 
+        handler_info
+    }
+
+    /// Unregister a trap handler by index
+    pub fn unregister_handler(&mut self, index: usize) -> bool {
+        if self.remove_by_index(index).is_none() {
+            return false;
+        }
+
         println!("Unregistered trap handler (index: {})", index);
         true
     }
 
+    /// 修改处理器的优先级，并移动到同类型处理器中对应的位置
+    ///
+    /// 在同一次可变访问中完成，持有写锁时分发不会看到排序到一半的处理器表。
+    /// 返回是否找到了该索引
+    pub fn set_handler_priority(&mut self, index: usize, priority: u8) -> bool {
+        match self.remove_by_index(index) {
+            Some(mut handler_info) => {
+                handler_info.priority = priority;
+                self.insert_sorted(handler_info);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 更新处理器指向的存储索引
+    ///
+    /// 用于存储整理时同步移动后的槽位，返回是否找到了原索引
+    pub fn remap_handler_index(&mut self, old_index: usize, new_index: usize) -> bool {
+        for i in 0..self.handler_count {
+            if let Some(handler_info) = self.handlers[i].as_mut() {
+                if handler_info.index == old_index {
+                    handler_info.index = new_index;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     /// Dispatch a trap to the appropriate handler
     /// 修改以接收外部存储
     pub fn dispatch_trap(
@@ -263,22 +357,48 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         context: &mut TrapContext,
         storage: &[Option<StandardTrapHandler>]
     ) -> TrapHandlerResult {
+        self.dispatch_detailed(trap_type, context, storage).result
+    }
+
+    /// 分发trap并记录运行了哪些处理器
+    ///
+    /// 按优先级依次运行处理器，直到某个处理器处理了该trap
+    pub fn dispatch_detailed(
+        &self,
+        trap_type: TrapType,
+        context: &mut TrapContext,
+        storage: &[Option<StandardTrapHandler>]
+    ) -> DispatchOutcome {
+        let mut handlers_tried = 0;
+
         // 查找匹配的处理器
         for i in 0..self.handler_count {
             if let Some(handler_info) = self.handlers[i] {
                 if handler_info.trap_type == trap_type {
                     // 从传入的存储中获取实际处理器实例
-                    if let Some(handler) = &storage[handler_info.index] {
+                    if let Some(handler) = storage.get(handler_info.index).and_then(Option::as_ref) {
+                        super::record_handler_invocation(handler_info.index);
+                        handlers_tried += 1;
+                        let handled = |result| DispatchOutcome {
+                            result,
+                            handlers_tried,
+                            handler_that_handled: Some(handler.get_description()),
+                        };
                         match handler.handle_trap(context) {
                             result @ TrapHandlerResult::Handled => {
                                 // 处理成功
-                                return result;
+                                return handled(result);
+                            }
+                            result @ TrapHandlerResult::HandledAndMaskInterrupts => {
+                                // 处理成功，返回时保持中断关闭
+                                context.mask_interrupts_on_return();
+                                return handled(result);
                             }
                             TrapHandlerResult::Pass => {
                                 // 传递给下一个处理器
                                 continue;
                             }
-                            result @ TrapHandlerResult::Failed(_) => {
+                            TrapHandlerResult::Failed(_) => {
                                 // 处理失败
                                 println!("Handler failed (index: {})", handler_info.index);
                                 continue;
@@ -294,58 +414,82 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         }
 
         // 没有处理器处理该中断
-        TrapHandlerResult::Failed(TrapError::NoHandler)
+        DispatchOutcome {
+            result: TrapHandlerResult::Failed(TrapError::NoHandler),
+            handlers_tried,
+            handler_that_handled: None,
+        }
     }
 
     /// Handle a trap event
     /// 修改以接收外部存储
+    ///
+    /// 返回已注册处理器的分发结果。处理期间当前核心的中断嵌套层级加一，
+    /// 处理器中`is_in_trap_context()`为true，`current_trap_nest_level()`为当前层级。
     pub fn handle_trap(
         &self,
         context: *mut TrapContext,
         storage: &[Option<StandardTrapHandler>]
-    ) {
+    ) -> TrapHandlerResult {
         let ctx = unsafe { &mut *context };
         let cause = ctx.get_cause();
         let trap_type = cause.to_trap_type();
+        record_trap(trap_type);
 
         // 记录中断发生
         if cause.is_interrupt() {
-            println!("Interrupt occurred: {:?}, code: {}",
+            trap_log!("Interrupt occurred: {:?}, code: {}",
                      trap_type, cause.code());
         } else {
-            println!("Exception occurred: {:?}, code: {}, addr: {:#x}",
+            trap_log!("Exception occurred: {:?}, code: {}, addr: {:#x}",
                      trap_type, cause.code(), ctx.stval);
         }
 
+        // 超过最大层级的中断已由入口丢弃，这里进入失败时只是不计入嵌套层级
+        let context_manager = self.get_context_manager();
+        let entered = context_manager.enter_interrupt().is_ok();
+
         // 分发给注册的处理器
-        match self.dispatch_trap(trap_type, ctx, storage) {
+        let result = self.dispatch_trap(trap_type, ctx, storage);
+        match result {
             TrapHandlerResult::Handled => {
-                println!("Interrupt handled successfully by registered handler");
+                trap_log!("Interrupt handled successfully by registered handler");
+            },
+            TrapHandlerResult::HandledAndMaskInterrupts => {
+                trap_log!("Interrupt handled by registered handler, interrupts stay masked on return");
             },
             TrapHandlerResult::Pass => {
                 // 所有处理器都传递了该中断
-                println!("All handlers passed the interrupt: {:?}", trap_type);
+                trap_log!("All handlers passed the interrupt: {:?}", trap_type);
 
                 // 默认处理逻辑
                 self.handle_unhandled_trap(trap_type, cause, ctx);
             },
             TrapHandlerResult::Failed(err) => {
                 // 处理失败
-                println!("Failed to handle interrupt: {:?}, error: {:?}", trap_type, err);
+                trap_log!("Failed to handle interrupt: {:?}, error: {:?}", trap_type, err);
 
                 // 默认处理逻辑
                 self.handle_unhandled_trap(trap_type, cause, ctx);
             }
         }
+
+        if entered {
+            let _ = context_manager.exit_interrupt();
+        }
+
+        result
     }
 
     /// Handle an unhandled trap with default behavior
     fn handle_unhandled_trap(&self, trap_type: TrapType, cause: TrapCause, ctx: &mut TrapContext) {
+        UNHANDLED_TRAP_COUNT.fetch_add(1, Ordering::SeqCst);
+
         // 默认处理逻辑
         if cause.is_interrupt() {
             match trap_type {
                 TrapType::TimerInterrupt => {
-                    println!("Default handling for timer interrupt");
+                    trap_log!("Default handling for timer interrupt");
                 },
                 TrapType::SoftwareInterrupt => {
                     unsafe {
@@ -353,7 +497,7 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
                     }
                 },
                 TrapType::ExternalInterrupt => {
-                    println!("Default handling for external interrupt");
+                    trap_log!("Default handling for external interrupt");
                 },
                 _ => {
                     println!("No default handler for interrupt type: {:?}", trap_type);
@@ -362,8 +506,8 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         } else {
             // 异常处理
             match trap_type {
-                TrapType::SystemCall => {
-                    println!("Default handling for system call");
+                TrapType::SystemCall | TrapType::SupervisorCall => {
+                    trap_log!("Default handling for system call");
                     // 系统调用需要跳过 ecall 指令
                     ctx.set_return_addr(ctx.sepc + 4);
                 },
@@ -441,6 +585,45 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         unsafe { self.error_manager.get_mut() }
     }
 
+    /// Total number of registered handlers
+    pub fn total_handler_count(&self) -> usize {
+        self.handler_count
+    }
+
+    /// Count handlers associated with a specific context
+    pub fn handler_count_for_context(&self, context_id: ContextId) -> usize {
+        let mut count = 0;
+
+        for i in 0..self.handler_count {
+            if let Some(handler_info) = self.handlers[i] {
+                if handler_info.context_id == Some(context_id) {
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    /// 获取已注册处理器的类型位图，第`trap_type as usize`位表示该类型有处理器
+    pub fn handler_type_mask(&self) -> u32 {
+        self.handlers[..self.handler_count].iter()
+            .flatten()
+            .fold(0, |mask, handler_info| mask | (1 << handler_info.trap_type as u32))
+    }
+
+    /// Whether a handler with this description is registered for the trap type
+    pub fn has_handler(&self, trap_type: TrapType, description: &str) -> bool {
+        self.handler_infos().iter()
+            .flatten()
+            .any(|handler_info| handler_info.trap_type == trap_type && handler_info.description == description)
+    }
+
+    /// Registered handler entries in dispatch order
+    pub fn handler_infos(&self) -> &[Option<HandlerInfo>] {
+        &self.handlers[..self.handler_count]
+    }
+
     /// Count handlers registered for a specific trap type
     pub fn handler_count_for_type(&self, trap_type: TrapType) -> usize {
         let mut count = 0;
@@ -456,14 +639,39 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
         count
     }
 
+    /// Visit all registered handlers grouped by trap type
+    ///
+    /// The callback receives the trap type, description, priority,
+    /// storage index and associated context of each handler. `Unknown`
+    /// handlers are visited after all defined trap types.
+    pub fn for_each_handler<F>(&self, storage: &[Option<StandardTrapHandler>], mut f: F)
+    where
+        F: FnMut(TrapType, &'static str, u8, usize, Option<ContextId>),
+    {
+        for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
+            for j in 0..self.handler_count {
+                if let Some(handler_info) = self.handlers[j] {
+                    if handler_info.trap_type == trap_type {
+                        let description = match &storage[handler_info.index] {
+                            Some(handler) => handler.get_description(),
+                            None => "<missing handler>",
+                        };
+
+                        f(trap_type, description, handler_info.priority,
+                          handler_info.index, handler_info.context_id);
+                    }
+                }
+            }
+        }
+    }
+
     /// Print all registered handlers (for debugging)
     /// 修改以接收外部存储
     pub fn print_handlers(&self, storage: &[Option<StandardTrapHandler>]) {
         println!("=== Registered Trap Handlers ===");
 
         // 按中断类型分类打印
-        for i in 0..TrapType::COUNT {
-            let trap_type = TrapType::from_index(i);
+        for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
             let mut handlers_found = false;
 
             // 查找该类型的所有处理器
@@ -471,7 +679,7 @@ impl<C: ContextManagerInterface, H: HardwareControlInterface, E: ErrorManagerInt
                 if let Some(handler_info) = self.handlers[j] {
                     if handler_info.trap_type == trap_type {
                         if !handlers_found {
-                            println!("{:?} Handlers:", trap_type);
+                            println!("{} Handlers:", trap_type);
                             handlers_found = true;
                         }
 
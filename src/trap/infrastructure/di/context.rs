@@ -1,8 +1,14 @@
 //! 上下文类型定义模块
 //!
 //! 定义了中断处理器上下文关联所需的类型和常量
+//!
+//! 进程销毁后它的ID被放入回收列表，之后生成ID时优先重用，
+//! 反复创建和销毁进程不会让ID无限增长。
 
 use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::trap::infrastructure::{disable_interrupts, restore_interrupts};
+use super::context_pool::CONTEXT_POOL_SIZE;
 
 /// 上下文ID类型，用于唯一标识一个系统上下文
 pub type ContextId = usize;
@@ -10,8 +16,86 @@ pub type ContextId = usize;
 /// 内核上下文ID，表示不属于特定上下文的处理器
 pub const KERNEL_CONTEXT_ID: Option<ContextId> = None;
 
-/// 生成全局唯一的上下文ID
+/// 回收列表最多保存的ID数量，与进程池大小相同，存活的进程不会超过这个数
+const FREE_ID_CAPACITY: usize = CONTEXT_POOL_SIZE;
+
+/// 下一个从未分配过的上下文ID
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// 已释放、等待重用的上下文ID
+struct FreeIds {
+    ids: [ContextId; FREE_ID_CAPACITY],
+    len: usize,
+}
+
+static FREE_IDS: Mutex<FreeIds> = Mutex::new(FreeIds {
+    ids: [0; FREE_ID_CAPACITY],
+    len: 0,
+});
+
+/// 生成上下文ID，优先重用已释放的ID
+///
+/// 回收列表为空且新ID已经用尽时返回None
+pub fn try_generate_context_id() -> Option<ContextId> {
+    // 中断处理程序中也可能生成ID，持有锁期间关闭中断
+    let was_enabled = disable_interrupts();
+    let reused = {
+        let mut free = FREE_IDS.lock();
+        if free.len > 0 {
+            free.len -= 1;
+            Some(free.ids[free.len])
+        } else {
+            None
+        }
+    };
+    restore_interrupts(was_enabled);
+    if reused.is_some() {
+        return reused;
+    }
+
+    NEXT_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |id| id.checked_add(1))
+        .ok()
+}
+
+/// 生成当前未被使用的上下文ID
+///
+/// # Panics
+///
+/// 上下文ID用尽时panic，需要处理这种情况的调用者应使用`try_generate_context_id`
 pub fn generate_context_id() -> ContextId {
-    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
-    NEXT_ID.fetch_add(1, Ordering::SeqCst)
-}
\ No newline at end of file
+    try_generate_context_id().expect("context IDs exhausted")
+}
+
+/// 释放上下文ID，之后可以被`generate_context_id`重用
+///
+/// 只能释放由生成函数分配、且不再被使用的ID。重复释放或回收列表已满时返回false，
+/// 此时ID不会被重用。
+pub fn release_context_id(id: ContextId) -> bool {
+    if id == 0 || id >= NEXT_ID.load(Ordering::SeqCst) {
+        return false;
+    }
+
+    let was_enabled = disable_interrupts();
+    let released = {
+        let mut free = FREE_IDS.lock();
+        let len = free.len;
+        if len == FREE_ID_CAPACITY || free.ids[..len].contains(&id) {
+            false
+        } else {
+            free.ids[len] = id;
+            free.len += 1;
+            true
+        }
+    };
+    restore_interrupts(was_enabled);
+    released
+}
+
+/// 回收列表中等待重用的ID数量
+pub fn free_context_id_count() -> usize {
+    let was_enabled = disable_interrupts();
+    let count = FREE_IDS.lock().len;
+    restore_interrupts(was_enabled);
+    count
+}
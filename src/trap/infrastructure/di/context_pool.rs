@@ -4,16 +4,18 @@
 //! 确保在上下文生命周期结束时正确触发Drop处理
 
 use core::fmt;
+use core::ops::Deref;
 use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use spin::Mutex;
 use crate::println;
-use super::context::{ContextId, generate_context_id};
+use super::context::{ContextId, try_generate_context_id, release_context_id};
 use crate::trap::ds::TrapType;
 use crate::trap::ds::TrapContext;
 use crate::trap::ds::TrapHandlerResult;
+use crate::trap::infrastructure::{disable_interrupts, restore_interrupts};
 
 /// 上下文对象池错误类型
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PoolError {
     /// 池已满
     PoolFull,
@@ -31,6 +33,10 @@ pub enum PoolError {
     AccessDenied,
     /// 锁已被占用（死锁风险）
     LockBusy,
+    /// 处理器槽位不足
+    HandlerSlotsExhausted,
+    /// 超出该上下文的处理器配额
+    HandlerQuotaExceeded,
 }
 
 impl fmt::Display for PoolError {
@@ -44,6 +50,8 @@ impl fmt::Display for PoolError {
             PoolError::ContextDestroyed => write!(f, "Context has been destroyed"),
             PoolError::AccessDenied => write!(f, "Access denied"),
             PoolError::LockBusy => write!(f, "Lock is busy"),
+            PoolError::HandlerSlotsExhausted => write!(f, "Not enough handler slots available"),
+            PoolError::HandlerQuotaExceeded => write!(f, "Context handler quota exceeded"),
         }
     }
 }
@@ -58,7 +66,7 @@ pub trait ContextObject: Sized {
 }
 
 /// 上下文对象池大小
-const CONTEXT_POOL_SIZE: usize = 64;
+pub const CONTEXT_POOL_SIZE: usize = 64;
 
 /// 上下文池槽位状态
 struct PoolSlot<T: ContextObject> {
@@ -70,6 +78,8 @@ struct PoolSlot<T: ContextObject> {
     token: u32,
     /// 对象版本号 - 防止访问已删除重新分配的对象
     version: usize,
+    /// 指向该对象的句柄数量
+    refs: AtomicUsize,
 }
 
 impl<T: ContextObject> PoolSlot<T> {
@@ -80,10 +90,11 @@ impl<T: ContextObject> PoolSlot<T> {
             in_use: false,
             token: 0,
             version: 0,
+            refs: AtomicUsize::new(0),
         }
     }
     
-    /// 设置对象
+    /// 设置对象，创建者持有第一个引用
     fn set(&mut self, obj: T) -> u32 {
         // 生成新的访问令牌
         let token = rand_token();
@@ -92,6 +103,7 @@ impl<T: ContextObject> PoolSlot<T> {
         self.token = token;
         self.in_use = true;
         self.version += 1;
+        self.refs.store(1, Ordering::SeqCst);
 
         // 存储对象
         self.object = Some(obj);
@@ -104,8 +116,10 @@ impl<T: ContextObject> PoolSlot<T> {
         // 标记为未使用
         self.in_use = false;
 
-        // 无效化令牌
+        // 无效化令牌，并让仍指向该对象的句柄的版本号失效
         self.token = 0;
+        self.version += 1;
+        self.refs.store(0, Ordering::SeqCst);
 
         // 取出并返回对象
         self.object.take()
@@ -238,15 +252,21 @@ impl<T: ContextObject> ContextPool<T> {
 
     /// 销毁上下文对象
     pub fn destroy_context(&mut self, id: ContextId) -> Result<(), PoolError> {
+        self.take_context(id).map(drop)
+    }
+
+    /// 从池中取出上下文对象，槽位随之释放
+    ///
+    /// 对象的Drop可能需要访问其他全局状态，调用者应在释放池锁之后再丢弃返回的对象
+    pub fn take_context(&mut self, id: ContextId) -> Result<T, PoolError> {
         // 查找匹配ID的对象
         let idx = match self.find_index_by_id(id) {
             Some(i) => i,
             None => return Err(PoolError::ContextNotFound),
         };
 
-        // 关键：取出对象，触发Drop
-        if let Some(obj_to_drop) = self.slots[idx].clear() {
-            // 对象离开作用域时会自动调用Drop
+        // 关键：取出对象，由调用者决定何时触发Drop
+        if let Some(obj) = self.slots[idx].clear() {
             println!("Destroying context with ID {} from index {}", id, idx);
 
             // 更新映射表
@@ -255,7 +275,7 @@ impl<T: ContextObject> ContextPool<T> {
             // 更新计数
             self.count -= 1;
 
-            Ok(())
+            Ok(obj)
         } else {
             // 这种情况不应该发生
             println!("Warning: Slot marked as in-use but no object found at index {}", idx);
@@ -269,7 +289,7 @@ impl<T: ContextObject> ContextPool<T> {
     }
 
     /// 查找ID对应的索引
-    fn find_index_by_id(&self, id: ContextId) -> Option<usize> {
+    pub(crate) fn find_index_by_id(&self, id: ContextId) -> Option<usize> {
         for i in 0..CONTEXT_POOL_SIZE {
             if self.id_to_index[i].0 == id && self.id_to_index[i].1 {
                 if self.slots[i].in_use {
@@ -301,6 +321,46 @@ impl<T: ContextObject> ContextPool<T> {
         Ok(idx)
     }
 
+    /// 检查槽位中的对象是否仍是句柄创建时的那一个
+    ///
+    /// 对象被销毁后槽位的版本号会改变，此时返回`ContextDestroyed`
+    pub fn check_slot(&self, index: usize, version: usize) -> Result<(), PoolError> {
+        let slot = self.slots.get(index).ok_or(PoolError::InvalidContextId)?;
+        if slot.version != version || !slot.in_use {
+            return Err(PoolError::ContextDestroyed);
+        }
+        Ok(())
+    }
+
+    /// 增加一个引用，对象已被销毁时返回false
+    pub fn acquire(&self, index: usize, version: usize) -> bool {
+        if self.check_slot(index, version).is_err() {
+            return false;
+        }
+        self.slots[index].refs.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+
+    /// 释放一个引用，最后一个引用释放时从池中取出对象
+    ///
+    /// 返回被取出的对象，调用者在释放池锁后丢弃它；对象已被销毁时什么也不做
+    pub fn release(&mut self, index: usize, version: usize) -> Option<T> {
+        self.check_slot(index, version).ok()?;
+        if self.slots[index].refs.fetch_sub(1, Ordering::SeqCst) != 1 {
+            return None;
+        }
+        let id = self.id_to_index[index].0;
+        self.take_context(id).ok()
+    }
+
+    /// 槽位中对象的引用数，对象已被销毁时为0
+    pub fn ref_count(&self, index: usize, version: usize) -> usize {
+        match self.check_slot(index, version) {
+            Ok(()) => self.slots[index].refs.load(Ordering::SeqCst),
+            Err(_) => 0,
+        }
+    }
+
     /// 安全地访问对象，传入一个回调函数
     pub fn with_object<F, R>(&self, id: ContextId, token: u32, version: usize, f: F) -> Result<R, PoolError>
     where
@@ -404,6 +464,10 @@ pub struct ProcessControlBlock {
     pub name: &'static str,
     /// 状态标志
     pub state: u8,
+    /// 已预留但尚未使用的处理器槽位数
+    pub reserved_handlers: usize,
+    /// 进程ID由进程池生成，销毁时归还以便重用
+    owns_pid: bool,
 }
 
 impl ContextObject for ProcessControlBlock {
@@ -416,6 +480,8 @@ impl ContextObject for ProcessControlBlock {
             pid: id,
             name: "unnamed",
             state: 0,
+            reserved_handlers: 0,
+            owns_pid: false,
         }
     }
 }
@@ -425,17 +491,42 @@ impl Drop for ProcessControlBlock {
         // 打印日志
         println!("Process {}: Dropping. Triggering handler cleanup.", self.pid);
         
+        // 归还未使用的预留槽位
+        if self.reserved_handlers > 0 {
+            super::release_handler_slots(self.reserved_handlers);
+        }
+
         // 调用handler清理函数
         let removed_count = super::unregister_handlers_for_context(self.pid);
+
+        // 取消进程的定时器，到期回调不会再被调用
+        crate::util::sbi::timer::cancel_context_timer(self.pid);
         
+        // 释放浮点保存区，重用该ID的进程不会继承这里的浮点状态
+        let _ = crate::trap::infrastructure::lazy_fp::release(self.pid);
+
         println!("Process {}: Cleaned up {} handlers.", self.pid, removed_count);
+
+        // 与该ID关联的状态都已清理，可以重用
+        if self.owns_pid {
+            release_context_id(self.pid);
+        }
     }
 }
 
 /// 进程句柄，用于安全地提供对进程的访问
+///
+/// 句柄带有引用计数：`clone`增加一个引用，`Drop`释放一个引用，
+/// 最后一个句柄被丢弃时进程随之销毁。`destroy_process`可以提前销毁进程，
+/// 此后其余句柄的访问都返回`PoolError::ContextDestroyed`。
+///
+/// 复制句柄使用`try_clone`，进程池锁被占用时返回`PoolError::LockBusy`；
+/// 丢弃句柄需要获取进程池锁，不能在持有进程池锁时进行。
 pub struct ProcessHandle {
     /// 进程ID
     pub pid: ContextId,
+    /// 进程所在的池槽位
+    index: usize,
     /// 进程内部访问令牌
     token: u32,
     /// 进程版本号，用于检测对象是否被重新分配
@@ -445,10 +536,11 @@ pub struct ProcessHandle {
 }
 
 impl ProcessHandle {
-    /// 创建新的进程句柄
-    fn new(pid: ContextId, token: u32, version: usize) -> Self {
+    /// 创建新的进程句柄，接管创建时的引用
+    fn new(pid: ContextId, index: usize, token: u32, version: usize) -> Self {
         Self {
             pid,
+            index,
             token,
             version,
             valid: true,
@@ -462,76 +554,110 @@ impl ProcessHandle {
         }
         Ok(())
     }
+
+    /// 验证句柄后访问进程
+    fn with_process<R>(&self, f: impl FnOnce(&ProcessControlBlock) -> R) -> Result<R, PoolError> {
+        self.check_valid()?;
+
+        // 获取池锁
+        let pool = PROCESS_POOL.try_lock().ok_or(PoolError::LockBusy)?;
+
+        // 进程被销毁后版本号不再匹配
+        pool.check_slot(self.index, self.version)?;
+        pool.with_object(self.pid, self.token, self.version, f)
+    }
+
+    /// 验证句柄后修改进程
+    fn with_process_mut<R>(&self, f: impl FnOnce(&mut ProcessControlBlock) -> R) -> Result<R, PoolError> {
+        self.check_valid()?;
+
+        // 获取池锁
+        let mut pool = PROCESS_POOL.try_lock().ok_or(PoolError::LockBusy)?;
+
+        pool.check_slot(self.index, self.version)?;
+        pool.with_object_mut(self.pid, self.token, self.version, f)
+    }
     
     /// 获取进程状态
     pub fn get_state(&self) -> Result<u8, PoolError> {
-        self.check_valid()?;
-        
-        // 获取池锁
-        let pool_guard = PROCESS_POOL.try_lock();
-        let pool = match pool_guard {
-            Some(guard) => guard,
-            None => return Err(PoolError::LockBusy),
-        };
-        
-        // 安全访问
-        pool.with_object(self.pid, self.token, self.version, |process| {
-            process.state
-        })
+        self.with_process(|process| process.state)
     }
     
     /// 设置进程状态
     pub fn set_state(&self, new_state: u8) -> Result<(), PoolError> {
-        self.check_valid()?;
-        
-        // 获取池锁
-        let mut pool_guard = PROCESS_POOL.try_lock();
-        let pool = match pool_guard.as_mut() {
-            Some(guard) => guard,
-            None => return Err(PoolError::LockBusy),
-        };
-        
-        // 安全修改
-        pool.with_object_mut(self.pid, self.token, self.version, |process| {
+        self.with_process_mut(|process| {
             process.state = new_state;
         })
     }
     
     /// 获取进程名称
     pub fn get_name(&self) -> Result<&'static str, PoolError> {
-        self.check_valid()?;
-        
-        // 获取池锁
-        let pool_guard = PROCESS_POOL.try_lock();
-        let pool = match pool_guard {
-            Some(guard) => guard,
-            None => return Err(PoolError::LockBusy),
-        };
-        
-        // 安全访问
-        pool.with_object(self.pid, self.token, self.version, |process| {
-            process.name
-        })
+        self.with_process(|process| process.name)
     }
     
     /// 设置进程名称
     pub fn set_name(&self, new_name: &'static str) -> Result<(), PoolError> {
-        self.check_valid()?;
-        
-        // 获取池锁
-        let mut pool_guard = PROCESS_POOL.try_lock();
-        let pool = match pool_guard.as_mut() {
-            Some(guard) => guard,
-            None => return Err(PoolError::LockBusy),
-        };
-        
-        // 安全修改
-        pool.with_object_mut(self.pid, self.token, self.version, |process| {
+        self.with_process_mut(|process| {
             process.name = new_name;
         })
     }
+
+    /// 指向该进程的句柄数量，进程已被销毁时为0
+    pub fn ref_count(&self) -> Result<usize, PoolError> {
+        if !self.valid {
+            return Ok(0);
+        }
+        let pool = PROCESS_POOL.try_lock().ok_or(PoolError::LockBusy)?;
+        Ok(pool.ref_count(self.index, self.version))
+    }
+
+    /// 复制句柄，增加一个引用
+    ///
+    /// 进程已被销毁时返回`ContextDestroyed`
+    pub fn try_clone(&self) -> Result<Self, PoolError> {
+        self.check_valid()?;
+        let pool = PROCESS_POOL.try_lock().ok_or(PoolError::LockBusy)?;
+        if !pool.acquire(self.index, self.version) {
+            return Err(PoolError::ContextDestroyed);
+        }
+        Ok(Self {
+            pid: self.pid,
+            index: self.index,
+            token: self.token,
+            version: self.version,
+            valid: true,
+        })
+    }
+    
+    /// 为该进程预先预留处理器槽位
+    ///
+    /// 预留成功后，后续的`register_handler`会优先使用预留的槽位，
+    /// 保证进程在创建时就能确认之后可以注册这些处理器。
+    pub fn reserve_handlers(&self, count: usize) -> Result<(), PoolError> {
+        // 先确认进程仍然存在，再预留全局槽位
+        self.get_reserved_handlers()?;
+
+        if !super::reserve_handler_slots(count) {
+            return Err(PoolError::HandlerSlotsExhausted);
+        }
+
+        let result = self.with_process_mut(|process| {
+            process.reserved_handlers += count;
+        });
+        if result.is_err() {
+            super::release_handler_slots(count);
+        }
+        result
+    }
+
+    /// 获取该进程剩余的预留槽位数
+    pub fn get_reserved_handlers(&self) -> Result<usize, PoolError> {
+        self.with_process(|process| process.reserved_handlers)
+    }
     
     /// 为该进程注册中断处理器
+    ///
+    /// 如果进程有预留槽位，则从预留中分配
     pub fn register_handler(
         &self,
         trap_type: TrapType,
@@ -540,29 +666,112 @@ impl ProcessHandle {
         description: &'static str
     ) -> Result<bool, PoolError> {
         self.check_valid()?;
-        
-        // 注册处理器
-        let result = super::register_handler(
+
+        if super::context_quota_reached(self.pid) {
+            return Err(PoolError::HandlerQuotaExceeded);
+        }
+
+        // 先从进程的预留中扣除一个槽位，全局预留计数和PCB中的计数不会对不上
+        let reserved = self.with_process_mut(|process| {
+            if process.reserved_handlers == 0 {
+                return false;
+            }
+            process.reserved_handlers -= 1;
+            true
+        })?;
+
+        if !reserved {
+            // 注册处理器
+            let result = super::register_handler(
+                trap_type,
+                handler_fn,
+                priority,
+                description,
+                Some(self.pid)
+            );
+
+            return Ok(result);
+        }
+
+        // 使用预留槽位注册
+        let result = super::register_reserved_handler(
             trap_type,
             handler_fn,
             priority,
             description,
             Some(self.pid)
         );
-        
+
+        // 注册失败时预留没有被消耗，还给进程；进程已无法访问时直接归还全局预留
+        if !result && self.with_process_mut(|process| process.reserved_handlers += 1).is_err() {
+            super::release_handler_slots(1);
+        }
+
         Ok(result)
     }
     
-    /// 使句柄无效
+    /// 使句柄无效并释放它持有的引用
+    ///
+    /// 这是最后一个句柄时进程随之销毁
     pub fn invalidate(&mut self) {
+        if !self.valid {
+            return;
+        }
         self.valid = false;
+
+        // 持锁期间关闭中断，中断处理程序中的句柄操作不会在这里等待
+        let was_enabled = disable_interrupts();
+        let released = PROCESS_POOL.lock().release(self.index, self.version);
+        restore_interrupts(was_enabled);
+
+        // PCB的Drop会注销处理器、释放ID，在池锁之外进行
+        drop(released);
     }
 }
 
 impl Drop for ProcessHandle {
     fn drop(&mut self) {
-        // 使句柄无效，防止进一步使用
-        self.valid = false;
+        // 释放引用并使句柄无效，防止进一步使用
+        self.invalidate();
+    }
+}
+
+/// 拥有进程所有权的句柄
+///
+/// 与`ProcessHandle`不同，离开作用域时总会调用`destroy_process`销毁进程，
+/// 即使还有从它复制出的句柄；进程注册的中断处理器随PCB的Drop一起注销。
+/// 通过`Deref`可以像`ProcessHandle`一样访问进程。
+pub struct OwnedProcess {
+    /// 内部的非拥有句柄
+    handle: ProcessHandle,
+}
+
+impl OwnedProcess {
+    /// 获取进程ID
+    pub fn pid(&self) -> ContextId {
+        self.handle.pid
+    }
+}
+
+impl Deref for OwnedProcess {
+    type Target = ProcessHandle;
+
+    fn deref(&self) -> &ProcessHandle {
+        &self.handle
+    }
+}
+
+impl Drop for OwnedProcess {
+    fn drop(&mut self) {
+        if !self.handle.valid {
+            return;
+        }
+
+        // 池锁被占用时无法销毁，只能记录下来，进程需要稍后手动销毁
+        if let Err(e) = destroy_process(self.handle.pid) {
+            println!("Failed to destroy owned process {}: {}", self.handle.pid, e);
+        }
+        self.handle.invalidate();
     }
 }
 
@@ -570,10 +779,24 @@ impl Drop for ProcessHandle {
 static PROCESS_POOL: Mutex<ContextPool<ProcessControlBlock>> = Mutex::new(ContextPool::new());
 
 /// 创建新进程
+///
+/// 未提供PID时生成一个，优先重用已销毁进程的PID；PID用尽时返回`PoolError::PoolFull`
 pub fn create_process(pid: Option<ContextId>) -> Result<ProcessHandle, PoolError> {
-    // 如果未提供PID，则生成一个
-    let real_pid = pid.unwrap_or_else(generate_context_id);
-    
+    let Some(pid) = pid else {
+        let pid = try_generate_context_id().ok_or(PoolError::PoolFull)?;
+        let result = insert_process(pid, true);
+        // 创建失败时归还生成的PID
+        if result.is_err() {
+            release_context_id(pid);
+        }
+        return result;
+    };
+
+    insert_process(pid, false)
+}
+
+/// 在进程池中创建进程，`owns_pid`表示PID由进程池生成
+fn insert_process(real_pid: ContextId, owns_pid: bool) -> Result<ProcessHandle, PoolError> {
     // 获取池锁
     let mut pool_guard = PROCESS_POOL.try_lock();
     let pool = match pool_guard.as_mut() {
@@ -582,10 +805,17 @@ pub fn create_process(pid: Option<ContextId>) -> Result<ProcessHandle, PoolError
     };
     
     // 创建进程
-    match pool.create_context(real_pid) {
-        Ok((id, token, version)) => Ok(ProcessHandle::new(id, token, version)),
-        Err(e) => Err(e),
-    }
+    let (id, token, version) = pool.create_context(real_pid)?;
+    pool.with_object_mut(id, token, version, |process| process.owns_pid = owns_pid)?;
+    let index = pool.find_index_by_id(id).ok_or(PoolError::ContextNotFound)?;
+    Ok(ProcessHandle::new(id, index, token, version))
+}
+
+/// 创建新进程，返回拥有所有权的句柄
+///
+/// 句柄离开作用域时进程会被自动销毁
+pub fn create_owned_process(pid: Option<ContextId>) -> Result<OwnedProcess, PoolError> {
+    create_process(pid).map(|handle| OwnedProcess { handle })
 }
 
 /// 销毁进程
@@ -596,6 +826,34 @@ pub fn destroy_process(pid: ContextId) -> Result<(), PoolError> {
         Some(guard) => guard,
         None => return Err(PoolError::LockBusy),
     };
-    
-    pool.destroy_context(pid)
-}
\ No newline at end of file
+
+    let process = pool.take_context(pid)?;
+    drop(pool_guard);
+
+    // PCB的Drop在池锁之外进行
+    drop(process);
+    Ok(())
+}
+
+/// 当前存在的进程数量
+pub fn process_count() -> usize {
+    PROCESS_POOL.lock().count()
+}
+
+/// 依次访问所有存在的进程，参数为(pid, 名称, 状态)
+///
+/// 遍历期间持有进程池锁，回调中不能创建、销毁、复制或丢弃进程句柄
+pub fn for_each_process(mut f: impl FnMut(ContextId, &'static str, u8)) {
+    PROCESS_POOL.lock().for_each(|pid, process| f(pid, process.name, process.state));
+}
+
+/// 按名称查找进程，有多个同名进程时返回第一个
+pub fn find_process_by_name(name: &str) -> Option<ContextId> {
+    let mut found = None;
+    for_each_process(|pid, process_name, _| {
+        if found.is_none() && process_name == name {
+            found = Some(pid);
+        }
+    });
+    found
+}
@@ -2,22 +2,44 @@
 //!
 //! This module provides concrete implementations of the trap system interfaces.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::println;
+use crate::try_println;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use crate::trap::ds::{
     TrapContext, TaskContext, TrapType, TrapHandlerResult, TrapError,
-    TrapMode, Interrupt, ContextError, ContextType, ContextState
+    TrapMode, Interrupt, ContextError, ContextType, ContextState, NestCounter
 };
+use crate::trap::ds::handler::{TrapHandler, TrapHandlerWithData};
 use super::traits::{
     TrapHandlerInterface, ContextManagerInterface, 
     HardwareControlInterface, TrapSystemConfig, ErrorManagerInterface
 };
 
+/// Handler function stored by `StandardTrapHandler`
+#[derive(Debug, Copy, Clone)]
+pub enum HandlerFn {
+    /// Plain handler that only receives the trap context
+    Plain(TrapHandler),
+    /// Handler that also receives the data pointer given at registration
+    WithData(TrapHandlerWithData, *mut ()),
+}
+
+impl HandlerFn {
+    /// Call the handler, passing the registered data pointer if there is one
+    #[inline]
+    fn call(self, context: &mut TrapContext) -> TrapHandlerResult {
+        match self {
+            HandlerFn::Plain(handler) => handler(context),
+            HandlerFn::WithData(handler, data) => handler(context, data),
+        }
+    }
+}
+
 /// Standard Trap Handler Implementation
 #[derive(Debug, Copy, Clone)]
 pub struct StandardTrapHandler {
     /// Function pointer to the handler implementation
-    handler_fn: fn(&mut TrapContext) -> TrapHandlerResult,
+    handler_fn: HandlerFn,
     
     /// Handler priority (lower = higher priority)
     priority: u8,
@@ -36,6 +58,27 @@ impl StandardTrapHandler {
         trap_type: TrapType,
         priority: u8,
         description: &'static str
+    ) -> Self {
+        Self::from_fn(HandlerFn::Plain(handler_fn), trap_type, priority, description)
+    }
+
+    /// Create a handler that receives `data` on every call
+    pub const fn with_data(
+        handler_fn: TrapHandlerWithData,
+        data: *mut (),
+        trap_type: TrapType,
+        priority: u8,
+        description: &'static str
+    ) -> Self {
+        Self::from_fn(HandlerFn::WithData(handler_fn, data), trap_type, priority, description)
+    }
+
+    /// Create a handler from either handler form
+    pub const fn from_fn(
+        handler_fn: HandlerFn,
+        trap_type: TrapType,
+        priority: u8,
+        description: &'static str
     ) -> Self {
         Self {
             handler_fn,
@@ -44,11 +87,22 @@ impl StandardTrapHandler {
             trap_type,
         }
     }
+
+    /// Change the priority recorded for this handler
+    pub fn set_priority(&mut self, priority: u8) {
+        self.priority = priority;
+    }
 }
 
+// SAFETY: the data pointer is only handed back to the handler it was
+// registered with; whoever registers it must keep it valid until the handler
+// is unregistered and make it safe to use from any hart.
+unsafe impl Send for StandardTrapHandler {}
+unsafe impl Sync for StandardTrapHandler {}
+
 impl TrapHandlerInterface for StandardTrapHandler {
     fn handle_trap(&self, context: &mut TrapContext) -> TrapHandlerResult {
-        (self.handler_fn)(context)
+        self.handler_fn.call(context)
     }
     
     fn get_trap_type(&self) -> TrapType {
@@ -77,27 +131,8 @@ impl RiscvHardwareControl {
 
 impl HardwareControlInterface for RiscvHardwareControl {
     fn init_trap_vector(&self, mode: TrapMode) {
-        // Implementation from the original vector.rs
-        unsafe {
-            // Declare the external assembly entry point
-            extern "C" {
-                fn __trap_entry();
-            }
-            
-            // Prepare value: address needs to be 4-byte aligned, mode in the lowest 2 bits
-            let addr = (__trap_entry as usize) & !0x3;
-            let mode_val = mode as usize;
-            let value = addr | mode_val;
-            
-            // Use inline assembly to directly write to stvec
-            core::arch::asm!(
-                "csrw stvec, {0}",
-                in(reg) value,
-                options(nostack)
-            );
-        }
-        
-        println!("Trap vector initialized with {:?} mode", mode);
+        // Shares the stvec setup (entry point or vector table) with vector.rs
+        crate::trap::infrastructure::init(mode);
     }
     
     fn enable_interrupts(&self) -> bool {
@@ -173,8 +208,46 @@ impl HardwareControlInterface for RiscvHardwareControl {
     }
 }
 
-/// Interrupt nesting counter, stored as atomic to be thread-safe
-static INTERRUPT_NEST_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Interrupt nesting counter, one level per hart
+static INTERRUPT_NEST_COUNT: NestCounter = NestCounter::new();
+
+/// Size of the guard band at the top of the interrupt stack
+const INTERRUPT_STACK_GUARD_SIZE: usize = 64;
+
+/// Pattern repeated through the guard band
+const INTERRUPT_STACK_CANARY: u64 = 0x5a5a_c3c3_a5a5_3c3c;
+
+/// Error code raised when a context no longer fits on the interrupt stack
+pub const INTERRUPT_STACK_OVERFLOW_ERROR_CODE: u16 = 0x60;
+
+/// Error code raised when the interrupt stack guard band has been overwritten
+pub const INTERRUPT_STACK_CORRUPTED_ERROR_CODE: u16 = 0x61;
+
+/// Number of interrupt stack overflows and guard band violations reported
+static INTERRUPT_STACK_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of interrupt stack overflows and guard band violations reported so far
+pub fn interrupt_stack_error_count() -> usize {
+    INTERRUPT_STACK_ERRORS.load(Ordering::SeqCst)
+}
+
+/// Report an interrupt stack problem to the error subsystem
+///
+/// This runs on the trap path, possibly while the interrupted code holds the
+/// error manager, so the error is only reported if the manager is free.
+fn report_interrupt_stack_error(code: u16, address: usize) {
+    INTERRUPT_STACK_ERRORS.fetch_add(1, Ordering::SeqCst);
+    let error = SystemError::new(
+        ErrorCode::new(ErrorSource::Interrupt, ErrorLevel::Critical, code),
+        Some(address),
+        0,
+        timer::now(),
+    );
+    try_println!("Interrupt stack error: {}", error);
+    if super::try_handle_system_error(error).is_none() {
+        try_println!("Error manager busy, interrupt stack error not logged");
+    }
+}
 
 /// Standard Context Manager Implementation
 /// 
@@ -198,34 +271,43 @@ impl StandardContextManager {
     /// Default maximum nesting level
     pub const DEFAULT_MAX_NEST_LEVEL: usize = 8;
     
+    /// Bytes of the interrupt stack that can hold saved contexts
+    pub const USABLE_STACK_SIZE: usize = Self::INTERRUPT_STACK_SIZE - INTERRUPT_STACK_GUARD_SIZE;
+    
     /// Create a new standard context manager
+    ///
+    /// The guard band at the top of the interrupt stack is filled with the canary pattern
     pub const fn new() -> Self {
+        let mut interrupt_stack = [0; Self::INTERRUPT_STACK_SIZE];
+        let canary = INTERRUPT_STACK_CANARY.to_le_bytes();
+        let mut i = Self::USABLE_STACK_SIZE;
+        while i < Self::INTERRUPT_STACK_SIZE {
+            interrupt_stack[i] = canary[i % canary.len()];
+            i += 1;
+        }
+
         Self {
-            interrupt_stack: [0; Self::INTERRUPT_STACK_SIZE],
+            interrupt_stack,
             interrupt_stack_top: 0,
             max_nest_level: Self::DEFAULT_MAX_NEST_LEVEL,
         }
     }
-    
-    /// Internal function to increase interrupt nesting level
-    fn enter_interrupt(&mut self) -> Result<usize, ContextError> {
-        let current = INTERRUPT_NEST_COUNT.fetch_add(1, Ordering::SeqCst);
-        if current >= self.max_nest_level {
-            // Roll back counter
-            INTERRUPT_NEST_COUNT.fetch_sub(1, Ordering::SeqCst);
-            return Err(ContextError::StackOverflow);
-        }
-        Ok(current + 1)
+
+    /// Whether the guard band still holds the canary pattern
+    fn guard_intact(&self) -> bool {
+        let canary = INTERRUPT_STACK_CANARY.to_le_bytes();
+        self.interrupt_stack[Self::USABLE_STACK_SIZE..]
+            .iter()
+            .enumerate()
+            .all(|(i, &byte)| byte == canary[(Self::USABLE_STACK_SIZE + i) % canary.len()])
     }
-    
-    /// Internal function to decrease interrupt nesting level
-    fn exit_interrupt(&mut self) -> Result<usize, ContextError> {
-        let current = INTERRUPT_NEST_COUNT.load(Ordering::Relaxed);
-        if current == 0 {
-            return Err(ContextError::StackUnderflow);
-        }
-        
-        Ok(INTERRUPT_NEST_COUNT.fetch_sub(1, Ordering::SeqCst) - 1)
+
+    /// Overwrite the start of the guard band, as a runaway write past the last context would
+    ///
+    /// Only for tests of the integrity check
+    pub(crate) fn simulate_guard_overrun(&mut self, bytes: usize) {
+        let end = Self::USABLE_STACK_SIZE + bytes.min(INTERRUPT_STACK_GUARD_SIZE);
+        self.interrupt_stack[Self::USABLE_STACK_SIZE..end].fill(0);
     }
 }
 
@@ -234,10 +316,14 @@ impl ContextManagerInterface for StandardContextManager {
         // Increase nesting level
         let level = self.enter_interrupt()?;
         
-        // Calculate stack position
+        // Calculate stack position, contexts must stay below the guard band
         let stack_offset = level * core::mem::size_of::<TrapContext>();
-        if stack_offset + core::mem::size_of::<TrapContext>() > Self::INTERRUPT_STACK_SIZE {
+        if stack_offset + core::mem::size_of::<TrapContext>() > Self::USABLE_STACK_SIZE {
             self.exit_interrupt().ok(); // Decrease nesting level
+            report_interrupt_stack_error(
+                INTERRUPT_STACK_OVERFLOW_ERROR_CODE,
+                self.interrupt_stack.as_ptr() as usize + stack_offset,
+            );
             return Err(ContextError::StackOverflow);
         }
         
@@ -256,6 +342,9 @@ impl ContextManagerInterface for StandardContextManager {
     }
     
     fn restore_context_from_interrupt(&mut self, ctx: &TrapContext) -> Result<(), ContextError> {
+        // Catch a context that overran its slot before it is restored
+        self.check_stack_integrity();
+
         // Decrease nesting level
         self.exit_interrupt()?;
         
@@ -296,15 +385,34 @@ impl ContextManagerInterface for StandardContextManager {
     
     fn get_interrupt_stack_usage(&self) -> (usize, usize) {
         let used = self.get_nest_level() * core::mem::size_of::<TrapContext>();
-        (used, Self::INTERRUPT_STACK_SIZE)
+        (used, Self::USABLE_STACK_SIZE)
+    }
+
+    fn check_stack_integrity(&self) -> bool {
+        if self.guard_intact() {
+            return true;
+        }
+        report_interrupt_stack_error(
+            INTERRUPT_STACK_CORRUPTED_ERROR_CODE,
+            self.interrupt_stack.as_ptr() as usize + Self::USABLE_STACK_SIZE,
+        );
+        false
     }
     
+    fn enter_interrupt(&self) -> Result<usize, ContextError> {
+        INTERRUPT_NEST_COUNT.enter(self.max_nest_level)
+    }
+
+    fn exit_interrupt(&self) -> Result<usize, ContextError> {
+        INTERRUPT_NEST_COUNT.exit()
+    }
+
     fn is_in_interrupt_context(&self) -> bool {
         self.get_nest_level() > 0
     }
     
     fn get_nest_level(&self) -> usize {
-        INTERRUPT_NEST_COUNT.load(Ordering::Relaxed)
+        INTERRUPT_NEST_COUNT.level()
     }
     
     fn set_max_nest_level(&mut self, level: usize) {
@@ -313,8 +421,8 @@ impl ContextManagerInterface for StandardContextManager {
 }
 
 use crate::trap::ds::{
-    SystemError, ErrorResult, ErrorHandler, ErrorHandlerEntry,
-    ErrorSource, ErrorLevel, ErrorCode, ErrorManager
+    SystemError, ErrorResult, ErrorHandler, ErrorHandlerWithContext, ErrorHandlerEntry,
+    ErrorSource, ErrorLevel, ErrorCode, ErrorManager, ErrorLogEntry,
 };
 use crate::util::sbi::timer;
 
@@ -360,6 +468,18 @@ impl ErrorManagerInterface for StandardErrorManager {
         let entry = ErrorHandlerEntry::new(handler, priority, description, source, level);
         self.manager.register_handler(entry)
     }
+
+    fn register_context_handler(
+        &mut self,
+        handler: ErrorHandlerWithContext,
+        priority: u8,
+        description: &'static str,
+        source: Option<ErrorSource>,
+        level: Option<ErrorLevel>
+    ) -> bool {
+        let entry = ErrorHandlerEntry::with_context(handler, priority, description, source, level);
+        self.manager.register_handler(entry)
+    }
     
     fn unregister_handler(&mut self, description: &str) -> bool {
         self.manager.unregister_handler(description)
@@ -368,10 +488,18 @@ impl ErrorManagerInterface for StandardErrorManager {
     fn handle_error(&mut self, error: SystemError) -> ErrorResult {
         self.manager.handle_error(error)
     }
+
+    fn handle_error_with_context(&mut self, error: SystemError, context: &mut TrapContext) -> ErrorResult {
+        self.manager.handle_error_with_context(error, context)
+    }
     
     fn print_error_log(&self, count: usize) {
         self.manager.get_log().print_recent(count)
     }
+
+    fn export_error_log(&self, out: &mut [ErrorLogEntry]) -> usize {
+        self.manager.get_log().snapshot(out)
+    }
     
     fn clear_error_log(&mut self) {
         self.manager.get_log_mut().clear();
@@ -381,6 +509,22 @@ impl ErrorManagerInterface for StandardErrorManager {
     fn print_handlers(&self) {
         self.manager.print_handlers()
     }
+
+    fn handler_capacity(&self) -> (usize, usize) {
+        (self.manager.handler_capacity(), self.manager.handler_count())
+    }
+
+    fn error_count(&self, source: ErrorSource) -> u64 {
+        self.manager.error_count(source)
+    }
+
+    fn print_error_summary(&self) {
+        self.manager.print_error_summary()
+    }
+
+    fn set_escalation_threshold(&self, threshold: Option<usize>) {
+        self.manager.set_escalation_threshold(threshold)
+    }
     
     fn is_panic_mode(&self) -> bool {
         self.manager.is_panic_mode()
@@ -399,6 +543,6 @@ impl ErrorManagerInterface for StandardErrorManager {
         ip: usize
     ) -> SystemError {
         let error_code = ErrorCode::new(source, level, code);
-        SystemError::new(error_code, address, ip, timer::get_time())
+        SystemError::new(error_code, address, ip, timer::now())
     }
 }
\ No newline at end of file
@@ -13,24 +13,38 @@ pub mod context_pool;
 
 use self::context::{ContextId, KERNEL_CONTEXT_ID};
 
-use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use spin::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use spin::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use crate::println;
+use crate::try_println;
+use crate::trap_log;
 use self::impls::StandardErrorManager;
 use crate::trap::ds::{
     TrapContext, TaskContext, TrapType, TrapHandlerResult, TrapError,
-    SystemError, ErrorResult, ErrorHandler, ErrorSource, ErrorLevel,
-    TrapMode, Interrupt, ContextError
+    SystemError, ErrorResult, ErrorHandler, ErrorHandlerWithContext, ErrorSource, ErrorLevel,
+    ErrorLogEntry, TrapMode, Interrupt, ContextError
 };
-use self::impls::{StandardContextManager, RiscvHardwareControl, StandardTrapHandler};
+use crate::trap::ds::init_phase::{InitPhase, advance_phase};
+use crate::util::delay::busy_wait_us;
+use self::impls::{StandardContextManager, RiscvHardwareControl, StandardTrapHandler, HandlerFn};
+use crate::trap::ds::handler::{TrapHandlerWithData, ProtectionLevel, SYSTEM_REGISTRAR_ID};
+use super::registry::HandlerDetails;
 use self::traits::DefaultTrapSystemConfig;
 use self::container::MAX_TRAP_HANDLERS;
+use crate::util::percpu::PerCpu;
+use crate::util::sbi::hart::{self, MAX_HARTS};
+
+pub use crate::util::sbi::hart::current_hart_id;
+
+/// 使用标准组件的trap系统
+pub type StandardTrapSystem = TrapSystem<StandardContextManager, RiscvHardwareControl, StandardErrorManager>;
 
 /// Global trap system instance flag - atomic for thread safety
 static TRAP_SYSTEM_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
-/// Static storage for context manager - protected by Mutex
-static CONTEXT_MANAGER: Mutex<StandardContextManager> = Mutex::new(StandardContextManager::new());
+/// 每个核心的上下文管理器，各自拥有独立的中断栈
+static CONTEXT_MANAGERS: PerCpu<Mutex<StandardContextManager>, MAX_HARTS> =
+    PerCpu::new([const { Mutex::new(StandardContextManager::new()) }; MAX_HARTS]);
 
 /// Static storage for hardware control - protected by Mutex
 static HARDWARE_CONTROL: Mutex<RiscvHardwareControl> = Mutex::new(RiscvHardwareControl::new());
@@ -38,36 +52,189 @@ static HARDWARE_CONTROL: Mutex<RiscvHardwareControl> = Mutex::new(RiscvHardwareC
 /// Static storage for trap system configuration
 static TRAP_SYSTEM_CONFIG: DefaultTrapSystemConfig = DefaultTrapSystemConfig {};
 
-/// Static storage for trap system - protected by Mutex
-static TRAP_SYSTEM: Mutex<Option<TrapSystem<StandardContextManager, RiscvHardwareControl, StandardErrorManager>>> = Mutex::new(None);
+/// 每个核心的trap系统，以核心ID为下标
+///
+/// 每个核心有自己的锁和处理器表，分发trap时只获取当前核心实例的读锁，
+/// 注册和注销才需要写锁。初始化时为所有存在的核心创建实例，超出核心数量的槽位保持为None。
+static TRAP_SYSTEMS: PerCpu<RwLock<Option<StandardTrapSystem>>, MAX_HARTS> =
+    PerCpu::new([const { RwLock::new(None) }; MAX_HARTS]);
 
 /// Static storage for error manager - protected by Mutex
+///
+/// 所有核心的trap系统共享同一个错误管理器，访问时必须持有该锁
 static ERROR_MANAGER: Mutex<StandardErrorManager> = Mutex::new(StandardErrorManager::new());
 
+/// 内核上下文的处理器是否注册到所有核心
+static BROADCAST_KERNEL_HANDLERS: AtomicBool = AtomicBool::new(true);
+
 /// Maximum number of custom handlers
 const MAX_CUSTOM_HANDLERS: usize = 64;
 
 /// Static storage for handler instances
-static HANDLER_STORAGE: Mutex<[Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]> = {
+///
+/// 分发只需要读锁，多个核心可以同时分发；注册、注销和整理持有写锁
+static HANDLER_STORAGE: RwLock<[Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]> = {
     const NONE_HANDLER: Option<StandardTrapHandler> = None;
-    Mutex::new([NONE_HANDLER; MAX_CUSTOM_HANDLERS])
+    RwLock::new([NONE_HANDLER; MAX_CUSTOM_HANDLERS])
 };
 
+/// 每个存储槽位上的处理器被调用的次数
+///
+/// 处理器存入槽位时清零，整理存储区时随处理器一起移动
+static HANDLER_INVOCATIONS: [AtomicUsize; MAX_CUSTOM_HANDLERS] =
+    [const { AtomicUsize::new(0) }; MAX_CUSTOM_HANDLERS];
+
 /// 为默认处理器预留的存储槽位范围
 const DEFAULT_HANDLER_START_IDX: usize = 0;
 const DEFAULT_HANDLER_END_IDX: usize = 9; // 预留10个槽位给默认处理器
 
+/// 每个上下文默认允许注册的处理器数量
+pub const DEFAULT_CONTEXT_HANDLER_QUOTA: usize = 8;
+
+/// 每个上下文允许注册的处理器数量，内核上下文不受限制
+static CONTEXT_HANDLER_QUOTA: AtomicUsize = AtomicUsize::new(DEFAULT_CONTEXT_HANDLER_QUOTA);
+
+/// 已为上下文预留但尚未使用的处理器槽位数
+///
+/// 只在持有 HANDLER_STORAGE 锁时修改，保证检查容量和更新预留的原子性
+static RESERVED_HANDLER_SLOTS: AtomicUsize = AtomicUsize::new(0);
+
+/// 处理器存储锁忙时的最大重试次数
+const STORAGE_LOCK_RETRIES: u32 = 8;
+
+/// 第一次重试前的退避时间（微秒），之后每次翻倍
+const STORAGE_LOCK_BACKOFF_US: u64 = 10;
+
+/// 经过重试才获得处理器存储锁的次数
+static STORAGE_LOCK_RETRY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 模拟的锁持有者在第几次退避时释放锁，`u32::MAX`表示没有模拟
+#[cfg(feature = "test_hooks")]
+static SIMULATED_RELEASE_AFTER: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// 每个核心已注册处理器的类型位图，注册、注销处理器或修复处理器表后更新
+///
+/// 分发时据此跳过没有处理器的类型，不必获取存储锁
+static HANDLER_TYPE_MASKS: PerCpu<AtomicU32, MAX_HARTS> =
+    PerCpu::new([const { AtomicU32::new(0) }; MAX_HARTS]);
+
+/// 分发时获取处理器存储锁的次数
+static DISPATCH_STORAGE_LOCKS: AtomicUsize = AtomicUsize::new(0);
+
+/// 每个核心上正在分发、持有处理器存储读锁的层数
+static DISPATCH_DEPTH: PerCpu<AtomicUsize, MAX_HARTS> =
+    PerCpu::new([const { AtomicUsize::new(0) }; MAX_HARTS]);
+
+/// 注册中断处理器失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterError {
+    /// trap系统尚未初始化
+    NotInitialized,
+    /// 处理器存储锁被占用，重试后仍未获得
+    StorageLocked,
+    /// 没有可用的存储槽位
+    StorageFull,
+    /// 重复注册、超出上下文配额或没有预留槽位等原因被拒绝
+    Rejected,
+}
+
+/// 分发期间持有的处理器存储读锁
+///
+/// 处理器运行期间读锁一直被持有，同一核心上的写锁请求在处理器返回之前不可能成功，
+/// 这里记录当前核心的分发层数，让`lock_handler_storage`不必白白退避。
+struct DispatchStorage {
+    guard: RwLockReadGuard<'static, [Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]>,
+}
+
+impl DispatchStorage {
+    fn lock() -> Self {
+        let guard = HANDLER_STORAGE.read();
+        DISPATCH_STORAGE_LOCKS.fetch_add(1, Ordering::SeqCst);
+        DISPATCH_DEPTH.get().fetch_add(1, Ordering::SeqCst);
+        Self { guard }
+    }
+}
+
+impl core::ops::Deref for DispatchStorage {
+    type Target = [Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS];
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl Drop for DispatchStorage {
+    fn drop(&mut self) {
+        DISPATCH_DEPTH.get().fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 获取处理器存储锁，锁忙时按指数退避有限次重试
+///
+/// 注册和注销可能与其他核心上的同类操作短暂竞争，
+/// 只有在全部重试之后仍然拿不到锁才返回None。
+/// 在处理器中调用时当前核心自己持有读锁，重试不会成功，直接返回None。
+fn lock_handler_storage() -> Option<RwLockWriteGuard<'static, [Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]>> {
+    if DISPATCH_DEPTH.get().load(Ordering::SeqCst) > 0 {
+        return None;
+    }
+
+    let mut backoff_us = STORAGE_LOCK_BACKOFF_US;
+
+    for attempt in 0..=STORAGE_LOCK_RETRIES {
+        if let Some(guard) = HANDLER_STORAGE.try_write() {
+            if attempt > 0 {
+                STORAGE_LOCK_RETRY_COUNT.fetch_add(1, Ordering::SeqCst);
+            }
+            return Some(guard);
+        }
+
+        if attempt == STORAGE_LOCK_RETRIES {
+            break;
+        }
+
+        #[cfg(feature = "test_hooks")]
+        if SIMULATED_RELEASE_AFTER.load(Ordering::SeqCst) == attempt {
+            SIMULATED_RELEASE_AFTER.store(u32::MAX, Ordering::SeqCst);
+            // 模拟的持有者在这里释放锁
+            unsafe { HANDLER_STORAGE.force_write_unlock() };
+        }
+
+        busy_wait_us(backoff_us);
+        backoff_us *= 2;
+    }
+
+    None
+}
+
+/// 获取经过重试才获得处理器存储锁的次数
+pub fn storage_lock_retry_count() -> usize {
+    STORAGE_LOCK_RETRY_COUNT.load(Ordering::SeqCst)
+}
+
+/// 模拟其他核心持有处理器存储锁
+///
+/// 立即占用锁，并在第`release_after`次退避时释放。仅供测试验证重试逻辑，
+/// `release_after`必须小于重试次数，否则锁不会被释放。
+#[cfg(feature = "test_hooks")]
+pub(crate) fn simulate_storage_contention(release_after: u32) {
+    assert!(release_after < STORAGE_LOCK_RETRIES);
+    core::mem::forget(HANDLER_STORAGE.write());
+    SIMULATED_RELEASE_AFTER.store(release_after, Ordering::SeqCst);
+}
+
 /// Default handler implementations
 
 /// Timer interrupt handler
 fn default_timer_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Timer interrupt occurred");
+    // Run tick callbacks and program the next deadline
+    crate::util::sbi::timer::handle_tick();
     TrapHandlerResult::Handled
 }
 
 /// Software interrupt handler
 fn default_software_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Software interrupt occurred");
+    trap_log!("Software interrupt occurred");
     with_trap_system(|trap_system| {
         trap_system.get_hardware_control().clear_soft_interrupt();
     });
@@ -76,13 +243,23 @@ fn default_software_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
 
 /// External interrupt handler
 fn default_external_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("External interrupt occurred");
+    // Claim and service pending IRQs; coalescing mode drains them all in one trap
+    if super::plic::handle_pending() == 0 {
+        try_println!("Spurious external interrupt: nothing to claim");
+    }
     TrapHandlerResult::Handled
 }
 
 /// System call handler
 fn default_syscall_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("System call occurred");
+    // Looks up a7 in the syscall table, writes a0 and advances PC past the ecall
+    crate::trap::syscall::dispatch(ctx);
+    TrapHandlerResult::Handled
+}
+
+/// Supervisor ecall handler
+fn default_supervisor_call_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
+    trap_log!("Supervisor call occurred");
     // Advance PC past the ecall instruction
     ctx.set_return_addr(ctx.sepc + 4);
     TrapHandlerResult::Handled
@@ -90,19 +267,19 @@ fn default_syscall_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
 
 /// Page fault handler
 fn default_page_fault_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Page fault occurred, address: {:#x}", ctx.stval);
+    try_println!("Page fault occurred, address: {:#x}", ctx.stval);
     TrapHandlerResult::Handled
 }
 
 /// Illegal instruction handler
 fn default_illegal_instruction_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Illegal instruction: {:#x}", ctx.stval);
+    try_println!("Illegal instruction: {:#x}", ctx.stval);
     TrapHandlerResult::Handled
 }
 
 /// Breakpoint handler
 fn default_breakpoint_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Breakpoint occurred at: {:#x}", ctx.sepc);
+    try_println!("Breakpoint occurred at: {:#x}", ctx.sepc);
     // 断点处理需要手动前进PC
     ctx.set_return_addr(ctx.sepc + 4);
     TrapHandlerResult::Handled
@@ -110,27 +287,17 @@ fn default_breakpoint_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
 
 /// Unknown trap handler
 fn default_unknown_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Unknown trap: cause={:#x}, addr={:#x}", ctx.scause, ctx.stval);
+    try_println!("Unknown trap: cause={:#x}, addr={:#x}", ctx.scause, ctx.stval);
     TrapHandlerResult::Handled
 }
 
-/// Initialize the trap system with dependency injection
-///
-/// # 并发安全性
+/// 为指定核心创建trap系统实例
 ///
-/// 此函数使用原子变量确保只初始化一次，即使多个核心并发调用也安全。
-pub fn initialize_trap_system(mode: TrapMode) {
-    // Use CAS operation to safely check and set initialization flag
-    if TRAP_SYSTEM_INITIALIZED.compare_exchange(
-        false, true, Ordering::SeqCst, Ordering::SeqCst
-    ).is_err() {
-        println!("Trap system already initialized");
-        return;
-    }
-
+/// 上下文管理器按核心独立，硬件控制和错误管理器由所有核心共享
+fn create_trap_system(hart: usize) -> StandardTrapSystem {
     // Create static references using raw pointers to static data with lock protection
     let context_manager = {
-        let mut cm = CONTEXT_MANAGER.lock();
+        let mut cm = CONTEXT_MANAGERS.get_for(hart).lock();
         container::StaticRef::new(&mut *cm as *mut StandardContextManager)
     };
 
@@ -144,23 +311,48 @@ pub fn initialize_trap_system(mode: TrapMode) {
         container::StaticRef::new(&mut *em as *mut StandardErrorManager)
     };
 
-    // Create trap system
-    let mut trap_system = container::TrapSystem::new(
+    container::TrapSystem::new(
         context_manager,
         hardware_control,
         error_manager,
         &TRAP_SYSTEM_CONFIG,
-    );
+    )
+}
 
-    // Initialize the system
-    trap_system.initialize(mode);
+/// Initialize the trap system with dependency injection
+///
+/// 为每个存在的核心创建一个trap系统实例，并在当前核心上设置trap向量。
+/// 其他核心启动后需要调用`initialize_hart`设置各自的trap向量。
+///
+/// # 并发安全性
+///
+/// 此函数使用原子变量确保只初始化一次，即使多个核心并发调用也安全。
+/// 本次调用完成了初始化时返回true，已经初始化过时返回false。
+pub fn initialize_trap_system(mode: TrapMode) -> bool {
+    // Use CAS operation to safely check and set initialization flag
+    if TRAP_SYSTEM_INITIALIZED.compare_exchange(
+        false, true, Ordering::SeqCst, Ordering::SeqCst
+    ).is_err() {
+        println!("Trap system already initialized");
+        return false;
+    }
 
-    // Store the trap system
-    {
-        let mut ts = TRAP_SYSTEM.lock();
-        *ts = Some(trap_system);
+    // 注册表按trap系统的配置限制每种类型的处理器数量
+    super::registry::configure(&TRAP_SYSTEM_CONFIG);
+
+    let harts = hart::hart_count();
+    for hart in 0..harts {
+        *TRAP_SYSTEMS.get_for(hart).write() = Some(create_trap_system(hart));
     }
+    println!("Created trap systems for {} harts", harts);
+
+    // Initialize the system on this hart
+    with_trap_system_mut(|trap_system| {
+        trap_system.initialize(mode);
+        publish_handler_type_mask(current_hart_id(), trap_system);
+    });
 
+    advance_phase(InitPhase::VectorReady);
     println!("Trap system initialized with dependency injection");
 
     // 注册默认处理器
@@ -168,6 +360,7 @@ pub fn initialize_trap_system(mode: TrapMode) {
 
     let default_handlers_registered = register_default_handlers();
     println!("Registered {} default trap handlers", default_handlers_registered);
+    true
 }
 
 /// 内部函数：注册默认处理器
@@ -178,8 +371,7 @@ fn register_default_handler(
     description: &'static str
 ) -> bool {
     // 加锁 HANDLER_STORAGE
-    let storage_result = HANDLER_STORAGE.try_lock();
-    let mut storage = match storage_result {
+    let mut storage = match lock_handler_storage() {
         Some(guard) => guard,
         None => {
             println!("Cannot register default handler: storage lock busy");
@@ -197,7 +389,7 @@ fn register_default_handler(
     }
 
     if idx == DEFAULT_HANDLER_END_IDX && storage[idx].is_some() {
-        println!("Cannot register default handler: no empty slots in reserved range");
+        try_println!("Cannot register default handler: no empty slots in reserved range");
         return false;
     }
 
@@ -210,20 +402,29 @@ fn register_default_handler(
     );
 
     storage[idx] = Some(handler);
+    HANDLER_INVOCATIONS[idx].store(0, Ordering::SeqCst);
 
     // 释放锁，防止死锁
     drop(storage);
 
-    // 调用 trap_system 注册处理器 - 使用内核上下文ID
-    let result = with_trap_system_mut(|trap_system| {
-        trap_system.register_handler(idx, priority, trap_type, description, KERNEL_CONTEXT_ID)
+    // 调用 trap_system 注册处理器 - 默认处理器使用内核上下文ID，总是注册到所有核心
+    let mut result = true;
+    for_each_trap_system(|hart, trap_system| {
+        result &= trap_system.register_handler(idx, priority, trap_type, description, KERNEL_CONTEXT_ID);
+        publish_handler_type_mask(hart, trap_system);
     });
+    if !result {
+        for_each_trap_system(|hart, trap_system| {
+            trap_system.unregister_handler(idx);
+            publish_handler_type_mask(hart, trap_system);
+        });
+    }
 
     // 如果注册失败，回滚
     if !result {
-        if let Some(mut storage) = HANDLER_STORAGE.try_lock() {
+        if let Some(mut storage) = lock_handler_storage() {
             storage[idx] = None;
-            println!("Failed to register default handler in trap system, rolling back storage");
+            try_println!("Failed to register default handler in trap system, rolling back storage");
         } else {
             println!("Warning: Failed to roll back handler registration, storage lock busy");
         }
@@ -276,6 +477,16 @@ fn register_default_handlers() -> usize {
         registered_count += 1;
     }
 
+    // 注册S模式ecall默认处理器
+    if register_default_handler(
+        TrapType::SupervisorCall,
+        default_supervisor_call_handler,
+        100,
+        "Default Supervisor Call Handler"
+    ) {
+        registered_count += 1;
+    }
+
     // 注册指令页错误默认处理器
     if register_default_handler(
         TrapType::InstructionPageFault,
@@ -339,34 +550,60 @@ fn register_default_handlers() -> usize {
     registered_count
 }
 
-/// Execute a function with a reference to the trap system
+/// 在其他核心上启用trap系统
+///
+/// 核心启动后调用，设置本核心的trap向量和上下文管理器。
+/// trap系统尚未初始化或本核心没有trap系统实例时返回false。
+pub fn initialize_hart(mode: TrapMode) -> bool {
+    if !get_trap_system_initialized() {
+        println!("Cannot initialize hart {}: trap system not initialized", current_hart_id());
+        return false;
+    }
+
+    match TRAP_SYSTEMS.get().write().as_mut() {
+        Some(trap_system) => {
+            trap_system.initialize(mode);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 内核上下文的处理器是否注册到所有核心
+pub fn broadcast_kernel_handlers() -> bool {
+    BROADCAST_KERNEL_HANDLERS.load(Ordering::SeqCst)
+}
+
+/// 设置之后注册的内核上下文处理器是否注册到所有核心
+///
+/// 关闭后内核处理器与其他上下文的处理器一样只注册到当前核心。
+/// 已经注册的处理器不受影响。
+pub fn set_broadcast_kernel_handlers(enabled: bool) {
+    BROADCAST_KERNEL_HANDLERS.store(enabled, Ordering::SeqCst);
+}
+
+/// Execute a function with a reference to the current hart's trap system
 ///
 /// # 并发安全性
 ///
-/// 此函数使用Mutex确保在中断上下文和多核环境中的安全访问。
-/// 不要在持有锁时禁用中断，否则可能导致死锁。
+/// 此函数获取当前核心trap系统的读锁，多个读者可以同时进入，
+/// 不同核心之间也不会互相阻塞。不要在持有锁时禁用中断，否则可能导致死锁。
 ///
 /// # Panics
 ///
 /// Panics if the trap system is not initialized
 pub fn with_trap_system<F, R>(f: F) -> R
 where
-    F: FnOnce(&TrapSystem<StandardContextManager, RiscvHardwareControl, StandardErrorManager>) -> R,
+    F: FnOnce(&StandardTrapSystem) -> R,
 {
-    if !TRAP_SYSTEM_INITIALIZED.load(Ordering::SeqCst) {
-        panic!("Trap system not initialized");
-    }
-
-    let guard = TRAP_SYSTEM.lock();
-    let trap_system = guard.as_ref().expect("Trap system is None but initialized flag is true");
-    f(trap_system)
+    with_trap_system_on(current_hart_id(), f)
 }
 
-/// Execute a function with a mutable reference to the trap system
+/// Execute a function with a mutable reference to the current hart's trap system
 ///
 /// # 并发安全性
 ///
-/// 此函数使用Mutex确保在中断上下文和多核环境中的安全访问。
+/// 此函数获取当前核心trap系统的写锁，会等待所有读者退出。
 /// 不要在持有锁时禁用中断，否则可能导致死锁。
 ///
 /// # Panics
@@ -374,22 +611,233 @@ where
 /// Panics if the trap system is not initialized
 pub fn with_trap_system_mut<F, R>(f: F) -> R
 where
-    F: FnOnce(&mut TrapSystem<StandardContextManager, RiscvHardwareControl, StandardErrorManager>) -> R,
+    F: FnOnce(&mut StandardTrapSystem) -> R,
+{
+    with_trap_system_on_mut(current_hart_id(), f)
+}
+
+/// 访问指定核心的trap系统
+///
+/// # Panics
+///
+/// trap系统未初始化或该核心没有trap系统实例时panic
+pub fn with_trap_system_on<F, R>(hart: usize, f: F) -> R
+where
+    F: FnOnce(&StandardTrapSystem) -> R,
+{
+    if !TRAP_SYSTEM_INITIALIZED.load(Ordering::SeqCst) {
+        panic!("Trap system not initialized");
+    }
+
+    let guard = TRAP_SYSTEMS.get_for(hart).read();
+    let trap_system = guard.as_ref().unwrap_or_else(|| panic!("No trap system for hart {}", hart));
+    f(trap_system)
+}
+
+/// 以可变方式访问指定核心的trap系统
+///
+/// # Panics
+///
+/// trap系统未初始化或该核心没有trap系统实例时panic
+pub fn with_trap_system_on_mut<F, R>(hart: usize, f: F) -> R
+where
+    F: FnOnce(&mut StandardTrapSystem) -> R,
 {
     if !TRAP_SYSTEM_INITIALIZED.load(Ordering::SeqCst) {
         panic!("Trap system not initialized");
     }
 
-    let mut guard = TRAP_SYSTEM.lock();
-    let trap_system = guard.as_mut().expect("Trap system is None but initialized flag is true");
+    let mut guard = TRAP_SYSTEMS.get_for(hart).write();
+    let trap_system = guard.as_mut().unwrap_or_else(|| panic!("No trap system for hart {}", hart));
     f(trap_system)
 }
 
+/// 同步分发快速路径使用的类型位图
+///
+/// 修改了该核心处理器表中处理器的集合后调用，其他修改不影响位图，不需要重新计算
+fn publish_handler_type_mask(hart: usize, trap_system: &StandardTrapSystem) {
+    HANDLER_TYPE_MASKS.get_for(hart).store(trap_system.handler_type_mask(), Ordering::SeqCst);
+}
+
+/// 依次访问每个拥有trap系统实例的核心
+///
+/// 一次只持有一个核心的锁，回调参数为核心ID和该核心的trap系统
+fn for_each_trap_system<F>(mut f: F)
+where
+    F: FnMut(usize, &mut StandardTrapSystem),
+{
+    for hart in 0..MAX_HARTS {
+        let mut guard = TRAP_SYSTEMS.get_for(hart).write();
+        if let Some(trap_system) = guard.as_mut() {
+            f(hart, trap_system);
+        }
+    }
+}
+
+/// 依次以只读方式访问每个拥有trap系统实例的核心
+///
+/// 与`for_each_trap_system`相同，但只获取读锁，不会阻塞其他核心上正在进行的分发
+fn for_each_trap_system_ref<F>(mut f: F)
+where
+    F: FnMut(usize, &StandardTrapSystem),
+{
+    for hart in 0..MAX_HARTS {
+        let guard = TRAP_SYSTEMS.get_for(hart).read();
+        if let Some(trap_system) = guard.as_ref() {
+            f(hart, trap_system);
+        }
+    }
+}
+
+/// 把处理器注册到它应当可见的核心上
+///
+/// 开启广播时内核上下文的处理器注册到所有核心，其余处理器只注册到当前核心。
+/// 任何一个核心注册失败时，撤销已经完成的注册并返回false。
+fn register_on_harts(
+    index: usize,
+    priority: u8,
+    trap_type: TrapType,
+    description: &'static str,
+    context_id: Option<ContextId>
+) -> bool {
+    if context_id != KERNEL_CONTEXT_ID || !broadcast_kernel_handlers() {
+        let hart = current_hart_id();
+        return with_trap_system_on_mut(hart, |trap_system| {
+            let registered = trap_system.register_handler(index, priority, trap_type, description, context_id);
+            publish_handler_type_mask(hart, trap_system);
+            registered
+        });
+    }
+
+    let mut failed = false;
+    for_each_trap_system(|hart, trap_system| {
+        if !failed {
+            failed = !trap_system.register_handler(index, priority, trap_type, description, context_id);
+            publish_handler_type_mask(hart, trap_system);
+        }
+    });
+
+    if failed {
+        // 注册失败的核心上没有该索引，注销只会作用于已经注册的核心
+        for_each_trap_system(|hart, trap_system| {
+            trap_system.unregister_handler(index);
+            publish_handler_type_mask(hart, trap_system);
+        });
+    }
+    !failed
+}
+
+/// 检查当前核心是否有处理器注册在指定类型上，不获取任何锁
+pub fn has_handlers_for(trap_type: TrapType) -> bool {
+    HANDLER_TYPE_MASKS.get().load(Ordering::SeqCst) & (1 << trap_type as u32) != 0
+}
+
+/// 获取分发时获取处理器存储锁的次数
+pub fn dispatch_storage_lock_count() -> usize {
+    DISPATCH_STORAGE_LOCKS.load(Ordering::SeqCst)
+}
+
 /// Check if the trap system is initialized
 pub fn get_trap_system_initialized() -> bool {
     TRAP_SYSTEM_INITIALIZED.load(Ordering::SeqCst)
 }
 
+/// 计算当前还能注册的处理器数量（包括已预留的部分）
+///
+/// 同时受自定义存储槽位和trap系统处理器表两者容量的限制，
+/// 处理器表的容量以剩余空间最少的核心为准
+fn free_handler_slots(storage: &[Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]) -> usize {
+    let free_storage = ((DEFAULT_HANDLER_END_IDX + 1)..MAX_CUSTOM_HANDLERS)
+        .filter(|&i| storage[i].is_none())
+        .count();
+
+    let mut free_system = MAX_TRAP_HANDLERS;
+    for_each_trap_system(|_, trap_system| {
+        free_system = free_system.min(MAX_TRAP_HANDLERS - trap_system.total_handler_count());
+    });
+
+    free_storage.min(free_system)
+}
+
+/// 获取未被预留、可供任意注册者使用的处理器槽位数
+pub fn unreserved_handler_slots() -> usize {
+    if !get_trap_system_initialized() {
+        return 0;
+    }
+
+    let storage = HANDLER_STORAGE.read();
+    free_handler_slots(&storage).saturating_sub(RESERVED_HANDLER_SLOTS.load(Ordering::SeqCst))
+}
+
+/// 预留指定数量的处理器槽位
+///
+/// 预留的槽位只能通过`register_reserved_handler`使用，
+/// 普通的`register_handler`不会占用它们。容量不足时返回false且不做任何预留。
+pub fn reserve_handler_slots(count: usize) -> bool {
+    if !get_trap_system_initialized() {
+        println!("Cannot reserve handler slots: trap system not initialized");
+        return false;
+    }
+
+    let storage = match lock_handler_storage() {
+        Some(guard) => guard,
+        None => {
+            println!("Cannot reserve handler slots: handler storage lock busy");
+            return false;
+        }
+    };
+
+    let reserved = RESERVED_HANDLER_SLOTS.load(Ordering::SeqCst);
+    let available = free_handler_slots(&storage).saturating_sub(reserved);
+    if count > available {
+        println!("Cannot reserve {} handler slots: only {} available", count, available);
+        return false;
+    }
+
+    RESERVED_HANDLER_SLOTS.store(reserved + count, Ordering::SeqCst);
+    true
+}
+
+/// 归还尚未使用的预留槽位
+pub fn release_handler_slots(count: usize) {
+    let _storage = HANDLER_STORAGE.write();
+    let reserved = RESERVED_HANDLER_SLOTS.load(Ordering::SeqCst);
+    RESERVED_HANDLER_SLOTS.store(reserved.saturating_sub(count), Ordering::SeqCst);
+}
+
+/// 获取每个上下文的处理器配额
+pub fn context_handler_quota() -> usize {
+    CONTEXT_HANDLER_QUOTA.load(Ordering::SeqCst)
+}
+
+/// 设置每个上下文的处理器配额
+///
+/// 已经超出新配额的上下文不受影响，但在降到配额以下之前不能再注册
+pub fn set_context_handler_quota(quota: usize) {
+    CONTEXT_HANDLER_QUOTA.store(quota, Ordering::SeqCst);
+}
+
+/// 获取与指定上下文关联的处理器数量
+///
+/// 直接统计所有核心trap系统中的注册信息，注销处理器后自动减少。
+/// 上下文的处理器只注册在一个核心上，不会被重复计数。
+pub fn context_handler_count(context_id: ContextId) -> usize {
+    if !get_trap_system_initialized() {
+        return 0;
+    }
+
+    let mut count = 0;
+    for_each_trap_system_ref(|_, trap_system| {
+        count += trap_system.handler_count_for_context(context_id);
+    });
+    count
+}
+
+/// 检查上下文是否已达到处理器配额
+pub fn context_quota_reached(context_id: ContextId) -> bool {
+    context_handler_count(context_id) >= context_handler_quota()
+}
+
 /// Register a custom trap handler
 ///
 /// # 并发安全性
@@ -402,34 +850,124 @@ pub fn register_handler(
     description: &'static str,
     context_id: Option<ContextId>
 ) -> bool {
+    register_handler_internal(trap_type, HandlerFn::Plain(handler_fn), priority, description, context_id, false).is_ok()
+}
+
+/// 注册中断处理器，失败时返回原因
+///
+/// 与`register_handler`相同，调用者可以据此区分存储锁被占用和存储区已满，
+/// 前者稍后重试即可。在处理器中调用时当前核心持有存储读锁，立即返回`StorageLocked`。
+pub fn try_register_handler(
+    trap_type: TrapType,
+    handler_fn: fn(&mut TrapContext) -> TrapHandlerResult,
+    priority: u8,
+    description: &'static str,
+    context_id: Option<ContextId>
+) -> Result<(), RegisterError> {
+    register_handler_internal(trap_type, HandlerFn::Plain(handler_fn), priority, description, context_id, false)
+}
+
+/// 注册携带用户数据的中断处理器
+///
+/// 每次分发时`data`原样传给处理器，可用于传递驱动的设备结构体等状态。
+///
+/// # Safety
+///
+/// 在处理器注销之前`data`必须一直有效，并且可以在任意核心的中断上下文中访问。
+pub unsafe fn register_handler_with_data(
+    trap_type: TrapType,
+    handler_fn: TrapHandlerWithData,
+    data: *mut (),
+    priority: u8,
+    description: &'static str,
+    context_id: Option<ContextId>
+) -> bool {
+    register_handler_internal(trap_type, HandlerFn::WithData(handler_fn, data), priority, description, context_id, false).is_ok()
+}
+
+/// 使用之前预留的槽位注册中断处理器
+///
+/// 成功时消耗一个预留槽位；调用者必须先通过`reserve_handler_slots`预留。
+pub fn register_reserved_handler(
+    trap_type: TrapType,
+    handler_fn: fn(&mut TrapContext) -> TrapHandlerResult,
+    priority: u8,
+    description: &'static str,
+    context_id: Option<ContextId>
+) -> bool {
+    register_handler_internal(trap_type, HandlerFn::Plain(handler_fn), priority, description, context_id, true).is_ok()
+}
+
+fn register_handler_internal(
+    trap_type: TrapType,
+    handler_fn: HandlerFn,
+    priority: u8,
+    description: &'static str,
+    context_id: Option<ContextId>,
+    use_reservation: bool
+) -> Result<(), RegisterError> {
     // 检查trap系统是否初始化
     if !get_trap_system_initialized() {
         println!("Cannot register handler: trap system not initialized");
-        return false;
+        return Err(RegisterError::NotInitialized);
     }
 
     // 加锁 HANDLER_STORAGE
-    let storage_result = HANDLER_STORAGE.try_lock();
-    let mut storage = match storage_result {
+    let mut storage = match lock_handler_storage() {
         Some(guard) => guard,
         None => {
-            println!("Cannot register handler: handler storage lock busy");
-            return false;
+            try_println!("Cannot register handler: handler storage lock busy");
+            return Err(RegisterError::StorageLocked);
         }
     };
 
+    // 拿到存储锁后再检查上下文的处理器配额，在处理器中调用时上面已经直接失败，
+    // 不会在统计时等待当前核心自己持有的trap系统锁
+    if let Some(id) = context_id {
+        if context_quota_reached(id) {
+            try_println!("Cannot register handler: context {} reached its quota of {} handlers",
+                         id, context_handler_quota());
+            return Err(RegisterError::Rejected);
+        }
+    }
+
     // 检查传入的 description 在 HANDLER_STORAGE 中是否已存在
     for i in 0..MAX_CUSTOM_HANDLERS {
         if let Some(handler) = &storage[i] {
             if handler.get_description() == description &&
                 handler.get_trap_type() == trap_type {
-                println!("Cannot register handler: description '{}' already exists for trap type {:?}",
-                         description, trap_type);
-                return false;
+                try_println!("Cannot register handler: description '{}' already exists for trap type {:?}",
+                             description, trap_type);
+                return Err(RegisterError::Rejected);
             }
         }
     }
 
+    // 处理器表可能残留存储区中已经没有的处理器，同样视为重复
+    let mut registered_on = None;
+    for_each_trap_system_ref(|hart, trap_system| {
+        if registered_on.is_none() && trap_system.has_handler(trap_type, description) {
+            registered_on = Some(hart);
+        }
+    });
+    if let Some(hart) = registered_on {
+        try_println!("Cannot register handler: description '{}' still registered for trap type {:?} on hart {}, run verify_consistency to repair",
+                     description, trap_type, hart);
+        return Err(RegisterError::Rejected);
+    }
+
+    // 未使用预留时，不能占用其他上下文预留的槽位
+    let reserved = RESERVED_HANDLER_SLOTS.load(Ordering::SeqCst);
+    if use_reservation {
+        if reserved == 0 {
+            try_println!("Cannot register reserved handler: no handler slots reserved");
+            return Err(RegisterError::Rejected);
+        }
+    } else if reserved > 0 && free_handler_slots(&storage) <= reserved {
+        try_println!("Cannot register handler: remaining {} slots are reserved", reserved);
+        return Err(RegisterError::StorageFull);
+    }
+
     // 查找第一个空槽位 - 从默认处理器范围之后开始
     let mut idx = MAX_CUSTOM_HANDLERS;
     for i in (DEFAULT_HANDLER_END_IDX + 1)..MAX_CUSTOM_HANDLERS {
@@ -440,28 +978,28 @@ pub fn register_handler(
     }
 
     // 输出调试信息
-    println!("Handler registration: found slot at index {}, type {:?}, desc '{}', context_id: {:?}",
-             idx, trap_type, description, context_id);
+    try_println!("Handler registration: found slot at index {}, type {:?}, desc '{}', context_id: {:?}",
+                 idx, trap_type, description, context_id);
 
     if idx == MAX_CUSTOM_HANDLERS {
-        println!("Cannot register handler: no empty slots in storage (all {} slots are full)",
-                 MAX_CUSTOM_HANDLERS);
+        try_println!("Cannot register handler: no empty slots in storage (all {} slots are full)",
+                     MAX_CUSTOM_HANDLERS);
         // 打印已占用的槽位
-        println!("Occupied slots:");
+        try_println!("Occupied slots:");
         let mut count = 0;
         for i in 0..MAX_CUSTOM_HANDLERS {
             if let Some(handler) = &storage[i] {
                 count += 1;
-                println!("  Slot {}: {:?} - '{}'",
-                         i, handler.get_trap_type(), handler.get_description());
+                try_println!("  Slot {}: {:?} - '{}'",
+                             i, handler.get_trap_type(), handler.get_description());
             }
         }
-        println!("Total occupied: {}/{}", count, MAX_CUSTOM_HANDLERS);
-        return false;
+        try_println!("Total occupied: {}/{}", count, MAX_CUSTOM_HANDLERS);
+        return Err(RegisterError::StorageFull);
     }
 
     // 创建并存储处理器实例
-    let handler = StandardTrapHandler::new(
+    let handler = StandardTrapHandler::from_fn(
         handler_fn,
         trap_type,
         priority,
@@ -469,27 +1007,30 @@ pub fn register_handler(
     );
 
     storage[idx] = Some(handler);
+    HANDLER_INVOCATIONS[idx].store(0, Ordering::SeqCst);
 
     // 释放锁，防止死锁
     drop(storage);
 
     // 调用 trap_system 注册处理器
-    let trap_result = with_trap_system_mut(|trap_system| {
-        trap_system.register_handler(idx, priority, trap_type, description, context_id)
-    });
+    let trap_result = register_on_harts(idx, priority, trap_type, description, context_id);
 
     // 如果注册失败，回滚
     if !trap_result {
-        if let Some(mut storage) = HANDLER_STORAGE.try_lock() {
+        if let Some(mut storage) = lock_handler_storage() {
             storage[idx] = None;
-            println!("Failed to register handler in trap system, rolling back storage");
+            try_println!("Failed to register handler in trap system, rolling back storage");
         } else {
-            println!("Warning: Failed to roll back handler registration, storage lock busy");
+            try_println!("Warning: Failed to roll back handler registration, storage lock busy");
         }
-        return false;
+        return Err(RegisterError::Rejected);
+    }
+
+    if use_reservation {
+        release_handler_slots(1);
     }
 
-    trap_result
+    Ok(())
 }
 
 // 添加一个便利函数，默认使用内核上下文
@@ -503,6 +1044,51 @@ pub fn register_handler_with_kernel_context(
     register_handler(trap_type, handler_fn, priority, description, KERNEL_CONTEXT_ID)
 }
 
+/// 修改已注册处理器的优先级
+///
+/// 在每个注册了该处理器的核心上原地更新优先级并重新排序，不需要先注销再注册，
+/// 因此不会出现该处理器暂时缺席的空档。每个核心的处理器表在其写锁内一次改完，
+/// 分发不会看到排序到一半的处理器表。找不到处理器时返回false。
+pub fn set_handler_priority(trap_type: TrapType, description: &str, new_priority: u8) -> bool {
+    if !get_trap_system_initialized() {
+        println!("Cannot set handler priority: trap system not initialized");
+        return false;
+    }
+
+    let mut storage = match lock_handler_storage() {
+        Some(guard) => guard,
+        None => {
+            println!("Cannot set handler priority: handler storage lock busy");
+            return false;
+        }
+    };
+
+    let Some(idx) = storage.iter().position(|slot| {
+        slot.as_ref().is_some_and(|handler| {
+            handler.get_trap_type() == trap_type && handler.get_description() == description
+        })
+    }) else {
+        try_println!("Cannot set handler priority: description '{}' not found for trap type {:?}",
+                     description, trap_type);
+        return false;
+    };
+
+    // 持有存储锁，避免处理器在修改过程中被注销或移动
+    let mut updated = false;
+    for_each_trap_system(|_, trap_system| {
+        updated |= trap_system.set_handler_priority(idx, new_priority);
+    });
+
+    if updated {
+        if let Some(handler) = storage[idx].as_mut() {
+            handler.set_priority(new_priority);
+        }
+        try_println!("Handler '{}' for {:?} now has priority {}", description, trap_type, new_priority);
+    }
+
+    updated
+}
+
 /// 注销指定上下文的所有中断处理器
 ///
 /// # 参数
@@ -523,14 +1109,22 @@ pub fn unregister_handlers_for_context(context_id: ContextId) -> usize {
         return 0;
     }
     
-    // 使用TrapSystem的方法获取存储索引
-    let storage_indices = with_trap_system_mut(|trap_system| {
-        trap_system.unregister_handlers_for_context(context_id)
+    // 使用TrapSystem的方法获取存储索引，上下文的处理器可能注册在任意核心上
+    let mut storage_indices = [None; MAX_TRAP_HANDLERS];
+    let mut found = 0;
+    for_each_trap_system(|hart, trap_system| {
+        for index in trap_system.unregister_handlers_for_context(context_id).into_iter().flatten() {
+            if found < MAX_TRAP_HANDLERS && !storage_indices[..found].contains(&Some(index)) {
+                storage_indices[found] = Some(index);
+                found += 1;
+            }
+        }
+        publish_handler_type_mask(hart, trap_system);
     });
     
     // 清理HANDLER_STORAGE
     let mut unregistered_count = 0;
-    let storage_guard = HANDLER_STORAGE.try_lock();
+    let storage_guard = lock_handler_storage();
     if let Some(mut storage) = storage_guard {
         for i in 0..MAX_TRAP_HANDLERS {
             if let Some(index) = storage_indices[i] {
@@ -542,7 +1136,7 @@ pub fn unregister_handlers_for_context(context_id: ContextId) -> usize {
                     };
                     
                     storage[index] = None;
-                    println!("Unregistered handler at storage index {}: {}", index, handler_desc);
+                    try_println!("Unregistered handler at storage index {}: {}", index, handler_desc);
                     unregistered_count += 1;
                 }
             } else if i > 0 {
@@ -566,7 +1160,7 @@ pub fn unregister_handlers_for_context(context_id: ContextId) -> usize {
 /// 确保在多核环境中的一致性
 pub fn unregister_handler(trap_type: TrapType, description: &'static str) -> bool {
     // 加锁 HANDLER_STORAGE 用于查找
-    let storage = HANDLER_STORAGE.lock();
+    let storage = HANDLER_STORAGE.read();
 
     // 根据 trap_type 和 description 查找索引
     let mut idx = MAX_CUSTOM_HANDLERS;
@@ -589,17 +1183,19 @@ pub fn unregister_handler(trap_type: TrapType, description: &'static str) -> boo
     // 释放查找锁
     drop(storage);
 
-    // 调用 trap_system 注销处理器
-    let result = with_trap_system_mut(|trap_system| {
-        trap_system.unregister_handler(idx)
+    // 调用 trap_system 注销处理器，广播的处理器需要从所有核心注销
+    let mut result = false;
+    for_each_trap_system(|hart, trap_system| {
+        result |= trap_system.unregister_handler(idx);
+        publish_handler_type_mask(hart, trap_system);
     });
 
     // 如果注销成功，清除存储
     if result {
-        let mut storage = HANDLER_STORAGE.lock();
+        let mut storage = HANDLER_STORAGE.write();
         storage[idx] = None;
-        println!("Unregistered trap handler: {} for {:?} (index: {})",
-                 description, trap_type, idx);
+        try_println!("Unregistered trap handler: {} for {:?} (index: {})",
+                     description, trap_type, idx);
     }
 
     result
@@ -615,7 +1211,7 @@ pub fn handler_count(trap_type: TrapType) -> usize {
 /// Print all registered handlers
 pub fn print_handlers() {
     // 锁定 HANDLER_STORAGE
-    let storage = HANDLER_STORAGE.lock();
+    let storage = HANDLER_STORAGE.read();
 
     // 调用 trap_system 打印处理器 - 需要转换为切片
     with_trap_system(|trap_system| {
@@ -623,19 +1219,244 @@ pub fn print_handlers() {
     });
 }
 
+/// 遍历所有注册的处理器，不做任何打印
+///
+/// 描述从`HANDLER_STORAGE`中解析，回调参数依次为中断类型、描述、优先级、
+/// 存储槽位索引和关联的上下文ID。回调执行时持有存储区和trap系统的锁，
+/// 不能在回调中注册或注销处理器。
+pub fn for_each_handler<F>(f: F)
+where
+    F: FnMut(TrapType, &'static str, u8, usize, Option<ContextId>),
+{
+    if !get_trap_system_initialized() {
+        return;
+    }
+
+    let storage = HANDLER_STORAGE.read();
+
+    with_trap_system(|trap_system| {
+        trap_system.for_each_handler(&storage[..], f);
+    });
+}
+
+/// 查询处理器的注册信息
+///
+/// 先在DI处理器中按中断类型和描述查找，找不到时再查找旧版注册表。
+/// DI处理器没有注册者和停用状态：关联了非内核上下文的处理器报告为用户级，
+/// 注册者ID总是`SYSTEM_REGISTRAR_ID`，并且总是启用。
+pub fn get_handler_info(trap_type: TrapType, description: &str) -> Option<HandlerDetails> {
+    let mut details = None;
+    for_each_handler(|handler_type, handler_description, priority, _index, context_id| {
+        if details.is_none() && handler_type == trap_type && handler_description == description {
+            let protection_level = if context_id != KERNEL_CONTEXT_ID {
+                ProtectionLevel::User
+            } else {
+                ProtectionLevel::System
+            };
+            details = Some(HandlerDetails {
+                priority,
+                protection_level,
+                registrar_id: SYSTEM_REGISTRAR_ID,
+                context_id,
+                enabled: true,
+            });
+        }
+    });
+
+    details.or_else(|| super::registry::get_handler_info(trap_type, description))
+}
+
 /// Internal function to handle trap events without conflicting with the main handler
-pub fn internal_handle_trap(context: *mut TrapContext) {
+///
+/// Returns the result of dispatching to the registered handlers
+///
+/// 分发期间只持有处理器存储区和当前核心trap系统的读锁，多个核心可以并行分发。
+/// 处理器运行时读锁仍被持有，因此处理器中不能调用注册或注销处理器的API，
+/// 它们需要写锁，会永远等待当前的读锁释放而死锁。
+pub fn internal_handle_trap(context: *mut TrapContext) -> TrapHandlerResult {
+    // 没有处理器的类型直接进入默认处理，不获取存储锁
+    let trap_type = unsafe { &*context }.get_cause().to_trap_type();
+    if !has_handlers_for(trap_type) {
+        return with_trap_system(|trap_system| {
+            trap_system.handle_trap(context, &[])
+        });
+    }
+
     // 锁定 HANDLER_STORAGE
-    let storage = HANDLER_STORAGE.lock();
+    let storage = DispatchStorage::lock();
 
     // 调用 trap_system 处理中断 - 需要转换为切片
     with_trap_system(|trap_system| {
-        trap_system.handle_trap(context, &storage[..]);
-    });
+        trap_system.handle_trap(context, &storage[..])
+    })
 
     // 锁会在函数返回时自动释放
 }
 
+/// 将trap分发给指定类型的处理器
+///
+/// 与`internal_handle_trap`相同，只需要读锁，处理器中不能调用注册或注销API
+pub fn dispatch_trap(trap_type: TrapType, context: &mut TrapContext) -> TrapHandlerResult {
+    let storage = DispatchStorage::lock();
+
+    with_trap_system(|trap_system| {
+        trap_system.dispatch_trap(trap_type, context, &storage[..])
+    })
+}
+
+/// 将trap分发给指定类型的处理器，并返回运行过的处理器数量和处理了该trap的处理器
+///
+/// 用于排查同一类型注册了多个处理器时的优先级顺序，锁的限制与`dispatch_trap`相同
+pub fn dispatch_detailed(trap_type: TrapType, context: &mut TrapContext) -> DispatchOutcome {
+    let storage = DispatchStorage::lock();
+
+    with_trap_system(|trap_system| {
+        trap_system.dispatch_detailed(trap_type, context, &storage[..])
+    })
+}
+
+/// 获取自定义处理器存储区的使用情况
+///
+/// 只统计默认处理器范围之后的槽位，返回`(used, free, fragmented)`，
+/// 其中`fragmented`是位于最后一个已用槽位之前的空闲槽位数。
+pub fn handler_storage_stats() -> (usize, usize, usize) {
+    let storage = HANDLER_STORAGE.read();
+    storage_stats(&storage)
+}
+
+fn storage_stats(storage: &[Option<StandardTrapHandler>; MAX_CUSTOM_HANDLERS]) -> (usize, usize, usize) {
+    let mut used = 0;
+    let mut holes = 0;
+    let mut fragmented = 0;
+
+    for i in (DEFAULT_HANDLER_END_IDX + 1)..MAX_CUSTOM_HANDLERS {
+        if storage[i].is_some() {
+            used += 1;
+            // 之前遇到的空闲槽位都是碎片
+            fragmented = holes;
+        } else {
+            holes += 1;
+        }
+    }
+
+    let free = MAX_CUSTOM_HANDLERS - (DEFAULT_HANDLER_END_IDX + 1) - used;
+    (used, free, fragmented)
+}
+
+/// 整理自定义处理器存储区，把已用槽位移动到前部
+///
+/// 移动槽位时同步更新trap系统中`HandlerInfo.index`的引用。
+/// 整理过程中关闭中断，避免trap处理读取到不一致的索引。
+/// 返回被移动的处理器数量。
+pub fn compact_handler_storage() -> usize {
+    if !get_trap_system_initialized() {
+        println!("Cannot compact handler storage: trap system not initialized");
+        return 0;
+    }
+
+    let was_enabled = disable_interrupts();
+    let mut moved = 0;
+
+    {
+        let mut storage = HANDLER_STORAGE.write();
+
+        let mut target = DEFAULT_HANDLER_END_IDX + 1;
+
+        for i in (DEFAULT_HANDLER_END_IDX + 1)..MAX_CUSTOM_HANDLERS {
+            if storage[i].is_none() {
+                continue;
+            }
+
+            if i != target {
+                // 所有注册了该处理器的核心都要同步更新索引
+                let mut remapped = false;
+                for_each_trap_system(|_, trap_system| {
+                    remapped |= trap_system.remap_handler_index(i, target);
+                });
+                if !remapped {
+                    try_println!("Warning: handler at storage index {} not registered in trap system", i);
+                }
+                storage[target] = storage[i].take();
+                HANDLER_INVOCATIONS[target].store(HANDLER_INVOCATIONS[i].swap(0, Ordering::SeqCst), Ordering::SeqCst);
+                moved += 1;
+            }
+            target += 1;
+        }
+    }
+
+    restore_interrupts(was_enabled);
+
+    println!("Handler storage compacted, moved {} handlers", moved);
+    moved
+}
+
+/// 核对处理器存储区与各核心trap系统的处理器表，并修复不一致之处
+///
+/// 处理器表中的每一项都必须指向存储区中类型和描述相同的处理器，
+/// 存储区中每个已用槽位都必须至少在一个核心上注册。
+/// 指向空槽位或其他处理器的表项会被注销，没有任何核心引用的槽位会被清空。
+/// 核对期间关闭中断，但注册过程中存储区和处理器表本来就短暂不一致，
+/// 因此不要与注册并发调用。两者一致时返回true。
+pub fn verify_consistency() -> bool {
+    if !get_trap_system_initialized() {
+        println!("Cannot verify handler consistency: trap system not initialized");
+        return false;
+    }
+
+    let was_enabled = disable_interrupts();
+    let mut consistent = true;
+
+    {
+        let mut storage = HANDLER_STORAGE.write();
+        let mut referenced = [false; MAX_CUSTOM_HANDLERS];
+
+        for_each_trap_system(|hart, trap_system| {
+            let mut stale = [None; MAX_TRAP_HANDLERS];
+            let mut stale_count = 0;
+
+            for handler_info in trap_system.handler_infos().iter().flatten() {
+                let matches = storage.get(handler_info.index)
+                    .and_then(Option::as_ref)
+                    .is_some_and(|handler| {
+                        handler.get_trap_type() == handler_info.trap_type
+                            && handler.get_description() == handler_info.description
+                    });
+
+                if matches {
+                    referenced[handler_info.index] = true;
+                } else {
+                    try_println!("Inconsistent handler on hart {}: '{}' for {:?} points at storage index {}",
+                                 hart, handler_info.description, handler_info.trap_type, handler_info.index);
+                    stale[stale_count] = Some(handler_info.index);
+                    stale_count += 1;
+                }
+            }
+
+            for index in stale.into_iter().flatten() {
+                trap_system.unregister_handler(index);
+                consistent = false;
+            }
+            publish_handler_type_mask(hart, trap_system);
+        });
+
+        for (index, slot) in storage.iter_mut().enumerate() {
+            if referenced[index] {
+                continue;
+            }
+            if let Some(handler) = slot.take() {
+                try_println!("Inconsistent handler storage: '{}' for {:?} at index {} is not registered on any hart",
+                             handler.get_description(), handler.get_trap_type(), index);
+                HANDLER_INVOCATIONS[index].store(0, Ordering::SeqCst);
+                consistent = false;
+            }
+        }
+    }
+
+    restore_interrupts(was_enabled);
+
+    consistent
+}
+
 /// Enable interrupts
 pub fn enable_interrupts() -> bool {
     with_trap_system(|trap_system| {
@@ -731,11 +1552,26 @@ pub fn get_interrupt_nest_level() -> usize {
     })
 }
 
+/// 检查所有核心中断栈顶部的保护区是否完好
+///
+/// 保护区被改写时会向错误子系统报告一个Critical级别的中断错误，有任何核心的保护区损坏时返回false。
+///
+/// 上下文管理器正被其所在核心的trap路径持有时不等待，跳过该核心，留到下次检查
+pub fn check_interrupt_stack_integrity() -> bool {
+    let mut intact = true;
+    for hart in 0..MAX_HARTS {
+        if let Some(manager) = CONTEXT_MANAGERS.get_for(hart).try_lock() {
+            intact &= manager.check_stack_integrity();
+        }
+    }
+    intact
+}
+
 /// 获取自定义处理器数量
 ///
 /// 返回通过DI系统注册的自定义处理器总数
 pub fn custom_handler_count() -> usize {
-    let storage = HANDLER_STORAGE.lock();
+    let storage = HANDLER_STORAGE.read();
     let mut count = 0;
     for i in 0..MAX_CUSTOM_HANDLERS {
         if storage[i].is_some() {
@@ -745,6 +1581,84 @@ pub fn custom_handler_count() -> usize {
     count
 }
 
+/// 记录存储槽位上的处理器被调用了一次
+pub(super) fn record_handler_invocation(index: usize) {
+    if let Some(counter) = HANDLER_INVOCATIONS.get(index) {
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// 单个处理器的调用次数
+#[derive(Debug, Clone, Copy)]
+pub struct HandlerInvocation {
+    /// 处理器负责的trap类型
+    pub trap_type: TrapType,
+    /// 处理器描述，在存储区中唯一
+    pub description: &'static str,
+    /// 被调用的次数
+    pub count: usize,
+}
+
+/// 获取所有已注册处理器的调用次数，以存储槽位为下标
+pub fn handler_invocations() -> [Option<HandlerInvocation>; MAX_CUSTOM_HANDLERS] {
+    let storage = HANDLER_STORAGE.read();
+    let mut invocations = [None; MAX_CUSTOM_HANDLERS];
+    for (i, slot) in storage.iter().enumerate() {
+        if let Some(handler) = slot {
+            invocations[i] = Some(HandlerInvocation {
+                trap_type: handler.get_trap_type(),
+                description: handler.get_description(),
+                count: HANDLER_INVOCATIONS[i].load(Ordering::SeqCst),
+            });
+        }
+    }
+    invocations
+}
+
+/// 按描述查找处理器被调用的次数，没有该处理器时返回0
+pub fn handler_invocation_count(description: &str) -> u64 {
+    let storage = HANDLER_STORAGE.read();
+    storage.iter()
+        .position(|slot| slot.as_ref().is_some_and(|handler| handler.get_description() == description))
+        .map_or(0, |index| HANDLER_INVOCATIONS[index].load(Ordering::SeqCst) as u64)
+}
+
+/// 按调用次数从多到少打印所有已注册的处理器
+pub fn print_handler_stats() {
+    let mut invocations = handler_invocations();
+    // None小于任何Some，降序排列后空槽位都在末尾
+    invocations.sort_unstable_by(|a, b| b.map(|i| i.count).cmp(&a.map(|i| i.count)));
+
+    println!("=== Handler Invocation Statistics ===");
+    for invocation in invocations.iter().flatten() {
+        println!("  {:>10}  {} ({:?})", invocation.count, invocation.description, invocation.trap_type);
+    }
+    println!("=====================================");
+}
+
+/// 获取自定义处理器存储区的总槽位数，包括为默认处理器预留的槽位
+pub const fn handler_storage_capacity() -> usize {
+    MAX_CUSTOM_HANDLERS
+}
+
+/// 在持有错误管理器锁的情况下执行闭包
+///
+/// 错误管理器由所有核心的trap系统共享，不经由某个核心的trap系统访问，
+/// 这样不同核心上的错误处理也能互斥
+fn with_error_manager<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut StandardErrorManager) -> R,
+{
+    f(&mut ERROR_MANAGER.lock())
+}
+
+/// 获取错误处理器容量，返回`(total, used)`
+pub fn error_handler_capacity() -> (usize, usize) {
+    with_error_manager(|error_manager| {
+        error_manager.handler_capacity()
+    })
+}
+
 /// Register an error handler
 pub fn register_error_handler(
     handler: ErrorHandler,
@@ -753,8 +1667,23 @@ pub fn register_error_handler(
     source: Option<ErrorSource>,
     level: Option<ErrorLevel>
 ) -> bool {
-    with_trap_system_mut(|trap_system| {
-        trap_system.get_error_manager_mut().register_handler(
+    with_error_manager(|error_manager| {
+        error_manager.register_handler(
+            handler, priority, description, source, level
+        )
+    })
+}
+
+/// 注册接收trap上下文的错误处理器
+pub fn register_error_handler_with_context(
+    handler: ErrorHandlerWithContext,
+    priority: u8,
+    description: &'static str,
+    source: Option<ErrorSource>,
+    level: Option<ErrorLevel>
+) -> bool {
+    with_error_manager(|error_manager| {
+        error_manager.register_context_handler(
             handler, priority, description, source, level
         )
     })
@@ -762,18 +1691,41 @@ pub fn register_error_handler(
 
 /// Unregister an error handler
 pub fn unregister_error_handler(description: &str) -> bool {
-    with_trap_system_mut(|trap_system| {
-        trap_system.get_error_manager_mut().unregister_handler(description)
+    with_error_manager(|error_manager| {
+        error_manager.unregister_handler(description)
     })
 }
 
 /// Handle a system error
 pub fn handle_system_error(error: SystemError) -> ErrorResult {
-    with_trap_system_mut(|trap_system| {
-        trap_system.get_error_manager_mut().handle_error(error)
+    with_error_manager(|error_manager| {
+        error_manager.handle_error(error)
     })
 }
 
+/// 与`handle_system_error`相同，但错误管理器被占用时返回None而不是等待
+///
+/// 供panic等不能等待的路径使用
+pub fn try_handle_system_error(error: SystemError) -> Option<ErrorResult> {
+    let mut error_manager = ERROR_MANAGER.try_lock()?;
+    Some(error_manager.handle_error(error))
+}
+
+/// 处理来自trap的系统错误，处理器可以修改trap上下文
+pub fn handle_system_error_with_context(error: SystemError, context: &mut TrapContext) -> ErrorResult {
+    with_error_manager(|error_manager| {
+        error_manager.handle_error_with_context(error, context)
+    })
+}
+
+/// 与`handle_system_error_with_context`相同，但错误管理器被占用时返回None而不是等待
+///
+/// 供trap处理器使用：错误处理器自身触发的trap不能再等待错误管理器的锁
+pub fn try_handle_system_error_with_context(error: SystemError, context: &mut TrapContext) -> Option<ErrorResult> {
+    let mut error_manager = ERROR_MANAGER.try_lock()?;
+    Some(error_manager.handle_error_with_context(error, context))
+}
+
 /// Create a new system error
 pub fn create_system_error(
     source: ErrorSource,
@@ -782,8 +1734,8 @@ pub fn create_system_error(
     address: Option<usize>,
     ip: usize
 ) -> SystemError {
-    with_trap_system(|trap_system| {
-        trap_system.get_error_manager().create_error(
+    with_error_manager(|error_manager| {
+        error_manager.create_error(
             source, level, code, address, ip
         )
     })
@@ -791,41 +1743,73 @@ pub fn create_system_error(
 
 /// Print error log
 pub fn print_error_log(count: usize) {
-    with_trap_system(|trap_system| {
-        trap_system.get_error_manager().print_error_log(count)
+    with_error_manager(|error_manager| {
+        error_manager.print_error_log(count)
+    })
+}
+
+/// Copy the most recent error records into `out`, oldest first
+pub fn export_error_log(out: &mut [ErrorLogEntry]) -> usize {
+    with_error_manager(|error_manager| {
+        error_manager.export_error_log(out)
     })
 }
 
 /// Clear error log
 pub fn clear_error_log() {
-    with_trap_system_mut(|trap_system| {
-        trap_system.get_error_manager_mut().clear_error_log()
+    with_error_manager(|error_manager| {
+        error_manager.clear_error_log()
+    })
+}
+
+/// 获取指定错误源累计发生的错误数
+pub fn error_count(source: ErrorSource) -> u64 {
+    with_error_manager(|error_manager| {
+        error_manager.error_count(source)
+    })
+}
+
+/// 打印每个错误源累计发生的错误数
+pub fn print_error_summary() {
+    with_error_manager(|error_manager| {
+        error_manager.print_error_summary()
+    })
+}
+
+/// 设置错误升级阈值，`None`表示不升级
+///
+/// 错误日志中保留的同一错误源的错误超过阈值后，该错误源之后的非致命错误按严重错误处理
+pub fn set_error_escalation_threshold(threshold: Option<usize>) {
+    with_error_manager(|error_manager| {
+        error_manager.set_escalation_threshold(threshold)
     })
 }
 
 /// Print registered error handlers
 pub fn print_error_handlers() {
-    with_trap_system(|trap_system| {
-        trap_system.get_error_manager().print_handlers()
+    with_error_manager(|error_manager| {
+        error_manager.print_handlers()
     })
 }
 
 /// Check if in panic mode
 pub fn is_in_panic_mode() -> bool {
-    with_trap_system(|trap_system| {
-        trap_system.get_error_manager().is_panic_mode()
+    with_error_manager(|error_manager| {
+        error_manager.is_panic_mode()
     })
 }
 
 /// Reset panic mode
 pub fn reset_panic_mode() {
-    with_trap_system(|trap_system| {
-        trap_system.get_error_manager().reset_panic_mode()
+    with_error_manager(|error_manager| {
+        error_manager.reset_panic_mode()
     })
 }
 
 // 导出公共函数和接口
-pub use self::container::{TrapSystem, StaticRef};
+pub use self::container::{TrapSystem, StaticRef, DispatchOutcome, unhandled_trap_count, trap_counts,
+    trap_count, record_trap, reset_trap_counts};
+pub use self::impls::interrupt_stack_error_count;
 pub use self::traits::{
     TrapHandlerInterface, ContextManagerInterface,
     HardwareControlInterface, TrapSystemConfig, ErrorManagerInterface
@@ -5,7 +5,8 @@
 
 use crate::trap::ds::{
     TrapContext, TaskContext, TrapType, TrapHandlerResult, 
-    SystemError, ErrorResult, ErrorHandler, ErrorSource, ErrorLevel,
+    SystemError, ErrorResult, ErrorHandler, ErrorHandlerWithContext, ErrorSource, ErrorLevel,
+    ErrorLogEntry,
     ContextError, ContextType, ContextState
 };
 
@@ -46,7 +47,18 @@ pub trait ContextManagerInterface: Send + Sync {
     
     /// Get current interrupt stack usage
     fn get_interrupt_stack_usage(&self) -> (usize, usize);
+
+    /// Check that the guard band at the top of the interrupt stack is intact
+    fn check_stack_integrity(&self) -> bool;
     
+    /// Enter one level of interrupt nesting on the current hart
+    ///
+    /// Returns the new level, or `ContextError::StackOverflow` beyond the maximum level
+    fn enter_interrupt(&self) -> Result<usize, ContextError>;
+
+    /// Leave one level of interrupt nesting on the current hart
+    fn exit_interrupt(&self) -> Result<usize, ContextError>;
+
     /// Check if currently in interrupt context
     fn is_in_interrupt_context(&self) -> bool;
     
@@ -131,20 +143,48 @@ pub trait ErrorManagerInterface: Send + Sync {
         level: Option<ErrorLevel>
     ) -> bool;
     
+    /// 注册接收trap上下文的错误处理器
+    fn register_context_handler(
+        &mut self,
+        handler: ErrorHandlerWithContext,
+        priority: u8,
+        description: &'static str,
+        source: Option<ErrorSource>,
+        level: Option<ErrorLevel>
+    ) -> bool;
+    
     /// 注销错误处理器
     fn unregister_handler(&mut self, description: &str) -> bool;
     
     /// 处理系统错误
     fn handle_error(&mut self, error: SystemError) -> ErrorResult;
+
+    /// 处理来自trap的系统错误，处理器可以修改上下文
+    fn handle_error_with_context(&mut self, error: SystemError, context: &mut TrapContext) -> ErrorResult;
     
     /// 打印错误日志
     fn print_error_log(&self, count: usize);
+
+    /// 把最近的错误记录按时间顺序复制到`out`，返回复制的条数
+    fn export_error_log(&self, out: &mut [ErrorLogEntry]) -> usize;
     
     /// 清空错误日志
     fn clear_error_log(&mut self);
     
     /// 打印所有注册的处理器
     fn print_handlers(&self);
+
+    /// 获取处理器容量，返回`(total, used)`
+    fn handler_capacity(&self) -> (usize, usize);
+
+    /// 获取指定错误源累计发生的错误数
+    fn error_count(&self, source: ErrorSource) -> u64;
+
+    /// 打印每个错误源累计发生的错误数
+    fn print_error_summary(&self);
+
+    /// 设置错误升级阈值，`None`表示不升级
+    fn set_escalation_threshold(&self, threshold: Option<usize>);
     
     /// 检查是否处于恐慌模式
     fn is_panic_mode(&self) -> bool;
@@ -0,0 +1,160 @@
+//! 双重故障（double fault）检测
+//!
+//! 记录每个核心当前的trap处理深度。如果在处理trap的过程中又发生了
+//! 不允许嵌套的trap（例如处理器本身访问了非法内存），就判定为双重故障，
+//! 记录原始原因和嵌套原因后停机，避免系统在递归故障中无提示地挂死。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::println;
+use crate::trap::ds::{
+    TrapContext, TrapCause,
+    SystemError, ErrorCode, ErrorSource, ErrorLevel,
+};
+use crate::util::sbi::hart::MAX_HARTS;
+use crate::util::percpu::PerCpu;
+use crate::util::sbi::system::{shutdown, ShutdownReason};
+use crate::util::sbi::timer;
+use crate::util::delay::{busy_wait_ms, OUTPUT_FLUSH_DELAY_MS};
+
+/// 双重故障使用的错误编号
+pub const DOUBLE_FAULT_ERROR_CODE: u16 = 0xDF;
+
+/// 单个核心的trap处理状态
+struct HartTrapState {
+    /// 当前的trap处理深度
+    depth: AtomicUsize,
+    /// 最外层trap的scause
+    original_cause: AtomicUsize,
+    /// 最外层trap的sepc
+    original_epc: AtomicUsize,
+}
+
+impl HartTrapState {
+    const fn new() -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            original_cause: AtomicUsize::new(0),
+            original_epc: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// 每个核心的trap处理状态
+static TRAP_STATE: PerCpu<HartTrapState, MAX_HARTS> =
+    PerCpu::new([const { HartTrapState::new() }; MAX_HARTS]);
+
+/// 最近一次检测到的双重故障，供停机前的诊断和测试使用
+static LAST_DOUBLE_FAULT: Mutex<Option<DoubleFault>> = Mutex::new(None);
+
+/// 双重故障信息
+#[derive(Debug, Clone, Copy)]
+pub struct DoubleFault {
+    /// 最外层trap的原因
+    pub original_cause: TrapCause,
+    /// 最外层trap的指令地址
+    pub original_epc: usize,
+    /// 嵌套trap的原因
+    pub nested_cause: TrapCause,
+    /// 嵌套trap的指令地址
+    pub nested_epc: usize,
+    /// 嵌套trap的附加信息(stval)
+    pub nested_tval: usize,
+    /// 发生嵌套时的处理深度
+    pub depth: usize,
+    /// 对应的系统错误
+    pub error: SystemError,
+}
+
+/// 判断在给定深度下是否允许再次进入trap
+///
+/// 中断总是允许嵌套，超过最大嵌套层级由`nest_overflow`屏蔽中断源并丢弃；
+/// 异常则不允许在trap处理中再次发生。
+fn nesting_allowed(cause: TrapCause, depth: usize) -> bool {
+    cause.is_interrupt() || depth == 0
+}
+
+/// 进入trap处理
+///
+/// 成功时返回进入后的处理深度；检测到双重故障时返回故障信息。
+/// 无论结果如何，处理深度都已增加，调用者离开时必须调用`exit()`。
+pub fn enter(ctx: &TrapContext) -> Result<usize, DoubleFault> {
+    let state = TRAP_STATE.get();
+    let cause = ctx.get_cause();
+    let depth = state.depth.fetch_add(1, Ordering::SeqCst);
+
+    if depth == 0 {
+        // 最外层trap，记录原始原因
+        state.original_cause.store(ctx.scause, Ordering::SeqCst);
+        state.original_epc.store(ctx.sepc, Ordering::SeqCst);
+        return Ok(1);
+    }
+
+    if nesting_allowed(cause, depth) {
+        return Ok(depth + 1);
+    }
+
+    let error = SystemError::new(
+        ErrorCode::new(ErrorSource::Interrupt, ErrorLevel::Fatal, DOUBLE_FAULT_ERROR_CODE),
+        Some(ctx.stval),
+        ctx.sepc,
+        timer::get_time(),
+    );
+
+    let fault = DoubleFault {
+        original_cause: TrapCause::from_bits(state.original_cause.load(Ordering::SeqCst)),
+        original_epc: state.original_epc.load(Ordering::SeqCst),
+        nested_cause: cause,
+        nested_epc: ctx.sepc,
+        nested_tval: ctx.stval,
+        depth,
+        error,
+    };
+
+    // 这里可能已经处于持锁状态，只尝试记录，不阻塞
+    if let Some(mut last) = LAST_DOUBLE_FAULT.try_lock() {
+        *last = Some(fault);
+    }
+
+    Err(fault)
+}
+
+/// 离开trap处理
+pub fn exit() {
+    let _ = TRAP_STATE.get().depth.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |depth| {
+        depth.checked_sub(1)
+    });
+}
+
+/// 获取当前核心的trap处理深度
+pub fn depth() -> usize {
+    TRAP_STATE.get().depth.load(Ordering::SeqCst)
+}
+
+/// 获取最近一次记录的双重故障
+pub fn last_double_fault() -> Option<DoubleFault> {
+    LAST_DOUBLE_FAULT.try_lock().and_then(|last| *last)
+}
+
+/// 打印双重故障诊断信息并停机
+///
+/// 双重故障发生时外层处理器可能持有trap系统的锁，
+/// 因此这里不经过错误管理器，直接输出并关机。
+pub fn report_and_halt(fault: &DoubleFault) -> ! {
+    println!("\n═════════════════════════════════════════════════════");
+    println!("FATAL ERROR: DOUBLE FAULT (fault within trap handler)");
+    println!("═════════════════════════════════════════════════════");
+    println!("Error: {}", fault.error);
+    println!("Nesting depth: {}", fault.depth);
+    println!("Original trap: {:?} ({:?}) at {:#018x}",
+             fault.original_cause.to_trap_type(), fault.original_cause, fault.original_epc);
+    println!("Nested trap:   {:?} ({:?}) at {:#018x}, stval={:#018x}",
+             fault.nested_cause.to_trap_type(), fault.nested_cause,
+             fault.nested_epc, fault.nested_tval);
+    println!("═════════════════════════════════════════════════════\n");
+
+    println!("System halting due to double fault.");
+    // 短暂延迟，确保消息能够输出
+    busy_wait_ms(OUTPUT_FLUSH_DELAY_MS);
+    shutdown(ShutdownReason::SystemFailure);
+}
@@ -4,9 +4,21 @@
 //! 打印详细的诊断信息并使系统停机，便于开发者定位问题。
 
 use crate::println;
-use crate::trap::ds::{TrapContext, TrapHandlerResult, TrapCause, TrapType};
+use crate::trap::ds::{
+    TrapContext, TrapHandlerResult, TrapCause, TrapType,
+    SystemError, ErrorCode, ErrorSource, ErrorLevel, ErrorResult,
+};
+use crate::util::sbi::timer;
 use crate::util::sbi::system::{shutdown, ShutdownReason};
+use crate::util::delay::{busy_wait_ms, OUTPUT_FLUSH_DELAY_MS};
 use super::di::context::KERNEL_CONTEXT_ID;
+use crate::mm::fault::{self, AccessKind};
+use crate::mm::layout;
+use super::backtrace;
+use super::insn;
+
+/// 异常报告中回溯的最大帧数
+const BACKTRACE_MAX_FRAMES: usize = 16;
 
 /// 通用异常处理函数，打印详细信息并停机
 ///
@@ -34,13 +46,10 @@ fn handle_exception_with_details(
     
     // 打印寄存器状态
     println!("\nRegister State:");
-    println!("  sstatus: {:#018x}", ctx.sstatus);
-    println!("  ra(x1):  {:#018x}  sp(x2):   {:#018x}", ctx.x[1], ctx.x[2]);
-    println!("  gp(x3):  {:#018x}  tp(x4):   {:#018x}", ctx.x[3], ctx.x[4]);
-    println!("  t0(x5):  {:#018x}  t1(x6):   {:#018x}", ctx.x[5], ctx.x[6]);
-    println!("  t2(x7):  {:#018x}  s0/fp(x8):{:#018x}", ctx.x[7], ctx.x[8]);
-    println!("  a0(x10): {:#018x}  a1(x11):  {:#018x}", ctx.x[10], ctx.x[11]);
-    println!("  a2(x12): {:#018x}  a3(x13):  {:#018x}", ctx.x[12], ctx.x[13]);
+    ctx.dump();
+
+    // 沿s0/fp链打印调用栈
+    backtrace::walk(ctx.x[8], BACKTRACE_MAX_FRAMES);
     
     // 结束分隔线
     println!("═════════════════════════════════════════════════════\n");
@@ -49,17 +58,49 @@ fn handle_exception_with_details(
     if should_panic {
         println!("System halting due to unrecoverable exception.");
         // 短暂延迟，确保消息能够输出
-        for _ in 0..10000000 {
-            core::hint::spin_loop();
-        }
+        busy_wait_ms(OUTPUT_FLUSH_DELAY_MS);
         shutdown(ShutdownReason::SystemFailure);
     }
     
     TrapHandlerResult::Handled
 }
 
+/// 页错误交给错误管理器时的错误码基值，加上异常号区分指令、加载和存储页错误
+pub const PAGE_FAULT_ERROR_CODE: u16 = 0x100;
+
+/// 存储页错误由AMO/LR/SC指令产生时在错误码中附加的标志
+///
+/// 写时复制需要让原子操作的读-改-写都落在新页上，处理器据此区别对待
+pub const AMO_PAGE_FAULT_FLAG: u16 = 0x80;
+
+/// 让接收trap上下文的内存错误处理器尝试修复页错误
+///
+/// 页错误以`ErrorSource::Memory`、`ErrorLevel::Error`交给错误管理器，地址为stval。
+/// 处理器返回`Handled`表示已经修复（例如映射了缺失的页面），sepc保持不变，
+/// trap返回后重新执行出错的指令。错误管理器被占用时无法修复。
+/// AMO产生的存储页错误在错误码中附加`AMO_PAGE_FAULT_FLAG`。
+fn try_recover_page_fault(ctx: &mut TrapContext, access: Option<AccessKind>) -> bool {
+    let mut code = PAGE_FAULT_ERROR_CODE + ctx.get_cause().code() as u16;
+    if access == Some(AccessKind::Store { is_atomic: true }) {
+        code |= AMO_PAGE_FAULT_FLAG;
+    }
+    let error = SystemError::new(
+        ErrorCode::new(ErrorSource::Memory, ErrorLevel::Error, code),
+        Some(ctx.stval),
+        ctx.sepc,
+        timer::now(),
+    );
+    matches!(
+        super::di::try_handle_system_error_with_context(error, ctx),
+        Some(ErrorResult::Handled)
+    )
+}
+
 /// 指令页错误增强处理器
 pub fn enhanced_instruction_page_fault_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
+    if try_recover_page_fault(ctx, Some(AccessKind::Execute)) {
+        return TrapHandlerResult::Handled;
+    }
     handle_exception_with_details(
         ctx,
         "INSTRUCTION PAGE FAULT",
@@ -69,6 +110,9 @@ pub fn enhanced_instruction_page_fault_handler(ctx: &mut TrapContext) -> TrapHan
 
 /// 加载页错误增强处理器
 pub fn enhanced_load_page_fault_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
+    if try_recover_page_fault(ctx, Some(AccessKind::Load)) {
+        return TrapHandlerResult::Handled;
+    }
     handle_exception_with_details(
         ctx,
         "LOAD PAGE FAULT",
@@ -77,10 +121,28 @@ pub fn enhanced_load_page_fault_handler(ctx: &mut TrapContext) -> TrapHandlerRes
 }
 
 /// 存储页错误增强处理器
+///
+/// 通过解码出错指令区分普通存储和AMO，两者在写时复制时需要不同的处理，
+/// 修复页错误的处理器从错误码中得知访问类型
 pub fn enhanced_store_page_fault_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
+    let instruction = fault::fetch_faulting_instruction(ctx.sepc);
+    let access = fault::access_kind(TrapType::StorePageFault, instruction);
+    if try_recover_page_fault(ctx, access) {
+        return TrapHandlerResult::Handled;
+    }
+
+    let description = match access {
+        Some(AccessKind::Store { is_atomic: true }) => "AMO PAGE FAULT",
+        _ => "STORE PAGE FAULT",
+    };
+
+    if let Some(instruction) = instruction {
+        println!("Faulting instruction: {:#010x}", instruction);
+    }
+
     handle_exception_with_details(
         ctx,
-        "STORE PAGE FAULT",
+        description,
         true
     )
 }
@@ -95,6 +157,7 @@ pub fn enhanced_illegal_instruction_handler(ctx: &mut TrapContext) -> TrapHandle
     
     // 打印额外的指令相关信息
     println!("Illegal instruction value: {:#010x}", instruction_bytes);
+    insn::print_instruction(ctx.sepc);
     
     // 尝试解析一些常见的非法指令情况
     if instruction_bytes == 0 {
@@ -165,8 +228,10 @@ pub fn enhanced_breakpoint_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
     // 打印更详细的调试信息
     println!("Breakpoint at PC: {:#x}, Instruction bytes: {:#x}", orig_pc, ctx.stval);
     
-    // 检查是否为压缩指令
-    let is_compressed = false;  // 这需要读取内存中的指令来确定，简化版先假设不是压缩指令
+    // 按sepc处指令的低两位判断是c.ebreak（2字节）还是ebreak（4字节），
+    // 无法读取指令时按32位的ebreak处理
+    let instruction_size = insn::print_instruction(orig_pc)
+        .map_or(4, insn::instruction_length);
     
     // 处理断点异常
     let result = handle_exception_with_details(
@@ -175,8 +240,7 @@ pub fn enhanced_breakpoint_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
         false // 断点不需要停机
     );
     
-    // 根据指令是否压缩，更新PC
-    let instruction_size = if is_compressed { 2 } else { 4 };
+    // 跳过断点指令
     ctx.set_return_addr(orig_pc + instruction_size);
     
     println!("Breakpoint handled: PC advanced from {:#x} to {:#x}", orig_pc, ctx.sepc);
@@ -215,6 +279,9 @@ pub fn enhanced_misaligned_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
     println!("Cause: Code {} ({})", cause.code(), exception_type);
     println!("Instruction Address: {:#018x}", ctx.sepc);
     println!("Misaligned Address: {:#018x}", ctx.stval);
+    if trap_type != TrapType::InstructionMisaligned {
+        insn::print_instruction(ctx.sepc);
+    }
     
     // 计算地址未对齐的程度和需要的对齐
     let misalignment = ctx.stval & 0xF;
@@ -270,13 +337,7 @@ pub fn enhanced_misaligned_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
     
     // 打印寄存器状态
     println!("\nRegister State:");
-    println!("  sstatus: {:#018x}", ctx.sstatus);
-    println!("  ra(x1):  {:#018x}  sp(x2):   {:#018x}", ctx.x[1], ctx.x[2]);
-    println!("  gp(x3):  {:#018x}  tp(x4):   {:#018x}", ctx.x[3], ctx.x[4]);
-    println!("  t0(x5):  {:#018x}  t1(x6):   {:#018x}", ctx.x[5], ctx.x[6]);
-    println!("  t2(x7):  {:#018x}  s0/fp(x8):{:#018x}", ctx.x[7], ctx.x[8]);
-    println!("  a0(x10): {:#018x}  a1(x11):  {:#018x}", ctx.x[10], ctx.x[11]);
-    println!("  a2(x12): {:#018x}  a3(x13):  {:#018x}", ctx.x[12], ctx.x[13]);
+    ctx.dump();
     
     // 建议修复方法
     println!("\nPossible Solutions:");
@@ -291,9 +352,7 @@ pub fn enhanced_misaligned_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
     // 如果需要停机，调用系统停机函数
     println!("System halting due to unrecoverable misaligned address exception.");
     // 短暂延迟，确保消息能够输出
-    for _ in 0..10000000 {
-        core::hint::spin_loop();
-    }
+    busy_wait_ms(OUTPUT_FLUSH_DELAY_MS);
     crate::util::sbi::system::shutdown(crate::util::sbi::system::ShutdownReason::SystemFailure);
     
     TrapHandlerResult::Handled
@@ -351,9 +410,10 @@ pub fn enhanced_memory_access_fault_handler(ctx: &mut TrapContext) -> TrapHandle
     }
     
     // 检查地址范围
-    if address < 0x80000000 || address >= 0x88000000 {
-        println!("  - Address {:#018x} may be outside valid physical memory range", address);
-        println!("    The typical RISC-V memory range for simple systems is 0x80000000-0x88000000");
+    if !layout::is_kernel_addr(address) {
+        let (start, end) = layout::kernel_range();
+        println!("  - Address {:#018x} is outside the kernel image", address);
+        println!("    The kernel occupies {:#018x}-{:#018x}", start, end);
     }
     
     // 内存映射和权限问题
@@ -370,13 +430,7 @@ pub fn enhanced_memory_access_fault_handler(ctx: &mut TrapContext) -> TrapHandle
     
     // 寄存器状态
     println!("\nRegister State:");
-    println!("  sstatus: {:#018x}", ctx.sstatus);
-    println!("  ra(x1):  {:#018x}  sp(x2):   {:#018x}", ctx.x[1], ctx.x[2]);
-    println!("  gp(x3):  {:#018x}  tp(x4):   {:#018x}", ctx.x[3], ctx.x[4]);
-    println!("  t0(x5):  {:#018x}  t1(x6):   {:#018x}", ctx.x[5], ctx.x[6]);
-    println!("  t2(x7):  {:#018x}  s0/fp(x8):{:#018x}", ctx.x[7], ctx.x[8]);
-    println!("  a0(x10): {:#018x}  a1(x11):  {:#018x}", ctx.x[10], ctx.x[11]);
-    println!("  a2(x12): {:#018x}  a3(x13):  {:#018x}", ctx.x[12], ctx.x[13]);
+    ctx.dump();
     
     // 可能的解决方案
     println!("\nPossible Solutions:");
@@ -390,9 +444,7 @@ pub fn enhanced_memory_access_fault_handler(ctx: &mut TrapContext) -> TrapHandle
     
     // 系统停机
     println!("System halting due to unrecoverable memory access fault.");
-    for _ in 0..10000000 {
-        core::hint::spin_loop();
-    }
+    busy_wait_ms(OUTPUT_FLUSH_DELAY_MS);
     crate::util::sbi::system::shutdown(crate::util::sbi::system::ShutdownReason::SystemFailure);
     
     TrapHandlerResult::Handled
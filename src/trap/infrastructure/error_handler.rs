@@ -3,29 +3,35 @@
 //! 提供全局错误处理器和默认错误处理实现。
 //! 设计为不依赖堆内存分配器。
 
+use core::sync::atomic::{AtomicBool, Ordering};
 use crate::println;
 use crate::trap::ds::{
     SystemError, ErrorResult, ErrorHandler, ErrorHandlerEntry,
     ErrorLog, ErrorSource, ErrorLevel, ErrorCode
 };
+use crate::trap::ds::init_phase::{InitPhase, advance_phase};
 use crate::trap::infrastructure::di;
+use crate::util::sbi::system::{shutdown, ShutdownReason};
+use crate::util::sbi::timer;
+
+/// 内核panic对应的错误编号
+pub const PANIC_ERROR_CODE: u16 = 0xffff;
 
 /// 初始化标志
-static mut INITIALIZED: bool = false;
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 /// 初始化错误处理系统
 pub fn init() {
-    unsafe {
-        if INITIALIZED {
-            println!("Error handling system already initialized");
-            return;
-        }
-        
-        // 注册默认处理器
-        register_default_handlers();
-        
-        INITIALIZED = true;
+    if INITIALIZED.compare_exchange(
+        false, true, Ordering::SeqCst, Ordering::SeqCst
+    ).is_err() {
+        println!("Error handling system already initialized");
+        return;
     }
+
+    // 注册默认处理器
+    register_default_handlers();
+    advance_phase(InitPhase::ErrorReady);
     
     println!("Error handling system initialized");
 }
@@ -111,6 +117,26 @@ pub fn create_error(
     di::create_system_error(source, level, code, address, ip)
 }
 
+/// 把内核panic作为致命错误处理，然后关机
+///
+/// `location`为panic位置记录（`core::panic::Location`）的地址。panic不是由某条指令
+/// 引发的异常，错误中的指令地址记为0，panic点由调用栈回溯给出。
+/// 错误以`ErrorSource::Unknown`、`ErrorLevel::Fatal`交给错误管理器，
+/// 记录到错误日志并运行致命错误处理器，最后通过SRST关机。
+/// 错误管理器被占用时（例如panic发生在错误处理过程中）跳过错误管理器直接关机。
+pub fn handle_panic(location: usize) -> ! {
+    let error = SystemError::new(
+        ErrorCode::new(ErrorSource::Unknown, ErrorLevel::Fatal, PANIC_ERROR_CODE),
+        Some(location),
+        0,
+        timer::now(),
+    );
+    if di::try_handle_system_error(error).is_none() {
+        println!("Error manager busy, skipping fatal error handlers");
+    }
+    shutdown(ShutdownReason::SystemFailure)
+}
+
 /// 打印错误日志
 pub fn print_error_log(count: usize) {
     di::print_error_log(count)
@@ -121,6 +147,21 @@ pub fn clear_error_log() {
     di::clear_error_log()
 }
 
+/// 获取指定错误源累计发生的错误数
+pub fn error_count(source: ErrorSource) -> u64 {
+    di::error_count(source)
+}
+
+/// 打印每个错误源累计发生的错误数
+pub fn print_error_summary() {
+    di::print_error_summary()
+}
+
+/// 设置错误升级阈值，`None`表示不升级
+pub fn set_escalation_threshold(threshold: Option<usize>) {
+    di::set_error_escalation_threshold(threshold)
+}
+
 /// 打印所有注册的错误处理器
 pub fn print_handlers() {
     di::print_error_handlers()
@@ -197,8 +238,7 @@ fn fatal_error_handler(error: &SystemError) -> ErrorResult {
     println!("FATAL ERROR: {}", error);
     println!("System will be halted");
     
-    // 输出最近错误日志
-    print_error_log(5);
+    // 处理器运行时错误管理器已被锁住，最近的错误日志由错误管理器自己输出
     
     // 可以尝试保存状态或执行紧急恢复措施
     ErrorResult::Partial // 返回Partial以允许其他处理器也处理
@@ -0,0 +1,79 @@
+//! Trap钩子
+//!
+//! 在每次trap分发前后调用的钩子，与处理器注册相互独立，
+//! 用于跟踪、性能统计或安全监控。钩子只能读取上下文，不能改变分发结果。
+//!
+//! 每次trap都要读取钩子表，所有对钩子表的访问都在关闭中断后进行，
+//! 注册钩子时发生的中断不会在同一核心上等待钩子表的锁。
+
+use spin::Mutex;
+use crate::trap::ds::{TrapContext, TrapType, TrapHandlerResult};
+use super::{disable_interrupts, restore_interrupts};
+
+/// 分发前调用的钩子
+pub type PreTrapHook = fn(&TrapContext, TrapType);
+
+/// 分发后调用的钩子
+pub type PostTrapHook = fn(&TrapContext, TrapType, TrapHandlerResult);
+
+/// 每类钩子的最大数量
+pub const MAX_TRAP_HOOKS: usize = 8;
+
+/// 钩子标识，注销时使用
+pub type HookId = usize;
+
+static PRE_HOOKS: Mutex<[Option<PreTrapHook>; MAX_TRAP_HOOKS]> = Mutex::new([None; MAX_TRAP_HOOKS]);
+static POST_HOOKS: Mutex<[Option<PostTrapHook>; MAX_TRAP_HOOKS]> = Mutex::new([None; MAX_TRAP_HOOKS]);
+
+/// 关闭中断并持有钩子表的锁执行`f`
+fn with_hooks<T, R>(hooks: &Mutex<T>, f: impl FnOnce(&mut T) -> R) -> R {
+    let was_enabled = disable_interrupts();
+    let result = f(&mut *hooks.lock());
+    restore_interrupts(was_enabled);
+    result
+}
+
+/// 在空槽位中登记钩子
+fn insert<T: Copy>(slots: &mut [Option<T>; MAX_TRAP_HOOKS], hook: T) -> Option<HookId> {
+    let id = slots.iter().position(|slot| slot.is_none())?;
+    slots[id] = Some(hook);
+    Some(id)
+}
+
+/// 注册分发前钩子，槽位已满时返回None
+pub fn register_pre_hook(hook: PreTrapHook) -> Option<HookId> {
+    with_hooks(&PRE_HOOKS, |hooks| insert(hooks, hook))
+}
+
+/// 注册分发后钩子，槽位已满时返回None
+pub fn register_post_hook(hook: PostTrapHook) -> Option<HookId> {
+    with_hooks(&POST_HOOKS, |hooks| insert(hooks, hook))
+}
+
+/// 注销分发前钩子
+pub fn unregister_pre_hook(id: HookId) -> bool {
+    id < MAX_TRAP_HOOKS && with_hooks(&PRE_HOOKS, |hooks| hooks[id].take().is_some())
+}
+
+/// 注销分发后钩子
+pub fn unregister_post_hook(id: HookId) -> bool {
+    id < MAX_TRAP_HOOKS && with_hooks(&POST_HOOKS, |hooks| hooks[id].take().is_some())
+}
+
+/// 调用所有分发前钩子
+///
+/// 先复制钩子列表再释放锁，钩子内部可以安全地注册或注销钩子
+pub fn run_pre_hooks(ctx: &TrapContext, trap_type: TrapType) {
+    let hooks = with_hooks(&PRE_HOOKS, |hooks| *hooks);
+    for hook in hooks.iter().flatten() {
+        hook(ctx, trap_type);
+    }
+}
+
+/// 调用所有分发后钩子
+pub fn run_post_hooks(ctx: &TrapContext, trap_type: TrapType, result: TrapHandlerResult) {
+    let hooks = with_hooks(&POST_HOOKS, |hooks| *hooks);
+    for hook in hooks.iter().flatten() {
+        hook(ctx, trap_type, result);
+    }
+}
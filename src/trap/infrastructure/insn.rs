@@ -0,0 +1,110 @@
+//! 出错指令的读取与解码
+//!
+//! 增强型异常处理器用它打印sepc处的指令。读取前先确认地址位于内核代码段内，
+//! 内核代码段总是恒等映射且可读，读取本身不会在异常处理器中再次引发异常；
+//! 其他地址（例如用户态代码）一律不读取。
+
+use crate::println;
+
+extern "C" {
+    fn stext();
+    fn etext();
+}
+
+/// 指令的编码长度：低两位不是`11`的是16位压缩指令
+pub const fn instruction_length(instruction: u32) -> usize {
+    if instruction & 0x3 == 0x3 { 4 } else { 2 }
+}
+
+/// 地址处的指令能否安全读取
+///
+/// 只有按2字节对齐、且整条指令都位于内核代码段内的地址才可以
+pub fn is_readable_code(pc: usize) -> bool {
+    let (start, end) = (stext as usize, etext as usize);
+    pc >= start && pc & 0x1 == 0 && pc.checked_add(2).is_some_and(|tail| tail <= end)
+}
+
+/// 读取内核代码段中的一条指令，16位压缩指令在低16位返回
+///
+/// 地址不在内核代码段内时返回None，不会访问该地址
+pub fn read_instruction(pc: usize) -> Option<u32> {
+    if !is_readable_code(pc) {
+        return None;
+    }
+
+    // 指令可能只按2字节对齐，分两次读取；32位指令的高半部分也必须在代码段内
+    let low = unsafe { core::ptr::read_volatile(pc as *const u16) } as u32;
+    if instruction_length(low) == 2 {
+        return Some(low);
+    }
+    if !is_readable_code(pc + 2) {
+        return None;
+    }
+    let high = unsafe { core::ptr::read_volatile((pc + 2) as *const u16) } as u32;
+    Some(low | (high << 16))
+}
+
+/// 指令的主操作码类别
+///
+/// 32位指令按opcode（低7位）分类；压缩指令只区分所在象限
+pub fn major_category(instruction: u32) -> &'static str {
+    if instruction_length(instruction) == 2 {
+        return match instruction & 0x3 {
+            0 => "COMPRESSED (Q0)",
+            1 => "COMPRESSED (Q1)",
+            _ => "COMPRESSED (Q2)",
+        };
+    }
+
+    match instruction & 0x7f {
+        0x03 => "LOAD",
+        0x07 => "LOAD-FP",
+        0x0f => "MISC-MEM",
+        0x13 => "OP-IMM",
+        0x17 => "AUIPC",
+        0x1b => "OP-IMM-32",
+        0x23 => "STORE",
+        0x27 => "STORE-FP",
+        0x2f => "AMO",
+        0x33 => "OP",
+        0x37 => "LUI",
+        0x3b => "OP-32",
+        0x43 | 0x47 | 0x4b | 0x4f => "FMADD",
+        0x53 => "OP-FP",
+        0x63 => "BRANCH",
+        0x67 => "JALR",
+        0x6f => "JAL",
+        0x73 => "SYSTEM",
+        _ => "UNKNOWN",
+    }
+}
+
+/// 指令的funct3字段，压缩指令取第13–15位
+pub const fn funct3(instruction: u32) -> u32 {
+    if instruction_length(instruction) == 2 {
+        (instruction >> 13) & 0x7
+    } else {
+        (instruction >> 12) & 0x7
+    }
+}
+
+/// 打印`pc`处的指令及其解码结果，返回读到的指令
+///
+/// 地址不在内核代码段内时只打印说明，不读取内存
+pub fn print_instruction(pc: usize) -> Option<u32> {
+    let Some(instruction) = read_instruction(pc) else {
+        println!("Instruction at {:#x}: <not in kernel text, not read>", pc);
+        return None;
+    };
+
+    if instruction_length(instruction) == 2 {
+        println!("Instruction at {:#x}: {:#06x} (compressed)", pc, instruction);
+        println!("  quadrant {}  funct3 {}  category {}",
+                 instruction & 0x3, funct3(instruction), major_category(instruction));
+    } else {
+        println!("Instruction at {:#x}: {:#010x}", pc, instruction);
+        println!("  opcode {:#04x}  funct3 {}  category {}",
+                 instruction & 0x7f, funct3(instruction), major_category(instruction));
+    }
+    Some(instruction)
+}
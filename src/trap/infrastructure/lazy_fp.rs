@@ -0,0 +1,303 @@
+//! 浮点上下文的惰性切换
+//!
+//! 任务切换时不保存也不恢复浮点寄存器，只根据寄存器的归属设置新任务的sstatus.FS：
+//! 寄存器仍是该任务的值时为Clean，否则为Off。任务第一次执行浮点指令时触发非法指令异常，
+//! 这时才把寄存器保存到上一个所有者的保存区、载入当前任务的值，并把所有权交给当前任务。
+//! 从不使用浮点的任务完全不付出浮点保存和恢复的开销。
+//!
+//! FS为Initial时浮点指令不会触发异常，所以未持有寄存器的任务使用Off。
+//! 所有者以`ContextId`按核心记录在上下文管理器中。寄存器只能在所属的核心上保存，
+//! 因此目前假定任务不会在持有浮点寄存器时迁移到其他核心。
+//!
+//! 调度器每次切换任务时调用`switch_current`设置当前的FS。引导任务没有进程，
+//! 以不会被分配的上下文ID 0参与同样的惰性切换。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::trap::ds::{
+    TrapContext, TrapHandlerResult, TrapType, FpState, FpRegisters,
+    ContextManager, with_context_manager, ContextManagerAccessError,
+};
+use crate::trap::infrastructure::di::{self, context::{ContextId, KERNEL_CONTEXT_ID}};
+use crate::util::cpu;
+
+/// 浮点保存区的数量，同时使用过浮点的任务不能超过该值
+pub const MAX_FP_CONTEXTS: usize = 16;
+
+/// 惰性浮点处理器的优先级，需要先于增强型非法指令处理器运行
+const LAZY_FP_PRIORITY: u8 = 5;
+
+/// sstatus.FS的最低位，置位后FS至少为Initial，浮点指令可以执行
+const SSTATUS_FS_INITIAL: usize = 1 << 13;
+
+/// sstatus.FS字段的位置
+const SSTATUS_FS_SHIFT: usize = 13;
+const SSTATUS_FS_MASK: usize = 0b11 << SSTATUS_FS_SHIFT;
+
+/// 代表引导任务的上下文ID，生成的上下文ID从1开始，不会与它冲突
+const BOOT_CONTEXT: ContextId = 0;
+
+/// 一个任务的浮点保存区
+struct FpSlot {
+    id: ContextId,
+    regs: FpRegisters,
+}
+
+/// 使用过浮点的任务的保存区
+static FP_AREAS: Mutex<[Option<FpSlot>; MAX_FP_CONTEXTS]> =
+    Mutex::new([const { None }; MAX_FP_CONTEXTS]);
+
+/// 因首次使用浮点而转移所有权的次数
+static FP_TRAPS: AtomicUsize = AtomicUsize::new(0);
+
+/// 判断指令是否为浮点指令
+///
+/// 识别LOAD-FP、STORE-FP、乘加、OP-FP以及访问fflags/frm/fcsr的CSR指令，
+/// 16位指令识别C.FLD、C.FSD、C.FLDSP和C.FSDSP。
+pub fn is_fp_instruction(insn: u32) -> bool {
+    if insn & 0b11 != 0b11 {
+        let quadrant = insn & 0b11;
+        let funct3 = (insn >> 13) & 0b111;
+        return matches!((quadrant, funct3), (0, 1) | (0, 5) | (2, 1) | (2, 5));
+    }
+
+    match insn & 0x7f {
+        0x07 | 0x27 | 0x43 | 0x47 | 0x4b | 0x4f | 0x53 => true,
+        0x73 => {
+            let funct3 = (insn >> 12) & 0b111;
+            let csr = insn >> 20;
+            funct3 != 0 && funct3 != 4 && (1..=3).contains(&csr)
+        }
+        _ => false,
+    }
+}
+
+/// 取得触发异常的指令
+///
+/// 优先使用stval中的指令编码；stval为0且来自S模式时从sepc读取
+fn faulting_instruction(ctx: &TrapContext) -> u32 {
+    if ctx.stval != 0 || !ctx.returns_to_supervisor() {
+        return ctx.stval as u32;
+    }
+
+    // sepc可能只按2字节对齐，分两次读取
+    let low = unsafe { core::ptr::read_volatile(ctx.sepc as *const u16) } as u32;
+    if low & 0b11 != 0b11 {
+        return low;
+    }
+    let high = unsafe { core::ptr::read_volatile((ctx.sepc + 2) as *const u16) } as u32;
+    low | (high << 16)
+}
+
+/// 寄存器仍属于`next`时为Clean，否则为Off
+fn fp_state_for(manager: &ContextManager, next: ContextId) -> FpState {
+    if manager.fp_owner() == Some(next) {
+        FpState::Clean
+    } else {
+        FpState::Off
+    }
+}
+
+/// 切换到任务`next`，按浮点寄存器的归属设置它返回时的FS
+///
+/// 返回设置后的浮点单元状态：寄存器仍属于`next`时为Clean，否则为Off
+pub fn switch_to(next: ContextId, next_ctx: &mut TrapContext) -> Result<FpState, ContextManagerAccessError> {
+    with_context_manager(|manager| {
+        manager.set_current_context(Some(next));
+        let state = fp_state_for(manager, next);
+        next_ctx.set_fp_state(state);
+        state
+    })
+}
+
+/// 调度器切换任务前调用，按浮点寄存器的归属设置当前核心的FS
+///
+/// `next`为None表示切换到引导任务。`task_switch`不保存sstatus，
+/// 这里设置的FS就是下一个任务继续运行时的状态。
+/// 第一次切换前引导任务可能已经在不持有寄存器的情况下用过浮点，
+/// 这时先把寄存器记在它名下，之后被其他任务取走时会保存到它的保存区。
+pub fn switch_current(next: Option<ContextId>) -> Result<FpState, ContextManagerAccessError> {
+    with_context_manager(|manager| {
+        if manager.current_context().is_none()
+            && manager.fp_owner().is_none()
+            && live_fp_state() != FpState::Off
+            && claim_area(BOOT_CONTEXT)
+        {
+            manager.set_fp_owner(Some(BOOT_CONTEXT));
+        }
+
+        let next = next.unwrap_or(BOOT_CONTEXT);
+        manager.set_current_context(Some(next));
+        let state = fp_state_for(manager, next);
+        set_live_fp_state(state);
+        state
+    })
+}
+
+/// 被抢占的任务在trap返回路径上切换回来后调用，按寄存器的归属修正陷阱帧中的FS
+///
+/// trap返回时按陷阱帧中保存的sstatus恢复FS，`switch_current`设置的值会被覆盖。
+/// 寄存器仍属于当前任务时保持原状，保存时为Dirty的值照常从陷阱帧恢复；
+/// 寄存器已被其他任务取走时设为Off，丢弃陷阱帧中的值，下次使用浮点时从保存区重新载入。
+pub fn resume_preempted(ctx: &mut TrapContext) -> Result<FpState, ContextManagerAccessError> {
+    with_context_manager(|manager| {
+        if let Some(current) = manager.current_context() {
+            if manager.fp_owner() != Some(current) {
+                ctx.set_fp_state(FpState::Off);
+            }
+        }
+        ctx.fp_state()
+    })
+}
+
+/// 当前核心sstatus中的FS
+fn live_fp_state() -> FpState {
+    let sstatus: usize;
+    unsafe {
+        core::arch::asm!("csrr {0}, sstatus", out(reg) sstatus, options(nomem, nostack));
+    }
+    FpState::from_sstatus(sstatus)
+}
+
+/// 设置当前核心sstatus中的FS
+fn set_live_fp_state(state: FpState) {
+    let bits: usize = match state {
+        FpState::Off => 0,
+        FpState::Initial => 1,
+        FpState::Clean => 2,
+        FpState::Dirty => 3,
+    };
+    unsafe {
+        core::arch::asm!(
+            "csrc sstatus, {0}",
+            "csrs sstatus, {1}",
+            in(reg) SSTATUS_FS_MASK,
+            in(reg) bits << SSTATUS_FS_SHIFT,
+            options(nostack),
+        );
+    }
+}
+
+/// 为`id`分配保存区，已经有保存区时直接返回true
+///
+/// 新保存区的内容稍后由寄存器转移时写入。没有空闲的保存区时返回false
+fn claim_area(id: ContextId) -> bool {
+    let mut areas = FP_AREAS.lock();
+    if areas.iter().flatten().any(|slot| slot.id == id) {
+        return true;
+    }
+    match areas.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(FpSlot { id, regs: FpRegisters::new() });
+            true
+        }
+        None => false,
+    }
+}
+
+/// 任务结束时释放它的浮点保存区和所有权
+///
+/// 当前核心的寄存器属于`id`时关闭浮点单元，之后运行的代码不会沿用这些值
+pub fn release(id: ContextId) -> Result<(), ContextManagerAccessError> {
+    with_context_manager(|manager| {
+        if manager.fp_owner() == Some(id) {
+            set_live_fp_state(FpState::Off);
+        }
+        manager.forget_context(id);
+        for slot in FP_AREAS.lock().iter_mut() {
+            if slot.as_ref().is_some_and(|slot| slot.id == id) {
+                *slot = None;
+            }
+        }
+    })
+}
+
+/// 当前核心浮点寄存器的所有者
+pub fn fp_owner() -> Option<ContextId> {
+    with_context_manager(|manager| manager.fp_owner()).ok().flatten()
+}
+
+/// 因首次使用浮点而转移所有权的次数
+pub fn fp_trap_count() -> usize {
+    FP_TRAPS.load(Ordering::SeqCst)
+}
+
+/// 把浮点寄存器从`previous`转移给`next`
+///
+/// 没有空闲的保存区时返回false，寄存器保持不变
+fn transfer(previous: Option<ContextId>, next: ContextId) -> bool {
+    let mut areas = FP_AREAS.lock();
+
+    let next_index = match areas
+        .iter()
+        .position(|slot| slot.as_ref().is_some_and(|slot| slot.id == next))
+        .or_else(|| areas.iter().position(Option::is_none))
+    {
+        Some(index) => index,
+        None => return false,
+    };
+
+    // 被打断的代码FS为Off，先打开浮点单元，trap返回时sstatus会整体恢复
+    unsafe {
+        core::arch::asm!("csrs sstatus, {0}", in(reg) SSTATUS_FS_INITIAL, options(nostack));
+    }
+
+    if let Some(previous) = previous {
+        if let Some(slot) = areas.iter_mut().flatten().find(|slot| slot.id == previous) {
+            unsafe { slot.regs.save() };
+        }
+    }
+
+    let slot = areas[next_index].get_or_insert_with(|| FpSlot {
+        id: next,
+        regs: FpRegisters::new(),
+    });
+    unsafe { slot.regs.restore() };
+    true
+}
+
+/// 处理浮点单元关闭时执行浮点指令引起的非法指令异常
+///
+/// 不是这种情况时返回`Pass`交给后续的非法指令处理器。
+/// 成功后sepc保持不变，trap返回时重新执行这条浮点指令。
+pub(crate) fn handle_fp_disabled(ctx: &mut TrapContext) -> TrapHandlerResult {
+    if ctx.fp_state() != FpState::Off || !cpu::isa_extensions().has_f() {
+        return TrapHandlerResult::Pass;
+    }
+    if !is_fp_instruction(faulting_instruction(ctx)) {
+        return TrapHandlerResult::Pass;
+    }
+
+    let granted = with_context_manager(|manager| {
+        // 没有任务在运行时不知道该把寄存器交给谁
+        let Some(current) = manager.current_context() else {
+            return false;
+        };
+
+        let previous = manager.fp_owner();
+        if previous != Some(current) && !transfer(previous, current) {
+            return false;
+        }
+        manager.set_fp_owner(Some(current));
+        true
+    });
+
+    if granted != Ok(true) {
+        return TrapHandlerResult::Pass;
+    }
+
+    ctx.set_fp_state(FpState::Clean);
+    FP_TRAPS.fetch_add(1, Ordering::SeqCst);
+    TrapHandlerResult::Handled
+}
+
+/// 注册惰性浮点处理器
+pub fn register_handler() -> bool {
+    di::register_handler(
+        TrapType::IllegalInstruction,
+        handle_fp_disabled,
+        LAZY_FP_PRIORITY,
+        "Lazy FP Handler",
+        KERNEL_CONTEXT_ID,
+    )
+}
@@ -4,20 +4,30 @@
 
 mod vector;
 mod context;
-mod registry;
+pub mod registry;  // 旧版处理器注册表
 //pub mod test;
 pub mod di;  // New dependency injection module
 pub mod error_handler;  // Error handling module
 //pub mod error_test;  // Error handling tests
 pub mod enhanced_handlers;  // 增强型异常处理器
+pub mod double_fault;  // 双重故障检测
+pub mod hooks;  // trap前后钩子
+pub mod nest_overflow;  // 中断嵌套溢出处理
+pub mod plic;  // PLIC外部中断处理
+pub mod backtrace;  // 帧指针栈回溯
+pub mod lazy_fp;  // 浮点上下文惰性切换
+pub mod insn;  // 出错指令的读取与解码
 //pub mod test_enhanced;  // 增强型异常处理器测试
 
 use crate::println;
+use crate::trap_log;
 use crate::trap::ds::{TrapContext, TaskContext, TrapMode, Interrupt, Exception, TrapType, TrapHandlerResult, TrapError};
 
 // Export APIs from submodules
 pub use vector::{
     init, 
+    set_trap_mode,
+    trap_mode,
     enable_interrupts, 
     disable_interrupts, 
     restore_interrupts,
@@ -25,13 +35,30 @@ pub use vector::{
     disable_interrupt,
     is_interrupt_enabled,
     is_interrupt_pending,
+    mask_all_except,
+    restore_mask,
+    InterruptMask,
+    allow_nested_interrupts,
+    NestedInterruptGuard,
     set_soft_interrupt,
     clear_soft_interrupt,
+    pending_interrupts,
+    clear_pending_interrupts,
+    PendingFlags,
+    vector_entry_count,
+    VECTOR_TABLE_ENTRIES,
 };
 
 // Export context management API
 pub use context::{
     task_switch,
+    set_resched_handler,
+    set_need_resched,
+    need_resched,
+    disable_preemption,
+    enable_preemption,
+    preemption_enabled,
+    PreemptGuard,
     prepare_task_context,
     trap_return,
     save_full_context,
@@ -46,7 +73,9 @@ pub use registry::{
     register_handler_with_owner,
     unregister_handler,
     unregister_handler_secure,
+    set_handler_enabled,
     dispatch_trap,
+    call_next_handler,
     handler_count,
     print_handlers,
     unregister_handlers_for_context_secure,
@@ -63,6 +92,9 @@ pub use error_handler::{
     print_handlers as print_error_handlers,
     print_error_log,
     clear_error_log,
+    error_count,
+    print_error_summary,
+    set_escalation_threshold as set_error_escalation_threshold,
     is_panic_mode as is_in_panic_mode,
     reset_panic_mode,
 };
@@ -113,6 +145,14 @@ fn register_default_handlers() {
         100,
         "Default System Call Handler"
     );
+
+    // Supervisor ecall default handler
+    registry::register_handler(
+        TrapType::SupervisorCall,
+        default_supervisor_call_handler,
+        100,
+        "Default Supervisor Call Handler"
+    );
     
     // Page fault default handlers
     registry::register_handler(
@@ -161,24 +201,32 @@ fn register_default_handlers() {
 
 // Default handler implementations
 fn default_timer_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Timer interrupt occurred");
+    crate::util::sbi::timer::handle_tick();
     TrapHandlerResult::Handled
 }
 
 fn default_software_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("Software interrupt occurred");
+    trap_log!("Software interrupt occurred");
     vector::clear_soft_interrupt();
     TrapHandlerResult::Handled
 }
 
 fn default_external_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("External interrupt occurred");
+    if plic::handle_pending() == 0 {
+        println!("Spurious external interrupt: nothing to claim");
+    }
     TrapHandlerResult::Handled
 }
 
 fn default_syscall_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
-    println!("System call occurred");
-    // System calls need to advance PC past the ecall instruction
+    // The dispatcher writes a0 and advances PC past the ecall instruction
+    crate::trap::syscall::dispatch(ctx);
+    TrapHandlerResult::Handled
+}
+
+fn default_supervisor_call_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
+    trap_log!("Supervisor call occurred");
+    // Supervisor ecalls also need to advance PC past the ecall instruction
     ctx.set_return_addr(ctx.sepc + 4);
     TrapHandlerResult::Handled
 }
@@ -205,6 +253,14 @@ fn default_unknown_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
     TrapHandlerResult::Handled
 }
 
+/// Maximum interrupt nesting level allowed by the global context manager
+///
+/// Falls back to the default level if the manager is not ready or busy.
+fn max_nest_level() -> usize {
+    crate::trap::ds::with_context_manager(|manager| manager.max_nest_level())
+        .unwrap_or(crate::trap::ds::ContextManager::DEFAULT_MAX_NEST_LEVEL)
+}
+
 /// Interrupt handler function
 /// 
 /// This function is the central entry point for all traps/interrupts in the system.
@@ -215,10 +271,41 @@ fn default_unknown_handler(ctx: &mut TrapContext) -> TrapHandlerResult {
 /// * `context` - Pointer to the trap context saved by the assembly entry point
 #[no_mangle]
 pub extern "C" fn handle_trap(context: *mut TrapContext) {
+    // Faults expected by a probe read are resolved before double fault detection,
+    // since probes are commonly issued from within other trap handlers
+    if crate::mm::probe::fixup(unsafe { &mut *context }) {
+        return;
+    }
+
+    // Detect traps taken while already handling a trap
+    let depth = match double_fault::enter(unsafe { &*context }) {
+        Ok(depth) => depth,
+        Err(fault) => double_fault::report_and_halt(&fault),
+    };
+
+    // Interrupts nested beyond the allowed level are dropped instead of handled
+    if depth > max_nest_level() {
+        match nest_overflow::handle_stack_overflow(unsafe { &*context }, depth) {
+            nest_overflow::OverflowAction::Dropped { .. } => {
+                double_fault::exit();
+                return;
+            }
+            nest_overflow::OverflowAction::Halt => {
+                nest_overflow::report_and_halt(unsafe { &*context }, depth);
+            }
+        }
+    }
+
+    // Run pre-dispatch hooks with a read-only view of the context
+    let hook_trap_type = unsafe { &*context }.get_cause().to_trap_type();
+    hooks::run_pre_hooks(unsafe { &*context }, hook_trap_type);
+
     // If the DI system is initialized, use it
     if di::get_trap_system_initialized() {
         // DI system will handle the trap
-        di::internal_handle_trap(context);
+        let result = di::internal_handle_trap(context);
+        hooks::run_post_hooks(unsafe { &*context }, hook_trap_type, result);
+        double_fault::exit();
         return;
     }
     
@@ -234,34 +321,39 @@ pub extern "C" fn handle_trap(context: *mut TrapContext) {
     
     // Record trap occurrence
     if cause.is_interrupt() {
-        println!("Interrupt occurred: {:?}, code: {}, nest level: {}", 
+        trap_log!("Interrupt occurred: {:?}, code: {}, nest level: {}", 
                  trap_type, cause.code(), nest_level);
     } else {
-        println!("Exception occurred: {:?}, code: {}, addr: {:#x}, nest level: {}", 
+        trap_log!("Exception occurred: {:?}, code: {}, addr: {:#x}, nest level: {}", 
                  trap_type, cause.code(), ctx.stval, nest_level);
     }
     
     // Dispatch to registered handlers
-    match registry::dispatch_trap(trap_type, ctx) {
+    let result = registry::dispatch_trap(trap_type, ctx);
+    match result {
         TrapHandlerResult::Handled => {
             // Successfully handled
-            println!("Interrupt handled successfully by registered handler");
+            trap_log!("Interrupt handled successfully by registered handler");
+        },
+        TrapHandlerResult::HandledAndMaskInterrupts => {
+            // Handled; the dispatcher already cleared SPIE in the saved context
+            trap_log!("Interrupt handled successfully, interrupts stay masked on return");
         },
         TrapHandlerResult::Pass => {
             // All handlers passed this interrupt
-            println!("All handlers passed the interrupt: {:?}", trap_type);
+            trap_log!("All handlers passed the interrupt: {:?}", trap_type);
             
             // Default handling logic...
             if cause.is_interrupt() {
                 match trap_type {
                     TrapType::TimerInterrupt => {
-                        println!("Fallback handling for timer interrupt");
+                        trap_log!("Fallback handling for timer interrupt");
                     },
                     TrapType::SoftwareInterrupt => {
                         vector::clear_soft_interrupt();
                     },
                     TrapType::ExternalInterrupt => {
-                        println!("Fallback handling for external interrupt");
+                        trap_log!("Fallback handling for external interrupt");
                     },
                     _ => {
                         println!("No fallback handler for interrupt type: {:?}", trap_type);
@@ -270,8 +362,8 @@ pub extern "C" fn handle_trap(context: *mut TrapContext) {
             } else {
                 // Exception handling
                 match trap_type {
-                    TrapType::SystemCall => {
-                        println!("Fallback handling for system call");
+                    TrapType::SystemCall | TrapType::SupervisorCall => {
+                        trap_log!("Fallback handling for system call");
                         // System calls need to advance PC past the ecall instruction
                         ctx.set_return_addr(ctx.sepc + 4);
                     },
@@ -292,5 +384,7 @@ pub extern "C" fn handle_trap(context: *mut TrapContext) {
         }
     }
     
-    println!("Exiting trap handler for {:?}, nest level: {}", trap_type, nest_level);
+    trap_log!("Exiting trap handler for {:?}, nest level: {}", trap_type, nest_level);
+    hooks::run_post_hooks(ctx, trap_type, result);
+    double_fault::exit();
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+//! 中断嵌套溢出处理
+//!
+//! 中断嵌套超过上下文管理器允许的最大层级（`ContextError::StackOverflow`）时，
+//! 说明出现了中断风暴，继续处理只会耗尽中断栈。这里的处理方式是：
+//!
+//! 1. 屏蔽当前仍然使能的优先级最低的中断源，打断中断风暴
+//! 2. 把`SystemError(ErrorSource::Interrupt)`交给错误管理器记录并处理
+//! 3. 丢弃这次中断，直接返回被打断的代码
+//!
+//! 溢出的trap如果是异常则无法丢弃；所有中断源都已屏蔽时风暴也无法打断。
+//! 这两种情况错误级别为致命，只能输出诊断信息后停机。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::println;
+use crate::try_println;
+use crate::trap::ds::{
+    TrapContext, Interrupt, SystemError, ErrorCode, ErrorSource, ErrorLevel,
+};
+use crate::trap::infrastructure::{di, disable_interrupt, is_interrupt_enabled};
+use crate::util::sbi::system::{shutdown, ShutdownReason};
+use crate::util::sbi::timer;
+use crate::util::delay::{busy_wait_ms, OUTPUT_FLUSH_DELAY_MS};
+
+/// 中断嵌套溢出使用的错误编号
+pub const NEST_OVERFLOW_ERROR_CODE: u16 = 0x5F;
+
+/// 按优先级从低到高排列的中断源（S态：外部 > 软件 > 定时器）
+const INTERRUPTS_BY_PRIORITY: [Interrupt; 3] = [
+    Interrupt::SupervisorTimer,
+    Interrupt::SupervisorSoft,
+    Interrupt::SupervisorExternal,
+];
+
+/// 发生嵌套溢出的次数
+static OVERFLOW_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 最近一次嵌套溢出对应的错误
+static LAST_OVERFLOW: Mutex<Option<SystemError>> = Mutex::new(None);
+
+/// 嵌套溢出的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowAction {
+    /// 中断已被丢弃，`masked`是为打断中断风暴而屏蔽的中断源
+    Dropped {
+        masked: Option<Interrupt>,
+    },
+    /// 溢出的是异常，无法安全丢弃，必须停机
+    Halt,
+}
+
+/// 处理中断嵌套溢出
+///
+/// 只做记录和屏蔽，不会阻塞或panic。返回`Dropped`时调用者应直接返回；
+/// 返回`Halt`时调用者应调用`report_and_halt`。
+pub fn handle_stack_overflow(ctx: &TrapContext, nest_level: usize) -> OverflowAction {
+    let cause = ctx.get_cause();
+    OVERFLOW_COUNT.fetch_add(1, Ordering::SeqCst);
+
+    // 异常无法丢弃，不屏蔽任何中断源
+    let masked = if cause.is_interrupt() {
+        mask_lowest_priority_interrupt()
+    } else {
+        None
+    };
+
+    // 打断了中断风暴时系统可以继续运行，否则只能停机
+    let level = if masked.is_some() { ErrorLevel::Critical } else { ErrorLevel::Fatal };
+    let error = SystemError::new(
+        ErrorCode::new(ErrorSource::Interrupt, level, NEST_OVERFLOW_ERROR_CODE),
+        Some(ctx.stval),
+        ctx.sepc,
+        timer::now(),
+    );
+
+    // 这里可能已经处于持锁状态，只尝试记录，不阻塞
+    if let Some(mut last) = LAST_OVERFLOW.try_lock() {
+        *last = Some(error);
+    }
+
+    try_println!("Interrupt nesting overflow at level {}: {:?}, error: {}",
+                 nest_level, cause.to_trap_type(), error);
+
+    // 交给错误管理器记录日志并运行错误处理器，溢出可能发生在错误处理过程中，不等待
+    if di::try_handle_system_error(error).is_none() {
+        try_println!("Error manager busy, nesting overflow not reported");
+    }
+
+    match masked {
+        Some(interrupt) => {
+            try_println!("Masked {:?} to break the interrupt storm", interrupt);
+            OverflowAction::Dropped { masked }
+        }
+        None => {
+            if cause.is_interrupt() {
+                try_println!("No enabled interrupt source left to mask");
+            }
+            OverflowAction::Halt
+        }
+    }
+}
+
+/// 屏蔽当前使能的优先级最低的中断源
+fn mask_lowest_priority_interrupt() -> Option<Interrupt> {
+    let interrupt = INTERRUPTS_BY_PRIORITY
+        .iter()
+        .copied()
+        .find(|&interrupt| is_interrupt_enabled(interrupt))?;
+    disable_interrupt(interrupt);
+    Some(interrupt)
+}
+
+/// 获取嵌套溢出发生的次数
+pub fn overflow_count() -> usize {
+    OVERFLOW_COUNT.load(Ordering::SeqCst)
+}
+
+/// 获取最近一次嵌套溢出对应的错误
+pub fn last_overflow() -> Option<SystemError> {
+    LAST_OVERFLOW.try_lock().and_then(|last| *last)
+}
+
+/// 打印嵌套溢出诊断信息并停机
+pub fn report_and_halt(ctx: &TrapContext, nest_level: usize) -> ! {
+    println!("\n═════════════════════════════════════════════════════");
+    println!("FATAL ERROR: TRAP NESTING OVERFLOW");
+    println!("═════════════════════════════════════════════════════");
+    println!("Nesting level: {}", nest_level);
+    println!("Trap: {:?} at {:#018x}, stval={:#018x}",
+             ctx.get_cause().to_trap_type(), ctx.sepc, ctx.stval);
+    println!("═════════════════════════════════════════════════════\n");
+
+    println!("System halting due to trap nesting overflow.");
+    busy_wait_ms(OUTPUT_FLUSH_DELAY_MS);
+    shutdown(ShutdownReason::SystemFailure);
+}
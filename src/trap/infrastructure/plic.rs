@@ -0,0 +1,348 @@
+//! PLIC外部中断处理
+//!
+//! 外部中断到达时从PLIC的claim寄存器取得中断号，调用对应的IRQ处理器，
+//! 再写回complete寄存器。`claim`、`complete`、`set_priority`和`enable`
+//! 也可以直接访问对应的寄存器。
+//!
+//! 默认每次trap只claim一个中断，其余待处理的中断各自再触发一次trap。
+//! 开启合并模式（`set_coalescing(true)`）后，一次trap中会循环claim
+//! 直到PLIC返回0，中断风暴下可以省去大量trap进出的开销。
+//!
+//! 寄存器的基地址在启动时从设备树读取（`init_from_fdt`），读取之前使用QEMU virt的地址。
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+use crate::util::sbi::hart::current_hart_id;
+
+/// QEMU virt平台的PLIC基地址，设备树中没有PLIC节点时使用
+pub const DEFAULT_PLIC_BASE: usize = 0x0c00_0000;
+
+/// 设备树中PLIC节点的路径，新版QEMU命名为`plic`，旧版为`interrupt-controller`
+const FDT_PLIC_PATHS: [&str; 2] = ["/soc/plic", "/soc/interrupt-controller"];
+
+/// PLIC寄存器的基地址
+static PLIC_BASE: AtomicUsize = AtomicUsize::new(DEFAULT_PLIC_BASE);
+
+/// 获取PLIC寄存器的基地址
+pub fn plic_base() -> usize {
+    PLIC_BASE.load(Ordering::Relaxed)
+}
+
+/// 使用设备树中PLIC节点的`reg`设置基地址
+///
+/// 找不到PLIC节点时保持原有地址并返回false。需要在开启外部中断之前调用。
+pub fn init_from_fdt(fdt: &crate::util::fdt::Fdt) -> bool {
+    let base = FDT_PLIC_PATHS.iter().find_map(|path| fdt.reg_address(path));
+    match base {
+        Some(base) => {
+            PLIC_BASE.store(base as usize, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 支持的最大中断号（不含）
+pub const MAX_IRQS: usize = 64;
+
+/// 单次合并处理的中断上限，防止持续到来的中断让trap无法返回
+pub const MAX_COALESCED_CLAIMS: usize = 64;
+
+/// IRQ处理器，参数为中断号
+pub type IrqHandler = fn(u32);
+
+/// claim/complete的来源
+pub trait ClaimSource {
+    /// 取得一个待处理的中断号，没有待处理中断时返回0
+    fn claim(&mut self) -> u32;
+    /// 通知中断处理完成
+    fn complete(&mut self, irq: u32);
+}
+
+/// 核心内M模式上下文的序号
+pub const MACHINE_CONTEXT: usize = 0;
+
+/// 核心内S模式上下文的序号
+pub const SUPERVISOR_CONTEXT: usize = 1;
+
+/// 核心`hart`上第`context`个上下文的全局编号
+///
+/// QEMU virt平台上每个核心有M、S两个上下文，编号为`2 * hart + context`
+const fn context_number(hart: usize, context: usize) -> usize {
+    2 * hart + context
+}
+
+/// 当前核心的S模式上下文编号
+fn supervisor_context() -> usize {
+    context_number(current_hart_id(), SUPERVISOR_CONTEXT)
+}
+
+/// 中断号是否在支持的范围内（0表示没有中断，不是有效的中断源）
+fn is_valid_irq(irq: usize) -> bool {
+    irq != 0 && irq < MAX_IRQS
+}
+
+/// 当前核心S模式上下文的PLIC claim/complete寄存器
+pub struct HartPlic {
+    claim_register: *mut u32,
+}
+
+impl HartPlic {
+    /// 获取当前核心的PLIC寄存器
+    pub fn current() -> Self {
+        let context = supervisor_context();
+        Self {
+            claim_register: (plic_base() + 0x20_0004 + context * 0x1000) as *mut u32,
+        }
+    }
+}
+
+impl ClaimSource for HartPlic {
+    fn claim(&mut self) -> u32 {
+        unsafe { core::ptr::read_volatile(self.claim_register) }
+    }
+
+    fn complete(&mut self, irq: u32) {
+        unsafe { core::ptr::write_volatile(self.claim_register, irq) }
+    }
+}
+
+/// 各中断号的处理器
+static IRQ_HANDLERS: Mutex<[Option<IrqHandler>; MAX_IRQS]> = Mutex::new([None; MAX_IRQS]);
+
+/// 是否开启中断合并
+static COALESCING: AtomicBool = AtomicBool::new(false);
+
+/// 没有处理器的中断次数
+static SPURIOUS_IRQS: AtomicUsize = AtomicUsize::new(0);
+
+/// 注册IRQ处理器，中断号越界或已被占用时返回false
+pub fn register_irq_handler(irq: u32, handler: IrqHandler) -> bool {
+    let irq = irq as usize;
+    if !is_valid_irq(irq) {
+        return false;
+    }
+
+    let mut handlers = IRQ_HANDLERS.lock();
+    if handlers[irq].is_some() {
+        return false;
+    }
+    handlers[irq] = Some(handler);
+    true
+}
+
+/// 注销IRQ处理器
+pub fn unregister_irq_handler(irq: u32) -> bool {
+    let irq = irq as usize;
+    irq < MAX_IRQS && IRQ_HANDLERS.lock()[irq].take().is_some()
+}
+
+/// 某个上下文中中断源的使能寄存器及其位
+fn enable_bit(irq: usize, context: usize) -> (*mut u32, u32) {
+    let register = plic_base() + 0x2000 + context * 0x80 + (irq / 32) * 4;
+    (register as *mut u32, 1 << (irq % 32))
+}
+
+/// 设置中断源的优先级，为0时该中断源不会被送达
+///
+/// 中断号越界时返回false
+pub fn set_priority(irq: u32, priority: u32) -> bool {
+    let irq = irq as usize;
+    if !is_valid_irq(irq) {
+        return false;
+    }
+
+    unsafe { core::ptr::write_volatile((plic_base() + irq * 4) as *mut u32, priority) };
+    true
+}
+
+/// 读取中断源的优先级，中断号越界时返回None
+pub fn priority(irq: u32) -> Option<u32> {
+    let irq = irq as usize;
+    is_valid_irq(irq).then(|| unsafe { core::ptr::read_volatile((plic_base() + irq * 4) as *const u32) })
+}
+
+/// 在核心`hart`的第`context`个上下文（`MACHINE_CONTEXT`或`SUPERVISOR_CONTEXT`）中使能中断源
+///
+/// 中断号越界或上下文无效时返回false
+pub fn enable(irq: u32, hart: usize, context: usize) -> bool {
+    let irq = irq as usize;
+    if !is_valid_irq(irq) || context > SUPERVISOR_CONTEXT {
+        return false;
+    }
+
+    let (register, bit) = enable_bit(irq, context_number(hart, context));
+    unsafe {
+        core::ptr::write_volatile(register, core::ptr::read_volatile(register) | bit);
+    }
+    true
+}
+
+/// claim当前核心S模式上下文中的一个待处理中断
+///
+/// 没有待处理中断时返回None。取得的中断处理完成后必须调用`complete`。
+pub fn claim() -> Option<u32> {
+    let irq = HartPlic::current().claim();
+    (irq != 0).then_some(irq)
+}
+
+/// 通知PLIC当前核心已处理完中断`irq`
+pub fn complete(irq: u32) {
+    HartPlic::current().complete(irq);
+}
+
+/// 在当前核心上开启中断源
+///
+/// 设置中断源的优先级（为0时不会被送达），在当前核心的S模式上下文中使能它，
+/// 并把该上下文的优先级阈值设为0。中断号越界或优先级为0时返回false。
+pub fn enable_irq(irq: u32, priority: u32) -> bool {
+    if priority == 0 || !set_priority(irq, priority) {
+        return false;
+    }
+
+    let threshold = (plic_base() + 0x20_0000 + supervisor_context() * 0x1000) as *mut u32;
+    unsafe { core::ptr::write_volatile(threshold, 0) };
+    enable(irq, current_hart_id(), SUPERVISOR_CONTEXT)
+}
+
+/// 在当前核心上关闭中断源
+pub fn disable_irq(irq: u32) -> bool {
+    let irq = irq as usize;
+    if !is_valid_irq(irq) {
+        return false;
+    }
+
+    let (register, bit) = enable_bit(irq, supervisor_context());
+    unsafe {
+        core::ptr::write_volatile(register, core::ptr::read_volatile(register) & !bit);
+    }
+    true
+}
+
+/// 开启或关闭外部中断合并
+pub fn set_coalescing(enabled: bool) {
+    COALESCING.store(enabled, Ordering::SeqCst);
+}
+
+/// 是否开启了外部中断合并
+pub fn is_coalescing() -> bool {
+    COALESCING.load(Ordering::SeqCst)
+}
+
+/// 没有处理器的中断次数
+pub fn spurious_irq_count() -> usize {
+    SPURIOUS_IRQS.load(Ordering::SeqCst)
+}
+
+/// 处理一个已claim的中断
+fn service_irq(irq: u32) {
+    // 先复制处理器再释放锁，处理器内部可以注册或注销处理器
+    let handler = IRQ_HANDLERS.lock().get(irq as usize).copied().flatten();
+    match handler {
+        Some(handler) => handler(irq),
+        None => {
+            SPURIOUS_IRQS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// 从给定来源claim并处理外部中断，返回处理的中断数量
+///
+/// 合并模式下一直claim到来源返回0（最多`MAX_COALESCED_CLAIMS`个），否则只处理一个
+pub fn handle_external<S: ClaimSource>(source: &mut S) -> usize {
+    let limit = if is_coalescing() { MAX_COALESCED_CLAIMS } else { 1 };
+    let mut serviced = 0;
+
+    while serviced < limit {
+        let irq = source.claim();
+        if irq == 0 {
+            break;
+        }
+        service_irq(irq);
+        source.complete(irq);
+        serviced += 1;
+    }
+
+    serviced
+}
+
+/// 模拟的claim来源，仅供测试使用
+#[cfg(feature = "test_hooks")]
+pub(crate) struct SimulatedPlic {
+    pending: [u32; MAX_COALESCED_CLAIMS],
+    head: usize,
+    len: usize,
+    completed: usize,
+}
+
+#[cfg(feature = "test_hooks")]
+impl SimulatedPlic {
+    const fn new() -> Self {
+        Self {
+            pending: [0; MAX_COALESCED_CLAIMS],
+            head: 0,
+            len: 0,
+            completed: 0,
+        }
+    }
+}
+
+#[cfg(feature = "test_hooks")]
+impl ClaimSource for SimulatedPlic {
+    fn claim(&mut self) -> u32 {
+        if self.len == 0 {
+            return 0;
+        }
+        let irq = self.pending[self.head];
+        self.head = (self.head + 1) % MAX_COALESCED_CLAIMS;
+        self.len -= 1;
+        irq
+    }
+
+    fn complete(&mut self, _irq: u32) {
+        self.completed += 1;
+    }
+}
+
+/// 模拟的待处理中断，为None时使用真实的PLIC
+#[cfg(feature = "test_hooks")]
+static SIMULATED: Mutex<Option<SimulatedPlic>> = Mutex::new(None);
+
+/// 改用模拟的claim来源，并排入待处理的中断
+///
+/// 仅供测试使用，结束后必须调用`end_simulation`恢复真实的PLIC
+#[cfg(feature = "test_hooks")]
+pub(crate) fn simulate_pending(irqs: &[u32]) {
+    let mut simulated = SIMULATED.lock();
+    let plic = simulated.get_or_insert_with(SimulatedPlic::new);
+    for &irq in irqs.iter().take(MAX_COALESCED_CLAIMS - plic.len) {
+        let tail = (plic.head + plic.len) % MAX_COALESCED_CLAIMS;
+        plic.pending[tail] = irq;
+        plic.len += 1;
+    }
+}
+
+/// 结束模拟，返回(仍待处理的数量, 已complete的数量)
+#[cfg(feature = "test_hooks")]
+pub(crate) fn end_simulation() -> (usize, usize) {
+    SIMULATED
+        .lock()
+        .take()
+        .map_or((0, 0), |plic| (plic.len, plic.completed))
+}
+
+/// 处理当前核心的外部中断，返回处理的中断数量
+pub fn handle_pending() -> usize {
+    #[cfg(feature = "test_hooks")]
+    {
+        // 模拟来源在处理期间被取出，IRQ处理器中不能再调用simulate_pending
+        let simulated = SIMULATED.lock().take();
+        if let Some(mut plic) = simulated {
+            let serviced = handle_external(&mut plic);
+            *SIMULATED.lock() = Some(plic);
+            return serviced;
+        }
+    }
+
+    handle_external(&mut HartPlic::current())
+}
@@ -5,8 +5,13 @@
 use crate::trap::ds::{TrapType, TrapContext, TrapHandler, HandlerEntry, TrapHandlerResult, TrapError};
 use crate::trap::ds::handler::{ProtectionLevel, RegistrarId, SYSTEM_REGISTRAR_ID};
 use crate::trap::infrastructure::di::context::ContextId;
+use crate::trap::infrastructure::di::{self, RegisterError};
+use crate::trap::infrastructure::di::traits::TrapSystemConfig;
+use crate::trap::api::IrqGuard;
 use crate::println;
-use spin::Mutex; 
+use crate::try_println;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use spin::{RwLock, RwLockWriteGuard};
 
 // 添加安全错误枚举
 #[derive(Debug)]
@@ -20,11 +25,44 @@ pub enum SecurityError {
 // 每种中断类型的最大处理器数量
 const MAX_HANDLERS_PER_TYPE: usize = 8;
 
+/// 配置的每种中断类型处理器上限，不超过`MAX_HANDLERS_PER_TYPE`
+static HANDLER_LIMIT: AtomicUsize = AtomicUsize::new(MAX_HANDLERS_PER_TYPE);
+
 /// 增加注册器结构，支持保护级别和所有权
 #[derive(Copy, Clone)]
 struct HandlerRegistration {
     entry: HandlerEntry,
     context_id: Option<ContextId>,
+    /// 停用的处理器保留插槽但不参与分发
+    enabled: bool,
+}
+
+/// 单个处理器的注册信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerDetails {
+    /// 优先级，数字越小优先级越高
+    pub priority: u8,
+    /// 保护级别
+    pub protection_level: ProtectionLevel,
+    /// 注册者ID
+    pub registrar_id: RegistrarId,
+    /// 关联的上下文ID
+    pub context_id: Option<ContextId>,
+    /// 是否参与分发
+    pub enabled: bool,
+}
+
+impl HandlerRegistration {
+    /// 转换为对外的注册信息
+    fn details(&self) -> HandlerDetails {
+        HandlerDetails {
+            priority: self.entry.priority,
+            protection_level: self.entry.protection_level,
+            registrar_id: self.entry.registrar_id,
+            context_id: self.context_id,
+            enabled: self.enabled,
+        }
+    }
 }
 
 /// 表示中断处理器注册表插槽的状态
@@ -69,12 +107,24 @@ impl HandlerSlot {
 
 /// 中断处理器注册表
 pub struct HandlerRegistry {
-    /// 每种中断类型的处理器数组
-    slots: [[HandlerSlot; MAX_HANDLERS_PER_TYPE]; TrapType::COUNT],
+    /// 每种中断类型（包括`Unknown`）的处理器数组
+    slots: [[HandlerSlot; MAX_HANDLERS_PER_TYPE]; TrapType::SLOTS],
 }
 
 // 全局静态注册表
-static REGISTRY: Mutex<HandlerRegistry> = Mutex::new(HandlerRegistry::new());
+//
+// 分发只需要读锁，多个核心可以同时分发；注册和注销持有写锁
+static REGISTRY: RwLock<HandlerRegistry> = RwLock::new(HandlerRegistry::new());
+
+/// 被停用的中断类型位图，第`trap_type as usize`位置位表示该类型停用
+///
+/// 与硬件的sie屏蔽无关：中断仍然会发生，只是不分发给处理器
+static DISABLED_TYPES: AtomicU32 = AtomicU32::new(0);
+
+/// 各中断类型运行处理器的次数，读取时不需要注册表锁
+///
+/// trap发生的次数与DI分发共用`di::trap_counts`，这里只统计处理器运行次数
+static HANDLER_RUNS: [AtomicU64; TrapType::SLOTS] = [const { AtomicU64::new(0) }; TrapType::SLOTS];
 
 impl HandlerRegistry {
     /// 创建新的处理器注册表
@@ -84,7 +134,7 @@ impl HandlerRegistry {
         const EMPTY_ARRAY: [HandlerSlot; MAX_HANDLERS_PER_TYPE] = [EMPTY_SLOT; MAX_HANDLERS_PER_TYPE];
         
         Self {
-            slots: [EMPTY_ARRAY; TrapType::COUNT],
+            slots: [EMPTY_ARRAY; TrapType::SLOTS],
         }
     }
     
@@ -116,7 +166,13 @@ impl HandlerRegistry {
         
         if insert_index == MAX_HANDLERS_PER_TYPE {
             // 没有可用插槽
-            println!("Cannot register handler: registry full for {:?}", trap_type);
+            try_println!("Cannot register handler: registry full for {:?}", trap_type);
+            return false;
+        }
+
+        let limit = handler_limit();
+        if occupied_count >= limit {
+            try_println!("Cannot register handler: configured limit of {} reached for {:?}", limit, trap_type);
             return false;
         }
         
@@ -124,7 +180,7 @@ impl HandlerRegistry {
         if !self.slots[type_index][insert_index].is_empty() {
             // 确保有足够的空间
             if occupied_count >= MAX_HANDLERS_PER_TYPE {
-                println!("Cannot register handler: registry full for {:?}", trap_type);
+                try_println!("Cannot register handler: registry full for {:?}", trap_type);
                 return false;
             }
             
@@ -141,17 +197,18 @@ impl HandlerRegistry {
         let registration = HandlerRegistration {
             entry,
             context_id: None,
+            enabled: true,
         };
         
         // 插入新处理器
         self.slots[type_index][insert_index] = HandlerSlot::Occupied(registration);
         
-        println!("Registered trap handler: {} for {:?} with priority {}", description, trap_type, priority);
+        try_println!("Registered trap handler: {} for {:?} with priority {}", description, trap_type, priority);
         true
     }
     
     /// 安全版注册内部方法
-    fn register_internal(&mut self, trap_type: TrapType, registration: HandlerRegistration) -> bool {
+    fn register_internal(&mut self, trap_type: TrapType, registration: HandlerRegistration) -> Result<(), RegisterError> {
         let type_index = trap_type as usize;
         
         // 查找可用插槽和正确的插入位置
@@ -178,16 +235,22 @@ impl HandlerRegistry {
         
         if insert_index == MAX_HANDLERS_PER_TYPE {
             // 没有可用插槽
-            println!("Cannot register handler: registry full for {:?}", trap_type);
-            return false;
+            try_println!("Cannot register handler: registry full for {:?}", trap_type);
+            return Err(RegisterError::StorageFull);
+        }
+
+        let limit = handler_limit();
+        if occupied_count >= limit {
+            try_println!("Cannot register handler: configured limit of {} reached for {:?}", limit, trap_type);
+            return Err(RegisterError::StorageFull);
         }
         
         // 如果需要腾出插入位置，向后移动其他处理器
         if !self.slots[type_index][insert_index].is_empty() {
             // 确保有足够的空间
             if occupied_count >= MAX_HANDLERS_PER_TYPE {
-                println!("Cannot register handler: registry full for {:?}", trap_type);
-                return false;
+                try_println!("Cannot register handler: registry full for {:?}", trap_type);
+                return Err(RegisterError::StorageFull);
             }
             
             // 向后移动插槽
@@ -199,10 +262,10 @@ impl HandlerRegistry {
         // 插入新处理器
         self.slots[type_index][insert_index] = HandlerSlot::Occupied(registration);
         
-        println!("Registered trap handler: {} for {:?} with priority {}, protection: {:?}, registrar: {}",
-                 registration.entry.description, trap_type, registration.entry.priority,
-                 registration.entry.protection_level, registration.entry.registrar_id);
-        true
+        try_println!("Registered trap handler: {} for {:?} with priority {}, protection: {:?}, registrar: {}",
+                     registration.entry.description, trap_type, registration.entry.priority,
+                     registration.entry.protection_level, registration.entry.registrar_id);
+        Ok(())
     }
     
     /// 注销处理器
@@ -223,14 +286,14 @@ impl HandlerRegistry {
                     // 清空最后一个插槽
                     self.slots[type_index][MAX_HANDLERS_PER_TYPE - 1] = HandlerSlot::Empty;
                     
-                    println!("Unregistered trap handler: {} for {:?}", description, trap_type);
+                    try_println!("Unregistered trap handler: {} for {:?}", description, trap_type);
                     return true;
                 }
             }
         }
         
-        println!("Cannot unregister handler: description '{}' not found for trap type {:?}",
-               description, trap_type);
+        try_println!("Cannot unregister handler: description '{}' not found for trap type {:?}",
+                   description, trap_type);
         false
     }
     
@@ -251,15 +314,15 @@ impl HandlerRegistry {
                     
                     // 系统级处理器只能由系统注销
                     if reg.entry.is_system() && registrar_id != SYSTEM_REGISTRAR_ID {
-                        println!("Cannot unregister system handler: {} by non-system registrar: {}",
-                                 description, registrar_id);
+                        try_println!("Cannot unregister system handler: {} by non-system registrar: {}",
+                                     description, registrar_id);
                         return Err(SecurityError::ProtectedHandler);
                     }
                     
                     // 用户级处理器需要匹配注册者ID
                     if !reg.entry.is_system() && reg.entry.registrar_id != registrar_id {
-                        println!("Cannot unregister handler: {} - registrar mismatch: expected {}, got {}",
-                                 description, reg.entry.registrar_id, registrar_id);
+                        try_println!("Cannot unregister handler: {} - registrar mismatch: expected {}, got {}",
+                                     description, reg.entry.registrar_id, registrar_id);
                         return Err(SecurityError::InvalidRegistrar);
                     }
                     
@@ -273,50 +336,71 @@ impl HandlerRegistry {
                     // 清空最后一个插槽
                     self.slots[type_index][MAX_HANDLERS_PER_TYPE - 1] = HandlerSlot::Empty;
                     
-                    println!("Unregistered trap handler: {} for {:?} (owner: {})",
-                             description, trap_type, registrar_id);
+                    try_println!("Unregistered trap handler: {} for {:?} (owner: {})",
+                                 description, trap_type, registrar_id);
                     return Ok(true);
                 }
             }
         }
         
         // 没有找到匹配的处理器
-        println!("Cannot unregister handler: description '{}' not found for trap type {:?}",
-                 description, trap_type);
+        try_println!("Cannot unregister handler: description '{}' not found for trap type {:?}",
+                     description, trap_type);
         Ok(false)
     }
     
-    /// 分发中断到已注册的处理器
-    pub fn dispatch(&self, trap_type: TrapType, ctx: &mut TrapContext) -> TrapHandlerResult {
+    /// 复制某一类型中启用的处理器列表
+    ///
+    /// 分发时先复制再释放锁，处理器内部可以通过`call_next_handler`再次访问注册表。
+    /// 停用的处理器被跳过，其余处理器保持优先级顺序。
+    fn snapshot(&self, trap_type: TrapType) -> [Option<HandlerEntry>; MAX_HANDLERS_PER_TYPE] {
         let type_index = trap_type as usize;
-        
-        // 按优先级依次尝试处理器
-        for i in 0..MAX_HANDLERS_PER_TYPE {
-            if let Some(entry) = self.slots[type_index][i].get_entry() {
-                match (entry.handler)(ctx) {
-                    TrapHandlerResult::Handled => {
-                        // 已处理，直接返回
-                        return TrapHandlerResult::Handled;
-                    }
-                    TrapHandlerResult::Pass => {
-                        // 传递给下一个处理器
-                        continue;
-                    }
-                    TrapHandlerResult::Failed(err) => {
-                        // 处理失败，记录日志
-                        println!("Handler '{}' failed with error: {:?}", entry.description, err);
-                        // 继续尝试下一个处理器
-                        continue;
-                    }
+        let mut entries = [None; MAX_HANDLERS_PER_TYPE];
+        let enabled = self.slots[type_index]
+            .iter()
+            .filter_map(HandlerSlot::get_registration)
+            .filter(|reg| reg.enabled);
+        for (entry, reg) in entries.iter_mut().zip(enabled) {
+            *entry = Some(reg.entry);
+        }
+        entries
+    }
+
+    /// 按描述查找处理器的注册信息
+    pub fn handler_details(&self, trap_type: TrapType, description: &str) -> Option<HandlerDetails> {
+        self.slots[trap_type as usize]
+            .iter()
+            .filter_map(HandlerSlot::get_registration)
+            .find(|reg| reg.entry.description == description)
+            .map(|reg| reg.details())
+    }
+
+    /// 启用或停用处理器，停用期间处理器仍占用插槽
+    ///
+    /// 找不到`description`对应的处理器时返回false
+    pub fn set_handler_enabled(&mut self, trap_type: TrapType, description: &str, enabled: bool) -> bool {
+        let type_index = trap_type as usize;
+        for slot in self.slots[type_index].iter_mut() {
+            if let HandlerSlot::Occupied(reg) = slot {
+                if reg.entry.description == description {
+                    reg.enabled = enabled;
+                    return true;
                 }
-            } else {
-                // 遇到空插槽，表示没有更多处理器
-                break;
             }
         }
-        
-        // 所有处理器都无法处理或没有处理器
-        TrapHandlerResult::Failed(TrapError::NoHandler)
+        false
+    }
+
+    /// 分发中断到已注册的处理器
+    pub fn dispatch(&self, trap_type: TrapType, ctx: &mut TrapContext) -> TrapHandlerResult {
+        di::record_trap(trap_type);
+
+        // 停用的类型不运行任何处理器，交给默认处理逻辑
+        if !is_type_enabled(trap_type) {
+            return TrapHandlerResult::Pass;
+        }
+
+        run_handlers(trap_type, &self.snapshot(trap_type), ctx)
     }
     
     /// 获取特定中断类型的处理器数量
@@ -335,12 +419,20 @@ impl HandlerRegistry {
         count
     }
     
+    /// 获取所有类型已占用的插槽总数
+    pub fn used_slots(&self) -> usize {
+        self.slots.iter()
+            .flatten()
+            .filter(|slot| !slot.is_empty())
+            .count()
+    }
+
     /// 安全版上下文关联处理器注销，验证所有权(无堆实现)
     fn unregister_context_secure(&mut self, context_id: ContextId, registrar_id: RegistrarId) -> usize {
         let mut total_count = 0;
         
         // 遍历所有trap类型
-        for type_index in 0..TrapType::COUNT {
+        for type_index in 0..TrapType::SLOTS {
             // 使用固定大小数组存储待删除的索引
             let mut removed_indices = [0; MAX_HANDLERS_PER_TYPE];
             let mut removed_count = 0;
@@ -388,29 +480,50 @@ impl HandlerRegistry {
                 // 清空最后一个插槽
                 self.slots[type_index][MAX_HANDLERS_PER_TYPE - 1] = HandlerSlot::Empty;
                 
-                println!("Unregistered handler for context {}: {} (type index: {})",
-                         context_id, desc, type_index);
+                try_println!("Unregistered handler for context {}: {} (type index: {})",
+                             context_id, desc, type_index);
                 
                 total_count += 1;
             }
         }
         
-        println!("Unregistered {} handlers for context {}", total_count, context_id);
+        try_println!("Unregistered {} handlers for context {}", total_count, context_id);
         total_count
     }
     
+    /// 按中断类型和优先级顺序遍历所有已注册的处理器
+    ///
+    /// 回调参数依次为中断类型、描述、优先级、保护级别、注册者ID和是否启用
+    pub fn for_each_handler<F>(&self, mut f: F)
+    where
+        F: FnMut(TrapType, &'static str, u8, ProtectionLevel, RegistrarId, bool),
+    {
+        for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
+            for j in 0..MAX_HANDLERS_PER_TYPE {
+                if let Some(reg) = self.slots[trap_type as usize][j].get_registration() {
+                    let entry = reg.entry;
+                    f(trap_type, entry.description, entry.priority,
+                      entry.protection_level, entry.registrar_id, reg.enabled);
+                } else {
+                    // 插槽按顺序填充，遇到空插槽表示没有更多处理器
+                    break;
+                }
+            }
+        }
+    }
+
     /// 打印所有注册的处理器信息（用于调试）
     pub fn print_handlers(&self) {
         println!("=== Registered Trap Handlers ===");
         
-        for i in 0..TrapType::COUNT {
-            let trap_type = TrapType::from_index(i);
+        for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
             let mut handlers_found = false;
             
             for j in 0..MAX_HANDLERS_PER_TYPE {
-                if let Some(entry) = self.slots[i][j].get_entry() {
+                if let Some(reg) = self.slots[trap_type as usize][j].get_registration() {
+                    let entry = reg.entry;
                     if !handlers_found {
-                        println!("{:?} Handlers:", trap_type);
+                        println!("{} Handlers:", trap_type);
                         handlers_found = true;
                     }
                     
@@ -421,14 +534,15 @@ impl HandlerRegistry {
                         "User"
                     };
                     
+                    // 停用的处理器仍然列出
+                    let state_str = if reg.enabled { "" } else { " [disabled]" };
+
                     // 单独打印，避免使用format!和String::new()
-                    println!("  {}. {} (Priority: {}, Protection: {})",
-                             j + 1, entry.description, entry.priority, protection_str);
+                    println!("  {}. {} (Priority: {}, Protection: {}){}",
+                             j + 1, entry.description, entry.priority, protection_str, state_str);
                     
                     // 注册者ID单独打印
-                    if let Some(reg) = self.slots[i][j].get_registration() {
-                        println!("     Registrar: {}", reg.entry.registrar_id);
-                    }
+                    println!("     Registrar: {}", entry.registrar_id);
                 } else if handlers_found {
                     // 遇到空插槽且已找到处理器，表示没有更多处理器
                     break;
@@ -444,19 +558,30 @@ impl HandlerRegistry {
 
 /// 注册中断处理器
 pub fn register_handler(trap_type: TrapType, handler: TrapHandler, priority: u8, description: &'static str) -> bool {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
-    
-    let mut guard = REGISTRY.lock();
-    let result = guard.register(trap_type, handler, priority, description);
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    result
+    let mut guard = REGISTRY.write();
+    guard.register(trap_type, handler, priority, description)
+}
+
+/// 注册表写锁被其他核心占用时的最大尝试次数
+const REGISTRY_LOCK_ATTEMPTS: usize = 64;
+
+/// 获取注册表写锁，被占用时有限次自旋重试
+fn try_write_registry() -> Option<RwLockWriteGuard<'static, HandlerRegistry>> {
+    for _ in 0..REGISTRY_LOCK_ATTEMPTS {
+        if let Some(guard) = REGISTRY.try_write() {
+            return Some(guard);
+        }
+        core::hint::spin_loop();
+    }
+    None
 }
 
 /// 安全版注册处理器函数
+///
+/// 注册表锁一直被占用时返回`StorageLocked`，稍后重试即可；插槽用尽或达到配置的上限时返回`StorageFull`
 pub fn register_handler_with_owner(
     trap_type: TrapType,
     handler: TrapHandler,
@@ -465,14 +590,17 @@ pub fn register_handler_with_owner(
     protection_level: ProtectionLevel,
     registrar_id: RegistrarId,
     context_id: Option<ContextId>
-) -> bool {
+) -> Result<(), RegisterError> {
     println!("Registering handler: {} for {:?} with priority {}, protection: {:?}, registrar: {}",
              description, trap_type, priority, protection_level, registrar_id);
     
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    let mut guard = REGISTRY.lock();
+    let Some(mut guard) = try_write_registry() else {
+        try_println!("Cannot register handler: registry lock busy");
+        return Err(RegisterError::StorageLocked);
+    };
     
     // 创建Handler条目
     let entry = HandlerEntry::new_with_protection(
@@ -487,29 +615,39 @@ pub fn register_handler_with_owner(
     let registration = HandlerRegistration {
         entry,
         context_id,
+        enabled: true,
     };
     
     // 调用内部注册方法
-    let result = guard.register_internal(trap_type, registration);
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
-    
-    result
+    guard.register_internal(trap_type, registration)
+}
+
+/// 启用或停用中断处理器，不释放其插槽
+///
+/// 找不到`description`对应的处理器时返回false
+pub fn set_handler_enabled(trap_type: TrapType, description: &str, enabled: bool) -> bool {
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
+
+    let mut guard = REGISTRY.write();
+    guard.set_handler_enabled(trap_type, description, enabled)
+}
+
+/// 查询处理器的注册信息，找不到时返回None
+pub fn get_handler_info(trap_type: TrapType, description: &str) -> Option<HandlerDetails> {
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
+
+    REGISTRY.read().handler_details(trap_type, description)
 }
 
 /// 注销中断处理器
 pub fn unregister_handler(trap_type: TrapType, description: &'static str) -> bool {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
-    
-    let mut guard = REGISTRY.lock();
-    let result = guard.unregister(trap_type, description);
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
-    
-    result
+    let mut guard = REGISTRY.write();
+    guard.unregister(trap_type, description)
 }
 
 /// 安全版注销处理器函数
@@ -518,40 +656,191 @@ pub fn unregister_handler_secure(
     description: &'static str,
     registrar_id: RegistrarId
 ) -> Result<bool, SecurityError> {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    let mut guard = REGISTRY.lock();
+    let mut guard = REGISTRY.write();
     
     // 查找处理器并验证权限
-    let result = guard.unregister_secure(trap_type, description, registrar_id);
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
-    
-    result
+    guard.unregister_secure(trap_type, description, registrar_id)
+}
+
+/// 按优先级依次尝试处理器，直到有处理器处理该trap
+fn run_handlers(
+    trap_type: TrapType,
+    entries: &[Option<HandlerEntry>],
+    ctx: &mut TrapContext,
+) -> TrapHandlerResult {
+    for entry in entries {
+        // 遇到空插槽，表示没有更多处理器
+        let Some(entry) = entry else { break };
+
+        HANDLER_RUNS[trap_type as usize].fetch_add(1, Ordering::Relaxed);
+        match (entry.handler)(ctx) {
+            TrapHandlerResult::Handled => {
+                // 已处理，直接返回
+                return TrapHandlerResult::Handled;
+            }
+            TrapHandlerResult::HandledAndMaskInterrupts => {
+                // 已处理，返回时保持中断关闭
+                ctx.mask_interrupts_on_return();
+                return TrapHandlerResult::HandledAndMaskInterrupts;
+            }
+            TrapHandlerResult::Pass => {
+                // 传递给下一个处理器
+                continue;
+            }
+            TrapHandlerResult::Failed(err) => {
+                // 处理失败，记录日志
+                try_println!("Handler '{}' failed with error: {:?}", entry.description, err);
+                // 继续尝试下一个处理器
+                continue;
+            }
+        }
+    }
+
+    // 所有处理器都无法处理或没有处理器
+    TrapHandlerResult::Failed(TrapError::NoHandler)
 }
 
 /// 分发中断到已注册的处理器
 pub fn dispatch_trap(trap_type: TrapType, ctx: &mut TrapContext) -> TrapHandlerResult {
     // 注意：这个函数可能在已禁用中断的情况下调用
     // 在中断上下文中使用锁时需特别小心
-    let guard = REGISTRY.lock();
-    guard.dispatch(trap_type, ctx)
+    di::record_trap(trap_type);
+
+    if !is_type_enabled(trap_type) {
+        return TrapHandlerResult::Pass;
+    }
+
+    // 只在复制处理器列表时持有锁，处理器运行期间注册表保持可用
+    let entries = REGISTRY.read().snapshot(trap_type);
+    run_handlers(trap_type, &entries, ctx)
+}
+
+/// 从处理器内部调用优先级顺序中的下一个处理器
+///
+/// 包装型处理器以自己的描述作为`after_description`，
+/// 从排在它之后的处理器开始继续分发，并可以在返回前对结果做后处理。
+/// 找不到`after_description`对应的处理器，或其后没有处理器时返回`Failed(NoHandler)`。
+pub fn call_next_handler(
+    trap_type: TrapType,
+    after_description: &str,
+    ctx: &mut TrapContext,
+) -> TrapHandlerResult {
+    let entries = REGISTRY.read().snapshot(trap_type);
+
+    let position = entries.iter().position(|entry| {
+        entry.is_some_and(|entry| entry.description == after_description)
+    });
+
+    match position {
+        Some(index) => run_handlers(trap_type, &entries[index + 1..], ctx),
+        None => TrapHandlerResult::Failed(TrapError::NoHandler),
+    }
+}
+
+/// 启用或停用某一中断类型的分发
+///
+/// 停用期间该类型的trap直接返回`Pass`，不运行任何处理器，也不修改sie
+pub fn set_type_enabled(trap_type: TrapType, enabled: bool) {
+    let bit = 1 << trap_type as u32;
+    if enabled {
+        DISABLED_TYPES.fetch_and(!bit, Ordering::SeqCst);
+    } else {
+        DISABLED_TYPES.fetch_or(bit, Ordering::SeqCst);
+    }
+}
+
+/// 检查某一中断类型的分发是否启用
+pub fn is_type_enabled(trap_type: TrapType) -> bool {
+    DISABLED_TYPES.load(Ordering::SeqCst) & (1 << trap_type as u32) == 0
+}
+
+/// 获取某一中断类型发生的次数
+///
+/// 与`di::trap_counts`是同一组计数，包括经DI分发的trap。只读取原子计数，不获取注册表锁
+pub fn trap_stats(trap_type: TrapType) -> u64 {
+    di::trap_count(trap_type) as u64
+}
+
+/// 获取某一中断类型运行处理器的次数
+///
+/// 一次分发中依次尝试的每个处理器（包括`call_next_handler`调用的）各计一次
+pub fn handler_runs(trap_type: TrapType) -> u64 {
+    HANDLER_RUNS[trap_type as usize].load(Ordering::Relaxed)
+}
+
+/// 清零所有分发统计，用于测试隔离
+pub fn reset_trap_stats() {
+    di::reset_trap_counts();
+    for handler_count in HANDLER_RUNS.iter() {
+        handler_count.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 打印分发统计，只列出发生过的中断类型
+pub fn print_trap_stats() {
+    println!("Trap dispatch statistics:");
+    for trap_type in TrapType::iter().chain([TrapType::Unknown]) {
+        let count = trap_stats(trap_type);
+        if count != 0 {
+            println!("  {}: {} traps, {} handler runs", trap_type, count, handler_runs(trap_type));
+        }
+    }
 }
 
 /// 获取特定中断类型的处理器数量
 pub fn handler_count(trap_type: TrapType) -> usize {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    let guard = REGISTRY.lock();
-    let count = guard.handler_count(trap_type);
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
-    
-    count
+    let guard = REGISTRY.read();
+    guard.handler_count(trap_type)
+}
+
+/// 按trap系统配置设置每种中断类型的处理器上限
+///
+/// 超过注册表数组大小的配置值按数组大小处理。只影响之后的注册，
+/// 已经注册的处理器不会被移除。
+pub fn configure(config: &dyn TrapSystemConfig) {
+    HANDLER_LIMIT.store(config.max_handlers_per_type().min(MAX_HANDLERS_PER_TYPE), Ordering::SeqCst);
+}
+
+/// 当前生效的每种中断类型处理器上限
+pub fn handler_limit() -> usize {
+    HANDLER_LIMIT.load(Ordering::SeqCst)
+}
+
+/// 注册表的容量信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityInfo {
+    /// 配置的每种中断类型处理器上限
+    pub configured_per_type: usize,
+    /// 注册表数组中每种中断类型的插槽数
+    pub slots_per_type: usize,
+    /// 所有类型已注册的处理器总数
+    pub used: usize,
+}
+
+/// 获取配置的上限和已注册的处理器数量
+pub fn capacity_info() -> CapacityInfo {
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
+
+    CapacityInfo {
+        configured_per_type: handler_limit(),
+        slots_per_type: MAX_HANDLERS_PER_TYPE,
+        used: REGISTRY.read().used_slots(),
+    }
+}
+
+/// 获取注册表容量，返回`(total, used)`
+///
+/// `total`按配置的每类型上限计算
+pub fn capacity() -> (usize, usize) {
+    let info = capacity_info();
+    (info.configured_per_type * TrapType::SLOTS, info.used)
 }
 
 /// 安全版上下文关联处理器注销函数
@@ -559,26 +848,33 @@ pub fn unregister_handlers_for_context_secure(
     context_id: ContextId,
     registrar_id: RegistrarId
 ) -> usize {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
-    
-    let mut guard = REGISTRY.lock();
-    let count = guard.unregister_context_secure(context_id, registrar_id);
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    count
+    let mut guard = REGISTRY.write();
+    guard.unregister_context_secure(context_id, registrar_id)
+}
+
+/// 遍历所有注册的处理器，不做任何打印
+///
+/// 回调在持有注册表锁且关闭中断的情况下执行，不能在回调中注册或注销处理器
+pub fn for_each_handler<F>(f: F)
+where
+    F: FnMut(TrapType, &'static str, u8, ProtectionLevel, RegistrarId, bool),
+{
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
+
+    let guard = REGISTRY.read();
+    guard.for_each_handler(f);
+    drop(guard);
 }
 
 /// 打印所有注册的处理器信息（用于调试）
 pub fn print_handlers() {
-    // 禁用中断以确保安全访问注册表
-    let was_enabled = crate::trap::infrastructure::disable_interrupts();
+    // 禁用中断以确保安全访问注册表，离开作用域时恢复
+    let _irq = IrqGuard::new();
     
-    let guard = REGISTRY.lock();
+    let guard = REGISTRY.read();
     guard.print_handlers();
-    
-    // 恢复中断状态
-    crate::trap::infrastructure::restore_interrupts(was_enabled);
 }
\ No newline at end of file
@@ -6,11 +6,26 @@
 .globl __trap_return
 .align 4  # 确保4字节对齐
 
-# RISC-V寄存器上下文大小 (32 gp + 4 CSR) * 8 = 288字节
-.equ CONTEXT_SIZE, 288
+# RISC-V寄存器上下文大小 (32 gp + 4 CSR + 32 fp + fcsr) * 8 = 552字节，按16字节对齐为560
+# 与trap::ds::context中的TRAP_FRAME_SIZE、TRAP_FP_OFFSET、TRAP_FCSR_OFFSET保持一致
+.equ CONTEXT_SIZE, 560
+.equ FP_OFFSET, 288
+.equ FCSR_OFFSET, 544
 
-# 中断入口点
+# sstatus.FS字段（第13-14位）为3表示浮点寄存器已被修改
+.equ SSTATUS_FS_SHIFT, 13
+.equ FS_DIRTY, 3
+
+# 直接模式下记录的向量号，与vector.rs中的NO_VECTOR一致
+.equ NO_VECTOR, 31
+
+# 中断入口点（直接模式）
+# 进入时sstatus.SIE已被硬件清除，sscratch在读出之前不会被嵌套trap改写
 __trap_entry:
+    csrwi sscratch, NO_VECTOR
+
+# 直接模式与向量模式共用的保存和分发代码，sscratch中为向量号
+__trap_common:
     # 分配栈空间保存上下文
     addi sp, sp, -CONTEXT_SIZE
     
@@ -60,11 +75,54 @@ __trap_entry:
     csrr t0, stval
     sd t0, 280(sp)  # 保存stval（中断附加信息）
     
-    # 为Rust处理函数准备参数 - 传递上下文指针
+    # 只有被中断的代码修改过浮点寄存器（FS为Dirty）时才保存，纯整数代码不付出这部分开销
+    ld t0, 256(sp)
+    srli t0, t0, SSTATUS_FS_SHIFT
+    andi t0, t0, 3
+    li t1, FS_DIRTY
+    bne t0, t1, 1f
+    fsd f0, FP_OFFSET+0(sp)
+    fsd f1, FP_OFFSET+8(sp)
+    fsd f2, FP_OFFSET+16(sp)
+    fsd f3, FP_OFFSET+24(sp)
+    fsd f4, FP_OFFSET+32(sp)
+    fsd f5, FP_OFFSET+40(sp)
+    fsd f6, FP_OFFSET+48(sp)
+    fsd f7, FP_OFFSET+56(sp)
+    fsd f8, FP_OFFSET+64(sp)
+    fsd f9, FP_OFFSET+72(sp)
+    fsd f10, FP_OFFSET+80(sp)
+    fsd f11, FP_OFFSET+88(sp)
+    fsd f12, FP_OFFSET+96(sp)
+    fsd f13, FP_OFFSET+104(sp)
+    fsd f14, FP_OFFSET+112(sp)
+    fsd f15, FP_OFFSET+120(sp)
+    fsd f16, FP_OFFSET+128(sp)
+    fsd f17, FP_OFFSET+136(sp)
+    fsd f18, FP_OFFSET+144(sp)
+    fsd f19, FP_OFFSET+152(sp)
+    fsd f20, FP_OFFSET+160(sp)
+    fsd f21, FP_OFFSET+168(sp)
+    fsd f22, FP_OFFSET+176(sp)
+    fsd f23, FP_OFFSET+184(sp)
+    fsd f24, FP_OFFSET+192(sp)
+    fsd f25, FP_OFFSET+200(sp)
+    fsd f26, FP_OFFSET+208(sp)
+    fsd f27, FP_OFFSET+216(sp)
+    fsd f28, FP_OFFSET+224(sp)
+    fsd f29, FP_OFFSET+232(sp)
+    fsd f30, FP_OFFSET+240(sp)
+    fsd f31, FP_OFFSET+248(sp)
+    frcsr t0
+    sd t0, FCSR_OFFSET(sp)
+1:
+    
+    # 为Rust处理函数准备参数 - 传递上下文指针和向量号
     mv a0, sp
+    csrr a1, sscratch
     
     # 调用Rust中断处理函数
-    call handle_trap
+    call handle_trap_entry
     
     # 跳转到中断返回代码
     j __trap_return
@@ -75,6 +133,47 @@ __trap_return:
     ld t0, 256(sp)
     csrw sstatus, t0  # 恢复sstatus
     
+    # 保存时FS为Dirty才需要恢复浮点寄存器，此时sstatus已恢复，浮点单元处于开启状态
+    srli t0, t0, SSTATUS_FS_SHIFT
+    andi t0, t0, 3
+    li t1, FS_DIRTY
+    bne t0, t1, 2f
+    ld t0, FCSR_OFFSET(sp)
+    fscsr t0
+    fld f0, FP_OFFSET+0(sp)
+    fld f1, FP_OFFSET+8(sp)
+    fld f2, FP_OFFSET+16(sp)
+    fld f3, FP_OFFSET+24(sp)
+    fld f4, FP_OFFSET+32(sp)
+    fld f5, FP_OFFSET+40(sp)
+    fld f6, FP_OFFSET+48(sp)
+    fld f7, FP_OFFSET+56(sp)
+    fld f8, FP_OFFSET+64(sp)
+    fld f9, FP_OFFSET+72(sp)
+    fld f10, FP_OFFSET+80(sp)
+    fld f11, FP_OFFSET+88(sp)
+    fld f12, FP_OFFSET+96(sp)
+    fld f13, FP_OFFSET+104(sp)
+    fld f14, FP_OFFSET+112(sp)
+    fld f15, FP_OFFSET+120(sp)
+    fld f16, FP_OFFSET+128(sp)
+    fld f17, FP_OFFSET+136(sp)
+    fld f18, FP_OFFSET+144(sp)
+    fld f19, FP_OFFSET+152(sp)
+    fld f20, FP_OFFSET+160(sp)
+    fld f21, FP_OFFSET+168(sp)
+    fld f22, FP_OFFSET+176(sp)
+    fld f23, FP_OFFSET+184(sp)
+    fld f24, FP_OFFSET+192(sp)
+    fld f25, FP_OFFSET+200(sp)
+    fld f26, FP_OFFSET+208(sp)
+    fld f27, FP_OFFSET+216(sp)
+    fld f28, FP_OFFSET+224(sp)
+    fld f29, FP_OFFSET+232(sp)
+    fld f30, FP_OFFSET+240(sp)
+    fld f31, FP_OFFSET+248(sp)
+2:
+    
     ld t0, 264(sp)
     csrw sepc, t0     # 恢复sepc
     
@@ -118,4 +217,66 @@ __trap_return:
     addi sp, sp, CONTEXT_SIZE  # 调整栈指针
     
     # 返回到中断点
-    sret
\ No newline at end of file
+    sret
+
+# 向量模式下的中断向量表
+# 异常跳转到表基址，中断跳转到 基址 + 4 * 中断号。
+# 每个表项跳转到各自的入口桩，桩把向量号写入sscratch后进入统一的保存代码，
+# 再由handle_trap根据scause分发。
+# 表项必须是4字节指令，因此这里禁用压缩指令。
+.globl __trap_vector_table
+.align 8
+__trap_vector_table:
+    .option push
+    .option norvc
+    .irp index, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+    j __trap_vector_\index
+    .endr
+    .option pop
+
+# 向量入口桩，csrwi不占用通用寄存器
+.irp index, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+__trap_vector_\index:
+    csrwi sscratch, \index
+    j __trap_common
+.endr
+
+# 任务切换
+# a0: 当前任务的TaskContext，a1: 下一个任务的TaskContext
+# 保存ra、sp和s0-s11后载入下一个任务的值，ret跳转到下一个任务的ra。
+# 写成独立的汇编函数，保存的sp和ra就是调用者的值，不受Rust函数序言的影响。
+# 浮点寄存器（包括fs0-fs11和fcsr）不在这里保存，调用者切换前由lazy_fp设置FS，
+# 寄存器在下一个任务第一次使用浮点时才转移。
+.globl __task_switch
+.align 2
+__task_switch:
+    sd ra, 0(a0)
+    sd sp, 8(a0)
+    sd s0, 16(a0)
+    sd s1, 24(a0)
+    sd s2, 32(a0)
+    sd s3, 40(a0)
+    sd s4, 48(a0)
+    sd s5, 56(a0)
+    sd s6, 64(a0)
+    sd s7, 72(a0)
+    sd s8, 80(a0)
+    sd s9, 88(a0)
+    sd s10, 96(a0)
+    sd s11, 104(a0)
+
+    ld ra, 0(a1)
+    ld sp, 8(a1)
+    ld s0, 16(a1)
+    ld s1, 24(a1)
+    ld s2, 32(a1)
+    ld s3, 40(a1)
+    ld s4, 48(a1)
+    ld s5, 56(a1)
+    ld s6, 64(a1)
+    ld s7, 72(a1)
+    ld s8, 80(a1)
+    ld s9, 88(a1)
+    ld s10, 96(a1)
+    ld s11, 104(a1)
+    ret
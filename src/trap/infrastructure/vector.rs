@@ -4,8 +4,11 @@
 
 use crate::println;
 use core::arch::global_asm;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use riscv::register::{stvec, scause, sie, sip, sstatus};
 use crate::trap::ds::{TrapMode, Interrupt, TrapContext};
+use crate::util::sbi::timer;
+use crate::util::csr;
 
 // 导入汇编中断入口代码
 global_asm!(include_str!("trap_entry.asm"));
@@ -16,6 +19,58 @@ extern "C" {
     fn __trap_entry();
     /// 从中断返回函数
     fn __trap_return();
+    /// 向量模式使用的中断向量表
+    fn __trap_vector_table();
+}
+
+/// 当前使用的中断模式
+static TRAP_MODE: AtomicU8 = AtomicU8::new(TrapMode::Direct as u8);
+
+/// 向量表的表项数量
+pub const VECTOR_TABLE_ENTRIES: usize = 16;
+
+/// 直接模式进入时记录的向量号，与trap_entry.asm中的NO_VECTOR一致
+const NO_VECTOR: usize = 31;
+
+/// 各向量入口被进入的次数
+static VECTOR_ENTRY_COUNTS: [AtomicUsize; VECTOR_TABLE_ENTRIES] =
+    [const { AtomicUsize::new(0) }; VECTOR_TABLE_ENTRIES];
+
+/// 汇编入口调用的Rust函数
+///
+/// `vector`为向量模式下进入的表项编号，直接模式下为`NO_VECTOR`。
+/// 记录向量入口后交给统一的`handle_trap`分发，返回前可能切换到其他任务。
+#[no_mangle]
+extern "C" fn handle_trap_entry(context: *mut TrapContext, vector: usize) {
+    if vector != NO_VECTOR {
+        if let Some(count) = VECTOR_ENTRY_COUNTS.get(vector) {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    super::handle_trap(context);
+
+    // 返回被打断的代码之前处理时钟中断留下的重新调度请求
+    super::context::resched_on_trap_return(unsafe { &mut *context });
+}
+
+/// 向量模式下指定表项被进入的次数
+///
+/// 异常都从第0项进入，中断从与中断号相同的表项进入。编号越界时返回0。
+pub fn vector_entry_count(index: usize) -> usize {
+    VECTOR_ENTRY_COUNTS
+        .get(index)
+        .map_or(0, |count| count.load(Ordering::Relaxed))
+}
+
+/// 计算指定模式下写入stvec的值
+///
+/// 直接模式指向统一入口，向量模式指向向量表；地址需要4字节对齐，模式在低2位
+fn stvec_value(mode: TrapMode) -> usize {
+    let base = match mode {
+        TrapMode::Direct => __trap_entry as usize,
+        TrapMode::Vectored => __trap_vector_table as usize,
+    };
+    (base & !0x3) | mode as usize
 }
 
 /// 初始化中断向量表
@@ -24,24 +79,42 @@ extern "C" {
 ///
 /// * `mode` - 中断模式（直接或向量）
 pub fn init(mode: TrapMode) {
-    // 直接用原始方式写寄存器
-    unsafe {
-        // 准备值：地址需要4字节对齐，模式在低2位
-        let addr = (__trap_entry as usize) & !0x3;
-        let mode_val = mode as usize;
-        let value = addr | mode_val;
-        
-        // 使用内联汇编直接写stvec
-        core::arch::asm!(
-            "csrw stvec, {0}",
-            in(reg) value,
-            options(nostack)
-        );
-    }
+    // stvec写入后无需屏障，见util::csr::write_stvec
+    csr::write_stvec(stvec_value(mode));
+    TRAP_MODE.store(mode as u8, Ordering::SeqCst);
     
     println!("Trap vector initialized with {:?} mode", mode);
 }
 
+/// 运行时切换中断模式
+///
+/// 在关中断的情况下重写stvec并回读确认。stvec的模式位是WARL字段，
+/// 硬件不支持的模式写入后会读回其他值，此时恢复原来的设置并返回`Err`。
+pub fn set_trap_mode(mode: TrapMode) -> Result<(), TrapMode> {
+    let was_enabled = disable_interrupts();
+    let previous = csr::read_stvec();
+
+    csr::write_stvec(stvec_value(mode));
+    let result = if TrapMode::from_stvec(csr::read_stvec()) == Some(mode) {
+        TRAP_MODE.store(mode as u8, Ordering::SeqCst);
+        Ok(())
+    } else {
+        csr::write_stvec(previous);
+        Err(mode)
+    };
+
+    restore_interrupts(was_enabled);
+    result
+}
+
+/// 获取当前使用的中断模式
+pub fn trap_mode() -> TrapMode {
+    match TRAP_MODE.load(Ordering::SeqCst) {
+        1 => TrapMode::Vectored,
+        _ => TrapMode::Direct,
+    }
+}
+
 /// 获取当前中断原因
 pub fn get_trap_cause() -> scause::Scause {
     scause::read()
@@ -94,6 +167,93 @@ pub fn disable_interrupt(interrupt: Interrupt) {
     }
 }
 
+/// `mask_all_except`之前保存的各类中断使能状态
+#[must_use = "屏蔽状态需要通过restore_mask恢复"]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptMask {
+    /// 与`Interrupt::ALL`一一对应
+    enabled: [bool; Interrupt::ALL.len()],
+}
+
+/// 屏蔽白名单之外的所有中断
+///
+/// 白名单中的中断保持原来的使能状态，不会被额外开启。
+/// 返回修改前的状态，由`restore_mask`恢复。
+pub fn mask_all_except(allowed: &[Interrupt]) -> InterruptMask {
+    let was_enabled = disable_interrupts();
+
+    let saved = InterruptMask {
+        enabled: Interrupt::ALL.map(is_interrupt_enabled),
+    };
+    for interrupt in Interrupt::ALL {
+        if !allowed.contains(&interrupt) {
+            disable_interrupt(interrupt);
+        }
+    }
+
+    restore_interrupts(was_enabled);
+    saved
+}
+
+/// 恢复`mask_all_except`保存的中断使能状态
+pub fn restore_mask(mask: InterruptMask) {
+    let was_enabled = disable_interrupts();
+
+    for (interrupt, enabled) in Interrupt::ALL.into_iter().zip(mask.enabled) {
+        if enabled {
+            enable_interrupt(interrupt);
+        } else {
+            disable_interrupt(interrupt);
+        }
+    }
+
+    restore_interrupts(was_enabled);
+}
+
+/// 中断处理器中开启嵌套中断的守卫
+///
+/// 守卫存在期间中断开启，正在处理的中断源保持屏蔽；释放时重新关闭中断并恢复该中断源。
+#[must_use = "守卫被丢弃时会立即重新关闭中断"]
+pub struct NestedInterruptGuard {
+    /// 正在处理的中断源
+    source: Option<Interrupt>,
+    /// 开启嵌套前该中断源是否使能
+    source_was_enabled: bool,
+}
+
+impl Drop for NestedInterruptGuard {
+    fn drop(&mut self) {
+        // 先关中断，再恢复中断源，之后不会再被嵌套
+        disable_interrupts();
+        if let (Some(source), true) = (self.source, self.source_was_enabled) {
+            enable_interrupt(source);
+        }
+    }
+}
+
+/// 在中断处理器中重新开启中断，让耗时的处理可以被其他中断打断
+///
+/// 只对中断生效：`ctx`是异常时返回None，异常处理（包括增强型致命异常处理器）始终关中断运行。
+/// 不在trap处理中，或者再嵌套一层就会超过最大嵌套层级时也返回None。
+/// 正在处理的中断源在守卫存在期间被屏蔽，不会重入自身；
+/// 电平触发的中断源（例如外部中断）需要处理器在开启前完成claim。
+pub fn allow_nested_interrupts(ctx: &TrapContext) -> Option<NestedInterruptGuard> {
+    let cause = ctx.get_cause();
+    let depth = super::double_fault::depth();
+    if !cause.is_interrupt() || depth == 0 || depth >= super::max_nest_level() {
+        return None;
+    }
+
+    let source = Interrupt::from_code(cause.code());
+    let source_was_enabled = source.is_some_and(is_interrupt_enabled);
+    if let Some(source) = source {
+        disable_interrupt(source);
+    }
+    enable_interrupts();
+
+    Some(NestedInterruptGuard { source, source_was_enabled })
+}
+
 /// 检查特定类型的中断是否使能
 pub fn is_interrupt_enabled(interrupt: Interrupt) -> bool {
     match interrupt {
@@ -124,4 +284,72 @@ pub fn clear_soft_interrupt() {
     unsafe {
         sip::clear_ssoft();
     }
-}
\ No newline at end of file
+}
+
+/// sip中各类中断的等待位，位号与scause中的中断编号一致
+const SIP_SSIP: usize = 1 << Interrupt::SupervisorSoft.code();
+const SIP_STIP: usize = 1 << Interrupt::SupervisorTimer.code();
+const SIP_SEIP: usize = 1 << Interrupt::SupervisorExternal.code();
+
+/// 当前等待处理的中断
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PendingFlags {
+    /// 软件中断等待位(SSIP)
+    pub software: bool,
+    /// 时钟中断等待位(STIP)
+    pub timer: bool,
+    /// 外部中断等待位(SEIP)
+    pub external: bool,
+}
+
+impl PendingFlags {
+    /// 从sip寄存器的原始值解码
+    pub const fn from_bits(bits: usize) -> Self {
+        Self {
+            software: bits & SIP_SSIP != 0,
+            timer: bits & SIP_STIP != 0,
+            external: bits & SIP_SEIP != 0,
+        }
+    }
+
+    /// 是否有任何中断等待处理
+    pub const fn any(&self) -> bool {
+        self.software || self.timer || self.external
+    }
+}
+
+/// 读取当前等待处理的中断
+pub fn pending_interrupts() -> PendingFlags {
+    PendingFlags::from_bits(sip::read().bits())
+}
+
+/// 打印并清除当前等待处理的中断
+///
+/// 应在开启中断之前调用，避免残留的等待位立即触发意外的trap。
+/// 软件中断位可以直接清除；时钟中断位在S模式下只读，通过把定时器
+/// 推迟到最远来清除；外部中断位需要由中断控制器claim，这里只做报告。
+/// 返回清除前的等待状态。
+pub fn clear_pending_interrupts() -> PendingFlags {
+    let pending = pending_interrupts();
+    if !pending.any() {
+        return pending;
+    }
+
+    println!("Pending interrupts before enabling: {:?}", pending);
+
+    if pending.software {
+        clear_soft_interrupt();
+        println!("  Cleared pending software interrupt");
+    }
+
+    if pending.timer {
+        timer::set_timer(u64::MAX);
+        println!("  Cleared pending timer interrupt");
+    }
+
+    if pending.external {
+        println!("  External interrupt pending, cannot be cleared without interrupt controller");
+    }
+
+    pending
+}
@@ -6,6 +6,7 @@ use crate::println;
 pub(crate) mod infrastructure;
 pub mod ds;  // Data structures module
 pub mod api; // Public API module
+pub mod syscall; // System call dispatch
 
 // Export only the API module's public interface
 pub use api::*;
@@ -61,9 +62,15 @@ pub use infrastructure::{
 */
 
 /// Initialize the trap system
+///
+/// Only the first call does any work; later calls log a warning and return,
+/// so default and enhanced handlers are never registered twice.
 pub fn init() {
     // Initialize the trap system using the DI system
-    infrastructure::di::initialize_trap_system(ds::TrapMode::Direct);
+    if !infrastructure::di::initialize_trap_system(ds::TrapMode::Direct) {
+        println!("Warning: trap::init() called again, skipping re-initialization");
+        return;
+    }
     
     // Initialize global context manager (for backward compatibility)
     ds::init_global_context_manager();
@@ -73,6 +80,14 @@ pub fn init() {
 
     // 注册增强型异常处理器
     infrastructure::enhanced_handlers::register_enhanced_handlers();
+
+    // 任务第一次使用浮点时才切换浮点寄存器
+    infrastructure::lazy_fp::register_handler();
+
+    // 开启中断之前清除残留的等待位
+    infrastructure::clear_pending_interrupts();
+
+    ds::init_phase::advance_phase(ds::InitPhase::Complete);
     
     println!("Trap system fully initialized");
 }
@@ -0,0 +1,68 @@
+//! 系统调用分发
+//!
+//! 按照RISC-V的调用约定，调用号在a7中，参数在a0–a5中，返回值写回a0。
+//! 调用号作为下标在固定大小的表中查找处理函数，未注册的调用号返回`-ENOSYS`。
+
+use crate::trap::ds::TrapContext;
+use crate::try_println;
+use spin::Mutex;
+
+/// 系统调用表的大小，调用号必须小于该值
+pub const MAX_SYSCALLS: usize = 64;
+
+/// 系统调用不存在的错误码
+pub const ENOSYS: isize = 38;
+
+/// 系统调用参数个数（a0–a5）
+pub const SYSCALL_ARGS: usize = 6;
+
+/// 系统调用处理函数，参数为a0–a5，返回值写回a0
+pub type SyscallFn = fn(&[usize; SYSCALL_ARGS]) -> isize;
+
+/// 调用号到处理函数的映射
+static SYSCALL_TABLE: Mutex<[Option<SyscallFn>; MAX_SYSCALLS]> = Mutex::new([None; MAX_SYSCALLS]);
+
+/// 注册系统调用，调用号越界或已被占用时返回false
+pub fn register_syscall(num: usize, handler: SyscallFn) -> bool {
+    if num >= MAX_SYSCALLS {
+        return false;
+    }
+
+    let mut table = SYSCALL_TABLE.lock();
+    if table[num].is_some() {
+        return false;
+    }
+    table[num] = Some(handler);
+    true
+}
+
+/// 注销系统调用
+pub fn unregister_syscall(num: usize) -> bool {
+    num < MAX_SYSCALLS && SYSCALL_TABLE.lock()[num].take().is_some()
+}
+
+/// 未注册的调用号使用的处理函数
+fn unknown_syscall(_args: &[usize; SYSCALL_ARGS]) -> isize {
+    -ENOSYS
+}
+
+/// 分发一次系统调用
+///
+/// 从a7读取调用号、a0–a5读取参数，把返回值写入a0，并让sepc跳过ecall指令。
+/// 返回处理函数的返回值。
+pub fn dispatch(ctx: &mut TrapContext) -> isize {
+    let num = ctx.arg(7);
+    let args: [usize; SYSCALL_ARGS] = core::array::from_fn(|n| ctx.arg(n));
+
+    // 先复制处理函数再释放锁，系统调用内部可以注册或注销系统调用
+    let handler = SYSCALL_TABLE.lock().get(num).copied().flatten();
+    let handler = handler.unwrap_or_else(|| {
+        try_println!("Unknown syscall {} at {:#x}", num, ctx.pc());
+        unknown_syscall
+    });
+
+    let ret = handler(&args);
+    ctx.set_return_value(ret as usize);
+    ctx.set_return_addr(ctx.pc() + 4);
+    ret
+}
@@ -0,0 +1,217 @@
+//! CPU特性检测
+//!
+//! 记录当前核心支持的ISA扩展（F、D、C、A等）。
+//!
+//! `misa`只能在M模式下访问，内核运行在S模式，读取会触发非法指令异常。
+//! 因此扩展集合来自设备树的`riscv,isa`字符串（启动代码调用`init_from_fdt`读取），
+//! 在此之前使用内核编译目标启用的扩展——内核本身就是用这些扩展编译的，
+//! 能运行到这里的核心必然支持它们。
+
+use core::fmt;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+/// 单字母扩展在位图中的位置，与`misa`的编码相同（A为第0位）
+const fn ext_bit(letter: u8) -> u32 {
+    1 << (letter - b'a')
+}
+
+/// `g`展开后的扩展集合
+const G_EXTENSIONS: u32 = ext_bit(b'i') | ext_bit(b'm') | ext_bit(b'a') | ext_bit(b'f') | ext_bit(b'd');
+
+/// ISA字符串中单字母扩展的规范顺序
+const CANONICAL_ORDER: &[u8] = b"iemafdqcv";
+
+/// 核心支持的ISA扩展
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaExtensions {
+    /// 单字母扩展位图，编码与`misa`的低26位相同
+    bits: u32,
+    /// 寄存器宽度（32或64）
+    xlen: u8,
+}
+
+impl IsaExtensions {
+    /// 由`misa`的值构造
+    ///
+    /// 供M模式固件传递或测试使用，最高两位的MXL决定寄存器宽度
+    pub const fn from_misa(misa: usize) -> Self {
+        let mxl = misa >> (usize::BITS - 2);
+        let xlen = match mxl {
+            1 => 32,
+            3 => 128,
+            _ => 64,
+        };
+        Self {
+            bits: (misa & 0x03ff_ffff) as u32,
+            xlen,
+        }
+    }
+
+    /// 解析设备树中的`riscv,isa`字符串，例如`"rv64imafdc"`
+    ///
+    /// 只识别单字母扩展，`_`之后的多字母扩展（如`_zicsr`）被忽略。
+    /// 字符串不以`rv32`/`rv64`开头时返回None。
+    pub fn parse(isa: &str) -> Option<Self> {
+        let isa = isa.trim();
+        let lower = |b: &u8| b.to_ascii_lowercase();
+        let bytes = isa.as_bytes();
+        if bytes.len() < 4 || lower(&bytes[0]) != b'r' || lower(&bytes[1]) != b'v' {
+            return None;
+        }
+
+        let xlen = match &bytes[2..4] {
+            b"32" => 32,
+            b"64" => 64,
+            _ => return None,
+        };
+
+        let mut bits = 0;
+        for letter in bytes[4..].iter().map(lower) {
+            match letter {
+                b'_' => break,
+                b'g' => bits |= G_EXTENSIONS,
+                b'a'..=b'z' => bits |= ext_bit(letter),
+                // 版本号等其他字符不影响扩展集合
+                _ => {}
+            }
+        }
+
+        Some(Self { bits, xlen })
+    }
+
+    /// 内核编译目标启用的扩展
+    pub const fn compiled() -> Self {
+        let mut bits = ext_bit(b'i');
+        if cfg!(target_feature = "m") {
+            bits |= ext_bit(b'm');
+        }
+        if cfg!(target_feature = "a") {
+            bits |= ext_bit(b'a');
+        }
+        if cfg!(target_feature = "f") {
+            bits |= ext_bit(b'f');
+        }
+        if cfg!(target_feature = "d") {
+            bits |= ext_bit(b'd');
+        }
+        if cfg!(target_feature = "c") {
+            bits |= ext_bit(b'c');
+        }
+        Self {
+            bits,
+            xlen: usize::BITS as u8,
+        }
+    }
+
+    /// 是否支持某个单字母扩展
+    pub const fn has(&self, letter: char) -> bool {
+        let letter = (letter as u8).to_ascii_lowercase();
+        letter.is_ascii_lowercase() && self.bits & ext_bit(letter) != 0
+    }
+
+    /// 单精度浮点扩展
+    pub const fn has_f(&self) -> bool {
+        self.has('f')
+    }
+
+    /// 双精度浮点扩展
+    pub const fn has_d(&self) -> bool {
+        self.has('d')
+    }
+
+    /// 压缩指令扩展
+    pub const fn has_c(&self) -> bool {
+        self.has('c')
+    }
+
+    /// 原子指令扩展
+    pub const fn has_a(&self) -> bool {
+        self.has('a')
+    }
+
+    /// 寄存器宽度
+    pub const fn xlen(&self) -> u8 {
+        self.xlen
+    }
+
+    /// 单字母扩展位图
+    pub const fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// 打印扩展信息
+    pub fn print(&self) {
+        crate::println!("ISA: {}", self);
+    }
+}
+
+impl fmt::Display for IsaExtensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rv{}", self.xlen)?;
+        // 先按ISA字符串的规范顺序输出常见扩展，其余扩展按字母顺序
+        let mut remaining = self.bits;
+        for &letter in CANONICAL_ORDER {
+            if remaining & ext_bit(letter) != 0 {
+                write!(f, "{}", letter as char)?;
+                remaining &= !ext_bit(letter);
+            }
+        }
+        for letter in b'a'..=b'z' {
+            if remaining & ext_bit(letter) != 0 {
+                write!(f, "{}", letter as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `riscv,isa`字符串中是否列出了某个多字母扩展，例如`"sstc"`
+///
+/// 多字母扩展以`_`分隔，名字不区分大小写
+pub fn isa_string_has(isa: &str, extension: &str) -> bool {
+    isa.trim().split('_').skip(1).any(|name| name.eq_ignore_ascii_case(extension))
+}
+
+/// 从设备树获取的扩展位图，0表示尚未提供
+static DETECTED_BITS: AtomicU32 = AtomicU32::new(0);
+
+/// 从设备树获取的寄存器宽度
+static DETECTED_XLEN: AtomicU8 = AtomicU8::new(0);
+
+/// 使用设备树的`riscv,isa`字符串初始化扩展集合
+///
+/// 字符串无法解析时保持原有结果并返回false
+pub fn init_from_isa_string(isa: &str) -> bool {
+    match IsaExtensions::parse(isa) {
+        Some(extensions) => {
+            DETECTED_XLEN.store(extensions.xlen, Ordering::SeqCst);
+            DETECTED_BITS.store(extensions.bits, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 使用设备树中第一个核心的`riscv,isa`属性初始化扩展集合
+///
+/// 没有该属性或无法解析时保持原有结果并返回false
+pub fn init_from_fdt(fdt: &crate::util::fdt::Fdt) -> bool {
+    fdt.property("/cpus/cpu", "riscv,isa")
+        .and_then(|isa| core::str::from_utf8(isa).ok())
+        .is_some_and(|isa| init_from_isa_string(isa.trim_end_matches('\0')))
+}
+
+/// 获取当前核心支持的ISA扩展
+///
+/// 尚未从设备树获取时返回编译目标启用的扩展
+pub fn isa_extensions() -> IsaExtensions {
+    let bits = DETECTED_BITS.load(Ordering::SeqCst);
+    if bits == 0 {
+        return IsaExtensions::compiled();
+    }
+
+    IsaExtensions {
+        bits,
+        xlen: DETECTED_XLEN.load(Ordering::SeqCst),
+    }
+}
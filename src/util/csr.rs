@@ -0,0 +1,113 @@
+//! 特权CSR写入辅助模块
+//!
+//! 集中处理需要配合屏障指令的CSR配置写入：
+//!
+//! * `stvec` —— 同一核心上的CSR写入按程序顺序生效，下一次trap一定使用新值，无需屏障
+//! * `satp` —— 地址转换缓存可能仍持有旧的映射，写入后必须执行`sfence.vma`
+//! * 修改了将被执行的代码（例如向量表中的跳转指令）—— 需要`fence.i`同步指令缓存
+//!
+//! 屏障的执行次数会被计数，便于测试确认调用路径确实发出了屏障。
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// 已执行的sfence.vma次数
+static SFENCE_VMA_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 已执行的fence.i次数
+static FENCE_I_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// 屏障执行次数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierCounts {
+    /// sfence.vma次数
+    pub sfence_vma: usize,
+    /// fence.i次数
+    pub fence_i: usize,
+}
+
+/// 获取屏障执行次数
+pub fn barrier_counts() -> BarrierCounts {
+    BarrierCounts {
+        sfence_vma: SFENCE_VMA_COUNT.load(Ordering::SeqCst),
+        fence_i: FENCE_I_COUNT.load(Ordering::SeqCst),
+    }
+}
+
+/// 刷新本核心全部地址转换缓存
+#[inline]
+pub fn sfence_vma_all() {
+    unsafe {
+        core::arch::asm!("sfence.vma", options(nostack));
+    }
+    SFENCE_VMA_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 同步本核心的指令缓存
+///
+/// 在写入了随后会被执行的指令之后调用
+#[inline]
+pub fn fence_i() {
+    unsafe {
+        core::arch::asm!("fence.i", options(nostack));
+    }
+    FENCE_I_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// 写入stvec
+///
+/// 同一核心上的CSR写入按程序顺序生效，之后发生的trap一定会使用新值，
+/// 因此这里不需要额外的屏障。如果同时改写了向量表中的指令，调用者需另行调用`fence_i`。
+#[inline]
+pub fn write_stvec(value: usize) {
+    unsafe {
+        core::arch::asm!(
+            "csrw stvec, {0}",
+            in(reg) value,
+            options(nostack)
+        );
+    }
+}
+
+/// 读取stvec
+#[inline]
+pub fn read_stvec() -> usize {
+    let value: usize;
+    unsafe {
+        core::arch::asm!(
+            "csrr {0}, stvec",
+            out(reg) value,
+            options(nomem, nostack)
+        );
+    }
+    value
+}
+
+/// 写入satp并刷新地址转换缓存
+///
+/// 写入新的页表根后，TLB中可能仍缓存着旧页表的映射，
+/// 必须执行`sfence.vma`后新的地址空间才保证生效。
+#[inline]
+pub fn write_satp(value: usize) {
+    unsafe {
+        core::arch::asm!(
+            "csrw satp, {0}",
+            in(reg) value,
+            options(nostack)
+        );
+    }
+    sfence_vma_all();
+}
+
+/// 读取satp
+#[inline]
+pub fn read_satp() -> usize {
+    let value: usize;
+    unsafe {
+        core::arch::asm!(
+            "csrr {0}, satp",
+            out(reg) value,
+            options(nomem, nostack)
+        );
+    }
+    value
+}
@@ -0,0 +1,89 @@
+//! 延迟功能模块
+//!
+//! 基于time CSR和时基频率实现的忙等待延迟，
+//! 用于替代与CPU频率相关的固定次数自旋循环。
+
+use crate::util::sbi::timer;
+
+/// 停机前等待输出刷新的时间(毫秒)
+pub const OUTPUT_FLUSH_DELAY_MS: u64 = 100;
+
+/// 计时器不可用时每毫秒的自旋次数
+///
+/// 只是一个粗略的估计值（按约1亿次/秒的自旋速度计算），
+/// 仅在极早期启动或time CSR不前进时使用，不保证精度。
+pub const FALLBACK_SPINS_PER_MS: u64 = 100_000;
+
+/// 将毫秒转换为time CSR的计数值
+#[inline]
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    ms.saturating_mul(timer::timebase_frequency()) / 1000
+}
+
+/// 将微秒转换为time CSR的计数值
+#[inline]
+pub fn us_to_ticks(us: u64) -> u64 {
+    us.saturating_mul(timer::timebase_frequency()) / 1_000_000
+}
+
+/// 忙等待指定的毫秒数
+///
+/// 通过`timer::now()`计时。如果计时器在`FALLBACK_SPINS_PER_MS`次自旋内
+/// 都没有前进，则认为计时器不可用，改用固定次数的自旋完成剩余的等待。
+pub fn busy_wait_ms(ms: u64) {
+    busy_wait_ticks(ms_to_ticks(ms));
+}
+
+/// 忙等待指定的微秒数
+///
+/// 用于锁竞争退避等短暂等待，计时方式与`busy_wait_ms`相同
+pub fn busy_wait_us(us: u64) {
+    busy_wait_ticks(us_to_ticks(us));
+}
+
+/// 忙等待指定的time CSR计数值
+fn busy_wait_ticks(ticks: u64) {
+    let start = timer::now();
+    let mut last = start;
+    let mut stalled_spins = 0;
+
+    loop {
+        let now = timer::now();
+        let elapsed = now - start;
+        if elapsed >= ticks {
+            return;
+        }
+
+        if now == last {
+            stalled_spins += 1;
+            if stalled_spins >= FALLBACK_SPINS_PER_MS {
+                // 计时器不前进，按固定次数完成剩余时间
+                let remaining_us = (ticks - elapsed) * 1_000_000 / timer::timebase_frequency();
+                spin_wait_us(remaining_us);
+                return;
+            }
+        } else {
+            last = now;
+            stalled_spins = 0;
+        }
+
+        core::hint::spin_loop();
+    }
+}
+
+/// 以固定自旋次数近似等待指定的毫秒数
+///
+/// 不依赖计时器，适用于计时器尚不可用的极早期启动或panic路径。
+/// 实际等待时间取决于CPU频率，见`FALLBACK_SPINS_PER_MS`。
+pub fn spin_wait_ms(ms: u64) {
+    for _ in 0..ms.saturating_mul(FALLBACK_SPINS_PER_MS) {
+        core::hint::spin_loop();
+    }
+}
+
+/// 以固定自旋次数近似等待指定的微秒数
+fn spin_wait_us(us: u64) {
+    for _ in 0..us.saturating_mul(FALLBACK_SPINS_PER_MS) / 1000 {
+        core::hint::spin_loop();
+    }
+}
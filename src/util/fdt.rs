@@ -0,0 +1,178 @@
+//! 扁平设备树（FDT）的最小解析
+//!
+//! SBI固件启动内核时在a1中传入设备树的物理地址。这里只支持按路径查找属性，
+//! 用于启动时读取时基频率、设备基地址等少量平台参数，不建立节点树，也不使用堆。
+
+/// FDT头部的魔数
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// FDT头部的长度
+const FDT_HEADER_LEN: usize = 40;
+
+/// 结构块中的标记
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// 读取大端序的u32
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// 向上对齐到4字节
+const fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+/// 取出从`offset`开始的以0结尾的字符串
+fn c_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let rest = bytes.get(offset..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&rest[..len]).ok()
+}
+
+/// 把一个或两个cell的属性值解析为整数
+///
+/// 设备树中的整数以大端序的32位cell存储，64位的值占两个cell
+pub fn cells_to_u64(value: &[u8]) -> Option<u64> {
+    match value.len() {
+        4 => be_u32(value, 0).map(u64::from),
+        8 => Some((u64::from(be_u32(value, 0)?) << 32) | u64::from(be_u32(value, 4)?)),
+        _ => None,
+    }
+}
+
+/// 节点名是否与路径中的一段匹配
+///
+/// 路径段不带单元地址（`@`之后的部分）时忽略节点名的单元地址
+fn node_matches(component: &str, name: &str) -> bool {
+    if component.contains('@') {
+        return component == name;
+    }
+    name.split('@').next() == Some(component)
+}
+
+/// 一棵扁平设备树
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// 从完整的设备树数据构造，头部无效时返回None
+    pub fn new(blob: &'a [u8]) -> Option<Self> {
+        if blob.len() < FDT_HEADER_LEN || be_u32(blob, 0)? != FDT_MAGIC {
+            return None;
+        }
+
+        let total = (be_u32(blob, 4)? as usize).min(blob.len());
+        let struct_offset = be_u32(blob, 8)? as usize;
+        let strings_offset = be_u32(blob, 12)? as usize;
+        let strings_size = be_u32(blob, 32)? as usize;
+        let struct_size = be_u32(blob, 36)? as usize;
+
+        Some(Self {
+            structs: blob.get(struct_offset..struct_offset.checked_add(struct_size)?.min(total))?,
+            strings: blob.get(strings_offset..strings_offset.checked_add(strings_size)?.min(total))?,
+        })
+    }
+
+    /// 从内存中的设备树构造
+    ///
+    /// # Safety
+    ///
+    /// `addr`为0或不是设备树时返回None；非0时调用者需要保证头部所声明的整个范围可读
+    pub unsafe fn from_addr(addr: usize) -> Option<Fdt<'static>> {
+        if addr == 0 || addr % 4 != 0 {
+            return None;
+        }
+
+        let header = core::slice::from_raw_parts(addr as *const u8, FDT_HEADER_LEN);
+        if be_u32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+        let total = be_u32(header, 4)? as usize;
+        Fdt::new(core::slice::from_raw_parts(addr as *const u8, total))
+    }
+
+    /// 查找属性的值
+    ///
+    /// `path`为以`/`分隔的节点路径，例如`"/cpus"`；路径段省略单元地址时匹配第一个同名节点。
+    /// 找不到节点或属性、或结构块损坏时返回None。
+    pub fn property(&self, path: &str, name: &str) -> Option<&'a [u8]> {
+        let components = path.split('/').filter(|c| !c.is_empty());
+        let target_depth = components.clone().count();
+
+        // depth为当前所在节点的层数（根节点为0），matched为路径中已匹配的层数
+        let mut depth: Option<usize> = None;
+        let mut matched = 0;
+        let mut offset = 0;
+
+        loop {
+            let token = be_u32(self.structs, offset)?;
+            offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let node_name = c_str(self.structs, offset)?;
+                    offset = align4(offset + node_name.len() + 1);
+
+                    let level = depth.map_or(0, |d| d + 1);
+                    depth = Some(level);
+                    if level > 0
+                        && matched == level - 1
+                        && components.clone().nth(level - 1).is_some_and(|c| node_matches(c, node_name))
+                    {
+                        matched = level;
+                    }
+                }
+                FDT_END_NODE => {
+                    let level = depth?;
+                    if matched == level && level > 0 {
+                        // 已经离开匹配的节点，同名节点只查找第一个
+                        if level == target_depth {
+                            return None;
+                        }
+                        matched -= 1;
+                    }
+                    // 根节点结束后不会再有属性
+                    depth = Some(level.checked_sub(1)?);
+                }
+                FDT_PROP => {
+                    let len = be_u32(self.structs, offset)? as usize;
+                    let name_offset = be_u32(self.structs, offset + 4)? as usize;
+                    let value = self.structs.get(offset + 8..offset + 8 + len)?;
+                    offset = align4(offset + 8 + len);
+
+                    if depth == Some(target_depth)
+                        && matched == target_depth
+                        && c_str(self.strings, name_offset)? == name
+                    {
+                        return Some(value);
+                    }
+                }
+                FDT_NOP => {}
+                // FDT_END或无法识别的标记
+                _ => return None,
+            }
+        }
+    }
+
+    /// `/cpus`节点的`timebase-frequency`属性
+    pub fn timebase_frequency(&self) -> Option<u64> {
+        self.property("/cpus", "timebase-frequency").and_then(cells_to_u64)
+    }
+
+    /// 节点第一个`reg`区域的起始地址
+    ///
+    /// 地址占用的cell数取自父节点的`#address-cells`，父节点没有该属性时按规范默认为2
+    pub fn reg_address(&self, path: &str) -> Option<u64> {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let cells = self.property(parent, "#address-cells").and_then(cells_to_u64).unwrap_or(2);
+        let reg = self.property(path, "reg")?;
+        cells_to_u64(reg.get(..(cells as usize).checked_mul(4)?)?)
+    }
+}
@@ -1 +1,11 @@
-pub mod sbi;
\ No newline at end of file
+pub mod sbi;
+pub mod delay;
+pub mod csr;
+pub mod percpu;
+pub mod slice_writer;
+pub mod cpu;
+pub mod fdt;
+pub mod watchdog;
+pub mod spsc;
+
+pub use slice_writer::SliceWriter;
@@ -0,0 +1,90 @@
+//! 按核心划分的数据
+//!
+//! 每个核心在数组中拥有一个槽位，以当前核心ID为下标访问。
+//! 嵌套计数、调度标志、空闲统计等需要按核心保存的状态都应放在这里，
+//! 而不是各自定义`[T; MAX_HARTS]`数组。
+
+use core::cell::UnsafeCell;
+use crate::util::sbi::hart::current_hart_id;
+
+/// 获取当前核心ID
+#[inline]
+pub fn this_hart() -> usize {
+    current_hart_id()
+}
+
+/// 按核心划分的数据，容量为`MAX_HARTS`个核心
+///
+/// 启动入口会停下ID超出`hart::MAX_HARTS`的核心，因此在trap处理等路径上调用`get`
+/// 不会因为核心ID越界而panic；只有向`get_for`传入越界的ID才会panic。
+///
+/// `get`返回共享引用，适合原子类型等内部可变的数据；
+/// `get_mut`返回可变引用，要求调用者保证只在对应核心上访问，
+/// 并且访问期间不会被同一核心上的中断处理程序重入。
+pub struct PerCpu<T, const MAX_HARTS: usize> {
+    slots: UnsafeCell<[T; MAX_HARTS]>,
+}
+
+// 每个槽位只由对应的核心修改，共享访问只产生`&T`
+unsafe impl<T: Send + Sync, const MAX_HARTS: usize> Sync for PerCpu<T, MAX_HARTS> {}
+
+impl<T, const MAX_HARTS: usize> PerCpu<T, MAX_HARTS> {
+    /// 使用每个核心的初始值创建
+    pub const fn new(slots: [T; MAX_HARTS]) -> Self {
+        Self {
+            slots: UnsafeCell::new(slots),
+        }
+    }
+
+    /// 支持的核心数量
+    pub const fn capacity(&self) -> usize {
+        MAX_HARTS
+    }
+
+    /// 检查核心ID是否在容量范围内
+    #[inline]
+    fn check_hart(hart: usize) {
+        if hart >= MAX_HARTS {
+            panic!("hart id {} exceeds per-CPU capacity {}", hart, MAX_HARTS);
+        }
+    }
+
+    /// 获取当前核心的数据
+    #[inline]
+    pub fn get(&self) -> &T {
+        self.get_for(this_hart())
+    }
+
+    /// 获取指定核心的数据
+    #[inline]
+    pub fn get_for(&self, hart: usize) -> &T {
+        Self::check_hart(hart);
+        unsafe { &(*self.slots.get())[hart] }
+    }
+
+    /// 获取当前核心数据的可变引用
+    ///
+    /// # Safety
+    ///
+    /// 调用者必须保证在返回的引用存活期间一直运行在当前核心上，
+    /// 且没有其他代码（包括本核心的中断处理程序）访问同一槽位。
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self) -> &mut T {
+        // SAFETY: 槽位独占访问由调用者按上面的约定保证
+        self.get_mut_for(this_hart())
+    }
+
+    /// 获取指定核心数据的可变引用
+    ///
+    /// # Safety
+    ///
+    /// 与`get_mut`相同，调用者必须保证没有其他代码同时访问该核心的槽位。
+    /// 通常只用于初始化或测试模拟其他核心。
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut_for(&self, hart: usize) -> &mut T {
+        Self::check_hart(hart);
+        &mut (*self.slots.get())[hart]
+    }
+}
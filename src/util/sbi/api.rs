@@ -7,20 +7,86 @@ use sbi_rt::{
     self,
     legacy,
     HartMask,
-    Shutdown, ColdReboot, // 具体类型，实现了ResetType
-    NoReason, SystemFailure, // 具体类型，实现了ResetReason
+    SbiRet,
 };
 
-/// 系统关机
-pub fn shutdown() -> ! {
-    sbi_rt::system_reset(Shutdown, NoReason);
-    unreachable!("关机失败！");
+/// SBI调用返回的标准错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SbiError {
+    /// 调用失败(SBI_ERR_FAILED)
+    Failed,
+    /// 不支持该调用(SBI_ERR_NOT_SUPPORTED)
+    NotSupported,
+    /// 参数无效(SBI_ERR_INVALID_PARAM)
+    InvalidParam,
+    /// 拒绝执行(SBI_ERR_DENIED)
+    Denied,
+    /// 地址无效(SBI_ERR_INVALID_ADDRESS)
+    InvalidAddress,
+    /// 资源已可用，例如目标核心已经启动(SBI_ERR_ALREADY_AVAILABLE)
+    AlreadyAvailable,
+    /// 已经开始(SBI_ERR_ALREADY_STARTED)
+    AlreadyStarted,
+    /// 已经停止(SBI_ERR_ALREADY_STOPPED)
+    AlreadyStopped,
+    /// 没有共享内存(SBI_ERR_NO_SHMEM)
+    NoShmem,
+    /// 规范之外的错误码
+    Unknown(isize),
 }
 
-/// 系统重启
-pub fn reboot() -> ! {
-    sbi_rt::system_reset(ColdReboot, SystemFailure);
-    unreachable!("重启失败！");
+impl SbiError {
+    /// 解码SBI返回的错误码，成功(0)时返回None
+    pub const fn from_code(code: usize) -> Option<Self> {
+        let error = match code as isize {
+            0 => return None,
+            -1 => SbiError::Failed,
+            -2 => SbiError::NotSupported,
+            -3 => SbiError::InvalidParam,
+            -4 => SbiError::Denied,
+            -5 => SbiError::InvalidAddress,
+            -6 => SbiError::AlreadyAvailable,
+            -7 => SbiError::AlreadyStarted,
+            -8 => SbiError::AlreadyStopped,
+            -9 => SbiError::NoShmem,
+            other => SbiError::Unknown(other),
+        };
+        Some(error)
+    }
+}
+
+/// 将SBI返回值转换为Result
+fn check(ret: SbiRet) -> Result<usize, SbiError> {
+    match SbiError::from_code(ret.error) {
+        None => Ok(ret.value),
+        Some(error) => Err(error),
+    }
+}
+
+/// 通过SRST扩展复位系统
+///
+/// `reset_type`和`reason`为SBI规范中的原始编码。成功时不会返回，
+/// 返回值是SBI实现拒绝复位时给出的错误。
+pub fn system_reset(reset_type: u32, reason: u32) -> SbiError {
+    match check(sbi_rt::system_reset(reset_type, reason)) {
+        Ok(_) => SbiError::Failed,
+        Err(error) => error,
+    }
+}
+
+/// 通过旧版SBI调用关机
+///
+/// 在SBI实现不支持SRST扩展时使用
+pub fn legacy_shutdown() -> ! {
+    legacy::shutdown();
+}
+
+/// 探测SBI扩展是否可用
+///
+/// `extension`可以是`sbi_rt`中的扩展类型；依赖`sbi-rt`的`integer-impls`特性，
+/// 也可以直接传入扩展ID（`usize`）
+pub fn probe_extension<E: sbi_rt::Extension>(extension: E) -> bool {
+    sbi_rt::probe_extension(extension).is_available()
 }
 
 /// 向控制台输出一个字符
@@ -28,6 +94,18 @@ pub fn console_putchar(c: char) {
     legacy::console_putchar(c as usize);
 }
 
+/// 通过DBCN扩展向调试控制台批量写入
+///
+/// 一次SBI调用写出整个缓冲区，返回实际写入的字节数，可能少于`bytes.len()`；
+/// 出错或扩展不可用时返回0。内核直接映射内存，缓冲区的地址即物理地址。
+pub fn debug_console_write(bytes: &[u8]) -> usize {
+    if bytes.is_empty() {
+        return 0;
+    }
+    let physical = sbi_rt::Physical::new(bytes.len(), bytes.as_ptr() as usize, 0);
+    check(sbi_rt::console_write(physical)).unwrap_or(0)
+}
+
 /// 从控制台读取一个字符
 pub fn console_getchar() -> Option<char> {
     let c = legacy::console_getchar();
@@ -39,8 +117,15 @@ pub fn console_getchar() -> Option<char> {
 }
 
 /// 设置下一次时钟中断的时间
-pub fn set_timer(time: u64) {
-    sbi_rt::set_timer(time);
+pub fn set_timer(time: u64) -> Result<usize, SbiError> {
+    check(sbi_rt::set_timer(time))
+}
+
+/// 通过旧版SBI调用设置下一次时钟中断的时间
+///
+/// 在SBI实现不支持TIME扩展时使用
+pub fn legacy_set_timer(time: u64) {
+    legacy::set_timer(time);
 }
 
 /// 发送处理器间中断
@@ -48,8 +133,8 @@ pub fn set_timer(time: u64) {
 /// # 参数
 /// 
 /// * `hart_mask` - 目标处理器掩码
-pub fn send_ipi(hart_mask: HartMask) {
-    sbi_rt::send_ipi(hart_mask);
+pub fn send_ipi(hart_mask: HartMask) -> Result<usize, SbiError> {
+    check(sbi_rt::send_ipi(hart_mask))
 }
 
 /// 远程TLB刷新
@@ -57,8 +142,8 @@ pub fn send_ipi(hart_mask: HartMask) {
 /// # 参数
 /// 
 /// * `hart_mask` - 目标处理器掩码
-pub fn remote_fence_i(hart_mask: HartMask) {
-    sbi_rt::remote_fence_i(hart_mask);
+pub fn remote_fence_i(hart_mask: HartMask) -> Result<usize, SbiError> {
+    check(sbi_rt::remote_fence_i(hart_mask))
 }
 
 /// 远程TLB刷新(SFENCE.VMA)
@@ -68,8 +153,8 @@ pub fn remote_fence_i(hart_mask: HartMask) {
 /// * `hart_mask` - 目标处理器掩码
 /// * `start` - 开始地址
 /// * `size` - 地址范围大小
-pub fn remote_sfence_vma(hart_mask: HartMask, start: usize, size: usize) {
-    sbi_rt::remote_sfence_vma(hart_mask, start, size);
+pub fn remote_sfence_vma(hart_mask: HartMask, start: usize, size: usize) -> Result<usize, SbiError> {
+    check(sbi_rt::remote_sfence_vma(hart_mask, start, size))
 }
 
 /// 远程TLB刷新(SFENCE.VMA.ASID)
@@ -80,8 +165,28 @@ pub fn remote_sfence_vma(hart_mask: HartMask, start: usize, size: usize) {
 /// * `start` - 开始地址
 /// * `size` - 地址范围大小
 /// * `asid` - 地址空间ID
-pub fn remote_sfence_vma_asid(hart_mask: HartMask, start: usize, size: usize, asid: usize) {
-    sbi_rt::remote_sfence_vma_asid(hart_mask, start, size, asid);
+pub fn remote_sfence_vma_asid(hart_mask: HartMask, start: usize, size: usize, asid: usize) -> Result<usize, SbiError> {
+    check(sbi_rt::remote_sfence_vma_asid(hart_mask, start, size, asid))
+}
+
+/// 启动指定核心(HSM扩展)
+///
+/// 目标核心从`start_addr`开始以S模式执行，a0为hartid，a1为`opaque`
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<(), SbiError> {
+    check(sbi_rt::hart_start(hart_id, start_addr, opaque)).map(|_| ())
+}
+
+/// 停止当前核心(HSM扩展)，成功时不会返回
+pub fn hart_stop() -> SbiError {
+    match check(sbi_rt::hart_stop()) {
+        Ok(_) => SbiError::Failed,
+        Err(error) => error,
+    }
+}
+
+/// 获取指定核心的HSM状态码
+pub fn hart_get_status(hart_id: usize) -> Result<usize, SbiError> {
+    check(sbi_rt::hart_get_status(hart_id))
 }
 
 /// 获取SBI规范版本
@@ -113,4 +218,80 @@ pub fn get_marchid() -> usize {
 /// 获取可见的MIMPID CSR值
 pub fn get_mimpid() -> usize {
     sbi_rt::get_mimpid()
-}
\ No newline at end of file
+}
+
+/// 传给PMU调用的原始标志位
+struct PmuFlags(usize);
+
+impl sbi_rt::ConfigFlags for PmuFlags {
+    fn raw(&self) -> usize {
+        self.0
+    }
+}
+
+impl sbi_rt::StartFlags for PmuFlags {
+    fn raw(&self) -> usize {
+        self.0
+    }
+}
+
+impl sbi_rt::StopFlags for PmuFlags {
+    fn raw(&self) -> usize {
+        self.0
+    }
+}
+
+/// 获取PMU计数器（硬件和固件）的数量
+pub fn pmu_num_counters() -> usize {
+    sbi_rt::pmu_num_counters()
+}
+
+/// 获取PMU计数器的信息，编码见SBI规范PMU章节
+pub fn pmu_counter_get_info(counter_idx: usize) -> Result<usize, SbiError> {
+    check(sbi_rt::pmu_counter_get_info(counter_idx))
+}
+
+/// 在`counter_idx_base`和`counter_idx_mask`表示的计数器中找到一个能统计该事件的计数器并配置它
+///
+/// 成功时返回选中的计数器编号
+pub fn pmu_counter_config_matching(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    config_flags: usize,
+    event_idx: usize,
+    event_data: u64,
+) -> Result<usize, SbiError> {
+    check(sbi_rt::pmu_counter_config_matching(
+        counter_idx_base,
+        counter_idx_mask,
+        PmuFlags(config_flags),
+        event_idx,
+        event_data,
+    ))
+}
+
+/// 启动一组计数器
+pub fn pmu_counter_start(
+    counter_idx_base: usize,
+    counter_idx_mask: usize,
+    start_flags: usize,
+    initial_value: u64,
+) -> Result<(), SbiError> {
+    check(sbi_rt::pmu_counter_start(
+        counter_idx_base,
+        counter_idx_mask,
+        PmuFlags(start_flags),
+        initial_value,
+    ))
+    .map(|_| ())
+}
+
+/// 停止一组计数器
+pub fn pmu_counter_stop(counter_idx_base: usize, counter_idx_mask: usize, stop_flags: usize) -> Result<(), SbiError> {
+    check(sbi_rt::pmu_counter_stop(counter_idx_base, counter_idx_mask, PmuFlags(stop_flags))).map(|_| ())
+}
+
+/// 读取固件计数器的当前值
+pub fn pmu_counter_fw_read(counter_idx: usize) -> Result<usize, SbiError> {
+    check(sbi_rt::pmu_counter_fw_read(counter_idx))
+}
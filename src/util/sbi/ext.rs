@@ -10,6 +10,9 @@ use super::api;
 /// 系统管理相关功能
 pub mod system {
     use super::api;
+    use core::fmt::{self, Write};
+    use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+    use crate::util::SliceWriter;
     
     /// 系统关机原因枚举
     #[derive(Debug, Clone, Copy)]
@@ -21,6 +24,16 @@ pub mod system {
         /// 用户请求
         UserRequest,
     }
+
+    impl ShutdownReason {
+        /// 对应的SRST复位原因
+        pub const fn reset_reason(self) -> ResetReason {
+            match self {
+                ShutdownReason::SystemFailure => ResetReason::SystemFailure,
+                ShutdownReason::Normal | ShutdownReason::UserRequest => ResetReason::NoReason,
+            }
+        }
+    }
     
     /// 安全关机函数
     ///
@@ -38,8 +51,7 @@ pub mod system {
             ShutdownReason::UserRequest => crate::println!("User requested shutdown"),
         }
         
-        // 调用SBI关机接口
-        api::shutdown();
+        system_reset(ResetType::Shutdown, reason.reset_reason());
     }
     
     /// 系统重启类型枚举
@@ -50,6 +62,16 @@ pub mod system {
         /// 热重启 - 快速重启，不完全重置硬件
         Warm,
     }
+
+    impl RebootType {
+        /// 对应的SRST复位类型
+        pub const fn reset_type(self) -> ResetType {
+            match self {
+                RebootType::Cold => ResetType::ColdReboot,
+                RebootType::Warm => ResetType::WarmReboot,
+            }
+        }
+    }
     
     /// 系统重启函数
     ///
@@ -62,8 +84,232 @@ pub mod system {
             RebootType::Warm => crate::println!("System warm reboot..."),
         }
         
-        // 目前SBI只支持冷重启，这里做一个封装以便未来扩展
-        api::reboot();
+        system_reset(reboot_type.reset_type(), ResetReason::NoReason);
+    }
+
+    /// SRST复位类型，取值与SBI规范一致
+    #[repr(u32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ResetType {
+        /// 关机
+        Shutdown = 0,
+        /// 冷重启
+        ColdReboot = 1,
+        /// 热重启
+        WarmReboot = 2,
+    }
+
+    /// SRST复位原因，取值与SBI规范一致
+    #[repr(u32)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ResetReason {
+        /// 没有特别原因
+        NoReason = 0,
+        /// 系统故障
+        SystemFailure = 1,
+    }
+
+    /// 复位系统
+    ///
+    /// 优先使用SRST扩展；SRST不可用或SBI实现返回错误时退回旧版关机调用，
+    /// 保证不会从这里返回。旧版SBI没有重启调用，重启请求只能关机。
+    pub fn system_reset(reset_type: ResetType, reason: ResetReason) -> ! {
+        if capabilities().has_srst() {
+            let error = api::system_reset(reset_type as u32, reason as u32);
+            crate::println!("SBI system reset failed: {:?}, falling back to legacy shutdown", error);
+        } else if reset_type != ResetType::Shutdown {
+            crate::println!("SBI SRST extension unavailable, shutting down instead");
+        }
+
+        api::legacy_shutdown();
+    }
+
+    /// 内核关心的SBI扩展
+    #[repr(u8)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SbiExtension {
+        /// 定时器扩展(TIME)
+        Time = 0,
+        /// 处理器间中断扩展(IPI)
+        Ipi = 1,
+        /// 远程屏障扩展(RFENCE)
+        Rfence = 2,
+        /// 核心状态管理扩展(HSM)
+        Hsm = 3,
+        /// 系统复位扩展(SRST)
+        Srst = 4,
+        /// 调试控制台扩展(DBCN)
+        Dbcn = 5,
+        /// 性能监控扩展(PMU)
+        Pmu = 6,
+    }
+
+    impl SbiExtension {
+        /// 所有需要探测的扩展
+        pub const ALL: [SbiExtension; 7] = [
+            SbiExtension::Time,
+            SbiExtension::Ipi,
+            SbiExtension::Rfence,
+            SbiExtension::Hsm,
+            SbiExtension::Srst,
+            SbiExtension::Dbcn,
+            SbiExtension::Pmu,
+        ];
+
+        /// 向SBI实现探测该扩展是否可用
+        fn probe(self) -> bool {
+            match self {
+                SbiExtension::Time => api::probe_extension(sbi_rt::Timer),
+                SbiExtension::Ipi => api::probe_extension(sbi_rt::Ipi),
+                SbiExtension::Rfence => api::probe_extension(sbi_rt::Fence),
+                SbiExtension::Hsm => api::probe_extension(sbi_rt::Hsm),
+                SbiExtension::Srst => api::probe_extension(sbi_rt::Reset),
+                SbiExtension::Dbcn => api::probe_extension(sbi_rt::Console),
+                SbiExtension::Pmu => api::probe_extension(sbi_rt::Pmu),
+            }
+        }
+
+        /// 在能力位图中对应的位
+        const fn bit(self) -> u8 {
+            1 << (self as u8)
+        }
+
+        /// SBI规范中的扩展名称
+        pub const fn name(self) -> &'static str {
+            match self {
+                SbiExtension::Time => "TIME",
+                SbiExtension::Ipi => "IPI",
+                SbiExtension::Rfence => "RFENCE",
+                SbiExtension::Hsm => "HSM",
+                SbiExtension::Srst => "SRST",
+                SbiExtension::Dbcn => "DBCN",
+                SbiExtension::Pmu => "PMU",
+            }
+        }
+    }
+
+    /// SBI实现支持的扩展集合
+    ///
+    /// 使用某个扩展前应先检查对应的能力，不可用时的退回方式：
+    ///
+    /// * TIME —— 使用旧版`set_timer`调用
+    /// * SRST —— 使用旧版关机调用，重启请求退化为关机
+    /// * DBCN —— 控制台目前只使用旧版逐字符输出，将来的批量输出路径需在此检查
+    /// * HSM —— 内核目前不启动或停止其他核心，将来的核心控制需在此检查
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SbiCapabilities {
+        bits: u8,
+    }
+
+    impl SbiCapabilities {
+        /// 不包含任何扩展的能力集合
+        pub const fn empty() -> Self {
+            Self { bits: 0 }
+        }
+
+        /// 根据每个扩展的探测结果构造能力集合
+        pub fn from_probe<F: FnMut(SbiExtension) -> bool>(mut probe: F) -> Self {
+            let mut caps = Self::empty();
+            for ext in SbiExtension::ALL {
+                if probe(ext) {
+                    caps.bits |= ext.bit();
+                }
+            }
+            caps
+        }
+
+        /// 检查是否支持指定扩展
+        pub const fn has(&self, ext: SbiExtension) -> bool {
+            self.bits & ext.bit() != 0
+        }
+
+        /// 是否支持TIME扩展
+        pub const fn has_time(&self) -> bool {
+            self.has(SbiExtension::Time)
+        }
+
+        /// 是否支持IPI扩展
+        pub const fn has_ipi(&self) -> bool {
+            self.has(SbiExtension::Ipi)
+        }
+
+        /// 是否支持RFENCE扩展
+        pub const fn has_rfence(&self) -> bool {
+            self.has(SbiExtension::Rfence)
+        }
+
+        /// 是否支持HSM扩展
+        pub const fn has_hsm(&self) -> bool {
+            self.has(SbiExtension::Hsm)
+        }
+
+        /// 是否支持SRST扩展
+        pub const fn has_srst(&self) -> bool {
+            self.has(SbiExtension::Srst)
+        }
+
+        /// 是否支持DBCN扩展
+        pub const fn has_dbcn(&self) -> bool {
+            self.has(SbiExtension::Dbcn)
+        }
+
+        /// 是否支持PMU扩展
+        pub const fn has_pmu(&self) -> bool {
+            self.has(SbiExtension::Pmu)
+        }
+
+        /// 打印能力集合
+        pub fn print(&self) {
+            crate::println!("==== SBI Extensions ====");
+            for ext in SbiExtension::ALL {
+                crate::println!("{:?}: {}", ext, if self.has(ext) { "available" } else { "unavailable" });
+            }
+            crate::println!("========================");
+        }
+    }
+
+    /// 以空格分隔列出支持的扩展名称，没有任何扩展时输出`none`
+    impl fmt::Display for SbiCapabilities {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if self.bits == 0 {
+                return write!(f, "none");
+            }
+            let mut separator = "";
+            for ext in SbiExtension::ALL.into_iter().filter(|&ext| self.has(ext)) {
+                write!(f, "{}{}", separator, ext.name())?;
+                separator = " ";
+            }
+            Ok(())
+        }
+    }
+
+    /// 缓存的能力位图
+    static CAPABILITIES: AtomicU8 = AtomicU8::new(0);
+
+    /// 能力位图是否已经探测过
+    static CAPABILITIES_PROBED: AtomicBool = AtomicBool::new(false);
+
+    /// 探测SBI实现支持的扩展并缓存结果
+    pub fn probe_extensions() -> SbiCapabilities {
+        let caps = SbiCapabilities::from_probe(SbiExtension::probe);
+        CAPABILITIES.store(caps.bits, Ordering::SeqCst);
+        CAPABILITIES_PROBED.store(true, Ordering::SeqCst);
+        caps
+    }
+
+    /// 获取缓存的能力集合，首次调用时进行探测
+    pub fn capabilities() -> SbiCapabilities {
+        if !CAPABILITIES_PROBED.load(Ordering::SeqCst) {
+            return probe_extensions();
+        }
+        SbiCapabilities { bits: CAPABILITIES.load(Ordering::SeqCst) }
+    }
+
+    /// 内核关心的扩展（TIME、IPI、RFENCE、HSM、SRST、PMU、DBCN）中SBI实现支持的部分
+    ///
+    /// 调用HSM、SRST、PMU等扩展前应先检查，结果与`capabilities`相同，首次调用时进行探测
+    pub fn supported_extensions() -> SbiCapabilities {
+        capabilities()
     }
     
     /// 获取系统信息
@@ -78,6 +324,7 @@ pub mod system {
             mvendorid: api::get_mvendorid(),
             marchid: api::get_marchid(),
             mimpid: api::get_mimpid(),
+            extensions: capabilities(),
         }
     }
     
@@ -98,19 +345,37 @@ pub mod system {
         pub marchid: usize,
         /// 机器实现ID
         pub mimpid: usize,
+        /// SBI实现支持的扩展
+        pub extensions: SbiCapabilities,
     }
     
     impl SystemInfo {
         /// 打印系统信息
         pub fn print(&self) {
-            crate::println!("==== System Information ====");
-            crate::println!("SBI Spec Version: {}.{}", self.sbi_spec_version_major, self.sbi_spec_version_minor);
-            crate::println!("SBI Implementation ID: {}", self.sbi_impl_id);
-            crate::println!("SBI Implementation Version: {}", self.sbi_impl_version);
-            crate::println!("Machine Vendor ID: 0x{:x}", self.mvendorid);
-            crate::println!("Machine Architecture ID: 0x{:x}", self.marchid);
-            crate::println!("Machine Implementation ID: 0x{:x}", self.mimpid);
-            crate::println!("============================");
+            crate::print!("{}", self);
+        }
+
+        /// 将系统信息写入调用者提供的缓冲区，不输出到控制台
+        ///
+        /// 返回写入的字节数；缓冲区不足时内容被截断
+        pub fn format_into(&self, buffer: &mut [u8]) -> usize {
+            let mut writer = SliceWriter::new(buffer);
+            let _ = write!(writer, "{}", self);
+            writer.len()
+        }
+    }
+
+    impl fmt::Display for SystemInfo {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            writeln!(f, "==== System Information ====")?;
+            writeln!(f, "SBI Spec Version: {}.{}", self.sbi_spec_version_major, self.sbi_spec_version_minor)?;
+            writeln!(f, "SBI Implementation ID: {}", self.sbi_impl_id)?;
+            writeln!(f, "SBI Implementation Version: {}", self.sbi_impl_version)?;
+            writeln!(f, "Machine Vendor ID: 0x{:x}", self.mvendorid)?;
+            writeln!(f, "Machine Architecture ID: 0x{:x}", self.marchid)?;
+            writeln!(f, "Machine Implementation ID: 0x{:x}", self.mimpid)?;
+            writeln!(f, "SBI Extensions: {}", self.extensions)?;
+            writeln!(f, "============================")
         }
     }
 }
@@ -119,6 +384,12 @@ pub mod system {
 pub mod console {
     use super::api;
     use core::fmt;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use crate::trap::ds::Interrupt;
+    use crate::trap::infrastructure::{self, plic};
+    use crate::util::spsc::SpscRing;
+
+    pub use crate::util::spsc::OverflowPolicy;
     
     /// 控制台输出缓冲区大小
     const CONSOLE_BUFFER_SIZE: usize = 128;
@@ -144,9 +415,21 @@ pub mod console {
         }
         
         /// 将缓冲区内容写入控制台
+        ///
+        /// 支持DBCN扩展时整块写出，每次SBI调用输出多个字节；
+        /// 否则（或DBCN没有写出任何字节时）逐字符调用`console_putchar`
         fn flush(&mut self) {
-            for i in 0..self.len {
-                api::console_putchar(self.buffer[i] as char);
+            let mut written = 0;
+            if super::system::capabilities().has_dbcn() {
+                while written < self.len {
+                    match api::debug_console_write(&self.buffer[written..self.len]) {
+                        0 => break,
+                        count => written += count.min(self.len - written),
+                    }
+                }
+            }
+            for &byte in &self.buffer[written..self.len] {
+                api::console_putchar(byte as char);
             }
             self.clear();
         }
@@ -203,22 +486,141 @@ pub mod console {
         }
     }
     
+    /// 输入环形缓冲区大小，必须是2的幂
+    pub const INPUT_BUFFER_SIZE: usize = 256;
+
+    /// QEMU virt平台UART的PLIC中断号
+    pub const UART_IRQ: u32 = 10;
+
+    /// UART中断的PLIC优先级
+    const UART_IRQ_PRIORITY: u32 = 1;
+
+    /// UART中断使能寄存器（IER），第0位为接收数据中断
+    const UART_IER: usize = 0x1000_0001;
+
+    /// 外部中断送来的输入
+    ///
+    /// UART中断处理器是唯一的生产者，读取字符的代码是唯一的消费者。
+    /// 轮询时主循环也会写入，此时先关中断，保证同一时刻只有一个生产者。
+    static INPUT: SpscRing<u8, INPUT_BUFFER_SIZE> = SpscRing::new(OverflowPolicy::DropNewest);
+
+    /// 是否已启用中断驱动的输入
+    static INTERRUPT_INPUT: AtomicBool = AtomicBool::new(false);
+
+    /// 把字节放入输入缓冲区，返回成功放入的数量，其余计入丢弃数
+    fn push_input(bytes: impl Iterator<Item = u8>) -> usize {
+        let was_enabled = infrastructure::disable_interrupts();
+        let pushed = bytes.filter(|&byte| INPUT.push(byte)).count();
+        infrastructure::restore_interrupts(was_enabled);
+        pushed
+    }
+
+    /// 从SBI读出所有已到达的字符放入缓冲区，返回读到的数量
+    fn drain_input() -> usize {
+        push_input(core::iter::from_fn(|| api::console_getchar().map(|c| c as u8)))
+    }
+
+    /// UART接收中断处理器
+    fn handle_uart_irq(_irq: u32) {
+        drain_input();
+    }
+
+    /// 启用中断驱动的控制台输入
+    ///
+    /// 注册UART的IRQ处理器，打开UART接收中断，在PLIC中开启该中断源并打开S模式外部中断。
+    /// 之后输入由外部中断送入缓冲区，`getchar`在缓冲区为空时执行WFI等待。
+    /// 需要在trap系统初始化之后调用，IRQ处理器注册失败时返回false。
+    pub fn init_interrupt_input() -> bool {
+        if INTERRUPT_INPUT.load(Ordering::SeqCst) {
+            return true;
+        }
+        if !plic::register_irq_handler(UART_IRQ, handle_uart_irq) {
+            return false;
+        }
+
+        // 先取走启用前已经到达的字符
+        drain_input();
+        unsafe { core::ptr::write_volatile(UART_IER as *mut u8, 1) };
+        plic::enable_irq(UART_IRQ, UART_IRQ_PRIORITY);
+        infrastructure::enable_interrupt(Interrupt::SupervisorExternal);
+        INTERRUPT_INPUT.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// 是否已启用中断驱动的控制台输入
+    pub fn is_interrupt_input() -> bool {
+        INTERRUPT_INPUT.load(Ordering::SeqCst)
+    }
+
+    /// 缓冲区中可以立即读取的字符数
+    pub fn input_available() -> usize {
+        INPUT.len()
+    }
+
+    /// 缓冲区已满而丢弃的字符数
+    pub fn dropped_input() -> usize {
+        INPUT.dropped()
+    }
+
+    /// 输入缓冲区满时的处理方式，默认丢弃新到的字符
+    pub fn input_overflow_policy() -> OverflowPolicy {
+        INPUT.policy()
+    }
+
+    /// 设置输入缓冲区满时丢弃新到的字符还是最早的字符
+    pub fn set_input_overflow_policy(policy: OverflowPolicy) {
+        INPUT.set_policy(policy);
+    }
+
+    /// 把字节作为控制台输入放入缓冲区，返回成功放入的数量
+    ///
+    /// 仅供测试使用
+    #[cfg(feature = "test_hooks")]
+    pub(crate) fn simulate_input(bytes: &[u8]) -> usize {
+        push_input(bytes.iter().copied())
+    }
+
+    /// 清空输入缓冲区，返回丢弃的字符数
+    ///
+    /// 仅供测试使用
+    #[cfg(feature = "test_hooks")]
+    pub(crate) fn clear_input() -> usize {
+        INPUT.clear()
+    }
+
     /// 等待并获取一个字符
     ///
-    /// 如果没有输入，将阻塞直到有输入
+    /// 如果没有输入，将阻塞直到有输入。
+    /// 启用中断驱动输入且中断打开时，缓冲区为空就执行WFI等待外部中断，而不是忙等。
     pub fn getchar() -> char {
         loop {
-            if let Some(c) = api::console_getchar() {
-                return c;
+            let was_enabled = infrastructure::disable_interrupts();
+            if let Some(byte) = INPUT.pop() {
+                infrastructure::restore_interrupts(was_enabled);
+                return byte as char;
+            }
+
+            if is_interrupt_input() && was_enabled {
+                // 在关中断的状态下执行WFI，检查缓冲区和进入等待之间到达的中断不会丢失，
+                // 重新开中断后立即进入trap，由IRQ处理器填充缓冲区
+                super::hart::wait_for_interrupt();
+                infrastructure::restore_interrupts(was_enabled);
+            } else {
+                // 中断不可用时只能轮询
+                infrastructure::restore_interrupts(was_enabled);
+                drain_input();
             }
         }
     }
-    
+
     /// 无阻塞获取一个字符
     ///
-    /// 如果没有输入，返回None
+    /// 如果没有输入，返回None。未启用中断驱动输入时先轮询SBI。
     pub fn try_getchar() -> Option<char> {
-        api::console_getchar()
+        if !is_interrupt_input() {
+            drain_input();
+        }
+        INPUT.pop().map(|byte| byte as char)
     }
     
     /// 读取一行输入
@@ -275,7 +677,127 @@ pub mod console {
 /// 时钟和定时器相关功能
 pub mod timer {
     use super::api;
-    
+    use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use spin::Mutex;
+    use crate::trap::infrastructure;
+    use crate::trap::infrastructure::di::context::ContextId;
+    use crate::trap::infrastructure::di::context_pool::CONTEXT_POOL_SIZE;
+
+    /// 默认的时基频率(Hz)，与QEMU virt平台的10 MHz一致
+    ///
+    /// 启动时从设备树读取到实际频率之前使用
+    pub const DEFAULT_TIMEBASE_FREQUENCY: u64 = 10_000_000;
+
+    /// 每秒的纳秒数
+    pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+    /// 当前使用的时基频率(Hz)
+    static TIMEBASE_FREQUENCY: AtomicU64 = AtomicU64::new(DEFAULT_TIMEBASE_FREQUENCY);
+
+    /// 获取time CSR的时基频率(Hz)
+    #[inline]
+    pub fn timebase_frequency() -> u64 {
+        TIMEBASE_FREQUENCY.load(Ordering::Relaxed)
+    }
+
+    /// 设置时基频率(Hz)
+    ///
+    /// 平台的实际频率与默认值不同时（例如从设备树读取后）调用，传入0会被忽略。
+    /// 测试可以用它临时替换频率，结束后需要恢复原值。
+    pub fn set_timebase_frequency(hz: u64) {
+        if hz != 0 {
+            TIMEBASE_FREQUENCY.store(hz, Ordering::Relaxed);
+        }
+    }
+
+    /// 使用设备树中`/cpus`节点的`timebase-frequency`设置时基频率
+    ///
+    /// 设备树中没有该属性时保持原有频率并返回false
+    pub fn init_timebase_from_fdt(fdt: &crate::util::fdt::Fdt) -> bool {
+        match fdt.timebase_frequency() {
+            Some(hz) if hz != 0 => {
+                set_timebase_frequency(hz);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 是否可以直接写stimecmp（Sstc扩展），启动时检测一次
+    static SSTC_AVAILABLE: AtomicBool = AtomicBool::new(false);
+
+    /// 设备树是否报告了Sstc扩展
+    ///
+    /// 检查第一个核心的`riscv,isa-extensions`列表，没有该属性时检查`riscv,isa`字符串
+    pub fn fdt_reports_sstc(fdt: &crate::util::fdt::Fdt) -> bool {
+        if let Some(list) = fdt.property("/cpus/cpu", "riscv,isa-extensions") {
+            return list.split(|&b| b == 0).any(|name| name.eq_ignore_ascii_case(b"sstc"));
+        }
+        fdt.property("/cpus/cpu", "riscv,isa")
+            .and_then(|isa| core::str::from_utf8(isa).ok())
+            .is_some_and(|isa| crate::util::cpu::isa_string_has(isa.trim_end_matches('\0'), "sstc"))
+    }
+
+    /// 根据设备树检测Sstc扩展并缓存结果
+    ///
+    /// S模式能否访问stimecmp还取决于固件是否在menvcfg中打开了STCE，
+    /// OpenSBI在检测到Sstc时会打开它。返回是否检测到Sstc。
+    pub fn init_sstc_from_fdt(fdt: &crate::util::fdt::Fdt) -> bool {
+        let available = fdt_reports_sstc(fdt);
+        SSTC_AVAILABLE.store(available, Ordering::Relaxed);
+        available
+    }
+
+    /// 是否检测到Sstc扩展
+    #[inline]
+    pub fn has_sstc() -> bool {
+        SSTC_AVAILABLE.load(Ordering::Relaxed)
+    }
+
+    /// 直接写stimecmp设置定时器，不经过SBI调用
+    ///
+    /// 没有检测到Sstc时不做任何事并返回false。
+    /// 时钟中断的等待位由硬件按time与stimecmp的比较结果更新，不需要另外清除。
+    #[inline]
+    pub fn set_timer_stimecmp(deadline: u64) -> bool {
+        if !has_sstc() {
+            return false;
+        }
+        unsafe {
+            // stimecmp的CSR编号为0x14d
+            core::arch::asm!("csrw 0x14d, {0}", in(reg) deadline, options(nomem, nostack));
+        }
+        true
+    }
+
+    /// 把time CSR的计数值换算为纳秒
+    ///
+    /// 使用u128保存中间结果，计数值很大时也不会溢出；结果超出u64时饱和
+    #[inline]
+    pub fn ticks_to_ns(ticks: u64) -> u64 {
+        let ns = ticks as u128 * NANOS_PER_SEC as u128 / timebase_frequency() as u128;
+        ns.min(u64::MAX as u128) as u64
+    }
+
+    /// 单调的纳秒时钟
+    ///
+    /// 由`now()`的计数值按时基频率换算，起点为time CSR的零点（通常是上电或复位时刻）
+    #[inline]
+    pub fn now_ns() -> u64 {
+        ticks_to_ns(now())
+    }
+
+    /// 等待指定的毫秒数
+    ///
+    /// 基于`now_ns`计时。与`delay::busy_wait_ms`不同，它不在计时器停止时退回固定次数的自旋，
+    /// 因此只应在计时器确定可用之后使用。
+    pub fn sleep_ms(ms: u64) {
+        let deadline = now_ns().saturating_add(ms.saturating_mul(1_000_000));
+        while now_ns() < deadline {
+            core::hint::spin_loop();
+        }
+    }
+
     /// 获取当前的时间计数器值
     /// 
     /// 这个函数需要在RISC-V的S模式下通过读取time CSR来实现
@@ -292,14 +814,48 @@ pub mod timer {
         }
         time
     }
+
+    /// 已观察到的最大时间计数值
+    static LAST_TIME: AtomicU64 = AtomicU64::new(0);
+
+    /// 获取单调不减的当前时间计数值
+    ///
+    /// 规范保证`rdtime`单调，但模拟器或硬件异常时读数可能短暂回退。
+    /// 这里返回原始读数与此前观察到的最大值中较大的一个，
+    /// 内核中计算时间差的地方都应使用本函数，避免出现负的时间差。
+    #[inline]
+    pub fn now() -> u64 {
+        observe(&LAST_TIME, get_time())
+    }
+
+    /// 把一次原始时间读数记入`last`，返回单调化后的时间
+    ///
+    /// `now`使用全局的`LAST_TIME`，测试可传入自己的计数器和回退的读数验证单调性，
+    /// 不会影响全局时间
+    pub(crate) fn observe(last: &AtomicU64, raw: u64) -> u64 {
+        let previous = last.fetch_max(raw, Ordering::SeqCst);
+        previous.max(raw)
+    }
     
     /// 设置定时器，在指定的时间后触发时钟中断
     ///
+    /// 有Sstc扩展时直接写stimecmp，否则通过SBI调用设置
+    ///
     /// # 参数
     ///
     /// * `time_value` - 绝对时间值
     pub fn set_timer(time_value: u64) {
-        api::set_timer(time_value);
+        if set_timer_stimecmp(time_value) {
+            return;
+        }
+        if super::system::capabilities().has_time() {
+            // 在时钟中断中调用，不能等待控制台锁
+            if let Err(error) = api::set_timer(time_value) {
+                crate::try_println!("SBI set_timer({:#x}) failed: {:?}", time_value, error);
+            }
+        } else {
+            api::legacy_set_timer(time_value);
+        }
     }
     
     /// 设置相对定时器，在当前时间后的指定时间差触发时钟中断
@@ -308,21 +864,252 @@ pub mod timer {
     ///
     /// * `delta` - 相对当前时间的时间差
     pub fn set_timer_rel(delta: u64) {
-        let current = get_time();
+        let current = now();
         set_timer(current + delta);
     }
     
+    /// 时钟回调的最大数量
+    pub const MAX_TICK_CALLBACKS: usize = 8;
+
+    /// 时钟回调标识，注销时使用
+    pub type TickCallbackId = usize;
+
+    /// 每次周期时钟到期时调用的回调
+    static TICK_CALLBACKS: Mutex<[Option<fn()>; MAX_TICK_CALLBACKS]> = Mutex::new([None; MAX_TICK_CALLBACKS]);
+
+    /// 周期时钟的间隔（时钟周期），0表示不重新设置定时器
+    static TICK_INTERVAL: AtomicU64 = AtomicU64::new(0);
+
+    /// 已处理的时钟中断次数
+    static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+    /// 下一次周期时钟的时间，未开启周期时钟时为u64::MAX
+    static NEXT_TICK: AtomicU64 = AtomicU64::new(u64::MAX);
+
+    /// 上下文定时器的最大数量，与上下文池的大小相同
+    pub const MAX_CONTEXT_TIMERS: usize = CONTEXT_POOL_SIZE;
+
+    /// 上下文定时器到期时的回调，参数为到期的上下文
+    pub type ContextTimerCallback = fn(ContextId);
+
+    /// 一个上下文的定时器
+    #[derive(Clone, Copy)]
+    struct ContextTimer {
+        id: ContextId,
+        /// 最近的到期时间，没有等待中的定时时为None
+        deadline: Option<u64>,
+        /// 到期时调用的回调
+        callback: Option<ContextTimerCallback>,
+    }
+
+    /// 按上下文记录的定时器
+    ///
+    /// 时钟中断处理中也会获取该锁，中断处理之外的访问都先关闭本核心的中断
+    static CONTEXT_TIMERS: Mutex<[Option<ContextTimer>; MAX_CONTEXT_TIMERS]> =
+        Mutex::new([None; MAX_CONTEXT_TIMERS]);
+
+    /// 关闭本核心中断后访问上下文定时器
+    fn with_context_timers<R>(f: impl FnOnce(&mut [Option<ContextTimer>; MAX_CONTEXT_TIMERS]) -> R) -> R {
+        let was_enabled = infrastructure::disable_interrupts();
+        let result = f(&mut CONTEXT_TIMERS.lock());
+        infrastructure::restore_interrupts(was_enabled);
+        result
+    }
+
+    /// 找到上下文的定时器，不存在时占用一个空槽位
+    fn context_timer_slot(
+        timers: &mut [Option<ContextTimer>; MAX_CONTEXT_TIMERS],
+        ctx: ContextId,
+    ) -> Option<&mut ContextTimer> {
+        let index = timers
+            .iter()
+            .position(|timer| timer.is_some_and(|timer| timer.id == ctx))
+            .or_else(|| timers.iter().position(Option::is_none))?;
+        Some(timers[index].get_or_insert(ContextTimer {
+            id: ctx,
+            deadline: None,
+            callback: None,
+        }))
+    }
+
+    /// 最近的上下文定时器到期时间
+    fn nearest_context_deadline(timers: &[Option<ContextTimer>; MAX_CONTEXT_TIMERS]) -> Option<u64> {
+        timers.iter().flatten().filter_map(|timer| timer.deadline).min()
+    }
+
+    /// 按下一次周期时钟和最近的上下文定时器设置硬件定时器
+    fn rearm(nearest_context_deadline: Option<u64>) {
+        let next_tick = NEXT_TICK.load(Ordering::SeqCst);
+        set_timer(nearest_context_deadline.map_or(next_tick, |deadline| deadline.min(next_tick)));
+    }
+
+    /// 设置上下文的回调，`ctx`的定时器到期时调用
+    ///
+    /// 槽位已满时返回false
+    pub fn set_context_timer_callback(ctx: ContextId, callback: ContextTimerCallback) -> bool {
+        with_context_timers(|timers| match context_timer_slot(timers, ctx) {
+            Some(timer) => {
+                timer.callback = Some(callback);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// 让上下文的定时器在`delta_cycles`个时钟周期后到期
+    ///
+    /// 已有更早的到期时间时保留原来的时间。到期时调用该上下文的回调，
+    /// 定时器随之清除。槽位已满时返回false。
+    pub fn set_context_timer(ctx: ContextId, delta_cycles: u64) -> bool {
+        let deadline = now().saturating_add(delta_cycles);
+        with_context_timers(|timers| {
+            let Some(timer) = context_timer_slot(timers, ctx) else {
+                return false;
+            };
+            timer.deadline = Some(timer.deadline.map_or(deadline, |current| current.min(deadline)));
+            rearm(nearest_context_deadline(timers));
+            true
+        })
+    }
+
+    /// 上下文定时器的到期时间，没有等待中的定时时返回None
+    pub fn context_timer_deadline(ctx: ContextId) -> Option<u64> {
+        with_context_timers(|timers| {
+            timers
+                .iter()
+                .flatten()
+                .find(|timer| timer.id == ctx)
+                .and_then(|timer| timer.deadline)
+        })
+    }
+
+    /// 取消上下文的定时器并移除它的回调
+    ///
+    /// 进程销毁时调用。返回是否有等待中的定时被取消。
+    pub fn cancel_context_timer(ctx: ContextId) -> bool {
+        with_context_timers(|timers| {
+            let Some(slot) = timers.iter_mut().find(|timer| timer.is_some_and(|timer| timer.id == ctx)) else {
+                return false;
+            };
+            let pending = slot.take().is_some_and(|timer| timer.deadline.is_some());
+            if pending {
+                rearm(nearest_context_deadline(timers));
+            }
+            pending
+        })
+    }
+
+    /// 调用所有已到期的上下文定时器的回调
+    ///
+    /// 到期的定时器先被清除再调用回调，回调中可以重新设置定时器。返回到期的数量。
+    fn expire_context_timers(now: u64) -> usize {
+        let mut expired = [None; MAX_CONTEXT_TIMERS];
+        let mut count = 0;
+        {
+            let mut timers = CONTEXT_TIMERS.lock();
+            for timer in timers.iter_mut().flatten() {
+                if timer.deadline.is_some_and(|deadline| deadline <= now) {
+                    timer.deadline = None;
+                    expired[count] = Some((timer.id, timer.callback));
+                    count += 1;
+                }
+            }
+        }
+
+        for (id, callback) in expired.iter().flatten() {
+            if let Some(callback) = callback {
+                callback(*id);
+            }
+        }
+        count
+    }
+
+    /// 注册时钟回调，槽位已满时返回None
+    ///
+    /// 回调只在周期时钟到期时调用，需要配合`set_periodic`使用
+    pub fn register_tick_callback(cb: fn()) -> Option<TickCallbackId> {
+        let mut callbacks = TICK_CALLBACKS.lock();
+        let id = callbacks.iter().position(|slot| slot.is_none())?;
+        callbacks[id] = Some(cb);
+        Some(id)
+    }
+
+    /// 注销时钟回调
+    pub fn unregister_tick_callback(id: TickCallbackId) -> bool {
+        let mut callbacks = TICK_CALLBACKS.lock();
+        id < MAX_TICK_CALLBACKS && callbacks[id].take().is_some()
+    }
+
+    /// 设置周期时钟
+    ///
+    /// 每次时钟中断处理完回调后，在`interval_cycles`个时钟周期后再次触发。
+    /// 传入0停止周期时钟，定时器推迟到最近的上下文定时器（没有时推迟到最远），
+    /// 以清除等待中的时钟中断。
+    pub fn set_periodic(interval_cycles: u64) {
+        TICK_INTERVAL.store(interval_cycles, Ordering::SeqCst);
+        let next_tick = match interval_cycles {
+            0 => u64::MAX,
+            interval => now().saturating_add(interval),
+        };
+        NEXT_TICK.store(next_tick, Ordering::SeqCst);
+        with_context_timers(|timers| rearm(nearest_context_deadline(timers)));
+    }
+
+    /// 当前周期时钟的间隔，0表示未开启
+    pub fn periodic_interval() -> u64 {
+        TICK_INTERVAL.load(Ordering::SeqCst)
+    }
+
+    /// 已处理的时钟中断次数
+    pub fn tick_count() -> u64 {
+        TICK_COUNT.load(Ordering::SeqCst)
+    }
+
+    /// 处理一次时钟中断
+    ///
+    /// 周期时钟到期时调用所有时钟回调并推进下一次周期时钟；已到期的上下文定时器
+    /// 单独按各自的到期时间唤醒，只因上下文定时器触发时不调用时钟回调。然后把定时器设为
+    /// 下一次周期时钟和最近的上下文定时器中较早的一个；两者都没有时
+    /// 把定时器推迟到最远，避免同一个时钟中断反复触发。返回调用的时钟回调数量。
+    pub fn handle_tick() -> usize {
+        TICK_COUNT.fetch_add(1, Ordering::SeqCst);
+
+        let mut called = 0;
+        let current = now();
+        if NEXT_TICK.load(Ordering::SeqCst) <= current {
+            // 先复制回调列表再释放锁，回调内部可以注册或注销回调
+            let callbacks = *TICK_CALLBACKS.lock();
+            for cb in callbacks.iter().flatten() {
+                cb();
+                called += 1;
+            }
+
+            let next_tick = match periodic_interval() {
+                0 => u64::MAX,
+                interval => current.saturating_add(interval),
+            };
+            NEXT_TICK.store(next_tick, Ordering::SeqCst);
+        }
+
+        expire_context_timers(now());
+        rearm(nearest_context_deadline(&CONTEXT_TIMERS.lock()));
+
+        called
+    }
+
     /// 睡眠指定的时钟周期
     ///
     /// 注意：此函数会阻塞线程执行，并且需要中断处理程序支持
     /// 这个函数只是一个示例，实际使用需要配合中断处理
     ///
+    /// 这是忙等待，会一直占用当前核心；任务中需要让出CPU时使用`sched::sleep_ms`。
+    ///
     /// # 参数
     ///
     /// * `cycles` - 睡眠的时钟周期数
     pub fn sleep_cycles(cycles: u64) {
-        let start = get_time();
-        while get_time() - start < cycles {
+        let start = now();
+        while now() - start < cycles {
             // 空循环等待
             core::hint::spin_loop();
         }
@@ -331,14 +1118,161 @@ pub mod timer {
 
 /// 多核处理器通信相关功能
 pub mod hart {
-    use super::api;
+    use super::api::{self, SbiError};
+    use core::sync::atomic::{AtomicUsize, Ordering};
     use sbi_rt::HartMask;
-    
+
+    /// 核心的HSM状态
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum HartState {
+        /// 已启动
+        Started,
+        /// 已停止
+        Stopped,
+        /// 正在启动
+        StartPending,
+        /// 正在停止
+        StopPending,
+        /// 已挂起
+        Suspended,
+        /// 正在挂起
+        SuspendPending,
+        /// 正在恢复
+        ResumePending,
+        /// 核心不存在、HSM扩展不可用或状态码未知
+        Unknown,
+    }
+
+    impl HartState {
+        /// 解码HSM状态码
+        pub const fn from_code(code: usize) -> Self {
+            match code {
+                0 => HartState::Started,
+                1 => HartState::Stopped,
+                2 => HartState::StartPending,
+                3 => HartState::StopPending,
+                4 => HartState::Suspended,
+                5 => HartState::SuspendPending,
+                6 => HartState::ResumePending,
+                _ => HartState::Unknown,
+            }
+        }
+    }
+
+    /// 启动指定核心
+    ///
+    /// 目标核心从`start_addr`开始以S模式执行，a0为hartid，a1为`opaque`。
+    /// 目标核心已经启动时返回`SbiError::AlreadyAvailable`，
+    /// 部分旧固件对已启动的核心也会返回成功，因此先查询一次状态。
+    pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<(), SbiError> {
+        if hart_get_status(hart_id) == HartState::Started {
+            return Err(SbiError::AlreadyAvailable);
+        }
+        api::hart_start(hart_id, start_addr, opaque)
+    }
+
+    /// 停止当前核心
+    ///
+    /// 核心交还给SBI实现，只能通过`hart_start`重新启动
+    pub fn hart_stop() -> ! {
+        let error = api::hart_stop();
+        panic!("hart {} failed to stop: {:?}", current_hart_id(), error);
+    }
+
+    /// 获取指定核心的状态
+    pub fn hart_get_status(hart_id: usize) -> HartState {
+        match api::hart_get_status(hart_id) {
+            Ok(code) => HartState::from_code(code),
+            Err(_) => HartState::Unknown,
+        }
+    }
+
+    /// 内核支持的最大核心数，用于按核心划分的静态状态数组
+    pub const MAX_HARTS: usize = 8;
+
+    /// 获取当前核心ID
+    ///
+    /// `_start`在启动时将SBI传入的hartid保存到tp寄存器，
+    /// 内核其余代码不会修改tp，因此可以直接读取。
+    #[inline]
+    pub fn current_hart_id() -> usize {
+        let id: usize;
+        unsafe {
+            core::arch::asm!(
+                "mv {0}, tp",
+                out(reg) id,
+                options(nomem, nostack)
+            );
+        }
+        id
+    }
+
+    /// 让当前核心休眠，直到有中断等待处理
+    ///
+    /// 执行`wfi`指令。sie中已开启的中断一旦等待处理就会唤醒核心，与sstatus.SIE无关，
+    /// 因此可以先关闭中断、确认无事可做后再休眠，醒来后开中断进入trap，
+    /// 不会错过检查与休眠之间到达的中断。规范允许`wfi`提前返回，调用者需要重新检查条件。
+    #[inline]
+    pub fn wait_for_interrupt() {
+        unsafe {
+            core::arch::asm!("wfi", options(nomem, nostack));
+        }
+    }
+
+    /// 系统中的核心数量，0表示尚未确定
+    static HART_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    /// 设置系统中的核心数量
+    ///
+    /// 从设备树读取`cpus`节点后调用，超过`MAX_HARTS`的部分被截断，传入0会被忽略
+    pub fn set_hart_count(count: usize) {
+        if count != 0 {
+            HART_COUNT.store(count.min(MAX_HARTS), Ordering::SeqCst);
+        }
+    }
+
+    /// 获取系统中的核心数量
+    ///
+    /// 未通过`set_hart_count`设置时，用HSM扩展从0号核心开始依次查询状态，
+    /// 遇到第一个不存在的核心为止；HSM不可用时只能确定当前核心存在。
+    /// 探测结果会被缓存。
+    pub fn hart_count() -> usize {
+        let count = HART_COUNT.load(Ordering::SeqCst);
+        if count != 0 {
+            return count;
+        }
+
+        let mut count = 0;
+        if super::system::capabilities().has_hsm() {
+            while count < MAX_HARTS && hart_get_status(count) != HartState::Unknown {
+                count += 1;
+            }
+        }
+        // 当前核心必然存在
+        let count = count.max(current_hart_id() + 1).min(MAX_HARTS);
+        HART_COUNT.store(count, Ordering::SeqCst);
+        count
+    }
+
+    /// 创建覆盖编号`[0, count)`的核心的HartMask
+    pub const fn harts_mask(count: usize) -> HartMask {
+        let mask = if count >= usize::BITS as usize {
+            usize::MAX
+        } else {
+            (1 << count) - 1
+        };
+        HartMask::from_mask_base(mask, 0)
+    }
+
     /// 创建一个包含所有可用核心的HartMask
     pub fn all_harts() -> HartMask {
-        // 假设系统最多支持8个核心
-        const MAX_HARTS: usize = 8;
-        HartMask::from_mask_base(usize::MAX, 0)
+        harts_mask(hart_count())
+    }
+
+    /// 创建一个包含除当前核心外所有可用核心的HartMask
+    pub fn other_harts() -> HartMask {
+        let (mask, base) = all_harts().into_inner();
+        HartMask::from_mask_base(mask & !(1 << current_hart_id()), base)
     }
     
     /// 创建一个包含单个核心的HartMask
@@ -355,27 +1289,34 @@ pub mod hart {
     /// # 参数
     ///
     /// * `hart_id` - 目标处理器核心ID
-    pub fn send_ipi_to_hart(hart_id: usize) {
-        api::send_ipi(single_hart(hart_id));
+    pub fn send_ipi_to_hart(hart_id: usize) -> Result<(), SbiError> {
+        api::send_ipi(single_hart(hart_id)).map(|_| ())
     }
     
     /// 发送处理器间中断到所有核心
-    pub fn send_ipi_to_all() {
-        api::send_ipi(all_harts());
+    pub fn send_ipi_to_all() -> Result<(), SbiError> {
+        api::send_ipi(all_harts()).map(|_| ())
     }
     
-    /// 在指定核心上执行远程TLB刷新
+    /// 在指定核心上执行远程FENCE.I，使其指令缓存与之前的写入同步
     ///
     /// # 参数
     ///
     /// * `hart_id` - 目标处理器核心ID
-    pub fn fence_i_on_hart(hart_id: usize) {
-        api::remote_fence_i(single_hart(hart_id));
+    pub fn fence_i_on_hart(hart_id: usize) -> Result<(), SbiError> {
+        api::remote_fence_i(single_hart(hart_id)).map(|_| ())
     }
     
-    /// 在所有核心上执行远程TLB刷新
-    pub fn fence_i_on_all() {
-        api::remote_fence_i(all_harts());
+    /// 在所有核心上执行远程FENCE.I
+    pub fn fence_i_on_all() -> Result<(), SbiError> {
+        api::remote_fence_i(all_harts()).map(|_| ())
+    }
+
+    /// 在除当前核心外的所有核心上执行远程FENCE.I
+    ///
+    /// 修改代码后让其他核心取到新的指令，不会刷新TLB；刷新TLB使用`sfence_vma_on_others`
+    pub fn fence_i_on_others() -> Result<(), SbiError> {
+        api::remote_fence_i(other_harts()).map(|_| ())
     }
     
     /// 在指定核心上执行SFENCE.VMA指令
@@ -385,8 +1326,8 @@ pub mod hart {
     /// * `hart_id` - 目标处理器核心ID
     /// * `start` - 开始地址
     /// * `size` - 地址范围大小
-    pub fn sfence_vma_on_hart(hart_id: usize, start: usize, size: usize) {
-        api::remote_sfence_vma(single_hart(hart_id), start, size);
+    pub fn sfence_vma_on_hart(hart_id: usize, start: usize, size: usize) -> Result<(), SbiError> {
+        api::remote_sfence_vma(single_hart(hart_id), start, size).map(|_| ())
     }
     
     /// 在所有核心上执行SFENCE.VMA指令
@@ -395,15 +1336,131 @@ pub mod hart {
     ///
     /// * `start` - 开始地址
     /// * `size` - 地址范围大小
-    pub fn sfence_vma_on_all(start: usize, size: usize) {
-        api::remote_sfence_vma(all_harts(), start, size);
+    pub fn sfence_vma_on_all(start: usize, size: usize) -> Result<(), SbiError> {
+        api::remote_sfence_vma(all_harts(), start, size).map(|_| ())
+    }
+
+    /// 在除当前核心外的所有核心上执行SFENCE.VMA指令
+    ///
+    /// # 参数
+    ///
+    /// * `start` - 开始地址
+    /// * `size` - 地址范围大小
+    pub fn sfence_vma_on_others(start: usize, size: usize) -> Result<(), SbiError> {
+        api::remote_sfence_vma(other_harts(), start, size).map(|_| ())
+    }
+
+    /// 在所有核心上执行带ASID的SFENCE.VMA指令，只刷新该地址空间的映射
+    ///
+    /// # 参数
+    ///
+    /// * `start` - 开始地址
+    /// * `size` - 地址范围大小
+    /// * `asid` - 地址空间ID
+    pub fn sfence_vma_asid_on_all(start: usize, size: usize, asid: usize) -> Result<(), SbiError> {
+        api::remote_sfence_vma_asid(all_harts(), start, size, asid).map(|_| ())
     }
 }
 
 /// TLB（地址转换缓冲区）相关功能
 pub mod tlb {
     use super::hart;
-    
+    use super::api::SbiError;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use crate::trap::infrastructure::{disable_interrupts, restore_interrupts};
+
+    /// satp中ASID字段的位置
+    const SATP_ASID_SHIFT: usize = 44;
+
+    /// RV64上ASID的最大位数
+    const MAX_ASID_BITS: usize = 16;
+
+    /// satp中MODE字段的位置
+    const SATP_MODE_SHIFT: usize = 60;
+
+    /// satp的MODE为Bare，不进行地址转换
+    const SATP_MODE_BARE: usize = 0;
+
+    /// 实现的ASID位数，`usize::MAX`表示尚未探测
+    static ASID_BITS: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    /// 探测实现的ASID位数（ASIDLEN）
+    ///
+    /// 向satp的ASID字段写入全1后读回，置位的位数即为ASIDLEN，随后恢复satp。
+    /// 结果会被缓存。
+    ///
+    /// 规范要求Bare模式下satp的其余字段为0，此时不探测、也不缓存，返回0：
+    /// 没有地址转换时只有ASID 0有意义。开启分页后再调用时使用当前的页表根探测。
+    pub fn asid_bits() -> usize {
+        let bits = ASID_BITS.load(Ordering::SeqCst);
+        if bits != usize::MAX {
+            return bits;
+        }
+
+        let mask = ((1 << MAX_ASID_BITS) - 1) << SATP_ASID_SHIFT;
+        let was_enabled = disable_interrupts();
+        let satp: usize;
+        unsafe { core::arch::asm!("csrr {0}, satp", out(reg) satp, options(nomem, nostack)) };
+        if satp >> SATP_MODE_SHIFT == SATP_MODE_BARE {
+            restore_interrupts(was_enabled);
+            return 0;
+        }
+
+        let probed: usize;
+        unsafe {
+            core::arch::asm!(
+                "or {tmp}, {orig}, {mask}",
+                "csrw satp, {tmp}",
+                "csrr {tmp}, satp",
+                "csrw satp, {orig}",
+                orig = in(reg) satp,
+                tmp = out(reg) probed,
+                mask = in(reg) mask,
+                options(nostack)
+            );
+        }
+        restore_interrupts(was_enabled);
+
+        let bits = ((probed & mask) >> SATP_ASID_SHIFT).count_ones() as usize;
+        ASID_BITS.store(bits, Ordering::SeqCst);
+        bits
+    }
+
+    /// ASID是否在实现的范围内
+    pub fn is_valid_asid(asid: usize) -> bool {
+        asid >> asid_bits() == 0
+    }
+
+    /// 刷新当前核心上某个地址空间的TLB，全局映射不受影响
+    ///
+    /// ASID超出实现的位数时返回false
+    pub fn flush_local_asid(asid: usize) -> bool {
+        if !is_valid_asid(asid) {
+            return false;
+        }
+        unsafe {
+            core::arch::asm!("sfence.vma zero, {0}", in(reg) asid, options(nostack));
+        }
+        true
+    }
+
+    /// 刷新所有核心上某个地址空间的TLB，全局映射不受影响
+    ///
+    /// ASID超出实现的位数或SBI调用失败时返回false
+    pub fn flush_asid_all_harts(asid: usize) -> bool {
+        if !is_valid_asid(asid) {
+            return false;
+        }
+        // 大小为usize::MAX时SBI刷新整个地址空间
+        match hart::sfence_vma_asid_on_all(0, usize::MAX, asid) {
+            Ok(()) => true,
+            Err(error) => {
+                crate::println!("Remote TLB flush for ASID {} failed: {:?}", asid, error);
+                false
+            }
+        }
+    }
+
     /// 刷新当前核心的TLB（全部）
     pub fn flush_local() {
         unsafe {
@@ -436,12 +1493,14 @@ pub mod tlb {
     }
     
     /// 刷新所有核心的TLB（全部）
-    pub fn flush_all_harts() {
+    ///
+    /// 本地TLB总会被刷新，通知其他核心失败时返回SBI的错误
+    pub fn flush_all_harts() -> Result<(), SbiError> {
         // 首先刷新本地TLB
         flush_local();
         
         // 然后通知其他核心刷新TLB
-        hart::fence_i_on_all();
+        hart::sfence_vma_on_others(0, usize::MAX)
     }
     
     /// 刷新所有核心指定地址范围的TLB
@@ -450,11 +1509,174 @@ pub mod tlb {
     ///
     /// * `start` - 开始地址
     /// * `size` - 地址范围大小
-    pub fn flush_range_all_harts(start: usize, size: usize) {
+    pub fn flush_range_all_harts(start: usize, size: usize) -> Result<(), SbiError> {
         // 首先刷新本地TLB范围
         flush_local_range(start, size);
         
         // 然后通知其他核心刷新指定范围TLB
-        hart::sfence_vma_on_all(start, size);
+        hart::sfence_vma_on_others(start, size)
     }
-}
\ No newline at end of file
+}
+
+/// 性能计数器（PMU扩展）相关功能
+///
+/// 计数器编号由SBI实现分配：0、1、2通常对应cycle、time、instret，
+/// 之后是hpmcounter3..31，再之后是固件计数器。硬件计数器的值直接从对应的CSR读取。
+pub mod pmu {
+    use super::api::{self, SbiError};
+    use super::system;
+
+    /// 硬件计数器CSR（cycle）的编号，hpmcounterN为该值加N
+    const COUNTER_CSR_BASE: usize = 0xc00;
+
+    /// 配置标志：配置时把计数器清零
+    const CFG_FLAG_CLEAR_VALUE: usize = 1 << 1;
+
+    /// 启动标志：使用给定的初始值
+    const START_FLAG_SET_INIT_VALUE: usize = 1 << 0;
+
+    /// 停止标志：解除计数器与事件的绑定
+    const STOP_FLAG_RESET: usize = 1 << 0;
+
+    /// 要统计的事件
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Event {
+        /// CPU周期数
+        CpuCycles,
+        /// 退休的指令数
+        Instructions,
+        /// SBI规范中的原始事件编号（类型在高位，编码在低16位）
+        Raw(usize),
+    }
+
+    impl Event {
+        /// SBI规范中的事件编号
+        pub const fn index(self) -> usize {
+            match self {
+                // 硬件通用事件，类型为0
+                Event::CpuCycles => 0x1,
+                Event::Instructions => 0x2,
+                Event::Raw(index) => index,
+            }
+        }
+    }
+
+    /// 计数器的种类
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum CounterInfo {
+        /// 硬件计数器，通过`csr`读取，有效位数为`width`
+        Hardware { csr: u16, width: u8 },
+        /// 固件计数器，只能通过SBI调用读取
+        Firmware,
+    }
+
+    impl CounterInfo {
+        /// 解码`pmu_counter_get_info`返回的信息
+        pub const fn from_raw(info: usize) -> Self {
+            if info >> (usize::BITS - 1) != 0 {
+                return CounterInfo::Firmware;
+            }
+            CounterInfo::Hardware {
+                csr: (info & 0xfff) as u16,
+                width: (((info >> 12) & 0x3f) + 1) as u8,
+            }
+        }
+    }
+
+    /// 读取编号为`CSR`的计数器CSR
+    #[inline(always)]
+    fn read_counter_csr<const CSR: usize>() -> u64 {
+        let value: usize;
+        unsafe {
+            core::arch::asm!("csrr {0}, {csr}", out(reg) value, csr = const CSR, options(nomem, nostack));
+        }
+        value as u64
+    }
+
+    /// 按编号读取硬件计数器CSR，编号不是计数器CSR时返回None
+    fn read_counter_by_csr(csr: usize) -> Option<u64> {
+        macro_rules! by_offset {
+            ($($n:literal)*) => {
+                match csr.checked_sub(COUNTER_CSR_BASE)? {
+                    $($n => Some(read_counter_csr::<{ COUNTER_CSR_BASE + $n }>()),)*
+                    _ => None,
+                }
+            };
+        }
+        by_offset!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31)
+    }
+
+    /// SBI实现是否支持PMU扩展
+    pub fn is_available() -> bool {
+        system::capabilities().has_pmu()
+    }
+
+    /// 计数器（硬件和固件）的数量，不支持PMU扩展时返回0
+    pub fn num_counters() -> usize {
+        if !is_available() {
+            return 0;
+        }
+        api::pmu_num_counters()
+    }
+
+    /// 获取计数器的种类
+    pub fn counter_info(counter: usize) -> Result<CounterInfo, SbiError> {
+        if !is_available() {
+            return Err(SbiError::NotSupported);
+        }
+        api::pmu_counter_get_info(counter).map(CounterInfo::from_raw)
+    }
+
+    /// 找到一个能统计`event`的计数器，把它清零并绑定到该事件
+    ///
+    /// 返回计数器编号，之后用`counter_start`开始计数。没有计数器能统计该事件时返回
+    /// `Err(NotSupported)`。
+    pub fn counter_config_matching(event: Event) -> Result<usize, SbiError> {
+        let count = num_counters().min(usize::BITS as usize);
+        if count == 0 {
+            return Err(SbiError::NotSupported);
+        }
+        let mask = if count == usize::BITS as usize { usize::MAX } else { (1 << count) - 1 };
+        api::pmu_counter_config_matching(0, mask, CFG_FLAG_CLEAR_VALUE, event.index(), 0)
+    }
+
+    /// 开始计数，`initial_value`为None时从计数器的当前值继续
+    pub fn counter_start(counter: usize, initial_value: Option<u64>) -> Result<(), SbiError> {
+        match initial_value {
+            Some(value) => api::pmu_counter_start(counter, 1, START_FLAG_SET_INIT_VALUE, value),
+            None => api::pmu_counter_start(counter, 1, 0, 0),
+        }
+    }
+
+    /// 停止计数，`reset`为true时同时解除计数器与事件的绑定
+    pub fn counter_stop(counter: usize, reset: bool) -> Result<(), SbiError> {
+        let flags = if reset { STOP_FLAG_RESET } else { 0 };
+        api::pmu_counter_stop(counter, 1, flags)
+    }
+
+    /// 读取计数器的当前值
+    ///
+    /// 硬件计数器直接读取对应的CSR，固件计数器通过SBI调用读取
+    pub fn counter_read(counter: usize) -> Result<u64, SbiError> {
+        match counter_info(counter)? {
+            CounterInfo::Hardware { csr, .. } => read_counter_by_csr(csr as usize).ok_or(SbiError::InvalidParam),
+            CounterInfo::Firmware => api::pmu_counter_fw_read(counter).map(|value| value as u64),
+        }
+    }
+
+    /// 直接读取cycle CSR
+    ///
+    /// 需要M模式固件在mcounteren中开放CY位（OpenSBI默认开放），否则会触发非法指令异常
+    #[inline]
+    pub fn read_cycles() -> u64 {
+        read_counter_csr::<COUNTER_CSR_BASE>()
+    }
+
+    /// 直接读取instret CSR
+    ///
+    /// 需要M模式固件在mcounteren中开放IR位（OpenSBI默认开放），否则会触发非法指令异常
+    #[inline]
+    pub fn read_instret() -> u64 {
+        read_counter_csr::<{ COUNTER_CSR_BASE + 2 }>()
+    }
+}
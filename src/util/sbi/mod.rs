@@ -14,4 +14,5 @@ pub use ext::system;
 pub use ext::console;
 pub use ext::timer;
 pub use ext::hart;
-pub use ext::tlb;
\ No newline at end of file
+pub use ext::tlb;
+pub use ext::pmu;
\ No newline at end of file
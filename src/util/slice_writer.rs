@@ -0,0 +1,57 @@
+//! 字节切片格式化输出
+//!
+//! 无堆环境下把`core::fmt`的输出写入调用者提供的缓冲区。
+
+use core::fmt;
+
+/// 向字节切片写入格式化文本的写入器
+///
+/// 缓冲区写满后丢弃剩余内容并返回`fmt::Error`，已写入的部分保持有效。
+/// 截断只发生在UTF-8字符边界上，缓冲区中的内容始终是合法的字符串。
+pub struct SliceWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    /// 创建写入器，从缓冲区开头写入
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// 已写入的字节数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 是否还没有写入任何内容
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 已写入的内容
+    pub fn as_str(&self) -> &str {
+        // 只会按字符边界写入完整的&str片段
+        unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.len]) }
+    }
+}
+
+impl fmt::Write for SliceWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = self.buffer.len() - self.len;
+        if s.len() <= available {
+            self.buffer[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            return Ok(());
+        }
+
+        // 空间不足，写入能放下的完整字符
+        let mut end = available;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.buffer[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        Err(fmt::Error)
+    }
+}
@@ -0,0 +1,165 @@
+//! 无锁的单生产者单消费者环形队列
+//!
+//! 中断处理程序写入、主循环读取的场景下，用锁保护队列需要在读取时关中断，
+//! 中断处理程序里等锁还有死锁的风险。这里只用两个原子下标：`tail`只由生产者推进，
+//! `head`由消费者推进，两端都不会阻塞。
+//!
+//! 下标是不回绕的计数，槽位为`下标 % N`，`tail - head`即队列长度。
+//! 队列满时按`OverflowPolicy`丢弃新到的或最早的元素。丢弃最早的元素时生产者也要推进`head`，
+//! 因此两端都用比较交换推进`head`，消费者读出的元素在推进失败时作废并重试。
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+/// 队列满时的处理方式
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 丢弃新到的元素，队列保持不变
+    DropNewest = 0,
+    /// 丢弃最早的元素，为新元素腾出位置
+    DropOldest = 1,
+}
+
+/// 固定容量的单生产者单消费者环形队列
+///
+/// `push`只能由一个生产者调用，`pop`只能由一个消费者调用，两者可以并发，
+/// 例如生产者是中断处理程序、消费者是被它打断的代码。容量`N`必须是2的幂。
+pub struct SpscRing<T: Copy, const N: usize> {
+    slots: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// 下一个要读取的位置
+    head: AtomicUsize,
+    /// 下一个要写入的位置
+    tail: AtomicUsize,
+    /// 队列满时的处理方式
+    policy: AtomicU8,
+    /// 因队列已满而丢弃的元素数量
+    dropped: AtomicUsize,
+}
+
+// 生产者只写入[tail]处尚未发布的槽位，消费者只读取已发布的槽位
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscRing<T, N> {}
+
+impl<T: Copy, const N: usize> SpscRing<T, N> {
+    /// 容量必须是2的幂，下标计数回绕时槽位才保持连续
+    const CAPACITY_IS_POWER_OF_TWO: () = assert!(N.is_power_of_two(), "SpscRing capacity must be a power of two");
+
+    /// 创建空队列
+    pub const fn new(policy: OverflowPolicy) -> Self {
+        let () = Self::CAPACITY_IS_POWER_OF_TWO;
+        Self {
+            slots: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            policy: AtomicU8::new(policy as u8),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// 队列容量
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 当前元素数量
+    pub fn len(&self) -> usize {
+        // 先读head，读到的tail不会比它旧，差值不会下溢
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(N)
+    }
+
+    /// 队列是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 队列满时的处理方式
+    pub fn policy(&self) -> OverflowPolicy {
+        match self.policy.load(Ordering::Relaxed) {
+            1 => OverflowPolicy::DropOldest,
+            _ => OverflowPolicy::DropNewest,
+        }
+    }
+
+    /// 设置队列满时的处理方式
+    pub fn set_policy(&self, policy: OverflowPolicy) {
+        self.policy.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// 因队列已满而丢弃的元素数量
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 放入一个元素，只能由生产者调用
+    ///
+    /// 不会阻塞。队列已满时按处理方式丢弃元素并计数；
+    /// 新到的元素被丢弃时返回false，其余情况返回true。
+    pub fn push(&self, value: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) >= N {
+            match self.policy() {
+                OverflowPolicy::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                OverflowPolicy::DropOldest => {
+                    // 失败说明消费者刚取走了最早的元素，已经有空位
+                    if self
+                        .head
+                        .compare_exchange(head, head.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            let slot = (self.slots.get() as *mut MaybeUninit<T>).add(tail % N);
+            core::ptr::write_volatile(slot, MaybeUninit::new(value));
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// 取出最早的元素，只能由消费者调用
+    ///
+    /// 不会阻塞，队列为空时返回None
+    pub fn pop(&self) -> Option<T> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                return None;
+            }
+
+            // 生产者可能在读取之后丢弃这个元素并改写槽位，此时推进head会失败，读到的值作废
+            let value = unsafe {
+                let slot = (self.slots.get() as *const MaybeUninit<T>).add(head % N);
+                core::ptr::read_volatile(slot)
+            };
+            if self
+                .head
+                .compare_exchange(head, head.wrapping_add(1), Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                return Some(unsafe { value.assume_init() });
+            }
+        }
+    }
+
+    /// 取出所有元素，返回取出的数量，只能由消费者调用
+    pub fn clear(&self) -> usize {
+        let mut count = 0;
+        while self.pop().is_some() {
+            count += 1;
+        }
+        count
+    }
+}
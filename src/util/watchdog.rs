@@ -0,0 +1,98 @@
+//! 软件看门狗
+//!
+//! `arm`之后必须在超时时间内调用`pet`，否则时钟回调以系统故障为原因冷重启系统。
+//! 状态全部保存在原子变量中，`pet`可以在任何上下文（包括中断处理器）中调用。
+//!
+//! 超时只在时钟中断中检查，实际的判定最多晚一个时钟周期。
+//! 没有开启周期时钟时，`arm`以`DEFAULT_CHECK_INTERVAL_MS`为间隔开启它，`disable`时再关闭。
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use crate::try_println;
+use crate::util::delay;
+use crate::util::sbi::system::{self, ResetType, ResetReason};
+use crate::util::sbi::timer;
+
+/// 看门狗自行开启周期时钟时使用的检查间隔(毫秒)
+pub const DEFAULT_CHECK_INTERVAL_MS: u64 = 10;
+
+/// 没有注册时钟回调
+const NO_CALLBACK: usize = usize::MAX;
+
+/// 超时时间(纳秒)，0表示看门狗未启用
+static TIMEOUT_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 上一次喂狗的时间(纳秒)
+static LAST_PET_NS: AtomicU64 = AtomicU64::new(0);
+
+/// 注册的时钟回调
+static CALLBACK_ID: AtomicUsize = AtomicUsize::new(NO_CALLBACK);
+
+/// 周期时钟是否由看门狗开启
+static STARTED_PERIODIC: AtomicBool = AtomicBool::new(false);
+
+/// 启用看门狗，此后需要每`timeout_ms`毫秒内至少调用一次`pet`
+///
+/// 启用时视为刚刚喂过狗。已经启用时只更新超时时间。
+/// 超时时间为0或时钟回调已满时返回false。
+pub fn arm(timeout_ms: u64) -> bool {
+    if timeout_ms == 0 {
+        return false;
+    }
+
+    if CALLBACK_ID.load(Ordering::SeqCst) == NO_CALLBACK {
+        match timer::register_tick_callback(watchdog_tick) {
+            Some(id) => CALLBACK_ID.store(id, Ordering::SeqCst),
+            None => return false,
+        }
+    }
+
+    pet();
+    TIMEOUT_NS.store(timeout_ms.saturating_mul(1_000_000), Ordering::SeqCst);
+
+    if timer::periodic_interval() == 0 {
+        timer::set_periodic(delay::ms_to_ticks(DEFAULT_CHECK_INTERVAL_MS));
+        STARTED_PERIODIC.store(true, Ordering::SeqCst);
+    }
+    true
+}
+
+/// 喂狗，重新开始计算超时
+#[inline]
+pub fn pet() {
+    LAST_PET_NS.store(timer::now_ns(), Ordering::SeqCst);
+}
+
+/// 停用看门狗
+pub fn disable() {
+    TIMEOUT_NS.store(0, Ordering::SeqCst);
+
+    let id = CALLBACK_ID.swap(NO_CALLBACK, Ordering::SeqCst);
+    if id != NO_CALLBACK {
+        timer::unregister_tick_callback(id);
+    }
+    if STARTED_PERIODIC.swap(false, Ordering::SeqCst) {
+        timer::set_periodic(0);
+    }
+}
+
+/// 看门狗是否已启用
+pub fn is_armed() -> bool {
+    TIMEOUT_NS.load(Ordering::SeqCst) != 0
+}
+
+/// 在`now_ns`时看门狗是否已经超时
+pub fn expired_at(now_ns: u64) -> bool {
+    let timeout = TIMEOUT_NS.load(Ordering::SeqCst);
+    timeout != 0 && now_ns.saturating_sub(LAST_PET_NS.load(Ordering::SeqCst)) > timeout
+}
+
+/// 时钟回调，超时后冷重启系统
+fn watchdog_tick() {
+    if !expired_at(timer::now_ns()) {
+        return;
+    }
+
+    try_println!("Watchdog expired after {}ms without a pet, resetting",
+                 TIMEOUT_NS.load(Ordering::SeqCst) / 1_000_000);
+    system::system_reset(ResetType::ColdReboot, ResetReason::SystemFailure);
+}